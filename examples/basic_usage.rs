@@ -174,6 +174,7 @@ async fn llm_provider_example() -> Result<()> {
         content: "Hello, can you help me code?".to_string(),
         tool_calls: None,
         tool_call_id: None,
+        parts: Vec::new(),
     }];
     
     let response = provider.generate(
@@ -41,6 +41,10 @@ pub struct Config {
     pub working_directory: Option<PathBuf>,
     /// Provider configurations
     pub providers: HashMap<String, ProviderConfig>,
+    /// Backends tried in order when the active one is rate limited, down or
+    /// rejects the model, as `provider` or `provider:model`
+    #[serde(default)]
+    pub fallback_chain: Vec<String>,
     /// UI preferences
     pub ui: UiConfig,
     /// Context generation settings
@@ -49,6 +53,12 @@ pub struct Config {
     pub execution: ExecutionConfig,
     /// Logging configuration
     pub logging: LoggingConfig,
+    /// On-disk LLM response cache
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Spending caps enforced against the usage ledger
+    #[serde(default)]
+    pub budget: BudgetConfig,
 }
 
 /// Configuration for an LLM provider
@@ -107,6 +117,63 @@ pub struct ExecutionConfig {
     pub max_retries: usize,
     /// Whether to pause on errors
     pub pause_on_error: bool,
+    /// How tasks are executed: "refined_instructions" or "tool_calling"
+    #[serde(default)]
+    pub mode: crate::execution::ExecutionMode,
+    /// Maximum model turns per task in tool-calling mode
+    #[serde(default = "default_max_tool_iterations")]
+    pub max_tool_iterations: usize,
+    /// Which generated plans wait for approval: "always", "never" or "destructive"
+    #[serde(default)]
+    pub plan_review: crate::planning::PlanReviewPolicy,
+    /// How deep and wide abstract tasks may be decomposed into sub-plans
+    #[serde(default)]
+    pub sub_plans: crate::planning::SubPlanLimits,
+}
+
+/// LLM response cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Whether identical LLM requests are served from disk
+    pub enabled: bool,
+    /// How long a cached response stays valid (seconds)
+    pub ttl_seconds: u64,
+    /// Size above which the oldest responses are evicted (megabytes)
+    pub max_size_mb: u64,
+}
+
+/// Spending caps in USD; unset caps are not enforced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    /// Maximum spend for a single kai-x session
+    pub session_limit: Option<f64>,
+    /// Maximum spend per calendar day (UTC)
+    pub daily_limit: Option<f64>,
+    /// Maximum spend per calendar month (UTC)
+    pub monthly_limit: Option<f64>,
+}
+
+fn default_max_tool_iterations() -> usize {
+    10
+}
+
+impl ExecutionConfig {
+    /// Settings for the execution engine and task executor
+    pub fn to_engine_config(&self) -> crate::execution::ExecutionConfig {
+        crate::execution::ExecutionConfig {
+            max_concurrent_tasks: self.max_concurrent_tasks,
+            default_timeout_seconds: self.default_timeout_seconds,
+            auto_retry: self.auto_retry,
+            max_retries: self.max_retries,
+            pause_on_error: self.pause_on_error,
+            mode: self.mode,
+            max_tool_iterations: self.max_tool_iterations,
+            plan_review: self.plan_review,
+            sub_plans: self.sub_plans,
+        }
+    }
 }
 
 /// Logging configuration
@@ -134,10 +201,13 @@ impl Default for Config {
             active_model: "google/gemini-2.5-pro".to_string(),
             working_directory: None,
             providers,
+            fallback_chain: Vec::new(),
             ui: UiConfig::default(),
             context: ContextConfig::default(),
             execution: ExecutionConfig::default(),
             logging: LoggingConfig::default(),
+            cache: CacheConfig::default(),
+            budget: BudgetConfig::default(),
         }
     }
 }
@@ -162,6 +232,15 @@ impl ProviderConfig {
                 Some("https://api.anthropic.com".to_string()),
                 Some("claude-3-haiku-20240307".to_string()),
             ),
+            // Self-hosted servers pick their own model names
+            "openai_compatible" => (
+                Some("http://localhost:8000/v1".to_string()),
+                None,
+            ),
+            "ollama" => (
+                Some("http://localhost:11434".to_string()),
+                Some("llama3.2".to_string()),
+            ),
             _ => (None, None), // Unknown provider
         };
 
@@ -180,9 +259,29 @@ impl ProviderConfig {
             "gemini" => Some("https://generativelanguage.googleapis.com/v1beta".to_string()),
             "openai" => Some("https://api.openai.com/v1".to_string()),
             "anthropic" => Some("https://api.anthropic.com".to_string()),
+            "openai_compatible" => Some("http://localhost:8000/v1".to_string()),
+            "ollama" => Some("http://localhost:11434".to_string()),
             _ => None,
         }
     }
+
+    /// Get provider settings as strings for the LLM factory
+    ///
+    /// Scalar values are stringified; arrays, objects and nulls are skipped.
+    pub fn string_settings(&self) -> HashMap<String, String> {
+        self.settings
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Number(n) => n.to_string(),
+                    serde_json::Value::Bool(b) => b.to_string(),
+                    _ => return None,
+                };
+                Some((key.clone(), value))
+            })
+            .collect()
+    }
 }
 
 
@@ -237,6 +336,20 @@ impl Default for ExecutionConfig {
             auto_retry: false,
             max_retries: 3,
             pause_on_error: true,
+            mode: crate::execution::ExecutionMode::default(),
+            max_tool_iterations: default_max_tool_iterations(),
+            plan_review: crate::planning::PlanReviewPolicy::default(),
+            sub_plans: crate::planning::SubPlanLimits::default(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_seconds: 7 * 24 * 60 * 60, // 1 week
+            max_size_mb: 100,
         }
     }
 }
@@ -477,6 +590,14 @@ impl Config {
             .and_then(|p| p.api_key.clone())
     }
 
+    /// Get base URL for a provider, preferring the configured value over the built-in default
+    pub fn get_base_url_for_provider(&self, provider_name: &str) -> Option<String> {
+        self.providers.get(provider_name)
+            .and_then(|p| p.base_url.clone())
+            .filter(|url| !url.trim().is_empty())
+            .or_else(|| ProviderConfig::get_base_url_for_provider(provider_name))
+    }
+
     /// Get API key for the currently active provider
     pub fn get_active_api_key(&self) -> Option<String> {
         self.get_api_key_for_provider(&self.active_provider)
@@ -494,8 +615,11 @@ impl Config {
                 ));
             }
 
-            // Validate active provider has API key (either in config or environment)
-            if self.get_active_provider_config().is_some() {
+            // Validate active provider has API key (either in config or environment),
+            // unless it runs without one
+            if self.get_active_provider_config().is_some()
+                && crate::utils::config::ApiKeyResolver::requires_api_key(&self.active_provider)
+            {
                 if self.get_active_api_key().is_none() {
                     let env_key_name = format!("{}_API_KEY", self.active_provider.to_uppercase());
                     return Err(KaiError::validation(
@@ -507,6 +631,28 @@ impl Config {
             }
         }
 
+        // Every fallback backend needs a configured provider for its settings
+        for entry in &self.fallback_chain {
+            let (provider_name, _) = crate::llm::fallback::parse_fallback_entry(entry);
+            if !self.providers.contains_key(&provider_name) {
+                return Err(KaiError::validation(
+                    "fallback_chain",
+                    format!("Fallback provider '{}' not found in configuration", provider_name)
+                ));
+            }
+        }
+
+        // Spending caps must be positive amounts
+        for (field, limit) in [
+            ("budget.session_limit", self.budget.session_limit),
+            ("budget.daily_limit", self.budget.daily_limit),
+            ("budget.monthly_limit", self.budget.monthly_limit),
+        ] {
+            if limit.is_some_and(|limit| limit.is_nan() || limit <= 0.0) {
+                return Err(KaiError::validation(field, "Spending cap must be greater than zero"));
+            }
+        }
+
         // Validate working directory exists if set
         if let Some(ref workdir) = self.working_directory {
             if !workdir.exists() {
@@ -1,6 +1,7 @@
 //! Global context management for project-wide state
 
 use super::{ContextConfig, ContextEntry, FileMetadata};
+use crate::llm::tokenizer::{Tokenizer, TokenizerRegistry};
 use crate::llm::LlmProvider;
 use crate::utils::errors::KaiError;
 use crate::Result;
@@ -11,6 +12,9 @@ use tokio::fs;
 use ignore::WalkBuilder;
 use serde::{Serialize, Deserialize};
 
+/// Files up to this many tokens are summarized in a single request
+const DIRECT_SUMMARY_MAX_TOKENS: usize = 2000;
+
 /// Global context that maintains a summary of the entire project
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalContext {
@@ -1324,12 +1328,13 @@ impl GlobalContext {
             .unwrap_or_else(|| "text".to_string());
         
         // If content is small enough, summarize directly
-        if content.len() <= 8000 {  // Roughly 2000 tokens
+        let tokenizer = TokenizerRegistry::global().for_model(model);
+        if tokenizer.count_tokens(content) <= DIRECT_SUMMARY_MAX_TOKENS {
             return self.generate_direct_summary(content, file_path, &language, llm_provider, model).await;
         }
         
         // For large files, use chunked summarization
-        self.generate_chunked_summary(content, file_path, &language, llm_provider, model).await
+        self.generate_chunked_summary(content, file_path, &language, tokenizer.as_ref(), llm_provider, model).await
     }
     
     /// Generate summary for small files directly
@@ -1370,14 +1375,15 @@ impl GlobalContext {
         content: &str,
         file_path: &Path,
         language: &str,
+        tokenizer: &dyn Tokenizer,
         llm_provider: &dyn LlmProvider,
         model: &str,
     ) -> Result<String> {
-        tracing::info!("Using chunked summarization for large file: {} ({} chars)", 
-                      file_path.display(), content.len());
+        tracing::info!("Using chunked summarization for large file: {} ({} chars, {} tokenizer)", 
+                      file_path.display(), content.len(), tokenizer.name());
         
         // Split content into logical chunks
-        let chunks = self.split_content_into_chunks(content, language);
+        let chunks = self.split_content_into_chunks(content, language, tokenizer);
         
         if chunks.is_empty() {
             return Ok("Empty file or failed to chunk content.".to_string());
@@ -1456,41 +1462,46 @@ impl GlobalContext {
     }
     
     /// Split content into logical chunks for processing
-    fn split_content_into_chunks(&self, content: &str, language: &str) -> Vec<String> {
-        const MAX_CHUNK_SIZE: usize = 6000;  // ~1500 tokens
-        const MIN_CHUNK_SIZE: usize = 1000;  // Don't create tiny chunks
-        
-        if content.len() <= MAX_CHUNK_SIZE {
+    ///
+    /// Sizes are in tokens as counted by the model's tokenizer, one per line
+    /// plus a newline token.
+    fn split_content_into_chunks(&self, content: &str, language: &str, tokenizer: &dyn Tokenizer) -> Vec<String> {
+        const MAX_CHUNK_TOKENS: usize = 1500;
+        const MIN_CHUNK_TOKENS: usize = 250;  // Don't create tiny chunks
+        
+        if tokenizer.count_tokens(content) <= MAX_CHUNK_TOKENS {
             return vec![content.to_string()];
         }
         
-        let lines: Vec<&str> = content.lines().collect();
+        let lines: Vec<(&str, usize)> = content
+            .lines()
+            .map(|line| (line, tokenizer.count_tokens(line) + 1))
+            .collect();
         
         match language.to_lowercase().as_str() {
             "rust" | "java" | "javascript" | "typescript" | "c++" | "c" | "go" => {
-                self.split_by_logical_blocks(&lines, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE)
+                self.split_by_logical_blocks(&lines, MAX_CHUNK_TOKENS, MIN_CHUNK_TOKENS)
             }
             "python" => {
-                self.split_python_by_functions(&lines, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE)
+                self.split_python_by_functions(&lines, MAX_CHUNK_TOKENS, MIN_CHUNK_TOKENS)
             }
             "markdown" | "text" => {
-                self.split_by_sections(&lines, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE)
+                self.split_by_sections(&lines, MAX_CHUNK_TOKENS, MIN_CHUNK_TOKENS)
             }
             _ => {
-                self.split_by_line_count(&lines, MAX_CHUNK_SIZE)
+                self.split_by_line_count(&lines, MAX_CHUNK_TOKENS)
             }
         }
     }
     
     /// Split content by logical code blocks (functions, classes, etc.)
-    fn split_by_logical_blocks(&self, lines: &[&str], max_size: usize, min_size: usize) -> Vec<String> {
+    fn split_by_logical_blocks(&self, lines: &[(&str, usize)], max_size: usize, min_size: usize) -> Vec<String> {
         let mut chunks: Vec<String> = Vec::new();
         let mut current_chunk = Vec::new();
         let mut current_size = 0;
         let mut brace_depth = 0;
         
-        for line in lines {
-            let line_size = line.len() + 1; // +1 for newline
+        for &(line, line_size) in lines {
             
             // Track brace depth to identify logical boundaries
             for ch in line.chars() {
@@ -1501,7 +1512,7 @@ impl GlobalContext {
                 }
             }
             
-            current_chunk.push(*line);
+            current_chunk.push(line);
             current_size += line_size;
             
             // Split at logical boundaries when size limit is approached
@@ -1523,13 +1534,12 @@ impl GlobalContext {
     }
     
     /// Split Python code by functions and classes
-    fn split_python_by_functions(&self, lines: &[&str], max_size: usize, min_size: usize) -> Vec<String> {
+    fn split_python_by_functions(&self, lines: &[(&str, usize)], max_size: usize, min_size: usize) -> Vec<String> {
         let mut chunks: Vec<String> = Vec::new();
         let mut current_chunk = Vec::new();
         let mut current_size = 0;
         
-        for line in lines {
-            let line_size = line.len() + 1;
+        for &(line, line_size) in lines {
             let trimmed = line.trim();
             
             // Check for function or class definition
@@ -1545,7 +1555,7 @@ impl GlobalContext {
                 }
             }
             
-            current_chunk.push(*line);
+            current_chunk.push(line);
             current_size += line_size;
             
             if current_size >= max_size {
@@ -1565,13 +1575,12 @@ impl GlobalContext {
     }
     
     /// Split by sections (for markdown and text files)
-    fn split_by_sections(&self, lines: &[&str], max_size: usize, _min_size: usize) -> Vec<String> {
+    fn split_by_sections(&self, lines: &[(&str, usize)], max_size: usize, _min_size: usize) -> Vec<String> {
         let mut chunks: Vec<String> = Vec::new();
         let mut current_chunk = Vec::new();
         let mut current_size = 0;
         
-        for line in lines {
-            let line_size = line.len() + 1;
+        for &(line, line_size) in lines {
             let is_header = line.starts_with('#') || line.starts_with("=") || line.starts_with("-");
             
             if is_header && current_size >= max_size / 2 && !current_chunk.is_empty() {
@@ -1580,7 +1589,7 @@ impl GlobalContext {
                 current_size = 0;
             }
             
-            current_chunk.push(*line);
+            current_chunk.push(line);
             current_size += line_size;
             
             if current_size >= max_size {
@@ -1600,15 +1609,14 @@ impl GlobalContext {
     }
     
     /// Simple split by line count as fallback
-    fn split_by_line_count(&self, lines: &[&str], max_size: usize) -> Vec<String> {
+    fn split_by_line_count(&self, lines: &[(&str, usize)], max_size: usize) -> Vec<String> {
         let mut chunks: Vec<String> = Vec::new();
         let mut current_chunk = Vec::new();
         let mut current_size = 0;
         
-        for line in lines {
-            let line_size = line.len() + 1;
+        for &(line, line_size) in lines {
             
-            current_chunk.push(*line);
+            current_chunk.push(line);
             current_size += line_size;
             
             if current_size >= max_size {
@@ -464,32 +464,7 @@ impl ContextManager {
     
     /// Detect circular dependencies in plan context
     fn detect_circular_dependencies(&self, plan_context: &PlanContext) -> Option<Vec<String>> {
-        use std::collections::{HashSet, VecDeque};
-        
-        for (task_id, _dependencies) in &plan_context.dependency_graph {
-            let mut visited = HashSet::new();
-            let mut stack = VecDeque::new();
-            stack.push_back(task_id.clone());
-            
-            while let Some(current_task) = stack.pop_back() {
-                if visited.contains(&current_task) {
-                    // Found a cycle
-                    return Some(visited.into_iter().collect());
-                }
-                
-                visited.insert(current_task.clone());
-                
-                if let Some(deps) = plan_context.dependency_graph.get(&current_task) {
-                    for dep in deps {
-                        if !visited.contains(dep) {
-                            stack.push_back(dep.clone());
-                        }
-                    }
-                }
-            }
-        }
-        
-        None
+        crate::planning::validation::find_dependency_cycle(&plan_context.dependency_graph)
     }
     
     /// Get the last health check report
@@ -1,10 +1,11 @@
 //! Task executor for individual task execution with security sandboxing
 
-use super::ExecutionConfig;
+use super::{llm_step_error, tools, ExecutionConfig, ExecutionMode};
 use crate::llm::{TaskExecutionResult, GenerationConfig};
+use crate::llm::{Message, MessageRole, PromptContext, PromptTemplates, ToolCall};
 use crate::planning::{Task, TaskType};
 use crate::utils::errors::KaiError;
-use crate::llm::LlmProvider;
+use crate::llm::{LlmError, LlmProvider};
 use crate::Result;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -15,6 +16,9 @@ use tokio::fs;
 use tokio::process::Command;
 use tracing::{debug, error, info, warn};
 
+/// Longest tool result (in characters) sent back to the model in one message
+const MAX_TOOL_RESULT_CHARS: usize = 16_000;
+
 /// Executes individual tasks with security sandboxing and enhanced capabilities
 pub struct TaskExecutor {
     /// Configuration for execution behavior
@@ -145,6 +149,184 @@ impl TaskExecutor {
         }
     }
 
+    /// Current execution mode
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.config.mode
+    }
+
+    /// Execute a task by letting the model drive executor primitives through tool calls
+    ///
+    /// Every tool call is dispatched as a primitive task carrying the parent
+    /// task's id, so path validation, command checks and audit logging apply
+    /// exactly as they do for planned tasks. Tool results are fed back until the
+    /// model answers without calling a tool or `max_tool_iterations` is reached.
+    pub async fn execute_task_with_tools(&mut self, task: &Task, context: &str) -> Result<TaskExecutionResult> {
+        let start_time = SystemTime::now();
+        let tool_definitions = tools::tool_definitions();
+
+        let prompt_context = PromptContext::new()
+            .with_variable("task_description", task.description.clone())
+            .with_variable("task_type", task.task_type.to_string())
+            .with_variable("task_parameters", serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
+            .with_variable("context", context);
+        let (system_message, user_message) = PromptTemplates::tool_execution()
+            .fill(&prompt_context)
+            .map_err(|e| KaiError::execution(format!("Failed to build tool-calling prompt: {}", e)))?;
+
+        let mut messages = vec![
+            Message {
+                role: MessageRole::System,
+                content: system_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: user_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+        ];
+        let config = GenerationConfig {
+            temperature: Some(0.2),
+            max_tokens: Some(4000),
+            ..Default::default()
+        };
+
+        let mut calls = Vec::new();
+        let mut modified_files = Vec::new();
+        let mut failed_calls = 0;
+
+        for turn in 1..=self.config.max_tool_iterations {
+            let response = self.llm_provider
+                .generate(&messages, &self.model, Some(&tool_definitions), Some(&config))
+                .await
+                .map_err(|e| llm_step_error(&format!("Tool-calling turn {} failed", turn), e))?;
+
+            let tool_calls = response.tool_calls.clone().unwrap_or_default();
+            if tool_calls.is_empty() {
+                let summary = response.content.unwrap_or_default();
+                let execution_time = start_time.elapsed().unwrap_or(Duration::ZERO).as_millis() as u64;
+                info!(
+                    "Task {} finished after {} tool-calling turns ({} calls, {} failed) in {}ms",
+                    task.id, turn, calls.len(), failed_calls, execution_time
+                );
+
+                let mut result = Self::success_result(
+                    Some(serde_json::json!({
+                        "summary": summary,
+                        "tool_calls": calls,
+                        "modified_files": modified_files,
+                    })),
+                    Some(summary),
+                    execution_time,
+                );
+                result.metadata.insert("turns".to_string(), serde_json::json!(turn));
+                result.metadata.insert("failed_tool_calls".to_string(), serde_json::json!(failed_calls));
+                return Ok(result);
+            }
+
+            messages.push(Message {
+                role: MessageRole::Assistant,
+                content: response.content.unwrap_or_default(),
+                tool_calls: Some(tool_calls.clone()),
+                tool_call_id: None,
+                parts: Vec::new(),
+            });
+
+            for call in &tool_calls {
+                debug!("Task {} calling tool {} with {}", task.id, call.function.name, call.function.arguments);
+                let result = self.execute_tool_call(task, call).await;
+
+                if result.success {
+                    let mutates = matches!(
+                        tools::task_type_for_tool(&call.function.name),
+                        Some(TaskType::WriteFile | TaskType::CreateDirectory | TaskType::Delete)
+                    );
+                    if let Some(path) = result.output.as_ref().and_then(|o| o["sanitized_path"].as_str()).filter(|_| mutates) {
+                        modified_files.push(path.to_string());
+                    }
+                } else {
+                    failed_calls += 1;
+                }
+
+                calls.push(serde_json::json!({
+                    "tool": call.function.name,
+                    "arguments": call.function.arguments,
+                    "success": result.success,
+                    "error": result.error,
+                }));
+                messages.push(Message {
+                    role: MessageRole::Tool,
+                    content: Self::tool_result_message(&result),
+                    tool_calls: None,
+                    tool_call_id: Some(call.id.clone()),
+                    parts: Vec::new(),
+                });
+            }
+        }
+
+        warn!("Task {} exhausted {} tool-calling turns", task.id, self.config.max_tool_iterations);
+        let mut result = Self::failure_result(
+            format!(
+                "Model did not finish within {} tool-calling turns",
+                self.config.max_tool_iterations
+            ),
+            None,
+            None,
+        );
+        result.output = Some(serde_json::json!({
+            "tool_calls": calls,
+            "modified_files": modified_files,
+        }));
+        result.execution_time_ms = start_time.elapsed().unwrap_or(Duration::ZERO).as_millis() as u64;
+        Ok(result)
+    }
+
+    /// Dispatch a single model tool call as a primitive task
+    ///
+    /// Unknown tools, malformed arguments and rejected paths or commands come
+    /// back as failed results so the model can correct itself.
+    pub async fn execute_tool_call(&mut self, task: &Task, call: &ToolCall) -> TaskExecutionResult {
+        let Some(task_type) = tools::task_type_for_tool(&call.function.name) else {
+            warn!("Task {} called unknown tool {}", task.id, call.function.name);
+            return Self::failure_result(format!("Unknown tool '{}'", call.function.name), None, None);
+        };
+
+        let parameters = match tools::tool_call_parameters(&task_type, call) {
+            Ok(parameters) => parameters,
+            Err(message) => return Self::failure_result(message, None, None),
+        };
+
+        let mut primitive = Task::new(task.id.clone(), format!("{} for {}", call.function.name, task.description), task_type);
+        primitive.parameters = parameters;
+
+        match self.execute_task(&primitive, "", "").await {
+            Ok(result) => result,
+            Err(e) => Self::failure_result(e.to_string(), None, None),
+        }
+    }
+
+    /// Render an execution result as a tool message, truncated to keep the conversation bounded
+    fn tool_result_message(result: &TaskExecutionResult) -> String {
+        let content = serde_json::json!({
+            "success": result.success,
+            "output": result.output,
+            "error": result.error,
+            "stdout": result.stdout,
+            "stderr": result.stderr,
+            "exit_code": result.exit_code,
+        })
+        .to_string();
+
+        match content.char_indices().nth(MAX_TOOL_RESULT_CHARS) {
+            Some((cut, _)) => format!("{}... [truncated {} bytes]", &content[..cut], content.len() - cut),
+            None => content,
+        }
+    }
+
     /// Execute a read file task with security validation
     async fn execute_read_file(&mut self, task: &Task) -> Result<TaskExecutionResult> {
         let start_time = SystemTime::now();
@@ -478,6 +660,7 @@ impl TaskExecutor {
                     metadata: HashMap::new(),
                 })
             }
+            Err(LlmError::Cancelled) => Err(LlmError::Cancelled.into()),
             Err(e) => {
                 let execution_time = start_time.elapsed().unwrap_or(Duration::ZERO).as_millis() as u64;
                 error!("Failed to generate content: {}", e);
@@ -1200,4 +1383,93 @@ fn detect_language_from_extension(path: &str) -> Option<String> {
                 _ => None,
             }
         })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::ScriptedProvider;
+    use crate::llm::{FunctionCall, LlmResponse};
+
+    fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments,
+            },
+        }
+    }
+
+    fn response(content: Option<&str>, tool_calls: Option<Vec<ToolCall>>) -> LlmResponse {
+        LlmResponse {
+            content: content.map(str::to_string),
+            tool_calls,
+            finish_reason: "stop".to_string(),
+            usage: None,
+            backend: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calling_loop_dispatches_through_executor() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = Arc::new(ScriptedProvider::new("scripted").with_replies(vec![
+            response(None, Some(vec![
+                tool_call("call_1", "write_file", serde_json::json!({"path": "notes/todo.txt", "content": "ship it"})),
+                tool_call("call_2", "read_file", serde_json::json!("{\"path\": \"/etc/passwd\"}")),
+            ])),
+            response(None, Some(vec![tool_call("call_3", "read_file", serde_json::json!({"path": "notes/todo.txt"}))])),
+            response(Some("Wrote notes/todo.txt"), None),
+        ]));
+        let config = ExecutionConfig {
+            mode: ExecutionMode::ToolCalling,
+            ..ExecutionConfig::default()
+        };
+        let mut executor = TaskExecutor::new(config, dir.path().to_path_buf(), provider.clone(), "m".to_string());
+        assert_eq!(executor.execution_mode(), ExecutionMode::ToolCalling);
+
+        let task = Task::new("task_1", "Write a todo note", TaskType::WriteFile);
+        let result = executor.execute_task_with_tools(&task, "").await.unwrap();
+
+        assert!(result.success);
+        assert_eq!(result.stdout.as_deref(), Some("Wrote notes/todo.txt"));
+        assert_eq!(result.metadata["failed_tool_calls"], 1);
+        let output = result.output.unwrap();
+        assert_eq!(output["tool_calls"].as_array().unwrap().len(), 3);
+        assert_eq!(output["modified_files"].as_array().unwrap().len(), 1);
+        assert_eq!(std::fs::read_to_string(dir.path().join("notes/todo.txt")).unwrap(), "ship it");
+
+        // The rejected path is audited against the parent task
+        assert!(executor.get_audit_log().iter().any(|entry| entry.task_id == "task_1" && !entry.allowed));
+
+        // Tool results are fed back with their call ids
+        let requests = provider.requests();
+        assert!(requests.iter().all(|request| request.tools == Some(6)));
+        let second_turn = &requests[1].messages;
+        let tool_messages: Vec<&Message> = second_turn.iter().filter(|m| matches!(m.role, MessageRole::Tool)).collect();
+        assert_eq!(tool_messages.len(), 2);
+        assert_eq!(tool_messages[0].tool_call_id.as_deref(), Some("call_1"));
+        assert!(tool_messages[1].content.contains("outside the working directory"));
+        assert!(requests[2].messages.last().unwrap().content.contains("ship it"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_calling_loop_stops_at_iteration_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let looping = || response(None, Some(vec![tool_call("call", "list_files", serde_json::json!({}))]));
+        let provider = Arc::new(ScriptedProvider::new("scripted").with_replies(vec![looping(), looping()]));
+        let config = ExecutionConfig {
+            mode: ExecutionMode::ToolCalling,
+            max_tool_iterations: 2,
+            ..ExecutionConfig::default()
+        };
+        let mut executor = TaskExecutor::new(config, dir.path().to_path_buf(), provider, "m".to_string());
+
+        let task = Task::new("task_1", "List forever", TaskType::ListFiles);
+        let result = executor.execute_task_with_tools(&task, "").await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("2 tool-calling turns"));
+    }
+}
@@ -1,9 +1,18 @@
 //! Task execution engine with agentic loop and dual-queue system
 
+use crate::config::BudgetConfig;
 use crate::context::{ContextManager, PlanContext};
-use crate::llm::LlmProvider;
-use crate::planning::{Plan, Task, TaskResult, TaskType};
+use crate::llm::content::generate_plan_with_attachments;
+use crate::llm::streaming::utils::generate_plan_observed;
+use crate::llm::registry::{ModelRegistry, ModelRequirements};
+use crate::llm::usage::{BudgetExceeded, UsageLedger};
+use crate::llm::{cancellation, ContentPart, LlmError, LlmProvider, StreamOutput, StreamStage};
+use crate::planning::control::{merge_revision, should_decompose, PlanControl};
+use crate::planning::diff::revision_prompt;
+use crate::planning::store::PlanStore;
+use crate::planning::{Plan, PlanDiff, PlanReviewPolicy, PlanStatus, SubPlanLimits, Task, TaskResult, TaskStatus, TaskType};
 use crate::utils::errors::KaiError;
+use crate::utils::templates::builders::ConversationMessageBuilder;
 use crate::Result;
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
@@ -17,6 +26,7 @@ use tracing::{debug, error, info, warn};
 
 pub mod executor;
 pub mod queue;
+pub mod tools;
 
 pub use executor::TaskExecutor;
 pub use queue::{TaskQueue, QueuePriority};
@@ -75,6 +85,13 @@ pub struct ExecutionEngine {
     event_sender: broadcast::Sender<ExecutionEvent>,
     /// Metrics collector
     metrics: Arc<RwLock<ExecutionMetrics>>,
+    /// Model capabilities checked before planning
+    model_registry: Option<Arc<ModelRegistry>>,
+    /// Where the current plan is saved after every state change
+    plan_store: Option<PlanStore>,
+    /// Plan token, review, spending cap and decomposition state shared with the coordinator;
+    /// reviewed plans remember the priority of their prompt
+    plan_control: PlanControl<PromptPriority>,
 }
 
 /// User prompt with metadata
@@ -84,6 +101,10 @@ pub struct UserPrompt {
     pub content: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub priority: PromptPriority,
+    /// Images and files sent to the model with the prompt
+    pub attachments: Vec<ContentPart>,
+    /// Summary of the chat so far, so follow-up requests can refer to earlier turns
+    pub conversation: Option<String>,
 }
 
 /// Priority levels for user prompts
@@ -104,6 +125,65 @@ pub enum ExecutionState {
     Cancelled,
 }
 
+/// How tasks are turned into executor operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionMode {
+    /// The LLM writes a free-text instruction that the executor interprets
+    #[default]
+    RefinedInstructions,
+    /// The LLM drives executor primitives through native function calls
+    ToolCalling,
+}
+
+impl ExecutionMode {
+    /// Features the model needs to execute tasks in this mode
+    pub fn model_requirements(&self) -> ModelRequirements {
+        ModelRequirements {
+            tools: *self == ExecutionMode::ToolCalling,
+            ..Default::default()
+        }
+    }
+}
+
+/// Fail when the model is known to lack a feature the plan needs
+///
+/// Plans need what the execution mode requires, plus image input when images
+/// are attached to the prompt. Without a registry nothing is checked.
+pub async fn ensure_model_supports_plan(
+    registry: Option<&ModelRegistry>,
+    llm_provider: &dyn LlmProvider,
+    model: &str,
+    mode: ExecutionMode,
+    attachments: &[ContentPart],
+) -> Result<()> {
+    let Some(registry) = registry else {
+        return Ok(());
+    };
+    let requirements = ModelRequirements {
+        vision: attachments.iter().any(|part| matches!(part, ContentPart::Image { .. })),
+        ..mode.model_requirements()
+    };
+    let unmet = registry.unmet_requirements(llm_provider, model, &requirements).await;
+    if unmet.is_empty() {
+        Ok(())
+    } else {
+        Err(KaiError::planning(format!(
+            "Model {} cannot run this plan because it lacks {}; pick another model with /model",
+            model,
+            unmet.join(", ")
+        )))
+    }
+}
+
+/// Wrap an LLM failure in a task step, keeping cancellations recognisable
+fn llm_step_error(step: &str, error: LlmError) -> KaiError {
+    match error {
+        LlmError::Cancelled => KaiError::Llm(error),
+        error => KaiError::execution(format!("{}: {}", step, error)),
+    }
+}
+
 /// Configuration for the execution engine
 #[derive(Debug, Clone)]
 pub struct ExecutionConfig {
@@ -112,6 +192,13 @@ pub struct ExecutionConfig {
     pub auto_retry: bool,
     pub max_retries: usize,
     pub pause_on_error: bool,
+    pub mode: ExecutionMode,
+    /// Maximum model turns per task in tool-calling mode
+    pub max_tool_iterations: usize,
+    /// Which generated plans wait for `approve_plan` before they run
+    pub plan_review: PlanReviewPolicy,
+    /// How deep and wide abstract tasks are decomposed into sub-plans
+    pub sub_plans: SubPlanLimits,
 }
 
 impl Default for ExecutionConfig {
@@ -122,6 +209,10 @@ impl Default for ExecutionConfig {
             auto_retry: false,
             max_retries: 3,
             pause_on_error: true,
+            mode: ExecutionMode::default(),
+            max_tool_iterations: 10,
+            plan_review: PlanReviewPolicy::default(),
+            sub_plans: SubPlanLimits::default(),
         }
     }
 }
@@ -136,6 +227,7 @@ impl ExecutionEngine {
         config: Option<ExecutionConfig>,
     ) -> Self {
         let config = config.unwrap_or_default();
+        let plan_store = PlanStore::for_project(&working_dir);
         let task_executor = TaskExecutor::new(
             config.clone(), 
             working_dir,
@@ -157,20 +249,70 @@ impl ExecutionEngine {
             current_plan_context: Arc::new(RwLock::new(None)),
             state: Arc::new(RwLock::new(ExecutionState::Idle)),
             config,
+            plan_control: PlanControl::new(cancellation_token.clone()),
             cancellation_token,
             running_tasks: Arc::new(RwLock::new(HashMap::new())),
             event_sender,
             metrics: Arc::new(RwLock::new(ExecutionMetrics::new())),
+            model_registry: ModelRegistry::global(),
+            plan_store: Some(plan_store),
         }
     }
 
+    /// Check model capabilities against `registry` instead of the shared model registry
+    pub fn with_model_registry(mut self, registry: Arc<ModelRegistry>) -> Self {
+        self.model_registry = Some(registry);
+        self
+    }
+
+    /// Save plans to `store` instead of the working directory's `.kai/plans`
+    pub fn with_plan_store(mut self, store: PlanStore) -> Self {
+        self.plan_store = Some(store);
+        self
+    }
+
+    /// Pause plans before a task that would exceed one of these spending caps
+    pub fn with_budget(mut self, budget: BudgetConfig) -> Self {
+        self.plan_control = self.plan_control.with_budget(budget);
+        self
+    }
+
+    /// Check spending caps against `ledger` instead of the shared usage ledger
+    pub fn with_usage_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.plan_control = self.plan_control.with_usage_ledger(ledger);
+        self
+    }
+
     /// Submit a user prompt to the high-priority queue
     pub async fn submit_user_prompt(&self, content: String, priority: PromptPriority) -> String {
+        self.submit_user_prompt_with_attachments(content, Vec::new(), priority).await
+    }
+
+    /// Submit a user prompt with images or files attached
+    pub async fn submit_user_prompt_with_attachments(
+        &self,
+        content: String,
+        attachments: Vec<ContentPart>,
+        priority: PromptPriority,
+    ) -> String {
+        self.submit_chat_prompt(content, attachments, None, priority).await
+    }
+
+    /// Submit a prompt from a chat session along with a summary of the conversation so far
+    pub async fn submit_chat_prompt(
+        &self,
+        content: String,
+        attachments: Vec<ContentPart>,
+        conversation: Option<String>,
+        priority: PromptPriority,
+    ) -> String {
         let prompt = UserPrompt {
             id: Uuid::new_v4().to_string(),
             content,
             timestamp: chrono::Utc::now(),
             priority,
+            attachments,
+            conversation,
         };
 
         let id = prompt.id.clone();
@@ -196,6 +338,26 @@ impl ExecutionEngine {
         id
     }
 
+    /// Answer a question about the project without creating a plan
+    pub async fn answer_question(&self, question: &str, conversation: Option<&str>) -> Result<String> {
+        let messages = {
+            let context_manager = self.context_manager.read().await;
+            let global_context = context_manager.get_global_context_summary().await?;
+            ConversationMessageBuilder::create(
+                question,
+                conversation.unwrap_or("No earlier messages"),
+                &global_context,
+                context_manager.working_directory(),
+            )?
+        };
+
+        let response = self.llm_provider.generate(&messages, &self.model, None, None).await?;
+        response
+            .content
+            .filter(|content| !content.trim().is_empty())
+            .ok_or_else(|| KaiError::execution("The model returned an empty answer"))
+    }
+
     /// Start the main execution loop with parallel task execution
     pub async fn start(&self) -> Result<()> {
         {
@@ -242,14 +404,49 @@ impl ExecutionEngine {
 
             // Priority 2: Check for user prompts (every cycle)
             if let Some(user_prompt) = self.pop_user_prompt().await {
-                self.handle_user_prompt(user_prompt).await?;
+                // A prompt the model cannot serve is reported and skipped, leaving the engine running
+                if let Err(e) = self.ensure_prompt_supported(&user_prompt).await {
+                    warn!("Skipping prompt {}: {}", user_prompt.id, e);
+                    self.emit_event(ExecutionEvent::PromptRejected {
+                        prompt_id: user_prompt.id,
+                        reason: match e {
+                            KaiError::Planning { message } => message,
+                            other => other.to_string(),
+                        },
+                    })
+                    .await;
+                    *self.state.write().await = ExecutionState::Executing;
+                    continue;
+                }
+                match self.handle_user_prompt(user_prompt).await {
+                    // Planning stopped by `cancel_plan` leaves the engine running
+                    Err(e) if e.is_cancelled() => {
+                        info!("Planning cancelled");
+                        *self.state.write().await = ExecutionState::Executing;
+                    }
+                    result => result?,
+                }
                 continue;
             }
 
             // Priority 3: Start new tasks if we have capacity
             let current_running = self.running_tasks.read().await.len();
             if current_running < self.config.max_concurrent_tasks {
+                if self.main_task_queue.read().await.ready_task_count() > 0 {
+                    if let Some(exceeded) = self.plan_control.check_budget().await {
+                        self.hold_for_budget(exceeded).await;
+                        continue;
+                    }
+                }
                 if let Some(task) = self.pop_task().await {
+                    if self.should_decompose(&task).await {
+                        match self.decompose_task(&task).await {
+                            Ok(()) => continue,
+                            // Cancelling the plan already cleared the queue
+                            Err(e) if e.is_cancelled() => continue,
+                            Err(e) => warn!("Could not decompose task {}, executing it directly: {}", task.id, e),
+                        }
+                    }
                     let task_handle = self.start_task_execution(task).await?;
                     futures.push(task_handle);
                     continue;
@@ -328,6 +525,49 @@ impl ExecutionEngine {
         }
     }
 
+    /// Cancel the current plan
+    ///
+    /// Running tasks stop and their outstanding LLM requests are aborted; they
+    /// are recorded as cancelled rather than failed. A plan waiting for review
+    /// is discarded, and a plan held on a spending cap no longer holds the engine.
+    pub async fn cancel_plan(&self) {
+        let cancelled = self.plan_control.cancel(&self.current_plan).await;
+        self.main_task_queue.write().await.clear();
+        if cancelled.released_budget_hold {
+            self.resume().await;
+        }
+
+        self.persist_plan().await;
+        self.emit_event(ExecutionEvent::PlanCancelled { plan_id: cancelled.plan_id }).await;
+    }
+
+    /// Whether a plan is being generated, waiting for review, or has tasks left to run
+    pub async fn has_active_plan(&self) -> bool {
+        *self.state.read().await == ExecutionState::Planning
+            || self.plan_control.has_pending_review().await
+            || !self.running_tasks.read().await.is_empty()
+            || !self.main_task_queue.read().await.is_empty()
+    }
+
+    /// Spending cap the current plan is paused on, if any
+    pub async fn budget_hold(&self) -> Option<BudgetExceeded> {
+        self.plan_control.budget_hold().await
+    }
+
+    /// Continue a plan paused on a spending cap, approving that cap for the rest of the plan
+    pub async fn continue_past_budget(&self) {
+        if self.plan_control.continue_past_budget().await.is_some() {
+            self.resume().await;
+        }
+    }
+
+    /// Pause the plan until the user approves the extra spend or cancels it
+    async fn hold_for_budget(&self, exceeded: BudgetExceeded) {
+        self.plan_control.hold_for_budget(exceeded.clone()).await;
+        self.pause().await;
+        self.emit_event(ExecutionEvent::BudgetExceeded(exceeded)).await;
+    }
+
     /// Get the current execution state
     pub async fn get_state(&self) -> ExecutionState {
         self.state.read().await.clone()
@@ -350,6 +590,18 @@ impl ExecutionEngine {
         queue.pop_ready_task()
     }
 
+    /// Check that the current model has the capabilities a prompt's plan needs
+    async fn ensure_prompt_supported(&self, prompt: &UserPrompt) -> Result<()> {
+        ensure_model_supports_plan(
+            self.model_registry.as_deref(),
+            self.llm_provider.as_ref(),
+            &self.model,
+            self.config.mode,
+            &prompt.attachments,
+        )
+        .await
+    }
+
     /// Handle a user prompt by generating and queuing a new plan
     async fn handle_user_prompt(&self, prompt: UserPrompt) -> Result<()> {
         {
@@ -357,6 +609,27 @@ impl ExecutionEngine {
             *state = ExecutionState::Planning;
         }
 
+        // An emergency prompt stops the current plan's requests before planning the next one
+        if prompt.priority == PromptPriority::Emergency {
+            self.plan_control.renew_plan_token().await;
+        }
+        let plan_token = self.plan_control.plan_token().await;
+
+        // An interrupt revises the running plan rather than replacing it
+        let running_plan = match prompt.priority {
+            PromptPriority::Interrupt => self
+                .current_plan
+                .read()
+                .await
+                .clone()
+                .filter(|plan| plan.status != PlanStatus::Cancelled),
+            _ => None,
+        };
+        let request = match &running_plan {
+            Some(current) => revision_prompt(current, &prompt.content),
+            None => prompt.content.clone(),
+        };
+
         // Get global context summary
         let context_manager = self.context_manager.read().await;
         let global_context = context_manager.get_global_context_summary().await?;
@@ -364,13 +637,95 @@ impl ExecutionEngine {
         // Debug: Log the context being passed to LLM
         tracing::info!("📋 [CONTEXT-DEBUG] Global context being passed to LLM:\n{}", global_context);
 
-        // Generate a new plan
-        let plan = self.llm_provider
-            .generate_plan(&prompt.content, &global_context, &self.model)
-            .await?;
+        // Earlier chat turns let the model resolve follow-ups like "now do the same for the tests"
+        let global_context = match &prompt.conversation {
+            Some(conversation) => format!("{}\n\n## Conversation So Far\n{}", global_context, conversation),
+            None => global_context,
+        };
 
-        // Handle plan based on priority
-        match prompt.priority {
+        // Generate a new plan, forwarding streamed text to event subscribers
+        let plan = cancellation::with_cancellation(plan_token, async {
+            if prompt.attachments.is_empty() {
+                let event_sender = self.event_sender.clone();
+                generate_plan_observed(
+                    self.llm_provider.as_ref(),
+                    &request,
+                    &global_context,
+                    &self.model,
+                    move |content| {
+                        let _ = event_sender.send(ExecutionEvent::LlmOutput(StreamOutput {
+                            stage: StreamStage::Planning,
+                            content: content.to_string(),
+                        }));
+                    },
+                )
+                .await
+            } else {
+                generate_plan_with_attachments(
+                    self.llm_provider.as_ref(),
+                    &request,
+                    &global_context,
+                    &prompt.attachments,
+                    &self.model,
+                )
+                .await
+            }
+        })
+        .await?;
+
+        // Revisions are always shown with their changes before they are applied
+        let reviewed = self
+            .plan_control
+            .review_or_run(&self.config.plan_review, running_plan.as_ref(), plan, prompt.priority)
+            .await;
+        match reviewed {
+            Some((plan, priority)) => self.dispatch_plan(plan, priority).await?,
+            None => {
+                if let Some(plan) = self.plan_control.pending_review().await {
+                    self.emit_event(ExecutionEvent::PlanAwaitingReview {
+                        plan_id: plan.id,
+                        description: plan.description,
+                    })
+                    .await;
+                }
+            }
+        }
+
+        {
+            let mut state = self.state.write().await;
+            *state = ExecutionState::Executing;
+        }
+
+        Ok(())
+    }
+
+    /// Plan waiting for the user to approve it, if any
+    pub async fn pending_review(&self) -> Option<Plan> {
+        self.plan_control.pending_review().await
+    }
+
+    /// How the plan under review changes the running plan, if it revises it
+    pub async fn pending_review_diff(&self) -> Option<PlanDiff> {
+        self.plan_control.pending_review_diff().await
+    }
+
+    /// Run the plan under review, with the user's edits
+    ///
+    /// The edited plan is validated again. If it fails, it stays under review
+    /// and the error lists the problems.
+    pub async fn approve_plan(&self, plan: Plan) -> Result<()> {
+        let (plan, priority) = self.plan_control.approve_plan(plan).await?;
+        self.dispatch_plan(plan, priority).await
+    }
+
+    /// Discard the plan under review without running it
+    pub async fn reject_plan(&self) -> Option<Plan> {
+        self.plan_control.reject_plan().await
+    }
+
+    /// Start a generated plan according to the priority of its prompt
+    async fn dispatch_plan(&self, plan: Plan, priority: PromptPriority) -> Result<()> {
+        match priority {
             PromptPriority::Emergency => {
                 // Replace current plan entirely
                 self.replace_current_plan(plan).await?;
@@ -385,11 +740,53 @@ impl ExecutionEngine {
             }
         }
 
-        {
-            let mut state = self.state.write().await;
-            *state = ExecutionState::Executing;
+        Ok(())
+    }
+
+    /// Whether a task is abstract and its sub-tasks would stay within the depth limit
+    async fn should_decompose(&self, task: &Task) -> bool {
+        match &*self.current_plan.read().await {
+            Some(plan) => should_decompose(plan, task, &self.config.sub_plans),
+            None => false,
         }
+    }
+
+    /// Decompose an abstract task into a sub-plan and queue its sub-tasks
+    ///
+    /// The sub-tasks join the current plan under the task, which then
+    /// completes through them instead of running itself.
+    async fn decompose_task(&self, task: &Task) -> Result<()> {
+        let context = {
+            let context_manager = self.context_manager.read().await;
+            let global_context = context_manager.get_global_context_summary().await?;
+            let plan_context = match &*self.current_plan_context.read().await {
+                Some(context) => context.get_summary(),
+                None => "No plan context available".to_string(),
+            };
+            format!("{}\n\n{}", global_context, plan_context)
+        };
+
+        let event_sender = self.event_sender.clone();
+        let subtasks = self
+            .plan_control
+            .decompose_task(
+                self.llm_provider.as_ref(),
+                &self.model,
+                &self.current_plan,
+                task,
+                &self.config.sub_plans,
+                &context,
+                move |content| {
+                    let _ = event_sender.send(ExecutionEvent::LlmOutput(StreamOutput {
+                        stage: StreamStage::Planning,
+                        content: content.to_string(),
+                    }));
+                },
+            )
+            .await?;
+        self.main_task_queue.write().await.add_tasks(subtasks, QueuePriority::Normal);
 
+        self.persist_plan().await;
         Ok(())
     }
 
@@ -399,6 +796,10 @@ impl ExecutionEngine {
         let start_time = Instant::now();
         
         debug!("Starting task execution: {} ({})", task.description, task_id);
+        if let Some(plan) = &mut *self.current_plan.write().await {
+            let _ = plan.update_task_status(&task_id, TaskStatus::InProgress);
+        }
+        self.persist_plan().await;
         self.emit_event(ExecutionEvent::TaskStarted {
             task_id: task_id.clone(),
             task_type: task.task_type.clone(),
@@ -415,7 +816,9 @@ impl ExecutionEngine {
         let timeout_duration = Duration::from_secs(self.config.default_timeout_seconds);
         let running_tasks = self.running_tasks.clone();
         let event_sender = self.event_sender.clone();
-        let cancellation_token = self.cancellation_token.child_token();
+        let cancellation_token = self.plan_control.plan_token().await.child_token();
+        let plan_control = self.plan_control.clone();
+        let spend_before = plan_control.spend_so_far();
 
         // Create async task
         let handle = tokio::spawn(async move {
@@ -430,17 +833,21 @@ impl ExecutionEngine {
                 tasks.insert(task_id.clone(), task_handle);
             }
 
-            let result = Self::execute_single_task_with_cancellation(
-                task,
-                context_manager,
-                llm_provider,
-                model,
-                task_executor,
-                current_plan,
-                current_plan_context,
-                timeout_duration,
-                cancellation_token,
-                event_sender,
+            // LLM requests made by the task are aborted along with it
+            let result = cancellation::with_cancellation(
+                cancellation_token.clone(),
+                Self::execute_single_task_with_cancellation(
+                    task,
+                    context_manager,
+                    llm_provider,
+                    model,
+                    task_executor,
+                    current_plan,
+                    current_plan_context,
+                    timeout_duration,
+                    cancellation_token,
+                    event_sender,
+                ),
             ).await;
 
             // Unregister as running task
@@ -449,6 +856,8 @@ impl ExecutionEngine {
                 tasks.remove(&task_id);
             }
 
+            plan_control.record_task_cost(spend_before).await;
+
             TaskExecutionWrapper {
                 task_id,
                 result,
@@ -475,10 +884,13 @@ impl ExecutionEngine {
         // Step 1: Context Assembly
         let context = Self::assemble_task_context_static(&task, &context_manager, &current_plan_context).await?;
 
-        // Step 2: LLM Pre-Execution Refinement
-        let refined_instruction = Self::refine_task_instruction_static(
-            &task, &context, &llm_provider, &model
-        ).await?;
+        // Step 2: LLM Pre-Execution Refinement (in tool-calling mode the model picks concrete calls itself)
+        let tool_calling = task_executor.lock().await.execution_mode() == ExecutionMode::ToolCalling;
+        let refined_instruction = if tool_calling {
+            String::new()
+        } else {
+            Self::refine_task_instruction_static(&task, &context, &llm_provider, &model).await?
+        };
 
         // Check cancellation before execution
         if cancellation_token.is_cancelled() {
@@ -488,8 +900,15 @@ impl ExecutionEngine {
         // Step 3: Execute Tool with timeout and cancellation
         let execution_result = {
             let mut executor = task_executor.lock().await;
+            let execution = async {
+                if tool_calling {
+                    executor.execute_task_with_tools(&task, &context).await
+                } else {
+                    executor.execute_task(&task, &refined_instruction, &context).await
+                }
+            };
             tokio::select! {
-                result = executor.execute_task(&task, &refined_instruction, &context) => {
+                result = execution => {
                     result?
                 }
                 _ = cancellation_token.cancelled() => {
@@ -608,7 +1027,7 @@ impl ExecutionEngine {
         );
 
         llm_provider.generate_content(&prompt, "", model, None).await
-            .map_err(|e| KaiError::execution(format!("Failed to refine task instruction: {}", e)))
+            .map_err(|e| llm_step_error("Failed to refine task instruction", e))
     }
 
     /// Analyze task execution result using LLM (static version)
@@ -635,7 +1054,7 @@ impl ExecutionEngine {
         );
 
         let analysis = llm_provider.generate_content(&prompt, "", model, None).await
-            .map_err(|e| KaiError::execution(format!("Failed to analyze task result: {}", e)))?;
+            .map_err(|e| llm_step_error("Failed to analyze task result", e))?;
 
         // Parse the analysis (simplified - in real implementation, use structured output)
         let success = analysis.contains("SUCCESS: true");
@@ -657,6 +1076,39 @@ impl ExecutionEngine {
 
     /// Replace the current plan with a new one
     async fn replace_current_plan(&self, plan: Plan) -> Result<()> {
+        let context = PlanContext::new(plan.id.clone());
+        self.set_current_plan(plan, context).await
+    }
+
+    /// Continue a saved plan, skipping the tasks that already completed
+    ///
+    /// Every other task runs again from the start, including tasks that were
+    /// running when the previous session ended.
+    pub async fn resume_plan(&self, mut plan: Plan, context: Option<PlanContext>) -> Result<()> {
+        for task in &mut plan.tasks {
+            if task.status != TaskStatus::Completed {
+                task.status = TaskStatus::Pending;
+                task.result = None;
+            }
+        }
+        plan.status = PlanStatus::Ready;
+
+        info!("Resuming plan {}: {}", plan.id, plan.description);
+        let context = context.unwrap_or_else(|| PlanContext::new(plan.id.clone()));
+        self.set_current_plan(plan, context).await
+    }
+
+    /// Make `plan` the current plan and queue its unfinished tasks
+    ///
+    /// Plans that fail `Plan::validate` are rejected, since tasks with unknown
+    /// or cyclic dependencies would never become ready.
+    async fn set_current_plan(&self, plan: Plan, context: PlanContext) -> Result<()> {
+        plan.ensure_valid()?;
+
+        if self.plan_control.start_plan().await {
+            self.resume().await;
+        }
+
         // Clear current plan and context
         {
             let mut current_plan = self.current_plan.write().await;
@@ -665,26 +1117,61 @@ impl ExecutionEngine {
 
         {
             let mut current_context = self.current_plan_context.write().await;
-            *current_context = Some(PlanContext::new(plan.id.clone()));
+            *current_context = Some(context);
         }
 
         // Clear main task queue and add new tasks
         {
             let mut queue = self.main_task_queue.write().await;
             queue.clear();
-            for task in &plan.tasks {
-                queue.add_task(task.clone(), QueuePriority::Normal);
-            }
+            queue.load_plan(&plan, QueuePriority::Normal);
         }
 
+        self.persist_plan().await;
         Ok(())
     }
 
-    /// Interrupt current plan with a new plan
+    /// Save the current plan and its context so an interrupted run can be resumed
+    async fn persist_plan(&self) {
+        let Some(store) = &self.plan_store else {
+            return;
+        };
+        let Some(plan) = self.current_plan.read().await.clone() else {
+            return;
+        };
+        let context = self.current_plan_context.read().await.clone();
+        if let Err(e) = store.save(&plan, context.as_ref()) {
+            warn!("Failed to save plan {}: {}", plan.id, e);
+        }
+    }
+
+    /// Fold a revision of the running plan into it
+    ///
+    /// The revision is merged again with `merge_plans`, so tasks that finished
+    /// while it was under review keep their results. The plan keeps its id and
+    /// context. Running tasks the revision left unchanged finish on their own
+    /// and are not queued again.
+    /// A plan that is not a revision of the current one replaces it.
     async fn interrupt_with_plan(&self, plan: Plan) -> Result<()> {
-        // For now, just replace the plan
-        // TODO: Implement proper interruption with plan merging
-        self.replace_current_plan(plan).await
+        let current = self.current_plan.read().await.clone();
+        let merged = match current {
+            Some(current) if current.id == plan.id => merge_revision(&current, &plan)?,
+            _ => return self.replace_current_plan(plan).await,
+        };
+
+        *self.current_plan.write().await = Some(merged.clone());
+        {
+            let mut queue = self.main_task_queue.write().await;
+            queue.clear();
+            queue.load_plan(&merged, QueuePriority::Normal);
+            // Running tasks the revision left unchanged are still in progress
+            for task in merged.tasks.iter().filter(|task| task.status == TaskStatus::InProgress) {
+                queue.remove_task(&task.id);
+            }
+        }
+
+        self.persist_plan().await;
+        Ok(())
     }
 
     /// Queue a plan to execute after current plan completes
@@ -720,12 +1207,52 @@ impl ExecutionEngine {
                     queue.mark_task_completed(&result.task_id);
                 }
 
+                // The task stored its result before the execution time was known
+                let completed_parents = match &mut *self.current_plan.write().await {
+                    Some(plan) => {
+                        let _ = plan.set_task_result(&result.task_id, task_result);
+                        plan.completed_parents(&result.task_id)
+                    }
+                    None => Vec::new(),
+                };
+                // Tasks waiting on a sub-plan can run once its parent rolls up
+                if !completed_parents.is_empty() {
+                    let mut queue = self.main_task_queue.write().await;
+                    for parent_id in &completed_parents {
+                        queue.mark_task_completed(parent_id);
+                    }
+                }
+
                 self.emit_event(ExecutionEvent::TaskCompleted {
                     task_id: result.task_id.clone(),
                     success: true,
                     execution_time_ms,
                 }).await;
             }
+            Err(e) if e.is_cancelled() => {
+                info!("Task {} cancelled after {}ms", result.task_id, execution_time_ms);
+
+                // Cancelled tasks are not failures: no retries, no pause on error
+                {
+                    let mut metrics = self.metrics.write().await;
+                    metrics.task_cancelled();
+                }
+
+                {
+                    let mut queue = self.main_task_queue.write().await;
+                    queue.mark_task_cancelled(&result.task_id);
+                }
+
+                // The task is gone if its plan was already replaced
+                if let Some(plan) = &mut *self.current_plan.write().await {
+                    let _ = plan.update_task_status(&result.task_id, TaskStatus::Cancelled);
+                }
+
+                self.emit_event(ExecutionEvent::TaskCancelled {
+                    task_id: result.task_id.clone(),
+                    execution_time_ms,
+                }).await;
+            }
             Err(e) => {
                 error!("Task {} failed: {}", result.task_id, e);
                 
@@ -735,10 +1262,30 @@ impl ExecutionEngine {
                     metrics.task_completed(execution_time_ms, false);
                 }
 
-                // Mark task as failed in queue
-                {
+                let failed_parents = match &mut *self.current_plan.write().await {
+                    Some(plan) => {
+                        let _ = plan.set_task_result(&result.task_id, TaskResult::failure(e.to_string(), execution_time_ms));
+                        plan.failed_parents(&result.task_id)
+                    }
+                    None => Vec::new(),
+                };
+                // Mark task as failed in queue, along with the parents its sub-plan failed,
+                // so tasks waiting on them are dropped instead of waiting forever
+                let dropped = {
                     let mut queue = self.main_task_queue.write().await;
-                    queue.mark_task_failed(&result.task_id);
+                    let mut dropped = queue.mark_task_failed(&result.task_id);
+                    for parent_id in &failed_parents {
+                        dropped.extend(queue.mark_task_failed(parent_id));
+                    }
+                    dropped
+                };
+                if !dropped.is_empty() {
+                    warn!("Skipping {} tasks that depend on failed task {}", dropped.len(), result.task_id);
+                    if let Some(plan) = &mut *self.current_plan.write().await {
+                        for task_id in &dropped {
+                            let _ = plan.update_task_status(task_id, TaskStatus::Skipped);
+                        }
+                    }
                 }
 
                 self.emit_event(ExecutionEvent::TaskCompleted {
@@ -764,6 +1311,7 @@ impl ExecutionEngine {
             }
         }
 
+        self.persist_plan().await;
         Ok(())
     }
 
@@ -915,6 +1463,7 @@ Please provide a JSON response with the following structure:
                     }
                 },
                 dependencies: task_spec.dependencies,
+                parent_id: None,
                 status: crate::planning::TaskStatus::Pending,
                 result: None,
                 created_at: chrono::Utc::now(),
@@ -1082,15 +1631,38 @@ pub enum ExecutionEvent {
         success: bool,
         execution_time_ms: u64,
     },
+    /// A task stopped because its plan was cancelled or replaced
+    TaskCancelled {
+        task_id: String,
+        execution_time_ms: u64,
+    },
     PlanStarted {
         plan_id: String,
         description: String,
     },
+    /// A generated plan is held until `approve_plan` or `reject_plan` is called
+    PlanAwaitingReview {
+        plan_id: String,
+        description: String,
+    },
     PlanCompleted {
         plan_id: String,
         success: bool,
         total_tasks: usize,
     },
+    /// `cancel_plan` stopped the current plan, or the one being generated
+    PlanCancelled {
+        plan_id: Option<String>,
+    },
+    /// A prompt was dropped because the current model cannot serve it
+    PromptRejected {
+        prompt_id: String,
+        reason: String,
+    },
+    /// Text streamed from the LLM while it is still generating
+    LlmOutput(StreamOutput),
+    /// The plan is paused before its next task until `continue_past_budget` or `cancel_plan`
+    BudgetExceeded(BudgetExceeded),
 }
 
 /// Execution metrics for monitoring
@@ -1099,6 +1671,7 @@ pub struct ExecutionMetrics {
     pub tasks_executed: u64,
     pub tasks_successful: u64,
     pub tasks_failed: u64,
+    pub tasks_cancelled: u64,
     pub total_execution_time_ms: u64,
     pub average_execution_time_ms: u64,
     pub engine_uptime: Duration,
@@ -1111,6 +1684,7 @@ impl ExecutionMetrics {
             tasks_executed: 0,
             tasks_successful: 0,
             tasks_failed: 0,
+            tasks_cancelled: 0,
             total_execution_time_ms: 0,
             average_execution_time_ms: 0,
             engine_uptime: Duration::ZERO,
@@ -1137,6 +1711,11 @@ impl ExecutionMetrics {
         self.engine_uptime = self.start_time.elapsed();
     }
 
+    pub fn task_cancelled(&mut self) {
+        self.tasks_cancelled += 1;
+        self.engine_uptime = self.start_time.elapsed();
+    }
+
     pub fn cleanup_old_entries(&mut self) {
         // Update uptime
         self.engine_uptime = self.start_time.elapsed();
@@ -1184,4 +1763,202 @@ impl ToString for TaskType {
             TaskType::Delete => "delete".to_string(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::ScriptedProvider;
+
+    fn engine_in(dir: &std::path::Path, store: PlanStore) -> ExecutionEngine {
+        engine_with(ScriptedProvider::new("scripted"), dir, store)
+    }
+
+    fn engine_with(provider: ScriptedProvider, dir: &std::path::Path, store: PlanStore) -> ExecutionEngine {
+        let provider: Arc<dyn LlmProvider> = Arc::new(provider);
+        let context_manager = Arc::new(RwLock::new(ContextManager::new(
+            dir.to_path_buf(),
+            provider.clone(),
+            "test-model".to_string(),
+            None,
+        )));
+        ExecutionEngine::new(context_manager, provider, "test-model".to_string(), dir.to_path_buf(), None)
+            .with_plan_store(store)
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_merges_into_the_running_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_in(dir.path(), PlanStore::new(dir.path().join("plans")));
+
+        let mut plan = Plan::new("Add a search command");
+        plan.add_task(Task::new("read", "Read the CLI module", TaskType::ReadFile).with_parameter("path", "src/cli.rs"));
+        plan.add_task(Task::new("build", "Implement the search command", TaskType::GenerateContent).with_dependency("read"));
+        plan.set_task_result("read", TaskResult::success(Some(serde_json::json!("fn main() {}")), 5)).unwrap();
+        let mut context = PlanContext::new(plan.id.clone());
+        context.set_variable("cli", "clap");
+        engine.resume_plan(plan.clone(), Some(context)).await.unwrap();
+
+        // The revision drops the completed read and adds a test run
+        let mut revised = plan.clone();
+        revised.tasks.retain(|task| task.id != "read");
+        revised.tasks[0].dependencies.clear();
+        revised.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("build"));
+        engine.dispatch_plan(revised, PromptPriority::Interrupt).await.unwrap();
+
+        let current = engine.get_current_plan().await.unwrap();
+        assert_eq!(current.id, plan.id);
+        let ids: Vec<&str> = current.tasks.iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(ids, vec!["read", "build", "test"]);
+        assert_eq!(current.tasks[0].status, TaskStatus::Completed);
+        let context = engine.current_plan_context.read().await.clone().unwrap();
+        assert_eq!(context.get_variable("cli"), Some(&serde_json::json!("clap")));
+
+        let queue = engine.main_task_queue.read().await;
+        let queued: Vec<&str> = queue.get_all_tasks().iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(queued, vec!["build", "test"]);
+    }
+
+    #[tokio::test]
+    async fn test_abstract_tasks_are_decomposed_into_sub_plans() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = ScriptedProvider::new("scripted").on_plan(|_| {
+            let mut sub_plan = Plan::new("Search command");
+            sub_plan.add_task(Task::new("parse", "Parse the search arguments", TaskType::GenerateContent));
+            sub_plan.add_task(
+                Task::new("write", "Write the command", TaskType::WriteFile)
+                    .with_parameter("path", "src/search.rs")
+                    .with_dependency("parse"),
+            );
+            Ok(sub_plan)
+        });
+        let engine = engine_with(provider.clone(), dir.path(), PlanStore::new(dir.path().join("plans")));
+
+        let mut plan = Plan::new("Add a search command");
+        plan.add_task(Task::new("build", "Implement the search command", TaskType::GenerateContent));
+        plan.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("build"));
+        engine.resume_plan(plan, None).await.unwrap();
+
+        let task = engine.pop_task().await.unwrap();
+        assert!(engine.should_decompose(&task).await);
+        engine.decompose_task(&task).await.unwrap();
+        assert_eq!(provider.calls_to("generate_plan"), 1);
+
+        let current = engine.get_current_plan().await.unwrap();
+        assert!(current.has_subtasks("build"));
+        assert!(!engine.should_decompose(&task).await);
+
+        // Sub-tasks run in order; the parent's dependents wait for all of them
+        assert_eq!(engine.pop_task().await.unwrap().id, "build.parse");
+        assert!(engine.pop_task().await.is_none());
+        engine.main_task_queue.write().await.mark_task_completed("build.parse");
+        assert_eq!(engine.pop_task().await.unwrap().id, "build.write");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_plan_stops_and_saves_the_current_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PlanStore::new(dir.path().join("plans"));
+        let engine = engine_in(dir.path(), store.clone());
+        let mut events = engine.subscribe_to_events();
+
+        let mut plan = Plan::new("Add a search command");
+        plan.add_task(Task::new("read", "Read the CLI module", TaskType::ReadFile).with_parameter("path", "src/cli.rs"));
+        plan.add_task(Task::new("build", "Implement the search command", TaskType::GenerateContent).with_dependency("read"));
+        engine.resume_plan(plan.clone(), None).await.unwrap();
+        assert!(engine.has_active_plan().await);
+
+        engine.cancel_plan().await;
+
+        assert!(!engine.has_active_plan().await);
+        assert_eq!(engine.get_queue_summary().await.total_tasks, 0);
+        assert_eq!(engine.get_current_plan().await.unwrap().status, PlanStatus::Cancelled);
+        assert_eq!(store.load(&plan.id).unwrap().plan.status, PlanStatus::Cancelled);
+        match events.try_recv().unwrap() {
+            ExecutionEvent::PlanCancelled { plan_id } => assert_eq!(plan_id, Some(plan.id)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_prompts_are_rejected_without_stopping_the_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = ScriptedProvider::new("scripted")
+            .with_models(vec![crate::llm::ModelInfo {
+                id: "test-model".to_string(),
+                name: "Test model".to_string(),
+                description: None,
+                context_length: None,
+                max_output_tokens: None,
+                pricing: None,
+                tokenizer: None,
+                capabilities: crate::llm::ModelCapabilities {
+                    vision: Some(false),
+                    ..Default::default()
+                },
+            }])
+            .on_plan(|_| {
+                let mut plan = Plan::new("Summarise the README");
+                plan.add_task(Task::new("read", "Read the README", TaskType::ReadFile).with_parameter("path", "README.md"));
+                Ok(plan)
+            });
+        let config = ExecutionConfig {
+            plan_review: PlanReviewPolicy::Always,
+            ..ExecutionConfig::default()
+        };
+        let provider: Arc<dyn LlmProvider> = Arc::new(provider);
+        let context_manager = Arc::new(RwLock::new(ContextManager::new(
+            dir.path().to_path_buf(),
+            provider.clone(),
+            "test-model".to_string(),
+            None,
+        )));
+        let engine = Arc::new(
+            ExecutionEngine::new(context_manager, provider, "test-model".to_string(), dir.path().to_path_buf(), Some(config))
+                .with_plan_store(PlanStore::new(dir.path().join("plans")))
+                .with_model_registry(Arc::new(ModelRegistry::new(dir.path().join("models")))),
+        );
+        let mut events = engine.subscribe_to_events();
+        let running = tokio::spawn({
+            let engine = engine.clone();
+            async move { engine.start().await }
+        });
+
+        let image = ContentPart::image("image/png", vec![0x89, 0x50, 0x4e, 0x47]);
+        let rejected = engine
+            .submit_chat_prompt("Describe this screenshot".to_string(), vec![image], None, PromptPriority::Normal)
+            .await;
+        engine
+            .submit_chat_prompt("Summarise the README".to_string(), Vec::new(), None, PromptPriority::Normal)
+            .await;
+
+        let reason = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let ExecutionEvent::PromptRejected { prompt_id, reason } = events.recv().await.unwrap() {
+                    assert_eq!(prompt_id, rejected);
+                    return reason;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(reason.contains("image input"), "{}", reason);
+
+        // The next prompt is still planned by the running engine
+        let pending = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(pending) = engine.pending_review().await {
+                    return pending;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pending.description, "Summarise the README");
+        assert!(!running.is_finished());
+
+        engine.stop().await;
+        running.await.unwrap().unwrap();
+    }
+}
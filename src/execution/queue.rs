@@ -1,6 +1,6 @@
 //! Task queue implementation with priority management
 
-use crate::planning::Task;
+use crate::planning::{Plan, Task, TaskStatus};
 use std::collections::{HashMap, VecDeque};
 
 /// Priority levels for tasks in the queue
@@ -23,6 +23,8 @@ pub struct TaskQueue {
     completed_tasks: std::collections::HashSet<String>,
     /// Set of in-progress task IDs
     in_progress_tasks: std::collections::HashSet<String>,
+    /// Set of failed task IDs
+    failed_tasks: std::collections::HashSet<String>,
 }
 
 impl TaskQueue {
@@ -39,6 +41,7 @@ impl TaskQueue {
             dependencies: HashMap::new(),
             completed_tasks: std::collections::HashSet::new(),
             in_progress_tasks: std::collections::HashSet::new(),
+            failed_tasks: std::collections::HashSet::new(),
         }
     }
 
@@ -62,6 +65,19 @@ impl TaskQueue {
         }
     }
 
+    /// Queue the unfinished tasks of a plan, counting its completed tasks as done
+    ///
+    /// Tasks with sub-tasks are not queued; they complete through their sub-tasks.
+    pub fn load_plan(&mut self, plan: &Plan, priority: QueuePriority) {
+        for task in &plan.tasks {
+            if task.status == TaskStatus::Completed {
+                self.completed_tasks.insert(task.id.clone());
+            } else if !plan.has_subtasks(&task.id) {
+                self.add_task(task.clone(), priority);
+            }
+        }
+    }
+
     /// Pop the next ready task (highest priority, dependencies satisfied)
     pub fn pop_ready_task(&mut self) -> Option<Task> {
         // Check queues in priority order (highest first)
@@ -102,10 +118,44 @@ impl TaskQueue {
         self.completed_tasks.insert(task_id.to_string());
     }
 
-    /// Mark a task as failed
-    pub fn mark_task_failed(&mut self, task_id: &str) {
+    /// Mark a task as failed, dropping the queued tasks that depend on it
+    ///
+    /// Failed tasks are not added to completed_tasks, so their dependents could
+    /// never run; the IDs of those dropped dependents are returned.
+    pub fn mark_task_failed(&mut self, task_id: &str) -> Vec<String> {
+        self.in_progress_tasks.remove(task_id);
+        self.failed_tasks.insert(task_id.to_string());
+
+        let mut dropped = Vec::new();
+        let mut failed = vec![task_id.to_string()];
+        while let Some(failed_id) = failed.pop() {
+            for queue in self.priority_queues.values_mut() {
+                queue.retain(|task| {
+                    if task.dependencies.contains(&failed_id) {
+                        failed.push(task.id.clone());
+                        dropped.push(task.id.clone());
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+        }
+        for task_id in &dropped {
+            self.dependencies.remove(task_id);
+        }
+        dropped
+    }
+
+    /// Check if a task has been marked as failed
+    pub fn is_failed(&self, task_id: &str) -> bool {
+        self.failed_tasks.contains(task_id)
+    }
+
+    /// Mark a task as cancelled
+    pub fn mark_task_cancelled(&mut self, task_id: &str) {
         self.in_progress_tasks.remove(task_id);
-        // Failed tasks are not added to completed_tasks, so dependent tasks won't run
+        // Like failed tasks, cancelled tasks never unblock their dependents
     }
 
     /// Check if a task's dependencies are satisfied
@@ -173,6 +223,7 @@ impl TaskQueue {
         self.dependencies.clear();
         self.completed_tasks.clear();
         self.in_progress_tasks.clear();
+        self.failed_tasks.clear();
     }
 
     /// Get a summary of the queue state
@@ -312,4 +363,46 @@ impl QueueSummary {
             self.completed_tasks
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::{SubPlanLimits, TaskResult, TaskType};
+
+    #[test]
+    fn test_failed_subtask_fails_its_parent_and_drops_its_dependents() {
+        let mut plan = Plan::new("Add a search command");
+        plan.add_task(Task::new("build", "Implement the search command", TaskType::GenerateContent));
+        plan.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("build"));
+        plan.add_task(Task::new("docs", "Document the command", TaskType::GenerateContent).with_dependency("test"));
+        plan.add_task(Task::new("lint", "Run clippy", TaskType::ExecuteCommand));
+        plan.add_subtasks(
+            "build",
+            vec![Task::new("parse", "Parse the search arguments", TaskType::GenerateContent)],
+            &SubPlanLimits::default(),
+        )
+        .unwrap();
+
+        let mut queue = TaskQueue::new();
+        queue.load_plan(&plan, QueuePriority::Normal);
+        let child = queue.pop_ready_task().unwrap();
+        assert_eq!(child.id, "build.parse");
+
+        // The engine fails the child, then the parent that roll-up failed with it
+        assert!(queue.mark_task_failed(&child.id).is_empty());
+        plan.set_task_result(&child.id, TaskResult::failure("parse error", 5)).unwrap();
+        let failed_parents = plan.failed_parents(&child.id);
+        assert_eq!(failed_parents, vec!["build"]);
+        let mut dropped = queue.mark_task_failed(&failed_parents[0]);
+        dropped.sort();
+
+        assert_eq!(dropped, vec!["docs", "test"]);
+        assert!(queue.is_failed("build"));
+        // Independent work still runs, and then the queue drains
+        assert_eq!(queue.pop_ready_task().unwrap().id, "lint");
+        queue.mark_task_completed("lint");
+        assert!(queue.is_empty());
+        assert!(queue.get_summary().is_complete());
+    }
+}
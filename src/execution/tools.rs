@@ -0,0 +1,220 @@
+//! Executor primitives exposed as LLM function-calling tools
+//!
+//! Each primitive `TaskType` (read, write, list, command, mkdir, delete) is
+//! described by a `ToolDefinition` with a JSON schema. Tool names match the
+//! snake_case task type names so a tool call maps straight back onto a task the
+//! `TaskExecutor` already knows how to run.
+
+use crate::llm::{FunctionDefinition, ToolCall, ToolDefinition};
+use crate::planning::TaskType;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Task types the model may drive directly through tool calls
+pub const TOOL_TASK_TYPES: [TaskType; 6] = [
+    TaskType::ReadFile,
+    TaskType::WriteFile,
+    TaskType::ListFiles,
+    TaskType::ExecuteCommand,
+    TaskType::CreateDirectory,
+    TaskType::Delete,
+];
+
+/// Build the tool definition for a primitive task type
+///
+/// Returns `None` for task types that need the LLM themselves
+/// (`generate_content`, `analyze_code`).
+pub fn tool_definition(task_type: &TaskType) -> Option<ToolDefinition> {
+    let (description, parameters) = match task_type {
+        TaskType::ReadFile => (
+            "Read a UTF-8 text file inside the working directory",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "File path relative to the working directory"}
+                },
+                "required": ["path"],
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::WriteFile => (
+            "Create or overwrite a file inside the working directory with the complete content",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "File path relative to the working directory"},
+                    "content": {"type": "string", "description": "Complete file content to write"}
+                },
+                "required": ["path", "content"],
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::ListFiles => (
+            "List files and directories inside the working directory",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Directory relative to the working directory", "default": "."},
+                    "recursive": {"type": "boolean", "description": "Descend into subdirectories", "default": false},
+                    "include_hidden": {"type": "boolean", "description": "Include dotfiles", "default": false}
+                },
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::ExecuteCommand => (
+            "Run a single command without shell operators (no pipes, redirects or chaining)",
+            json!({
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string", "description": "Command line to run, e.g. \"cargo test\""},
+                    "working_dir": {"type": "string", "description": "Directory relative to the working directory to run in"}
+                },
+                "required": ["command"],
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::CreateDirectory => (
+            "Create a directory (and missing parents) inside the working directory",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Directory path relative to the working directory"}
+                },
+                "required": ["path"],
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::Delete => (
+            "Delete a file or directory inside the working directory. Protected paths are refused.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Path relative to the working directory"},
+                    "force": {"type": "boolean", "description": "Must be true to confirm the deletion"}
+                },
+                "required": ["path", "force"],
+                "additionalProperties": false
+            }),
+        ),
+        TaskType::GenerateContent | TaskType::AnalyzeCode => return None,
+    };
+
+    Some(ToolDefinition {
+        r#type: "function".to_string(),
+        function: FunctionDefinition {
+            name: task_type.to_string(),
+            description: description.to_string(),
+            parameters,
+        },
+    })
+}
+
+/// Tool definitions for every executor primitive
+pub fn tool_definitions() -> Vec<ToolDefinition> {
+    TOOL_TASK_TYPES.iter().filter_map(tool_definition).collect()
+}
+
+/// Map a tool name back to its task type
+pub fn task_type_for_tool(name: &str) -> Option<TaskType> {
+    TOOL_TASK_TYPES
+        .iter()
+        .find(|task_type| task_type.to_string() == name)
+        .cloned()
+}
+
+/// Decode tool call arguments into task parameters
+///
+/// Providers hand arguments over either as a JSON object or as the raw JSON
+/// string the model produced; both are accepted. Required properties from the
+/// tool schema are checked so a malformed call is reported back to the model
+/// instead of falling through to a default.
+pub fn tool_call_parameters(
+    task_type: &TaskType,
+    call: &ToolCall,
+) -> std::result::Result<HashMap<String, Value>, String> {
+    let arguments = match &call.function.arguments {
+        Value::String(raw) if raw.trim().is_empty() => json!({}),
+        Value::String(raw) => serde_json::from_str(raw)
+            .map_err(|e| format!("Arguments for '{}' are not valid JSON: {}", call.function.name, e))?,
+        Value::Null => json!({}),
+        other => other.clone(),
+    };
+
+    let parameters: HashMap<String, Value> = match arguments {
+        Value::Object(map) => map.into_iter().collect(),
+        other => {
+            return Err(format!(
+                "Arguments for '{}' must be a JSON object, got {}",
+                call.function.name, other
+            ))
+        }
+    };
+
+    if let Some(definition) = tool_definition(task_type) {
+        let required = definition.function.parameters["required"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for name in required.iter().filter_map(|name| name.as_str()) {
+            if !parameters.contains_key(name) {
+                return Err(format!(
+                    "Missing required argument '{}' for '{}'",
+                    name, call.function.name
+                ));
+            }
+        }
+    }
+
+    Ok(parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::FunctionCall;
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments,
+            },
+        }
+    }
+
+    #[test]
+    fn test_tool_definitions_cover_primitives() {
+        let definitions = tool_definitions();
+        let names: Vec<&str> = definitions.iter().map(|d| d.function.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["read_file", "write_file", "list_files", "execute_command", "create_directory", "delete"]
+        );
+        for definition in &definitions {
+            assert_eq!(definition.function.parameters["type"], "object");
+            assert_eq!(task_type_for_tool(&definition.function.name).map(|t| t.to_string()), Some(definition.function.name.clone()));
+        }
+        assert!(tool_definition(&TaskType::GenerateContent).is_none());
+        assert!(task_type_for_tool("generate_content").is_none());
+    }
+
+    #[test]
+    fn test_tool_call_parameters() {
+        let parameters = tool_call_parameters(
+            &TaskType::WriteFile,
+            &call("write_file", json!("{\"path\": \"a.txt\", \"content\": \"hi\"}")),
+        )
+        .unwrap();
+        assert_eq!(parameters["path"], "a.txt");
+
+        let parameters = tool_call_parameters(&TaskType::ListFiles, &call("list_files", json!({}))).unwrap();
+        assert!(parameters.is_empty());
+
+        let error = tool_call_parameters(&TaskType::WriteFile, &call("write_file", json!({"path": "a.txt"})))
+            .unwrap_err();
+        assert!(error.contains("content"));
+        assert!(tool_call_parameters(&TaskType::ReadFile, &call("read_file", json!("not json"))).is_err());
+    }
+}
@@ -14,7 +14,7 @@ pub mod utils;
 // Re-export commonly used types and traits
 pub use config::{Config, ConfigManager, ProviderConfig};
 pub use context::{ContextManager, GlobalContext, PlanContext, ContextConfig};
-pub use execution::{ExecutionEngine, TaskExecutor, ExecutionConfig};
+pub use execution::{ExecutionEngine, TaskExecutor, ExecutionConfig, ExecutionMode};
 pub use llm::{LlmProvider, LlmProviderFactory, LlmError, Message, ToolDefinition};
 pub use planning::{Plan, Task, TaskStatus, TaskResult, TaskType};
 pub use ui::{UiManager, UiEvent};
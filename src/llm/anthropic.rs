@@ -0,0 +1,667 @@
+//! Anthropic Messages API provider implementation
+
+use super::content::InlinePart;
+use super::schema;
+use super::usage;
+use super::{
+    FunctionCall, GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, MessageRole,
+    ModelCapabilities, ModelInfo, ResponseFormat, TaskAnalysis, TaskExecutionResult, TaskRefinementContext, TokenUsage, ToolCall,
+    ToolDefinition,
+};
+use crate::utils::http::headers::HeaderBuilder;
+use crate::utils::http::{execute_with_retry, parse_http_error, HttpClient, HttpClientConfig};
+use crate::utils::templates::handlers::{StandardTemplateHandler, TemplateHandler};
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// Default Anthropic API root
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+/// Messages API version sent with every request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The Messages API requires `max_tokens`; used when the caller sets none
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Anthropic Messages API provider
+pub struct AnthropicProvider {
+    http_client: HttpClient,
+    api_key: String,
+    base_url: String,
+    template_handler: StandardTemplateHandler,
+}
+
+impl AnthropicProvider {
+    /// Create a new Anthropic provider
+    pub fn new(api_key: String) -> Result<Self, LlmError> {
+        Self::with_config(api_key, None, None)
+    }
+
+    /// Create a new Anthropic provider with custom configuration
+    pub fn with_config(
+        api_key: String,
+        base_url: Option<String>,
+        client_config: Option<HttpClientConfig>,
+    ) -> Result<Self, LlmError> {
+        let http_client = HttpClient::new(client_config.unwrap_or_default())?;
+
+        Ok(Self {
+            http_client,
+            api_key,
+            base_url: base_url
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            template_handler: StandardTemplateHandler,
+        })
+    }
+
+    /// Create a provider from factory settings
+    ///
+    /// Recognised keys: `api_key` (required), `base_url`, `timeout_secs`,
+    /// `retry_attempts` and `retry_delay_ms`.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let api_key = settings.get("api_key").ok_or_else(|| LlmError::Authentication {
+            message: "Anthropic API key not provided".to_string(),
+        })?;
+
+        let client_config = HttpClientConfig::default().with_settings(settings)?;
+
+        Self::with_config(api_key.clone(), settings.get("base_url").cloned(), Some(client_config))
+    }
+
+    /// Create Anthropic request headers
+    fn create_headers(&self) -> reqwest::header::HeaderMap {
+        let builder = HeaderBuilder::new().json_content_type().user_agent("KAI-X/1.0");
+
+        match builder
+            .header("x-api-key", self.api_key.as_str())
+            .and_then(|b| b.header("anthropic-version", ANTHROPIC_VERSION))
+        {
+            Ok(builder) => builder.build(),
+            Err(_) => HeaderBuilder::new().json_content_type().user_agent("KAI-X/1.0").build(),
+        }
+    }
+
+    /// Map HTTP errors, recognising Anthropic's `not_found_error` for unknown models
+    fn parse_error_response(status: u16, body: &str, model: Option<&str>) -> LlmError {
+        if status == 404 && body.contains("not_found_error") && body.contains("model") {
+            return LlmError::InvalidModel {
+                model: model.unwrap_or("unknown").to_string(),
+            };
+        }
+        parse_http_error(status, body, model)
+    }
+
+    /// Split messages into the top-level `system` prompt and Messages API turns
+    ///
+    /// System messages are joined into the `system` field, tool results become
+    /// `tool_result` blocks on a user turn and consecutive turns with the same
+    /// role are merged, since the API expects strictly alternating roles.
+    fn convert_messages(messages: &[Message]) -> (Option<String>, Vec<serde_json::Value>) {
+        let mut system_parts = Vec::new();
+        let mut turns: Vec<(&'static str, Vec<serde_json::Value>)> = Vec::new();
+
+        for message in messages {
+            let (role, blocks) = match message.role {
+                MessageRole::System => {
+                    system_parts.push(message.content.clone());
+                    continue;
+                }
+                MessageRole::User => (
+                    "user",
+                    message.inline_parts().iter().map(Self::content_block).collect(),
+                ),
+                MessageRole::Assistant => {
+                    let mut blocks = Vec::new();
+                    if !message.content.is_empty() {
+                        blocks.push(Self::text_block(&message.content));
+                    }
+                    for call in message.tool_calls.iter().flatten() {
+                        blocks.push(serde_json::json!({
+                            "type": "tool_use",
+                            "id": call.id,
+                            "name": call.function.name,
+                            "input": Self::tool_input(&call.function.arguments),
+                        }));
+                    }
+                    ("assistant", blocks)
+                }
+                MessageRole::Tool => (
+                    "user",
+                    vec![serde_json::json!({
+                        "type": "tool_result",
+                        "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                        "content": message.content,
+                    })],
+                ),
+            };
+
+            if blocks.is_empty() {
+                continue;
+            }
+
+            match turns.last_mut() {
+                Some((last_role, last_blocks)) if *last_role == role => last_blocks.extend(blocks),
+                _ => turns.push((role, blocks)),
+            }
+        }
+
+        let system = if system_parts.is_empty() {
+            None
+        } else {
+            Some(system_parts.join("\n\n"))
+        };
+
+        let turns = turns
+            .into_iter()
+            .map(|(role, content)| serde_json::json!({ "role": role, "content": content }))
+            .collect();
+
+        (system, turns)
+    }
+
+    fn text_block(text: &str) -> serde_json::Value {
+        serde_json::json!({ "type": "text", "text": text })
+    }
+
+    fn content_block(part: &InlinePart) -> serde_json::Value {
+        match part {
+            InlinePart::Text(text) => Self::text_block(text),
+            InlinePart::Image { mime_type, data } => serde_json::json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": mime_type, "data": data }
+            }),
+        }
+    }
+
+    /// `tool_use.input` must be a JSON object; decode string-encoded arguments
+    fn tool_input(arguments: &serde_json::Value) -> serde_json::Value {
+        match arguments {
+            serde_json::Value::String(raw) => serde_json::from_str::<serde_json::Value>(raw)
+                .ok()
+                .filter(|v| v.is_object())
+                .unwrap_or_else(|| serde_json::json!({})),
+            serde_json::Value::Object(_) => arguments.clone(),
+            _ => serde_json::json!({}),
+        }
+    }
+
+    /// Build the Messages API request body
+    fn build_request_body(
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> serde_json::Value {
+        let (system, turns) = Self::convert_messages(messages);
+
+        let max_tokens = config.and_then(|c| c.max_tokens).unwrap_or(DEFAULT_MAX_TOKENS);
+        let mut request_body = serde_json::json!({
+            "model": model,
+            "max_tokens": max_tokens,
+            "messages": turns,
+        });
+
+        if let Some(system) = system {
+            request_body["system"] = system.into();
+        }
+
+        // Frequency and presence penalties are not supported by the Messages API
+        if let Some(config) = config {
+            if let Some(temp) = config.temperature {
+                request_body["temperature"] = temp.into();
+            }
+            if let Some(top_p) = config.top_p {
+                request_body["top_p"] = top_p.into();
+            }
+            if let Some(stop) = &config.stop_sequences {
+                request_body["stop_sequences"] = stop.clone().into();
+            }
+        }
+
+        if let Some(tools) = tools {
+            if !tools.is_empty() {
+                request_body["tools"] = tools
+                    .iter()
+                    .map(|tool| {
+                        serde_json::json!({
+                            "name": tool.function.name,
+                            "description": tool.function.description,
+                            "input_schema": tool.function.parameters,
+                        })
+                    })
+                    .collect();
+            }
+        }
+
+        request_body
+    }
+
+    /// Parse a Messages API response body
+    fn parse_response(body: &serde_json::Value) -> Result<LlmResponse, LlmError> {
+        if body["type"] == "error" {
+            return Err(LlmError::RequestFailed {
+                status: 400,
+                message: body["error"].to_string(),
+            });
+        }
+
+        let blocks = body["content"].as_array().ok_or_else(|| LlmError::InvalidResponse {
+            message: "Expected 'content' array in response".to_string(),
+        })?;
+
+        let mut text_parts = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") => {
+                    if let Some(text) = block["text"].as_str() {
+                        text_parts.push(text.to_string());
+                    }
+                }
+                Some("tool_use") => tool_calls.push(ToolCall {
+                    id: block["id"].as_str().unwrap_or_default().to_string(),
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: block["input"].clone(),
+                    },
+                }),
+                _ => {}
+            }
+        }
+
+        let content = if text_parts.is_empty() {
+            None
+        } else {
+            Some(text_parts.join(""))
+        };
+
+        let finish_reason = body["stop_reason"].as_str().unwrap_or("unknown").to_string();
+
+        // Cached prompt tokens are billed as input and reported separately
+        let usage = body["usage"].as_object().map(|u| {
+            let prompt_tokens = ["input_tokens", "cache_creation_input_tokens", "cache_read_input_tokens"]
+                .iter()
+                .filter_map(|key| u.get(*key).and_then(|v| v.as_u64()))
+                .sum::<u64>() as u32;
+            let completion_tokens = u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            }
+        });
+
+        Ok(LlmResponse {
+            content,
+            tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+            finish_reason,
+            usage,
+            backend: None,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn provider_name(&self) -> &str {
+        "anthropic"
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        let operation = || async {
+            let url = format!("{}/v1/models?limit=1000", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .get(&url)
+                .headers(self.create_headers())
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(Self::parse_error_response(status, &body, None));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse models response: {}", e),
+            })?;
+
+            let data = body["data"].as_array().ok_or_else(|| LlmError::InvalidResponse {
+                message: "Expected 'data' array in response".to_string(),
+            })?;
+
+            let models = data
+                .iter()
+                .filter_map(|model| {
+                    let id = model["id"].as_str()?;
+                    Some(ModelInfo {
+                        id: id.to_string(),
+                        name: model["display_name"].as_str().unwrap_or(id).to_string(),
+                        description: None,
+                        context_length: None,
+                        max_output_tokens: None,
+                        pricing: None,
+                        // Claude's tokenizer is not published
+                        tokenizer: None,
+                        capabilities: ModelCapabilities {
+                            tools: Some(true),
+                            vision: Some(true),
+                            json_mode: None,
+                            streaming: Some(true),
+                        },
+                    })
+                })
+                .collect();
+
+            Ok(models)
+        };
+
+        execute_with_retry(operation, &self.http_client.retry_config()).await
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let request_body = Self::build_request_body(messages, model, tools, config);
+
+        let operation = || async {
+            let url = format!("{}/v1/messages", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .post(&url)
+                .headers(self.create_headers())
+                .json(&request_body)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(Self::parse_error_response(status, &body, Some(model)));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse generation response: {}", e),
+            })?;
+
+            Self::parse_response(&body)
+        };
+
+        execute_with_retry(operation, &self.http_client.retry_config())
+            .await
+            .inspect(|response| usage::report_generate(self.provider_name(), model, messages, response))
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let messages = self.template_handler.plan_generation_messages(prompt, context)?;
+
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let response = self.generate(&messages, model, None, config).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in response".to_string(),
+        })
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        let models = self.list_models().await?;
+        models
+            .into_iter()
+            .find(|m| m.id == model)
+            .ok_or_else(|| LlmError::InvalidModel {
+                model: model.to_string(),
+            })
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let messages = self.template_handler.task_refinement_messages(
+            task,
+            &context.plan_description,
+            &context.global_context,
+            &context.plan_context,
+            &context.dependency_outputs,
+        )?;
+
+        // Use focused configuration for refinement
+        let config = GenerationConfig {
+            temperature: Some(0.3),
+            max_tokens: Some(4000),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: None,
+        };
+
+        let response = self.generate(&messages, model, None, Some(&config)).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in task refinement response".to_string(),
+        })
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let messages = self
+            .template_handler
+            .execution_analysis_messages(task, execution_result, expected_outcome)?;
+
+        // Use lower temperature for consistent analysis
+        let config = GenerationConfig {
+            temperature: Some(0.1),
+            max_tokens: Some(2048),
+            top_p: Some(0.8),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
+        };
+
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+    use crate::llm::FunctionDefinition;
+
+    fn test_provider(server_url: String) -> AnthropicProvider {
+        let mut settings = HashMap::new();
+        settings.insert("api_key".to_string(), "sk-ant-test".to_string());
+        settings.insert("base_url".to_string(), server_url);
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        AnthropicProvider::from_settings(&settings).unwrap()
+    }
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_from_settings_requires_api_key() {
+        let result = AnthropicProvider::from_settings(&HashMap::new());
+        assert!(matches!(result, Err(LlmError::Authentication { .. })));
+    }
+
+    #[test]
+    fn test_convert_messages_splits_system_and_merges_tool_results() {
+        let messages = vec![
+            message(MessageRole::System, "You are helpful"),
+            message(MessageRole::User, "List and read"),
+            Message {
+                role: MessageRole::Assistant,
+                content: "Let me look".to_string(),
+                tool_calls: Some(vec![
+                    ToolCall {
+                        id: "toolu_1".to_string(),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: "list_files".to_string(),
+                            arguments: serde_json::json!({"path": "."}),
+                        },
+                    },
+                    ToolCall {
+                        id: "toolu_2".to_string(),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: "read_file".to_string(),
+                            arguments: serde_json::Value::String("{\"path\":\"a.rs\"}".to_string()),
+                        },
+                    },
+                ]),
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::Tool,
+                content: "a.rs".to_string(),
+                tool_calls: None,
+                tool_call_id: Some("toolu_1".to_string()),
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::Tool,
+                content: "fn main() {}".to_string(),
+                tool_calls: None,
+                tool_call_id: Some("toolu_2".to_string()),
+                parts: Vec::new(),
+            },
+        ];
+
+        let (system, turns) = AnthropicProvider::convert_messages(&messages);
+        assert_eq!(system.as_deref(), Some("You are helpful"));
+        assert_eq!(turns.len(), 3);
+
+        assert_eq!(turns[1]["role"], "assistant");
+        assert_eq!(turns[1]["content"][1]["type"], "tool_use");
+        assert_eq!(turns[1]["content"][2]["input"]["path"], "a.rs");
+
+        // Both tool results land in a single user turn
+        assert_eq!(turns[2]["role"], "user");
+        assert_eq!(turns[2]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(turns[2]["content"][0]["type"], "tool_result");
+        assert_eq!(turns[2]["content"][1]["tool_use_id"], "toolu_2");
+    }
+
+    #[tokio::test]
+    async fn test_generate_maps_tool_use_and_usage() {
+        let response = serde_json::json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Reading the file."},
+                {"type": "tool_use", "id": "toolu_9", "name": "read_file", "input": {"path": "src/lib.rs"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 40, "cache_read_input_tokens": 10, "output_tokens": 12}
+        });
+        let (server_url, captured) = mock_server::spawn(vec![(200, response.to_string())]).await;
+        let provider = test_provider(server_url);
+
+        let tools = vec![ToolDefinition {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "read_file".to_string(),
+                description: "Read a file".to_string(),
+                parameters: serde_json::json!({"type": "object", "properties": {"path": {"type": "string"}}}),
+            },
+        }];
+        let messages = vec![
+            message(MessageRole::System, "Be brief"),
+            message(MessageRole::User, "Open lib.rs"),
+        ];
+
+        let result = provider
+            .generate(&messages, "claude-sonnet-4-20250514", Some(&tools), None)
+            .await
+            .unwrap();
+
+        assert_eq!(result.content.as_deref(), Some("Reading the file."));
+        assert_eq!(result.finish_reason, "tool_use");
+        let tool_calls = result.tool_calls.unwrap();
+        assert_eq!(tool_calls[0].id, "toolu_9");
+        assert_eq!(tool_calls[0].function.arguments["path"], "src/lib.rs");
+        let usage = result.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 50);
+        assert_eq!(usage.completion_tokens, 12);
+        assert_eq!(usage.total_tokens, 62);
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests[0].request_line, "POST /v1/messages HTTP/1.1");
+        assert_eq!(requests[0].header("x-api-key").as_deref(), Some("sk-ant-test"));
+        assert_eq!(requests[0].header("anthropic-version").as_deref(), Some(ANTHROPIC_VERSION));
+        let body = requests[0].json();
+        assert_eq!(body["system"], "Be brief");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+    }
+
+    #[tokio::test]
+    async fn test_http_errors_are_mapped() {
+        let (server_url, _) = mock_server::spawn(vec![
+            (401, r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#.to_string()),
+            (404, r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude-nope"}}"#.to_string()),
+        ]).await;
+        let provider = test_provider(server_url);
+        let messages = vec![message(MessageRole::User, "hi")];
+
+        let auth = provider.generate(&messages, "claude-nope", None, None).await;
+        assert!(matches!(auth, Err(LlmError::Authentication { .. })));
+
+        let model = provider.generate(&messages, "claude-nope", None, None).await;
+        assert!(matches!(model, Err(LlmError::InvalidModel { model }) if model == "claude-nope"));
+    }
+}
@@ -0,0 +1,738 @@
+//! Content-addressed on-disk cache for LLM responses
+//!
+//! `CachingProvider` wraps any provider and stores each successful response
+//! under a hash of the provider, model and full request (messages, tools and
+//! generation config). Plans, refinements and analyses are keyed on the
+//! messages the provider will send, rendered with the shared builders, so
+//! editing a prompt override or the schema repair prompt changes the key
+//! instead of serving a reply to the old prompt. Re-running the same plan while debugging, or summarizing
+//! files that have not changed, is then served from disk instead of paying for
+//! the same call again. Entries expire after a TTL and the oldest are evicted
+//! once the cache grows past its size limit.
+
+use super::cassette::request_key;
+use super::embeddings::EmbeddingLlmProvider;
+use super::prompts::PromptTemplates;
+use super::streaming::{
+    LlmStream, PlanStreamCollector, StreamChunk, StreamCollector, StreamingLlmProvider,
+    TaskAnalysisCollector,
+};
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, ResponseFormat, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use crate::utils::templates::builders::{
+    ExecutionAnalysisMessageBuilder, PlanGenerationMessageBuilder, TaskRefinementMessageBuilder,
+};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Current cache entry format version; entries with another version are misses
+pub const CACHE_VERSION: u32 = 1;
+
+/// Default time an entry stays valid
+pub const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Default upper bound on the total size of the cache directory
+pub const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// A cached response as stored on disk
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    version: u32,
+    /// Provider method that produced the response
+    operation: String,
+    /// Unix time in milliseconds when the entry was written
+    created_at: i64,
+    response: Value,
+}
+
+/// Number of entries and bytes currently held by the cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Directory of cached responses, one JSON file per request key
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_bytes: u64,
+}
+
+impl ResponseCache {
+    /// Open a cache in the given directory with default limits
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Default cache location under the kai-x data directory
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_local_dir()
+            .or_else(dirs::data_dir)
+            .map(|dir| dir.join("kai-x").join("cache").join("llm"))
+    }
+
+    /// Open the default cache with the limits from configuration
+    ///
+    /// Returns `None` when the platform has no data directory.
+    pub fn from_config(config: &crate::config::CacheConfig) -> Option<Self> {
+        Some(
+            Self::new(Self::default_dir()?)
+                .with_ttl(Duration::from_secs(config.ttl_seconds))
+                .with_max_bytes(config.max_size_mb.saturating_mul(1024 * 1024)),
+        )
+    }
+
+    /// Set how long entries stay valid
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the size above which the oldest entries are evicted
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Directory holding the cache entries
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Look up a response, treating expired or unreadable entries as misses
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let path = self.entry_path(key);
+        let content = std::fs::read_to_string(&path).ok()?;
+
+        let entry = serde_json::from_str::<CacheEntry>(&content)
+            .ok()
+            .filter(|entry| entry.version == CACHE_VERSION)
+            .filter(|entry| !self.is_expired(entry.created_at));
+        match entry.and_then(|entry| serde_json::from_value(entry.response).ok()) {
+            Some(response) => Some(response),
+            None => {
+                // Expired, stale format or corrupt; drop it so it is rewritten
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Store a response and evict the oldest entries if the cache is over its size limit
+    pub fn put<T: Serialize>(&self, key: &str, operation: &str, response: &T) -> Result<(), LlmError> {
+        let entry = CacheEntry {
+            version: CACHE_VERSION,
+            operation: operation.to_string(),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            response: serde_json::to_value(response)?,
+        };
+        let content = serde_json::to_string(&entry)?;
+
+        std::fs::create_dir_all(&self.dir).map_err(|e| LlmError::Unknown {
+            message: format!("Failed to create cache directory {}: {}", self.dir.display(), e),
+        })?;
+        // Write then rename so concurrent readers never see a partial entry
+        let path = self.entry_path(key);
+        let temp_path = self.dir.join(format!("{}.{}.tmp", key, uuid::Uuid::new_v4()));
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_path);
+                LlmError::Unknown {
+                    message: format!("Failed to write cache entry {}: {}", path.display(), e),
+                }
+            })?;
+
+        self.enforce_size_limit();
+        Ok(())
+    }
+
+    /// Remove every entry, returning how many were deleted
+    pub fn clear(&self) -> Result<usize, LlmError> {
+        let mut removed = 0;
+        for (path, _, _) in self.entries() {
+            std::fs::remove_file(&path).map_err(|e| LlmError::Unknown {
+                message: format!("Failed to remove cache entry {}: {}", path.display(), e),
+            })?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Count the entries and bytes currently cached
+    pub fn stats(&self) -> CacheStats {
+        self.entries()
+            .iter()
+            .fold(CacheStats::default(), |stats, (_, bytes, _)| CacheStats {
+                entries: stats.entries + 1,
+                bytes: stats.bytes + bytes,
+            })
+    }
+
+    fn is_expired(&self, created_at: i64) -> bool {
+        let age_ms = chrono::Utc::now().timestamp_millis().saturating_sub(created_at);
+        age_ms < 0 || age_ms as u128 >= self.ttl.as_millis()
+    }
+
+    /// Cache entry files with their size and modification time
+    fn entries(&self) -> Vec<(PathBuf, u64, std::time::SystemTime)> {
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read_dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), modified))
+            })
+            .collect()
+    }
+
+    /// Delete the oldest entries until the cache fits within its size limit
+    fn enforce_size_limit(&self) {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, bytes, _)| bytes).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, bytes, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total = total.saturating_sub(bytes);
+            }
+        }
+    }
+}
+
+/// Store a response, logging instead of failing the call if the cache can't be written
+fn store<T: Serialize>(cache: &ResponseCache, key: &str, operation: &str, response: &T) {
+    if let Err(error) = cache.put(key, operation, response) {
+        warn!("Failed to cache {} response: {}", operation, error);
+    }
+}
+
+/// Decides from each chunk whether a streamed response is complete
+type CompletionCheck = Box<dyn FnMut(&StreamChunk) -> bool + Send>;
+
+/// State for a stream whose content is cached once it completes
+struct StreamRecording {
+    stream: LlmStream,
+    /// `None` once the response has been stored or the stream failed
+    collector: Option<StreamCollector>,
+    is_complete: CompletionCheck,
+    cache: ResponseCache,
+    key: String,
+    operation: String,
+}
+
+impl StreamRecording {
+    fn store(&mut self) {
+        if let Some(collector) = self.collector.take() {
+            store(&self.cache, &self.key, &self.operation, &collector.into_response());
+        }
+    }
+}
+
+/// Pass a stream through unchanged while caching its content
+///
+/// Plan and analysis collectors stop reading as soon as their JSON is complete,
+/// so completion is decided by the same rule rather than by the final chunk.
+fn record_stream(recording: StreamRecording) -> LlmStream {
+    Box::pin(futures::stream::unfold(recording, |mut recording| async move {
+        match recording.stream.next().await {
+            Some(Ok(chunk)) => {
+                if let Some(collector) = recording.collector.as_mut() {
+                    if collector.process_chunk(&chunk).is_err() {
+                        recording.collector = None;
+                    } else if (recording.is_complete)(&chunk) {
+                        recording.store();
+                    }
+                }
+                Some((Ok(chunk), recording))
+            }
+            Some(Err(error)) => {
+                recording.collector = None;
+                Some((Err(error), recording))
+            }
+            None => {
+                recording.store();
+                None
+            }
+        }
+    }))
+}
+
+/// Serve a cached response as a stream
+fn replay_stream(response: LlmResponse) -> LlmStream {
+    let mut chunks = Vec::new();
+    if let Some(content) = response.content {
+        chunks.push(Ok(StreamChunk::content(content)));
+    }
+    if let Some(tool_calls) = response.tool_calls {
+        chunks.push(Ok(StreamChunk::tool_calls(tool_calls)));
+    }
+    chunks.push(Ok(StreamChunk::finish(response.finish_reason, response.usage)));
+    Box::pin(futures::stream::iter(chunks))
+}
+
+/// Template text of the prompt that asks for a corrected structured reply
+fn repair_prompt() -> Value {
+    let template = PromptTemplates::schema_repair();
+    json!([template.system_message, template.user_template])
+}
+
+/// Plan request as the provider sends it, including its repair prompt
+fn plan_request(prompt: &str, context: &str, model: &str) -> Result<Value, LlmError> {
+    Ok(json!({
+        "messages": PlanGenerationMessageBuilder::create(prompt, context)?,
+        "model": model,
+        "config": GenerationConfig::structured(ResponseFormat::plan()),
+        "repair": repair_prompt(),
+    }))
+}
+
+/// Refinement request as the provider sends it
+fn refinement_request(
+    task: &crate::planning::Task,
+    context: &TaskRefinementContext,
+    model: &str,
+) -> Result<Value, LlmError> {
+    let messages = TaskRefinementMessageBuilder::create(
+        task,
+        &context.plan_description,
+        &context.global_context,
+        &context.plan_context,
+        &context.dependency_outputs,
+    )?;
+    Ok(json!({ "messages": messages, "model": model }))
+}
+
+/// Analysis request as the provider sends it, including its repair prompt
+///
+/// Wall-clock timing is left out so re-running the same task hits the cache.
+fn analysis_request(
+    task: &crate::planning::Task,
+    execution_result: &TaskExecutionResult,
+    expected_outcome: &str,
+    model: &str,
+) -> Result<Value, LlmError> {
+    let untimed = TaskExecutionResult {
+        execution_time_ms: 0,
+        ..execution_result.clone()
+    };
+    Ok(json!({
+        "messages": ExecutionAnalysisMessageBuilder::create(task, &untimed, expected_outcome)?,
+        "model": model,
+        "config": GenerationConfig::structured(ResponseFormat::task_analysis()),
+        "repair": repair_prompt(),
+    }))
+}
+
+/// Provider wrapper that serves repeated requests from a `ResponseCache`
+///
+/// Model listing and validation are passed straight through so they always
+/// reflect the provider's current state.
+pub struct CachingProvider {
+    inner: Box<dyn LlmProvider>,
+    cache: ResponseCache,
+}
+
+impl CachingProvider {
+    /// Wrap a provider with the given cache
+    pub fn new(inner: Box<dyn LlmProvider>, cache: ResponseCache) -> Self {
+        Self { inner, cache }
+    }
+
+    /// Cache backing this provider
+    pub fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+
+    fn key(&self, operation: &str, request: Value) -> String {
+        request_key(
+            operation,
+            &json!({ "provider": self.inner.provider_name(), "request": request }),
+        )
+    }
+
+    /// Serve a call from the cache, or make it and cache a successful response
+    async fn cached<T, F>(&self, operation: &str, request: Value, call: F) -> Result<T, LlmError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Future<Output = Result<T, LlmError>>,
+    {
+        let key = self.key(operation, request);
+        if let Some(response) = self.cache.get(&key) {
+            debug!("Serving {} from response cache ({})", operation, key);
+            return Ok(response);
+        }
+
+        let response = call.await?;
+        store(&self.cache, &key, operation, &response);
+        Ok(response)
+    }
+
+    /// Replay a cached stream, or open one and cache it once it completes
+    async fn cached_stream<F>(
+        &self,
+        operation: &str,
+        request: Value,
+        is_complete: CompletionCheck,
+        open: F,
+    ) -> Result<LlmStream, LlmError>
+    where
+        F: Future<Output = Result<LlmStream, LlmError>>,
+    {
+        let key = self.key(operation, request);
+        if let Some(response) = self.cache.get(&key) {
+            debug!("Serving {} from response cache ({})", operation, key);
+            return Ok(replay_stream(response));
+        }
+
+        Ok(record_stream(StreamRecording {
+            stream: open.await?,
+            collector: Some(StreamCollector::new()),
+            is_complete,
+            cache: self.cache.clone(),
+            key,
+            operation: operation.to_string(),
+        }))
+    }
+
+    fn inner_streaming(&self) -> Result<&dyn StreamingLlmProvider, LlmError> {
+        self.inner.as_streaming().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support streaming", self.inner.provider_name()),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachingProvider {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        self.inner.list_models().await
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let request = json!({
+            "messages": messages,
+            "model": model,
+            "tools": tools,
+            "config": config,
+        });
+        self.cached("generate", request, self.inner.generate(messages, model, tools, config))
+            .await
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let request = plan_request(prompt, context, model)?;
+        self.cached("generate_plan", request, self.inner.generate_plan(prompt, context, model))
+            .await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let request = json!({
+            "prompt": prompt,
+            "context": context,
+            "model": model,
+            "config": config,
+        });
+        self.cached(
+            "generate_content",
+            request,
+            self.inner.generate_content(prompt, context, model, config),
+        )
+        .await
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let request = refinement_request(task, context, model)?;
+        self.cached(
+            "refine_task_for_execution",
+            request,
+            self.inner.refine_task_for_execution(task, context, model),
+        )
+        .await
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let request = analysis_request(task, execution_result, expected_outcome, model)?;
+        self.cached(
+            "analyze_task_result",
+            request,
+            self.inner.analyze_task_result(task, execution_result, expected_outcome, model),
+        )
+        .await
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        self.inner.validate_model(model).await
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        self.inner.as_streaming().map(|_| self as &dyn StreamingLlmProvider)
+    }
+
+    /// Embeddings are not cached
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.inner.as_embedding()
+    }
+}
+
+#[async_trait]
+impl StreamingLlmProvider for CachingProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let request = json!({
+            "messages": messages,
+            "model": model,
+            "tools": tools,
+            "config": config,
+        });
+        let streaming = self.inner_streaming()?;
+        self.cached_stream(
+            "generate_stream",
+            request,
+            Box::new(|chunk| chunk.is_final()),
+            streaming.generate_stream(messages, model, tools, config),
+        )
+        .await
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let request = plan_request(prompt, context, model)?;
+        let streaming = self.inner_streaming()?;
+        let mut collector = PlanStreamCollector::new();
+        self.cached_stream(
+            "generate_plan_stream",
+            request,
+            Box::new(move |chunk| collector.process_chunk(chunk).unwrap_or(false)),
+            streaming.generate_plan_stream(prompt, context, model),
+        )
+        .await
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let request = refinement_request(task, context, model)?;
+        let streaming = self.inner_streaming()?;
+        self.cached_stream(
+            "refine_task_stream",
+            request,
+            Box::new(|chunk| chunk.is_final()),
+            streaming.refine_task_stream(task, context, model),
+        )
+        .await
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let request = analysis_request(task, execution_result, expected_outcome, model)?;
+        let streaming = self.inner_streaming()?;
+        let mut collector = TaskAnalysisCollector::new();
+        self.cached_stream(
+            "analyze_task_result_stream",
+            request,
+            Box::new(move |chunk| collector.process_chunk(chunk).unwrap_or(false)),
+            streaming.analyze_task_result_stream(task, execution_result, expected_outcome, model),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::ScriptedProvider;
+    use crate::llm::streaming::utils::collect_plan_stream;
+
+    /// Provider that summarizes prompts and streams a plan for them
+    fn counting_provider() -> ScriptedProvider {
+        ScriptedProvider::new("counting")
+            .on_content(|prompt, _context, model| {
+                if prompt == "fail" {
+                    return Err(LlmError::RateLimit { retry_after: None });
+                }
+                Ok(format!("{} summary from {}", prompt, model))
+            })
+            .with_stream(|prompt| {
+                vec![
+                    StreamChunk::content("```json\n{\"description\": "),
+                    StreamChunk::content(format!("\"{}\", \"tasks\": []}}", prompt)),
+                    StreamChunk::content("\n```"),
+                    StreamChunk::finish("stop", None),
+                ]
+            })
+    }
+
+    fn caching_provider(dir: &Path) -> (CachingProvider, ScriptedProvider) {
+        let inner = counting_provider();
+        (CachingProvider::new(Box::new(inner.clone()), ResponseCache::new(dir)), inner)
+    }
+
+    #[tokio::test]
+    async fn test_repeated_requests_are_served_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let (provider, inner) = caching_provider(dir.path());
+
+        let first = provider.generate_content("main.rs", "", "model-a", None).await.unwrap();
+        let second = provider.generate_content("main.rs", "", "model-a", None).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(inner.calls(), 1);
+
+        // A different model or prompt is a different request
+        provider.generate_content("main.rs", "", "model-b", None).await.unwrap();
+        provider.generate_content("lib.rs", "", "model-a", None).await.unwrap();
+        assert_eq!(inner.calls(), 3);
+
+        // Failures are not cached
+        assert!(provider.generate_content("fail", "", "model-a", None).await.is_err());
+        assert!(provider.generate_content("fail", "", "model-a", None).await.is_err());
+        assert_eq!(inner.calls(), 5);
+        assert_eq!(provider.cache().stats().entries, 3);
+
+        assert_eq!(provider.cache().clear().unwrap(), 3);
+        provider.generate_content("main.rs", "", "model-a", None).await.unwrap();
+        assert_eq!(inner.calls(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_plan_is_cached_when_its_json_completes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (provider, inner) = caching_provider(dir.path());
+        let streaming = provider.as_streaming().unwrap();
+
+        // The collector stops at the closing brace and never reads the finish chunk
+        let stream = streaming.generate_plan_stream("Add tests", "ctx", "model-a").await.unwrap();
+        let plan = collect_plan_stream(stream).await.unwrap();
+        assert_eq!(plan.description, "Add tests");
+
+        let stream = streaming.generate_plan_stream("Add tests", "ctx", "model-a").await.unwrap();
+        let replayed = collect_plan_stream(stream).await.unwrap();
+        assert_eq!(replayed.description, "Add tests");
+        assert_eq!(inner.calls(), 1);
+    }
+
+    #[test]
+    fn test_structured_keys_cover_the_rendered_prompts() {
+        let request = plan_request("Add tests", "ctx", "model-a").unwrap();
+        let messages = request["messages"].as_array().unwrap();
+        assert!(messages.last().unwrap()["content"].as_str().unwrap().contains("Add tests"));
+        assert_eq!(request["repair"][1], PromptTemplates::schema_repair().user_template);
+        assert_eq!(request["config"]["response_format"]["name"], "plan");
+
+        // Timing is not part of an analysis request
+        let task = crate::planning::Task::new("t1", "List files", crate::planning::TaskType::ListFiles);
+        let result = |execution_time_ms| TaskExecutionResult {
+            success: true,
+            stdout: Some("a.txt".to_string()),
+            stderr: None,
+            exit_code: Some(0),
+            output: None,
+            error: None,
+            execution_time_ms,
+            metadata: std::collections::HashMap::new(),
+        };
+        assert_eq!(
+            analysis_request(&task, &result(12), "files", "m").unwrap(),
+            analysis_request(&task, &result(999), "files", "m").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ttl_and_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let expired = ResponseCache::new(dir.path()).with_ttl(Duration::ZERO);
+        expired.put("a", "generate_content", &"old").unwrap();
+        assert_eq!(expired.get::<String>("a"), None);
+        assert_eq!(expired.stats().entries, 0);
+
+        let cache = ResponseCache::new(dir.path()).with_max_bytes(400);
+        let response = "x".repeat(100);
+        for key in ["a", "b", "c", "d"] {
+            cache.put(key, "generate_content", &response).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let stats = cache.stats();
+        assert!(stats.bytes <= 400);
+        assert!(stats.entries < 4);
+        assert_eq!(cache.get::<String>("a"), None);
+        assert_eq!(cache.get::<String>("d"), Some(response));
+    }
+}
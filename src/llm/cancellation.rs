@@ -0,0 +1,161 @@
+//! Cancellation of in-flight LLM requests
+//!
+//! Plans and tasks run their LLM calls inside [`with_cancellation`]. Retry
+//! loops, backoff sleeps, rate limit waits and response streams pick the token
+//! up from there, so cancelling it drops the outstanding HTTP request instead
+//! of waiting for the model to finish. Aborted calls fail with
+//! [`LlmError::Cancelled`].
+//!
+//! The token is task-local, so it does not follow work handed to
+//! `tokio::spawn`: requests made from a spawned task run to completion. Spawn
+//! with [`spawn`] instead, or wrap the spawned future in `with_cancellation`.
+
+use super::{LlmError, LlmStream};
+use futures::StreamExt;
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    static REQUEST_TOKEN: CancellationToken;
+}
+
+/// Run `future` with `token` cancelling every LLM request it makes
+///
+/// Only the requests are aborted; other work in `future` sees them fail with
+/// `LlmError::Cancelled` and decides how to stop.
+pub async fn with_cancellation<F: Future>(token: CancellationToken, future: F) -> F::Output {
+    REQUEST_TOKEN.scope(token, future).await
+}
+
+/// Token of the surrounding `with_cancellation` scope, if any
+pub fn current_token() -> Option<CancellationToken> {
+    REQUEST_TOKEN.try_with(CancellationToken::clone).ok()
+}
+
+/// Spawn `future` as a new task that keeps the current token, if any
+pub fn spawn<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match current_token() {
+        Some(token) => tokio::spawn(with_cancellation(token, future)),
+        None => tokio::spawn(future),
+    }
+}
+
+/// Run one request, aborting it as soon as the current token is cancelled
+pub async fn cancellable<T, F>(request: F) -> Result<T, LlmError>
+where
+    F: Future<Output = Result<T, LlmError>>,
+{
+    let Some(token) = current_token() else {
+        return request.await;
+    };
+    if token.is_cancelled() {
+        return Err(LlmError::Cancelled);
+    }
+
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(LlmError::Cancelled),
+        result = request => result,
+    }
+}
+
+/// Wait out a retry delay unless the current token is cancelled first
+pub async fn sleep(duration: Duration) -> Result<(), LlmError> {
+    cancellable(async {
+        tokio::time::sleep(duration).await;
+        Ok(())
+    })
+    .await
+}
+
+/// End `stream` with `LlmError::Cancelled` once the current token is cancelled
+///
+/// The token is captured when the stream is created, so it stays cancellable
+/// wherever it is read from.
+pub fn cancellable_stream(stream: LlmStream) -> LlmStream {
+    let Some(token) = current_token() else {
+        return stream;
+    };
+
+    Box::pin(futures::stream::unfold(Some((stream, token)), |state| async move {
+        let (mut stream, token) = state?;
+        let next = tokio::select! {
+            biased;
+            _ = token.cancelled() => None,
+            chunk = stream.next() => Some(chunk),
+        };
+        match next {
+            None => Some((Err(LlmError::Cancelled), None)),
+            Some(Some(chunk)) => Some((chunk, Some((stream, token)))),
+            Some(None) => None,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::StreamChunk;
+
+    #[tokio::test]
+    async fn test_cancelling_the_token_aborts_the_request() {
+        let token = CancellationToken::new();
+        let trigger = token.clone();
+
+        let request = with_cancellation(token, async {
+            cancellable(async {
+                trigger.cancel();
+                futures::future::pending::<Result<(), LlmError>>().await
+            })
+            .await
+        });
+
+        assert!(matches!(request.await, Err(LlmError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_requests_outside_a_scope_run_to_completion() {
+        assert!(current_token().is_none());
+        let result = cancellable(async { Ok::<_, LlmError>(7) }).await;
+        assert_eq!(result.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_tasks_only_keep_the_token_through_spawn() {
+        let token = CancellationToken::new();
+        let request = || cancellable(futures::future::pending::<Result<(), LlmError>>());
+
+        let (detached, carried) = with_cancellation(token.clone(), async {
+            (tokio::spawn(request()), spawn(request()))
+        })
+        .await;
+        token.cancel();
+
+        assert!(matches!(carried.await.unwrap(), Err(LlmError::Cancelled)));
+        // `tokio::spawn` leaves the scope behind, so its request is never aborted
+        let waited = tokio::time::timeout(Duration::from_millis(50), detached).await;
+        assert!(waited.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_cancelled_after_the_token_fires() {
+        let token = CancellationToken::new();
+        let source: LlmStream = Box::pin(
+            futures::stream::iter(vec![Ok(StreamChunk::content("first"))])
+                .chain(futures::stream::pending()),
+        );
+        let mut stream = with_cancellation(token.clone(), async { cancellable_stream(source) }).await;
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.content.as_deref(), Some("first"));
+
+        token.cancel();
+        assert!(matches!(stream.next().await, Some(Err(LlmError::Cancelled))));
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,730 @@
+//! Record-and-replay providers for deterministic tests
+//!
+//! `RecordingProvider` wraps a real provider and appends every request and its
+//! response to a JSON cassette file. `ReplayProvider` serves those responses
+//! back, keyed by a hash of the request, and fails loudly on any request that
+//! was never recorded. Together they let CI exercise the planning and execution
+//! loops end to end without calling a real model.
+
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Current cassette file format version
+pub const CASSETTE_VERSION: u32 = 1;
+
+/// Placeholder written in place of the working directory when redaction is enabled
+const WORKDIR_PLACEHOLDER: &str = "$WORKDIR";
+
+lazy_static::lazy_static! {
+    /// Serializes cassette writes so several recorders in one process don't clobber each other
+    static ref CASSETTE_WRITE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// A recorded request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    /// Hash of the operation and its request, used for lookup on replay
+    pub key: String,
+    /// Provider method that was called (e.g. "generate_plan")
+    pub operation: String,
+    /// Request arguments, kept for humans reading the cassette
+    pub request: Value,
+    /// Successful response returned by the wrapped provider
+    pub response: Value,
+}
+
+/// On-disk collection of recorded interactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Create an empty cassette
+    pub fn new() -> Self {
+        Self {
+            version: CASSETTE_VERSION,
+            interactions: Vec::new(),
+        }
+    }
+
+    /// Load a cassette from disk
+    pub fn load(path: &Path) -> Result<Self, LlmError> {
+        let content = std::fs::read_to_string(path).map_err(|e| LlmError::Unknown {
+            message: format!("Failed to read cassette {}: {}", path.display(), e),
+        })?;
+        let cassette: Self = serde_json::from_str(&content).map_err(|e| LlmError::InvalidResponse {
+            message: format!("Malformed cassette {}: {}", path.display(), e),
+        })?;
+
+        if cassette.version != CASSETTE_VERSION {
+            return Err(LlmError::InvalidResponse {
+                message: format!(
+                    "Cassette {} has version {}, expected {}. Re-record it.",
+                    path.display(),
+                    cassette.version,
+                    CASSETTE_VERSION
+                ),
+            });
+        }
+
+        Ok(cassette)
+    }
+
+    /// Load a cassette, starting an empty one if the file does not exist yet
+    pub fn load_or_new(path: &Path) -> Result<Self, LlmError> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::new())
+        }
+    }
+
+    /// Write the cassette to disk, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<(), LlmError> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| LlmError::Unknown {
+                message: format!("Failed to create cassette directory {}: {}", parent.display(), e),
+            })?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content).map_err(|e| LlmError::Unknown {
+            message: format!("Failed to write cassette {}: {}", path.display(), e),
+        })
+    }
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compute the lookup key for a request
+///
+/// Uses 64-bit FNV-1a over the canonical JSON so keys stay stable across
+/// processes and Rust versions (unlike `DefaultHasher`). Object keys are
+/// already sorted by `serde_json::Value`.
+pub fn request_key(operation: &str, request: &Value) -> String {
+    let canonical = json!({ "operation": operation, "request": request }).to_string();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in canonical.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Reduce a task to the fields that describe the request, dropping status and timestamps
+pub(super) fn task_fingerprint(task: &crate::planning::Task) -> Value {
+    json!({
+        "id": task.id,
+        "description": task.description,
+        "task_type": task.task_type,
+        "parameters": task.parameters,
+        "dependencies": task.dependencies,
+    })
+}
+
+/// Serialize an execution result without its wall-clock timing
+pub(super) fn execution_fingerprint(result: &TaskExecutionResult) -> Result<Value, LlmError> {
+    let mut value = serde_json::to_value(result)?;
+    if let Value::Object(map) = &mut value {
+        map.remove("execution_time_ms");
+    }
+    Ok(value)
+}
+
+/// Rewrite every string inside a JSON value
+fn map_strings(value: &mut Value, f: &dyn Fn(&str) -> String) {
+    match value {
+        Value::String(s) => *s = f(s),
+        Value::Array(items) => items.iter_mut().for_each(|item| map_strings(item, f)),
+        Value::Object(map) => map.values_mut().for_each(|item| map_strings(item, f)),
+        _ => {}
+    }
+}
+
+/// Replaces an absolute working directory with a placeholder so cassettes
+/// recorded in one checkout replay in another
+#[derive(Debug, Clone, Default)]
+struct WorkdirRedaction {
+    workdir: Option<String>,
+}
+
+impl WorkdirRedaction {
+    fn redact(&self, value: &mut Value) {
+        if let Some(workdir) = &self.workdir {
+            map_strings(value, &|s| s.replace(workdir.as_str(), WORKDIR_PLACEHOLDER));
+        }
+    }
+
+    fn restore(&self, value: &mut Value) {
+        if let Some(workdir) = &self.workdir {
+            map_strings(value, &|s| s.replace(WORKDIR_PLACEHOLDER, workdir));
+        }
+    }
+
+    /// Redact a request and compute its key
+    fn key(&self, operation: &str, mut request: Value) -> (String, Value) {
+        self.redact(&mut request);
+        (request_key(operation, &request), request)
+    }
+}
+
+/// Read the settings shared by both cassette providers
+fn cassette_settings(
+    settings: &HashMap<String, String>,
+) -> Result<(PathBuf, WorkdirRedaction), LlmError> {
+    let path = settings
+        .get("cassette")
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| LlmError::Unknown {
+            message: "Cassette path not provided. Set the 'cassette' setting.".to_string(),
+        })?;
+    let redaction = WorkdirRedaction {
+        workdir: settings.get("cassette_workdir").filter(|dir| !dir.is_empty()).cloned(),
+    };
+    Ok((path, redaction))
+}
+
+/// Provider wrapper that records every successful call to a cassette
+///
+/// Failed calls are passed through without being recorded. Interactions are
+/// appended to an existing cassette; delete the file to re-record from scratch.
+/// Streaming is not exposed, so callers fall back to the recorded
+/// non-streaming methods.
+pub struct RecordingProvider {
+    inner: Box<dyn LlmProvider>,
+    path: PathBuf,
+    redaction: WorkdirRedaction,
+}
+
+impl RecordingProvider {
+    /// Wrap a provider, recording to the given cassette path
+    pub fn new(inner: Box<dyn LlmProvider>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            redaction: WorkdirRedaction::default(),
+        }
+    }
+
+    /// Create a recorder from factory settings
+    ///
+    /// Recognised keys: `cassette` (required) and `cassette_workdir`.
+    pub fn from_settings(
+        inner: Box<dyn LlmProvider>,
+        settings: &HashMap<String, String>,
+    ) -> Result<Self, LlmError> {
+        let (path, redaction) = cassette_settings(settings)?;
+        Ok(Self {
+            inner,
+            path,
+            redaction,
+        })
+    }
+
+    /// Replace this absolute directory with a placeholder in recorded data
+    pub fn with_workdir(mut self, workdir: impl Into<String>) -> Self {
+        self.redaction.workdir = Some(workdir.into());
+        self
+    }
+
+    /// Path of the cassette being written
+    pub fn cassette_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a successful response to the cassette and hand it back
+    fn record<T: Serialize>(
+        &self,
+        operation: &str,
+        request: Value,
+        response: Result<T, LlmError>,
+    ) -> Result<T, LlmError> {
+        let response = response?;
+        let (key, request) = self.redaction.key(operation, request);
+        let mut recorded = serde_json::to_value(&response)?;
+        self.redaction.redact(&mut recorded);
+
+        let _guard = CASSETTE_WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // Re-read so other recorders writing the same cassette are preserved
+        let mut cassette = Cassette::load_or_new(&self.path)?;
+        cassette.interactions.push(Interaction {
+            key,
+            operation: operation.to_string(),
+            request,
+            response: recorded,
+        });
+        cassette.save(&self.path)?;
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RecordingProvider {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        let response = self.inner.list_models().await;
+        self.record("list_models", json!({}), response)
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let request = json!({
+            "messages": messages,
+            "model": model,
+            "tools": tools,
+            "config": config,
+        });
+        let response = self.inner.generate(messages, model, tools, config).await;
+        self.record("generate", request, response)
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let request = json!({ "prompt": prompt, "context": context, "model": model });
+        let response = self.inner.generate_plan(prompt, context, model).await;
+        self.record("generate_plan", request, response)
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let request = json!({
+            "prompt": prompt,
+            "context": context,
+            "model": model,
+            "config": config,
+        });
+        let response = self.inner.generate_content(prompt, context, model, config).await;
+        self.record("generate_content", request, response)
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let request = json!({
+            "task": task_fingerprint(task),
+            "context": context,
+            "model": model,
+        });
+        let response = self.inner.refine_task_for_execution(task, context, model).await;
+        self.record("refine_task_for_execution", request, response)
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let request = json!({
+            "task": task_fingerprint(task),
+            "execution_result": execution_fingerprint(execution_result)?,
+            "expected_outcome": expected_outcome,
+            "model": model,
+        });
+        let response = self
+            .inner
+            .analyze_task_result(task, execution_result, expected_outcome, model)
+            .await;
+        self.record("analyze_task_result", request, response)
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        let response = self.inner.validate_model(model).await;
+        self.record("validate_model", json!({ "model": model }), response)
+    }
+}
+
+/// Provider that serves responses from a cassette
+///
+/// Identical requests recorded more than once are served in recording order;
+/// once exhausted, the last response keeps being returned.
+pub struct ReplayProvider {
+    path: PathBuf,
+    responses: HashMap<String, Vec<Value>>,
+    /// Number of times each key has been served
+    served: Mutex<HashMap<String, usize>>,
+    redaction: WorkdirRedaction,
+}
+
+impl ReplayProvider {
+    /// Load a cassette for replay
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, LlmError> {
+        let path = path.into();
+        let cassette = Cassette::load(&path)?;
+
+        let mut responses: HashMap<String, Vec<Value>> = HashMap::new();
+        for interaction in cassette.interactions {
+            responses
+                .entry(interaction.key)
+                .or_default()
+                .push(interaction.response);
+        }
+
+        Ok(Self {
+            path,
+            responses,
+            served: Mutex::new(HashMap::new()),
+            redaction: WorkdirRedaction::default(),
+        })
+    }
+
+    /// Create a replay provider from factory settings
+    ///
+    /// Recognised keys: `cassette` (required) and `cassette_workdir`.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let (path, redaction) = cassette_settings(settings)?;
+        Ok(Self {
+            redaction,
+            ..Self::new(path)?
+        })
+    }
+
+    /// Substitute this directory back for the placeholder in recorded data
+    pub fn with_workdir(mut self, workdir: impl Into<String>) -> Self {
+        self.redaction.workdir = Some(workdir.into());
+        self
+    }
+
+    /// Path of the cassette being served
+    pub fn cassette_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Look up the recorded response for a request
+    fn replay<T: DeserializeOwned>(&self, operation: &str, request: Value) -> Result<T, LlmError> {
+        let (key, _) = self.redaction.key(operation, request);
+
+        let recorded = self.responses.get(&key).ok_or_else(|| {
+            tracing::error!("Cassette miss for {} (key {}) in {}", operation, key, self.path.display());
+            LlmError::Unknown {
+                message: format!(
+                    "No recorded response for {} (key {}) in cassette {}. Re-record it with the 'record' provider.",
+                    operation,
+                    key,
+                    self.path.display()
+                ),
+            }
+        })?;
+
+        let index = {
+            let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+            let count = served.entry(key).or_insert(0);
+            let index = (*count).min(recorded.len() - 1);
+            *count += 1;
+            index
+        };
+
+        let mut response = recorded[index].clone();
+        self.redaction.restore(&mut response);
+        serde_json::from_value(response).map_err(|e| LlmError::InvalidResponse {
+            message: format!(
+                "Recorded {} response in {} does not match the expected type: {}",
+                operation,
+                self.path.display(),
+                e
+            ),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    fn provider_name(&self) -> &str {
+        "replay"
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        self.replay("list_models", json!({}))
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        self.replay(
+            "generate",
+            json!({
+                "messages": messages,
+                "model": model,
+                "tools": tools,
+                "config": config,
+            }),
+        )
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        self.replay(
+            "generate_plan",
+            json!({ "prompt": prompt, "context": context, "model": model }),
+        )
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        self.replay(
+            "generate_content",
+            json!({
+                "prompt": prompt,
+                "context": context,
+                "model": model,
+                "config": config,
+            }),
+        )
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        self.replay(
+            "refine_task_for_execution",
+            json!({
+                "task": task_fingerprint(task),
+                "context": context,
+                "model": model,
+            }),
+        )
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        self.replay(
+            "analyze_task_result",
+            json!({
+                "task": task_fingerprint(task),
+                "execution_result": execution_fingerprint(execution_result)?,
+                "expected_outcome": expected_outcome,
+                "model": model,
+            }),
+        )
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        self.replay("validate_model", json!({ "model": model }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::{text_reply, ScriptedProvider};
+    use crate::planning::{Plan, Task, TaskType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Deterministic stand-in for a real backend
+    fn stub_provider() -> ScriptedProvider {
+        let replies = AtomicUsize::new(0);
+        ScriptedProvider::new("stub")
+            .on_generate(move |messages| {
+                let reply = replies.fetch_add(1, Ordering::SeqCst);
+                Ok(text_reply(&format!("reply {} to {}", reply, messages[0].content)))
+            })
+            .on_plan(|prompt| {
+                let mut plan = Plan::new(prompt);
+                plan.add_task(Task::new("task_1".to_string(), "List files".to_string(), TaskType::ListFiles));
+                Ok(plan)
+            })
+            .on_content(|_prompt, context, _model| Ok(format!("written in {}", context)))
+            .on_refine(|task| Ok(format!("run {}", task.description)))
+            .on_analysis(|task| {
+                Ok(TaskAnalysis {
+                    success: true,
+                    summary: format!("{} done", task.id),
+                    details: String::new(),
+                    extracted_data: None,
+                    next_steps: None,
+                    context_updates: None,
+                    modified_files: None,
+                    error: None,
+                    metadata: HashMap::new(),
+                })
+            })
+    }
+
+    fn execution_result(execution_time_ms: u64) -> TaskExecutionResult {
+        TaskExecutionResult {
+            success: true,
+            stdout: Some("a.txt".to_string()),
+            stderr: None,
+            exit_code: Some(0),
+            output: None,
+            error: None,
+            execution_time_ms,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn refinement_context() -> TaskRefinementContext {
+        TaskRefinementContext {
+            global_context: "project".to_string(),
+            plan_context: String::new(),
+            dependency_outputs: HashMap::new(),
+            plan_description: "List the project".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassettes").join("session.json");
+        let stub = stub_provider();
+        let recorder = RecordingProvider::new(Box::new(stub.clone()), &path);
+
+        let task = Task::new("task_1".to_string(), "List files".to_string(), TaskType::ListFiles);
+        let messages = vec![Message {
+            role: crate::llm::MessageRole::User,
+            content: "hi".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let plan = recorder.generate_plan("List the project", "ctx", "m").await.unwrap();
+        let first = recorder.generate(&messages, "m", None, None).await.unwrap();
+        let second = recorder.generate(&messages, "m", None, None).await.unwrap();
+        let refined = recorder.refine_task_for_execution(&task, &refinement_context(), "m").await.unwrap();
+        let analysis = recorder.analyze_task_result(&task, &execution_result(12), "files", "m").await.unwrap();
+        // Failures pass through unrecorded
+        assert!(recorder.validate_model("missing").await.is_err());
+        assert_eq!(stub.calls(), 6);
+        assert_eq!(Cassette::load(&path).unwrap().interactions.len(), 5);
+
+        let replay = ReplayProvider::new(&path).unwrap();
+        let replayed_plan = replay.generate_plan("List the project", "ctx", "m").await.unwrap();
+        assert_eq!(replayed_plan.id, plan.id);
+        assert_eq!(replayed_plan.tasks[0].id, "task_1");
+        // Identical requests are served in recording order
+        assert_eq!(replay.generate(&messages, "m", None, None).await.unwrap().content, first.content);
+        assert_eq!(replay.generate(&messages, "m", None, None).await.unwrap().content, second.content);
+        assert_eq!(
+            replay.refine_task_for_execution(&task, &refinement_context(), "m").await.unwrap(),
+            refined
+        );
+
+        // Timing and task status don't affect the key
+        let mut task = task;
+        task.status = crate::planning::TaskStatus::Completed;
+        let replayed = replay.analyze_task_result(&task, &execution_result(999), "files", "m").await.unwrap();
+        assert_eq!(replayed.summary, analysis.summary);
+        assert_eq!(stub.calls(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_replay_miss_fails_loudly() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let recorder = RecordingProvider::new(Box::new(stub_provider()), &path);
+        recorder.generate_plan("List the project", "ctx", "m").await.unwrap();
+
+        let replay = ReplayProvider::new(&path).unwrap();
+        let error = replay.generate_plan("Delete the project", "ctx", "m").await.unwrap_err();
+        match error {
+            LlmError::Unknown { message } => {
+                assert!(message.contains("generate_plan"));
+                assert!(message.contains("session.json"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        assert!(ReplayProvider::new(dir.path().join("missing.json")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_workdir_redaction_replays_in_other_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let recorder = RecordingProvider::new(Box::new(stub_provider()), &path).with_workdir("/home/alice/project");
+        recorder
+            .generate_content("write", "/home/alice/project/src", "m", None)
+            .await
+            .unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("/home/alice"));
+
+        let replay = ReplayProvider::new(&path).unwrap().with_workdir("/ci/build");
+        let content = replay.generate_content("write", "/ci/build/src", "m", None).await.unwrap();
+        assert_eq!(content, "written in /ci/build/src");
+    }
+
+    #[test]
+    fn test_factory_selects_cassette_providers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        Cassette::new().save(&path).unwrap();
+
+        let mut settings = HashMap::new();
+        assert!(crate::llm::LlmProviderFactory::create_provider("replay", settings.clone()).is_err());
+
+        settings.insert("cassette".to_string(), path.display().to_string());
+        let provider = crate::llm::LlmProviderFactory::create_provider("replay", settings.clone()).unwrap();
+        assert_eq!(provider.provider_name(), "replay");
+
+        // Recording needs a real provider to wrap
+        assert!(crate::llm::LlmProviderFactory::create_provider("record", settings.clone()).is_err());
+        settings.insert("record_provider".to_string(), "replay".to_string());
+        assert!(crate::llm::LlmProviderFactory::create_provider("record", settings.clone()).is_err());
+        settings.insert("record_provider".to_string(), "ollama".to_string());
+        let provider = crate::llm::LlmProviderFactory::create_provider("record", settings).unwrap();
+        assert_eq!(provider.provider_name(), "ollama");
+    }
+}
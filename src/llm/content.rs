@@ -0,0 +1,298 @@
+//! Typed message content beyond plain text: images and file references
+//!
+//! `Message.content` stays the primary text of a message. Additional parts are
+//! carried in `Message.parts` and mapped by each provider to its own multimodal
+//! format. File references are loaded when the request is built, so a message
+//! can point at a file without holding its contents.
+
+use super::schema;
+use super::{GenerationConfig, LlmError, LlmProvider, Message, MessageRole, ResponseFormat};
+use crate::planning::Plan;
+use crate::utils::errors::KaiError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Largest image accepted as an attachment; providers reject larger inline data
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// A typed piece of message content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// Plain text
+    Text { text: String },
+    /// Raw image bytes, base64-encoded when serialized
+    Image {
+        mime_type: String,
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    /// A file on disk, read when the request is sent
+    File { path: PathBuf },
+}
+
+/// A content part ready to put on the wire
+#[derive(Debug, Clone, PartialEq)]
+pub enum InlinePart {
+    Text(String),
+    /// Image with its data already base64-encoded
+    Image { mime_type: String, data: String },
+}
+
+impl ContentPart {
+    /// Text part
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// Image part from raw bytes
+    pub fn image<S: Into<String>>(mime_type: S, data: Vec<u8>) -> Self {
+        Self::Image {
+            mime_type: mime_type.into(),
+            data,
+        }
+    }
+
+    /// Reference to a file that is read when the message is sent
+    pub fn file<P: Into<PathBuf>>(path: P) -> Self {
+        Self::File { path: path.into() }
+    }
+
+    /// Read an image file into an image part
+    pub fn load_image(path: &Path) -> crate::Result<Self> {
+        let mime_type = image_mime_type(path)
+            .ok_or_else(|| KaiError::validation("attachment", format!("{} is not a supported image", path.display())))?;
+        let data = std::fs::read(path).map_err(|e| KaiError::file_system(path, e))?;
+        if data.len() > MAX_IMAGE_BYTES {
+            return Err(KaiError::validation(
+                "attachment",
+                format!("{} is larger than {} MB", path.display(), MAX_IMAGE_BYTES / (1024 * 1024)),
+            ));
+        }
+        Ok(Self::image(mime_type, data))
+    }
+
+    /// Resolve the part for sending, loading file references from disk
+    ///
+    /// Image files become images and anything else is sent as text. A file that
+    /// cannot be read is replaced by a note saying so, so the model still
+    /// knows it was referenced.
+    pub fn inline(&self) -> InlinePart {
+        match self {
+            Self::Text { text } => InlinePart::Text(text.clone()),
+            Self::Image { mime_type, data } => InlinePart::Image {
+                mime_type: mime_type.clone(),
+                data: base64_bytes::encode(data),
+            },
+            Self::File { path } => {
+                if image_mime_type(path).is_some() {
+                    match Self::load_image(path) {
+                        Ok(image) => return image.inline(),
+                        Err(e) => {
+                            warn!("Failed to attach {}: {}", path.display(), e);
+                            return InlinePart::Text(format!("[Could not attach {}: {}]", path.display(), e));
+                        }
+                    }
+                }
+                match std::fs::read_to_string(path) {
+                    Ok(contents) => InlinePart::Text(format!("File: {}\n```\n{}\n```", path.display(), contents)),
+                    Err(e) => {
+                        warn!("Failed to read {}: {}", path.display(), e);
+                        InlinePart::Text(format!("[Could not read {}: {}]", path.display(), e))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl InlinePart {
+    /// The part as an OpenAI-style chat completion content item
+    pub fn to_openai(&self) -> serde_json::Value {
+        match self {
+            Self::Text(text) => serde_json::json!({ "type": "text", "text": text }),
+            Self::Image { mime_type, data } => serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:{};base64,{}", mime_type, data) }
+            }),
+        }
+    }
+}
+
+impl Message {
+    /// Text content followed by the resolved additional parts
+    pub fn inline_parts(&self) -> Vec<InlinePart> {
+        let mut parts = Vec::with_capacity(self.parts.len() + 1);
+        if !self.content.is_empty() {
+            parts.push(InlinePart::Text(self.content.clone()));
+        }
+        parts.extend(self.parts.iter().map(ContentPart::inline));
+        parts
+    }
+
+    /// Content in the OpenAI chat format: a string, or an array of parts when the message has any
+    pub fn openai_content(&self) -> serde_json::Value {
+        if self.parts.is_empty() {
+            return self.content.clone().into();
+        }
+        self.inline_parts().iter().map(InlinePart::to_openai).collect()
+    }
+}
+
+/// Messages in the OpenAI chat format, with content parts expanded into content arrays
+pub fn openai_messages(messages: &[Message]) -> Result<Vec<serde_json::Value>, LlmError> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut wire = serde_json::to_value(message)?;
+            if let Some(object) = wire.as_object_mut() {
+                if object.remove("parts").is_some() {
+                    object.insert("content".to_string(), message.openai_content());
+                }
+            }
+            Ok(wire)
+        })
+        .collect()
+}
+
+/// Image MIME type for a path, judged by its extension
+pub fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Load every `@path` image reference in user input as an attachment
+///
+/// Paths are relative to `working_dir`, and references that resolve outside
+/// it are rejected. References to other file types are left to the planner,
+/// which reads them with its own tools.
+pub fn image_attachments(input: &str, working_dir: &Path) -> crate::Result<Vec<(PathBuf, ContentPart)>> {
+    let root = working_dir.canonicalize().map_err(|e| KaiError::file_system(working_dir, e))?;
+    input
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|path| Path::new(path.trim_end_matches([',', '.', ';', ':', '!', '?', ')'])))
+        .filter(|path| image_mime_type(path).is_some())
+        .map(|path| {
+            let joined = root.join(path);
+            // Canonicalize to resolve any .. components and symlinks
+            let full_path = joined.canonicalize().map_err(|e| KaiError::file_system(&joined, e))?;
+            if !full_path.starts_with(&root) {
+                return Err(KaiError::security(format!(
+                    "Attachment '{}' is outside the working directory '{}'",
+                    path.display(),
+                    root.display()
+                )));
+            }
+            ContentPart::load_image(&full_path).map(|part| (path.to_path_buf(), part))
+        })
+        .collect()
+}
+
+/// Generate a plan from a prompt with content parts attached to the request
+///
+/// Uses the shared plan generation template and the schema repair loop, so it
+/// works with any provider whose `generate` accepts multimodal messages.
+pub async fn generate_plan_with_attachments(
+    provider: &dyn LlmProvider,
+    prompt: &str,
+    context: &str,
+    attachments: &[ContentPart],
+    model: &str,
+) -> Result<Plan, LlmError> {
+    let mut messages = crate::utils::templates::builders::PlanGenerationMessageBuilder::create(prompt, context)?;
+    if let Some(message) = messages.iter_mut().rev().find(|m| matches!(m.role, MessageRole::User)) {
+        message.parts.extend(attachments.iter().cloned());
+    }
+
+    let config = GenerationConfig::structured(ResponseFormat::plan());
+    schema::generate_validated(provider, messages, model, &config, schema::parse_plan).await
+}
+
+/// Standard base64 (RFC 4648, padded) for image data
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn encode(bytes: &[u8]) -> String {
+        STANDARD.encode(bytes)
+    }
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_image_part_round_trips_as_base64() {
+        assert_eq!(base64_bytes::encode(b"Man"), "TWFu");
+        assert_eq!(base64_bytes::encode(b"Ma"), "TWE=");
+        assert_eq!(base64_bytes::encode(b"M"), "TQ==");
+
+        let part = ContentPart::image("image/png", vec![0x89, b'P', b'N', b'G', 0, 255]);
+        let json = serde_json::to_value(&part).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "image", "mime_type": "image/png", "data": "iVBORwD/" }));
+        assert_eq!(serde_json::from_value::<ContentPart>(json).unwrap(), part);
+    }
+
+    #[test]
+    fn test_openai_content_lists_text_and_image_parts() {
+        let mut message = Message {
+            role: MessageRole::User,
+            content: "Why is this button misaligned?".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        };
+        assert_eq!(message.openai_content(), "Why is this button misaligned?");
+
+        message.parts.push(ContentPart::image("image/jpeg", b"Man".to_vec()));
+        let content = message.openai_content();
+        assert_eq!(content[0], serde_json::json!({ "type": "text", "text": "Why is this button misaligned?" }));
+        assert_eq!(content[1]["image_url"]["url"], "data:image/jpeg;base64,TWFu");
+    }
+
+    #[test]
+    fn test_image_attachments_load_referenced_images() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/ui.PNG"), b"png").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+        let attachments = image_attachments("fix @docs/ui.PNG, see @main.rs", dir.path()).unwrap();
+        assert_eq!(attachments, vec![(PathBuf::from("docs/ui.PNG"), ContentPart::image("image/png", b"png".to_vec()))]);
+
+        assert!(image_attachments("@missing.png", dir.path()).is_err());
+
+        // References may not leave the working directory
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.png"), b"png").unwrap();
+        let absolute = format!("@{}", outside.path().join("secret.png").display());
+        assert!(image_attachments(&absolute, dir.path()).is_err());
+        let relative = format!("@../{}/secret.png", outside.path().file_name().unwrap().to_string_lossy());
+        assert!(std::fs::metadata(dir.path().join(&relative[1..])).is_ok());
+        assert!(image_attachments(&relative, dir.path()).is_err());
+        assert_eq!(
+            ContentPart::file(dir.path().join("main.rs")).inline(),
+            InlinePart::Text(format!("File: {}\n```\nfn main() {{}}\n```", dir.path().join("main.rs").display()))
+        );
+    }
+}
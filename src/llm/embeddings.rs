@@ -0,0 +1,144 @@
+//! Text embeddings
+//!
+//! Providers that can embed text implement `EmbeddingLlmProvider` and expose it
+//! through `LlmProvider::as_embedding`, the same way streaming is offered.
+//! Servers cap how many texts one request may carry, so providers split their
+//! input with `embed_in_batches` and return one vector per text, in order.
+
+use super::{LlmError, TokenUsage};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// Embedding vectors for a list of texts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    /// One vector per input text, in input order
+    pub embeddings: Vec<Vec<f32>>,
+    /// Tokens consumed, when the provider reports them
+    pub usage: Option<TokenUsage>,
+}
+
+/// Trait for LLM providers that can embed text
+#[async_trait]
+pub trait EmbeddingLlmProvider: Send + Sync {
+    /// Embed `texts` with an embedding `model`
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingResponse, LlmError>;
+}
+
+/// Embed `texts` in requests of at most `batch_size` texts and join the results
+///
+/// Usage is only reported when every batch reported it, so callers can fall
+/// back to estimating it instead of under-counting.
+pub async fn embed_in_batches<'a, F, Fut>(
+    texts: &'a [String],
+    batch_size: usize,
+    mut embed_batch: F,
+) -> Result<EmbeddingResponse, LlmError>
+where
+    F: FnMut(&'a [String]) -> Fut,
+    Fut: Future<Output = Result<EmbeddingResponse, LlmError>>,
+{
+    let mut embeddings = Vec::with_capacity(texts.len());
+    let mut usage = Some(TokenUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        total_tokens: 0,
+    });
+
+    for batch in texts.chunks(batch_size.max(1)) {
+        let response = embed_batch(batch).await?;
+        if response.embeddings.len() != batch.len() {
+            return Err(LlmError::InvalidResponse {
+                message: format!(
+                    "Expected {} embeddings but received {}",
+                    batch.len(),
+                    response.embeddings.len()
+                ),
+            });
+        }
+        embeddings.extend(response.embeddings);
+        usage = match (usage, response.usage) {
+            (Some(total), Some(batch_usage)) => Some(TokenUsage {
+                prompt_tokens: total.prompt_tokens + batch_usage.prompt_tokens,
+                completion_tokens: total.completion_tokens + batch_usage.completion_tokens,
+                total_tokens: total.total_tokens + batch_usage.total_tokens,
+            }),
+            _ => None,
+        };
+    }
+
+    Ok(EmbeddingResponse {
+        embeddings,
+        usage: usage.filter(|_| !texts.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn texts(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("text {}", i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batches_keep_input_order_and_sum_usage() {
+        let input = texts(5);
+        let batches = Mutex::new(Vec::new());
+
+        let response = embed_in_batches(&input, 2, |batch| {
+            batches.lock().unwrap().push(batch.len());
+            let embeddings = batch
+                .iter()
+                .map(|text| vec![text.trim_start_matches("text ").parse::<f32>().unwrap()])
+                .collect();
+            async move {
+                Ok(EmbeddingResponse {
+                    embeddings,
+                    usage: Some(TokenUsage {
+                        prompt_tokens: 3,
+                        completion_tokens: 0,
+                        total_tokens: 3,
+                    }),
+                })
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(*batches.lock().unwrap(), vec![2, 2, 1]);
+        assert_eq!(response.embeddings, vec![vec![0.0], vec![1.0], vec![2.0], vec![3.0], vec![4.0]]);
+        assert_eq!(response.usage.unwrap().prompt_tokens, 9);
+    }
+
+    #[tokio::test]
+    async fn test_missing_vectors_and_usage_are_reported() {
+        let input = texts(3);
+
+        let short = embed_in_batches(&input, 10, |_| async {
+            Ok(EmbeddingResponse {
+                embeddings: vec![vec![0.5]],
+                usage: None,
+            })
+        })
+        .await;
+        assert!(matches!(short, Err(LlmError::InvalidResponse { .. })));
+
+        let mut first = true;
+        let partial = embed_in_batches(&input, 2, |batch| {
+            let usage = std::mem::take(&mut first).then_some(TokenUsage {
+                prompt_tokens: 2,
+                completion_tokens: 0,
+                total_tokens: 2,
+            });
+            let embeddings = vec![vec![0.5]; batch.len()];
+            async move { Ok(EmbeddingResponse { embeddings, usage }) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(partial.embeddings.len(), 3);
+        assert!(partial.usage.is_none());
+    }
+}
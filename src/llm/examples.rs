@@ -32,6 +32,7 @@ mod examples {
             content: "Hello, how are you?".to_string(),
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         }];
         
         // Generate response (commented out to avoid actual API call)
@@ -115,6 +116,7 @@ mod examples {
             completion_cost: Some(0.075),
             total_cost: Some(0.125),
             currency: Some("USD".to_string()),
+            price_source: None,
         };
         
         tracker.record_usage("gpt-3.5-turbo", &cost_breakdown);
@@ -253,12 +255,14 @@ pub mod workflows {
                     content: system_message,
                     tool_calls: None,
                     tool_call_id: None,
+                    parts: Vec::new(),
                 },
                 Message {
                     role: MessageRole::User,
                     content: user_message,
                     tool_calls: None,
                     tool_call_id: None,
+                    parts: Vec::new(),
                 },
             ];
             
@@ -0,0 +1,489 @@
+//! Fallback chains across models and providers
+//!
+//! `FallbackProvider` tries a list of backends in order: the primary provider
+//! with the caller's model, then alternative models or providers. A backend is
+//! passed over when it fails with an error `is_retryable_error` considers
+//! transient (rate limits, network and 5xx failures) or with `InvalidModel`;
+//! any other error is returned as-is. The backend that answered is recorded in
+//! `LlmResponse::backend`, and for every call, including plans, content,
+//! refinements and streams, it is reported to [`answered_by`].
+
+use super::embeddings::EmbeddingLlmProvider;
+use super::streaming::{LlmStream, StreamingLlmProvider};
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, ResponseBackend,
+    TaskAnalysis, TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use crate::utils::http::is_retryable_error;
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+tokio::task_local! {
+    static ANSWERED_BY: Arc<Mutex<Option<ResponseBackend>>>;
+}
+
+/// Run a request and return the fallback backend that answered it
+///
+/// Returns `None` alongside the result when the request did not go through
+/// a `FallbackProvider`, or when no backend answered.
+pub async fn answered_by<T>(request: impl Future<Output = T>) -> (T, Option<ResponseBackend>) {
+    let slot = Arc::new(Mutex::new(None));
+    let result = ANSWERED_BY.scope(slot.clone(), request).await;
+    let backend = slot.lock().unwrap_or_else(|e| e.into_inner()).take();
+    (result, backend)
+}
+
+/// Check whether an error should move the request on to the next backend
+pub fn should_fall_back(error: &LlmError) -> bool {
+    is_retryable_error(error) || matches!(error, LlmError::InvalidModel { .. })
+}
+
+/// Split a fallback chain entry of the form `provider` or `provider:model`
+///
+/// Only the first colon separates the two, so model tags such as
+/// `ollama:llama3.2:latest` are kept intact.
+pub fn parse_fallback_entry(entry: &str) -> (String, Option<String>) {
+    match entry.trim().split_once(':') {
+        Some((provider, model)) if !model.trim().is_empty() => {
+            (provider.trim().to_string(), Some(model.trim().to_string()))
+        }
+        Some((provider, _)) => (provider.trim().to_string(), None),
+        None => (entry.trim().to_string(), None),
+    }
+}
+
+/// One link in a fallback chain
+struct Backend {
+    provider: Box<dyn LlmProvider>,
+    /// Model to use instead of the caller's; `None` keeps the requested model
+    model: Option<String>,
+}
+
+/// Composite provider that falls back to other models and providers on failure
+pub struct FallbackProvider {
+    backends: Vec<Backend>,
+    /// Backend and model pairs where the model does not exist; skipped for the rest of the session
+    ///
+    /// Keyed by model as well, so a mistyped model only retires that model and
+    /// the backend keeps serving every other one.
+    unavailable: Mutex<HashSet<(usize, String)>>,
+    last_backend: Mutex<Option<ResponseBackend>>,
+}
+
+/// Run a call against each available backend in turn
+///
+/// Binds `$provider` and `$model` for every backend and evaluates to
+/// `Result<(ResponseBackend, T), LlmError>`.
+macro_rules! with_fallback {
+    ($self:ident, $operation:literal, $requested_model:expr, |$provider:ident, $model:ident| $call:expr) => {{
+        let mut outcome = None;
+        let mut last_error = None;
+        for (index, backend) in $self.backends.iter().enumerate() {
+            let $provider: &dyn LlmProvider = backend.provider.as_ref();
+            let $model: &str = backend.model.as_deref().unwrap_or($requested_model);
+            if $self.is_unavailable(index, $model) {
+                continue;
+            }
+            match $call.await {
+                Ok(value) => {
+                    outcome = Some(Ok(($self.answered(index, $provider, $model), value)));
+                    break;
+                }
+                Err(error) if should_fall_back(&error) => {
+                    $self.failed(index, $operation, $provider, $model, &error);
+                    last_error = Some(error);
+                }
+                Err(error) => {
+                    outcome = Some(Err(error));
+                    break;
+                }
+            }
+        }
+        outcome.unwrap_or_else(|| {
+            Err(last_error.unwrap_or_else(|| LlmError::Unknown {
+                message: "No backend in the fallback chain is available".to_string(),
+            }))
+        })
+    }};
+}
+
+impl FallbackProvider {
+    /// Start a chain with the primary provider, which uses the caller's model
+    pub fn new(primary: Box<dyn LlmProvider>) -> Self {
+        Self {
+            backends: vec![Backend {
+                provider: primary,
+                model: None,
+            }],
+            unavailable: Mutex::new(HashSet::new()),
+            last_backend: Mutex::new(None),
+        }
+    }
+
+    /// Append a backend, optionally pinned to its own model
+    pub fn with_fallback(mut self, provider: Box<dyn LlmProvider>, model: Option<String>) -> Self {
+        self.backends.push(Backend { provider, model });
+        self
+    }
+
+    /// Number of backends in the chain, including the primary
+    pub fn len(&self) -> usize {
+        self.backends.len()
+    }
+
+    /// Whether the chain has no backends (never true for a constructed chain)
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+
+    /// Backend that answered the most recent successful call
+    pub fn last_backend(&self) -> Option<ResponseBackend> {
+        self.last_backend.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn is_unavailable(&self, index: usize, model: &str) -> bool {
+        self.unavailable
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&(index, model.to_string()))
+    }
+
+    /// Record the backend that answered
+    fn answered(&self, index: usize, provider: &dyn LlmProvider, model: &str) -> ResponseBackend {
+        let backend = ResponseBackend {
+            provider: provider.provider_name().to_string(),
+            model: model.to_string(),
+        };
+        if index > 0 {
+            info!("Fallback backend {}/{} answered", backend.provider, backend.model);
+        }
+        *self.last_backend.lock().unwrap_or_else(|e| e.into_inner()) = Some(backend.clone());
+        let _ = ANSWERED_BY.try_with(|slot| {
+            *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(backend.clone());
+        });
+        backend
+    }
+
+    /// Log a failure that moves the request on, retiring unknown models on that backend
+    fn failed(&self, index: usize, operation: &str, provider: &dyn LlmProvider, model: &str, error: &LlmError) {
+        let next = if index + 1 < self.backends.len() { "trying next backend" } else { "no backends left" };
+        warn!("{} via {}/{} failed: {} ({})", operation, provider.provider_name(), model, error, next);
+        if matches!(error, LlmError::InvalidModel { .. }) {
+            self.unavailable
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert((index, model.to_string()));
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackProvider {
+    fn provider_name(&self) -> &str {
+        self.backends[0].provider.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        with_fallback!(self, "list_models", "", |provider, _model| provider.list_models())
+            .map(|(_, models)| models)
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let (backend, mut response) = with_fallback!(self, "generate", model, |provider, model| {
+            provider.generate(messages, model, tools, config)
+        })?;
+        response.backend = Some(backend);
+        Ok(response)
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        with_fallback!(self, "generate_plan", model, |provider, model| {
+            provider.generate_plan(prompt, context, model)
+        })
+        .map(|(_, plan)| plan)
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        with_fallback!(self, "generate_content", model, |provider, model| {
+            provider.generate_content(prompt, context, model, config)
+        })
+        .map(|(_, content)| content)
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        with_fallback!(self, "refine_task_for_execution", model, |provider, model| {
+            provider.refine_task_for_execution(task, context, model)
+        })
+        .map(|(_, instruction)| instruction)
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let (backend, mut analysis) = with_fallback!(self, "analyze_task_result", model, |provider, model| {
+            provider.analyze_task_result(task, execution_result, expected_outcome, model)
+        })?;
+        analysis.metadata.insert("backend".to_string(), serde_json::to_value(&backend)?);
+        Ok(analysis)
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        with_fallback!(self, "validate_model", model, |provider, model| provider.validate_model(model))
+            .map(|(_, info)| info)
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        // Only stream when every backend can, so a fallback never loses the stream
+        if self.backends.iter().all(|backend| backend.provider.as_streaming().is_some()) {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    /// Vectors from different models cannot be compared, so embeddings never fall back
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.backends.first()?.provider.as_embedding()
+    }
+}
+
+/// Streams fall back only while being established; errors mid-stream are returned to the caller
+#[async_trait]
+impl StreamingLlmProvider for FallbackProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        with_fallback!(self, "generate_stream", model, |provider, model| async move {
+            match provider.as_streaming() {
+                Some(streaming) => streaming.generate_stream(messages, model, tools, config).await,
+                None => Err(LlmError::Unknown {
+                    message: format!("{} does not support streaming", provider.provider_name()),
+                }),
+            }
+        })
+        .map(|(_, stream)| stream)
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        with_fallback!(self, "generate_plan_stream", model, |provider, model| async move {
+            match provider.as_streaming() {
+                Some(streaming) => streaming.generate_plan_stream(prompt, context, model).await,
+                None => Err(LlmError::Unknown {
+                    message: format!("{} does not support streaming", provider.provider_name()),
+                }),
+            }
+        })
+        .map(|(_, stream)| stream)
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        with_fallback!(self, "refine_task_stream", model, |provider, model| async move {
+            match provider.as_streaming() {
+                Some(streaming) => streaming.refine_task_stream(task, context, model).await,
+                None => Err(LlmError::Unknown {
+                    message: format!("{} does not support streaming", provider.provider_name()),
+                }),
+            }
+        })
+        .map(|(_, stream)| stream)
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        with_fallback!(self, "analyze_task_result_stream", model, |provider, model| async move {
+            match provider.as_streaming() {
+                Some(streaming) => {
+                    streaming
+                        .analyze_task_result_stream(task, execution_result, expected_outcome, model)
+                        .await
+                }
+                None => Err(LlmError::Unknown {
+                    message: format!("{} does not support streaming", provider.provider_name()),
+                }),
+            }
+        })
+        .map(|(_, stream)| stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+    use crate::llm::openai_compatible::OpenAiCompatibleProvider;
+    use std::collections::HashMap;
+
+    fn provider(server_url: String) -> Box<dyn LlmProvider> {
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), server_url);
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        Box::new(OpenAiCompatibleProvider::from_settings(&settings).unwrap())
+    }
+
+    fn completion(content: &str) -> String {
+        serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "content": content}, "finish_reason": "stop"}]
+        })
+        .to_string()
+    }
+
+    fn user_message() -> Vec<Message> {
+        vec![Message {
+            role: crate::llm::MessageRole::User,
+            content: "hello".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }]
+    }
+
+    #[test]
+    fn test_parse_fallback_entry() {
+        assert_eq!(parse_fallback_entry("gemini"), ("gemini".to_string(), None));
+        assert_eq!(
+            parse_fallback_entry(" openrouter:anthropic/claude-3.5-sonnet "),
+            ("openrouter".to_string(), Some("anthropic/claude-3.5-sonnet".to_string()))
+        );
+        assert_eq!(
+            parse_fallback_entry("ollama:llama3.2:latest"),
+            ("ollama".to_string(), Some("llama3.2:latest".to_string()))
+        );
+        assert!(should_fall_back(&LlmError::RateLimit { retry_after: None }));
+        assert!(should_fall_back(&LlmError::InvalidModel { model: "m".to_string() }));
+        assert!(!should_fall_back(&LlmError::Authentication { message: "bad key".to_string() }));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_rate_limit_and_records_backend() {
+        let (primary_url, primary_requests) = mock_server::spawn(vec![
+            (429, r#"{"error": {"message": "rate limited"}}"#.to_string()),
+            (200, completion("primary is back")),
+        ])
+        .await;
+        let (secondary_url, secondary_requests) = mock_server::spawn(vec![(200, completion("from secondary"))]).await;
+        let chain = FallbackProvider::new(provider(primary_url))
+            .with_fallback(provider(secondary_url), Some("backup-model".to_string()));
+
+        let response = chain.generate(&user_message(), "main-model", None, None).await.unwrap();
+        assert_eq!(response.content.as_deref(), Some("from secondary"));
+        assert_eq!(
+            response.backend,
+            Some(ResponseBackend {
+                provider: "openai_compatible".to_string(),
+                model: "backup-model".to_string()
+            })
+        );
+        assert_eq!(secondary_requests.lock().unwrap()[0].json()["model"], "backup-model");
+
+        // Rate limits are transient, so the primary is tried again next time
+        let response = chain.generate(&user_message(), "main-model", None, None).await.unwrap();
+        assert_eq!(response.backend.unwrap().model, "main-model");
+        assert_eq!(primary_requests.lock().unwrap().len(), 2);
+        assert_eq!(chain.last_backend().unwrap().model, "main-model");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_only_retires_that_model() {
+        let unknown_model = r#"{"error": {"message": "The model `typo` does not exist"}}"#.to_string();
+        let (primary_url, primary_requests) = mock_server::spawn(vec![
+            (400, unknown_model),
+            (200, completion("primary with a real model")),
+        ])
+        .await;
+        let (secondary_url, _) = mock_server::spawn(vec![(200, completion("from secondary"))]).await;
+        let chain = FallbackProvider::new(provider(primary_url))
+            .with_fallback(provider(secondary_url), Some("backup-model".to_string()));
+
+        let response = chain.generate(&user_message(), "typo", None, None).await.unwrap();
+        assert_eq!(response.backend.unwrap().model, "backup-model");
+
+        // The primary still serves models that exist
+        let response = chain.generate(&user_message(), "main-model", None, None).await.unwrap();
+        assert_eq!(response.content.as_deref(), Some("primary with a real model"));
+        assert_eq!(primary_requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chain_members_leave_failures_to_the_chain() {
+        let (primary_url, primary_requests) = mock_server::spawn(vec![
+            (503, r#"{"error": {"message": "overloaded"}}"#.to_string()),
+            (200, completion("a retry")),
+        ])
+        .await;
+        let (secondary_url, _) = mock_server::spawn(vec![(200, completion("from secondary"))]).await;
+        // Default HTTP retry settings, as a configured chain member would have
+        let member = |url: String| {
+            crate::llm::LlmProviderFactory::create_rate_limited(
+                "openai_compatible",
+                HashMap::from([("base_url".to_string(), url)]),
+                true,
+            )
+            .unwrap()
+        };
+        let chain = FallbackProvider::new(member(primary_url)).with_fallback(member(secondary_url), None);
+
+        let response = chain.generate(&user_message(), "main-model", None, None).await.unwrap();
+        assert_eq!(response.content.as_deref(), Some("from secondary"));
+        assert_eq!(primary_requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_non_fallback_errors_are_returned() {
+        let (primary_url, _) = mock_server::spawn(vec![(401, r#"{"error": {"message": "bad key"}}"#.to_string())]).await;
+        let (secondary_url, secondary_requests) = mock_server::spawn(vec![(200, completion("unused"))]).await;
+        let chain = FallbackProvider::new(provider(primary_url)).with_fallback(provider(secondary_url), None);
+
+        let error = chain.generate(&user_message(), "main-model", None, None).await.unwrap_err();
+        assert!(matches!(error, LlmError::Authentication { .. }));
+        assert!(secondary_requests.lock().unwrap().is_empty());
+        assert!(chain.last_backend().is_none());
+    }
+}
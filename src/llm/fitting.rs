@@ -0,0 +1,900 @@
+//! Fitting requests into the model's context window
+//!
+//! `ContextFitter` shrinks the lowest-priority parts of a request until it
+//! fits a token budget: older conversation turns first, then file context,
+//! then outputs of earlier tasks. `FittingProvider` wraps any provider, fits
+//! each request to the context length reported in `ModelInfo` before sending
+//! it, and retries once with a smaller budget when the provider still rejects
+//! the request as too long.
+
+use super::embeddings::EmbeddingLlmProvider;
+use super::registry::ModelRegistry;
+use super::streaming::{LlmStream, StreamingLlmProvider};
+use super::tokenizer::{Tokenizer, TokenizerRegistry};
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, MessageRole, ModelInfo, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use crate::utils::templates::builders::{
+    ExecutionAnalysisMessageBuilder, PlanGenerationMessageBuilder, TaskRefinementMessageBuilder,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+/// Tokens kept free for the response when neither the request nor the model sets a limit
+pub const DEFAULT_OUTPUT_RESERVE: usize = 4096;
+
+/// Share of the context window left unused to absorb token counting error, in percent
+const SAFETY_MARGIN_PERCENT: usize = 5;
+
+/// Older conversation turns are cut to this many tokens
+const OLD_TURN_TOKENS: usize = 200;
+
+/// Size of the retry request relative to the one that was rejected, in percent
+const RETRY_SHRINK_PERCENT: usize = 75;
+
+/// Tokens counted per message for its role and formatting
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Shrink passes before giving up; later passes absorb token counting rounding
+const FIT_PASSES: usize = 3;
+
+/// Parts of a request that may be shrunk, in the order they are shrunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Priority {
+    OldTurns,
+    FileContext,
+    DependencyOutputs,
+}
+
+const TRIM_ORDER: [Priority; 3] = [Priority::OldTurns, Priority::FileContext, Priority::DependencyOutputs];
+
+/// Which end of a shortened text is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keep {
+    Head,
+    /// The newest lines, for conversation history
+    Tail,
+    /// Both ends, for command output where errors tend to come last
+    Ends,
+}
+
+/// A piece of request text, shrinkable when it has a priority
+#[derive(Debug, Clone)]
+struct Segment {
+    text: String,
+    priority: Option<Priority>,
+    keep: Keep,
+}
+
+impl Segment {
+    fn new(text: impl Into<String>, priority: Option<Priority>, keep: Keep) -> Self {
+        Self {
+            text: text.into(),
+            priority,
+            keep,
+        }
+    }
+}
+
+/// Shrinks requests to a token budget
+#[derive(Clone)]
+pub struct ContextFitter {
+    tokenizer: Arc<dyn Tokenizer>,
+    budget: usize,
+    output_reserve: usize,
+    retried: bool,
+}
+
+impl ContextFitter {
+    /// Fitter for a fixed input budget
+    pub fn new(tokenizer: Arc<dyn Tokenizer>, budget: usize) -> Self {
+        Self {
+            tokenizer,
+            budget,
+            output_reserve: DEFAULT_OUTPUT_RESERVE,
+            retried: false,
+        }
+    }
+
+    /// Fitter for a context window, keeping `output_reserve` tokens free for the response
+    pub fn for_window(tokenizer: Arc<dyn Tokenizer>, context_length: usize, output_reserve: usize) -> Self {
+        let output_reserve = output_reserve.min(context_length / 2);
+        Self {
+            output_reserve,
+            ..Self::new(tokenizer, input_budget(context_length, output_reserve))
+        }
+    }
+
+    /// Fitter that leaves requests unchanged, for models with an unknown window
+    pub fn unlimited(tokenizer: Arc<dyn Tokenizer>) -> Self {
+        Self::new(tokenizer, usize::MAX)
+    }
+
+    /// Input tokens a request may use
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Tokens in a piece of text
+    pub fn count(&self, text: &str) -> usize {
+        self.tokenizer.count_tokens(text)
+    }
+
+    /// Tokens a list of messages takes up, including tool calls
+    pub fn count_messages(&self, messages: &[Message]) -> usize {
+        messages
+            .iter()
+            .map(|message| {
+                let tool_calls = message
+                    .tool_calls
+                    .as_ref()
+                    .map(|calls| self.count(&serde_json::to_string(calls).unwrap_or_default()))
+                    .unwrap_or(0);
+                self.count(&message.content) + tool_calls + MESSAGE_OVERHEAD_TOKENS
+            })
+            .sum()
+    }
+
+    /// Smaller fitter for the one retry after the provider reported an overflow
+    ///
+    /// Returns `None` for any other error, or when this fitter was already the retry.
+    pub fn retry_after(&self, error: &LlmError, sent_tokens: usize) -> Option<Self> {
+        let LlmError::ContextLengthExceeded { limit, .. } = error else {
+            return None;
+        };
+        if self.retried {
+            return None;
+        }
+
+        let shrunk = sent_tokens * RETRY_SHRINK_PERCENT / 100;
+        let stated = limit
+            .map(|limit| input_budget(limit as usize, self.output_reserve.min(limit as usize / 2)))
+            .unwrap_or(usize::MAX);
+        Some(Self {
+            budget: shrunk.min(stated),
+            retried: true,
+            ..self.clone()
+        })
+    }
+
+    /// Fit a message list to the budget
+    ///
+    /// Older turns are cut to their opening lines first, oldest first. If that
+    /// is not enough, conversation, context and dependency output sections of
+    /// the remaining messages are shrunk, and as a last resort the largest
+    /// message is cut in the middle. System messages, the latest user message
+    /// and the last two messages are never treated as old turns.
+    pub fn fit_messages(&self, messages: &[Message]) -> Result<Vec<Message>, LlmError> {
+        let mut fitted = messages.to_vec();
+        let mut total = self.count_messages(&fitted);
+        if total <= self.budget {
+            return Ok(fitted);
+        }
+
+        let last_user = fitted.iter().rposition(|m| matches!(m.role, MessageRole::User));
+        let recent_start = fitted.len().saturating_sub(2);
+        for index in 0..recent_start {
+            if total <= self.budget {
+                break;
+            }
+            if matches!(fitted[index].role, MessageRole::System) || Some(index) == last_user {
+                continue;
+            }
+            fitted[index].content = shorten(self.tokenizer.as_ref(), &fitted[index].content, OLD_TURN_TOKENS, Keep::Head);
+            total = self.count_messages(&fitted);
+        }
+
+        if total > self.budget {
+            let mut sections: Vec<Vec<Segment>> = fitted
+                .iter()
+                .map(|message| split_sections(&message.content, None))
+                .collect();
+            let mut all: Vec<&mut Segment> = sections.iter_mut().flatten().collect();
+            self.shrink(&mut all, total - self.budget);
+            for (message, segments) in fitted.iter_mut().zip(&sections) {
+                message.content = join(segments);
+            }
+            total = self.count_messages(&fitted);
+        }
+
+        if total > self.budget {
+            if let Some(largest) = fitted.iter_mut().max_by_key(|m| m.content.len()) {
+                let tokens = self.tokenizer.count_tokens(&largest.content);
+                let target = tokens.saturating_sub(total - self.budget);
+                largest.content = shorten(self.tokenizer.as_ref(), &largest.content, target, Keep::Ends);
+            }
+            total = self.count_messages(&fitted);
+        }
+
+        if total > self.budget {
+            return Err(self.overflow(total));
+        }
+        Ok(fitted)
+    }
+
+    /// Fit the free-form context of a templated request
+    ///
+    /// `render` builds the messages the provider sends for a given context.
+    /// Conversation sections in the context are shrunk first, keeping the
+    /// newest turns, then the rest of the context from its end. Returns the
+    /// context and the size of the rendered request.
+    pub fn fit_context<F>(&self, context: &str, render: F) -> Result<(String, usize), LlmError>
+    where
+        F: Fn(&str) -> Result<Vec<Message>, LlmError>,
+    {
+        let mut segments = split_sections(context, Some(Priority::FileContext));
+        for pass in 0..=FIT_PASSES {
+            let context = join(&segments);
+            let total = self.count_messages(&render(&context)?);
+            if total <= self.budget {
+                return Ok((context, total));
+            }
+            if pass == FIT_PASSES {
+                return Err(self.overflow(total));
+            }
+            self.shrink(&mut segments.iter_mut().collect::<Vec<_>>(), total - self.budget);
+        }
+        unreachable!("the last pass returns")
+    }
+
+    /// Fit the context of a task refinement request
+    ///
+    /// The global and plan context are shrunk before the outputs of
+    /// dependencies; a shortened output is replaced by its truncated JSON text.
+    pub fn fit_refinement(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+    ) -> Result<(TaskRefinementContext, usize), LlmError> {
+        let render = |context: &TaskRefinementContext| {
+            TaskRefinementMessageBuilder::create(
+                task,
+                &context.plan_description,
+                &context.global_context,
+                &context.plan_context,
+                &context.dependency_outputs,
+            )
+        };
+        let mut global = split_sections(&context.global_context, Some(Priority::FileContext));
+        let mut plan = Segment::new(context.plan_context.clone(), Some(Priority::FileContext), Keep::Head);
+        let mut outputs: Vec<(String, Segment)> = context
+            .dependency_outputs
+            .iter()
+            .map(|(task_id, output)| {
+                let text = serde_json::to_string_pretty(output).unwrap_or_default();
+                (task_id.clone(), Segment::new(text, Some(Priority::DependencyOutputs), Keep::Head))
+            })
+            .collect();
+        // Largest outputs are shrunk first
+        outputs.sort_by_key(|(_, segment)| std::cmp::Reverse(segment.text.len()));
+        let originals: Vec<String> = outputs.iter().map(|(_, segment)| segment.text.clone()).collect();
+
+        for pass in 0..=FIT_PASSES {
+            let mut fitted = context.clone();
+            fitted.global_context = join(&global);
+            fitted.plan_context = plan.text.clone();
+            for ((task_id, segment), original) in outputs.iter().zip(&originals) {
+                if segment.text != *original {
+                    fitted.dependency_outputs.insert(task_id.clone(), serde_json::Value::String(segment.text.clone()));
+                }
+            }
+
+            let total = self.count_messages(&render(&fitted)?);
+            if total <= self.budget {
+                return Ok((fitted, total));
+            }
+            if pass == FIT_PASSES {
+                return Err(self.overflow(total));
+            }
+
+            let mut all: Vec<&mut Segment> = global.iter_mut().collect();
+            all.push(&mut plan);
+            all.extend(outputs.iter_mut().map(|(_, segment)| segment));
+            self.shrink(&mut all, total - self.budget);
+        }
+        unreachable!("the last pass returns")
+    }
+
+    /// Fit an execution result for analysis, shortening its output
+    ///
+    /// Standard output and raw output data are cut in the middle, since errors
+    /// and summaries tend to come last; standard error is shortened last.
+    pub fn fit_execution_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+    ) -> Result<(TaskExecutionResult, usize), LlmError> {
+        let render = |result: &TaskExecutionResult| ExecutionAnalysisMessageBuilder::create(task, result, expected_outcome);
+        let output_text = execution_result
+            .output
+            .as_ref()
+            .map(|output| serde_json::to_string_pretty(output).unwrap_or_default());
+        let mut stdout = Segment::new(execution_result.stdout.clone().unwrap_or_default(), Some(Priority::DependencyOutputs), Keep::Ends);
+        let mut output = Segment::new(output_text.clone().unwrap_or_default(), Some(Priority::DependencyOutputs), Keep::Ends);
+        let mut stderr = Segment::new(execution_result.stderr.clone().unwrap_or_default(), Some(Priority::DependencyOutputs), Keep::Ends);
+
+        for pass in 0..=FIT_PASSES {
+            let mut fitted = execution_result.clone();
+            if fitted.stdout.is_some() {
+                fitted.stdout = Some(stdout.text.clone());
+            }
+            if fitted.stderr.is_some() {
+                fitted.stderr = Some(stderr.text.clone());
+            }
+            if output_text.as_ref().is_some_and(|original| *original != output.text) {
+                fitted.output = Some(serde_json::Value::String(output.text.clone()));
+            }
+
+            let total = self.count_messages(&render(&fitted)?);
+            if total <= self.budget {
+                return Ok((fitted, total));
+            }
+            if pass == FIT_PASSES {
+                return Err(self.overflow(total));
+            }
+            self.shrink(&mut [&mut stdout, &mut output, &mut stderr], total - self.budget);
+        }
+        unreachable!("the last pass returns")
+    }
+
+    /// Shrink segments in priority order until `excess` tokens are saved
+    fn shrink(&self, segments: &mut [&mut Segment], mut excess: usize) {
+        for priority in TRIM_ORDER {
+            for segment in segments.iter_mut().filter(|s| s.priority == Some(priority)) {
+                if excess == 0 {
+                    return;
+                }
+                let tokens = self.count(&segment.text);
+                let shortened = shorten(self.tokenizer.as_ref(), &segment.text, tokens.saturating_sub(excess), segment.keep);
+                excess = excess.saturating_sub(tokens.saturating_sub(self.count(&shortened)));
+                segment.text = shortened;
+            }
+        }
+    }
+
+    fn overflow(&self, tokens: usize) -> LlmError {
+        LlmError::ContextLengthExceeded {
+            message: format!(
+                "request needs {} input tokens after trimming, but only {} fit in the context window",
+                tokens, self.budget
+            ),
+            limit: None,
+        }
+    }
+}
+
+/// Input tokens available in a window once the response and safety margin are set aside
+fn input_budget(context_length: usize, output_reserve: usize) -> usize {
+    context_length
+        .saturating_sub(output_reserve)
+        .saturating_sub(context_length * SAFETY_MARGIN_PERCENT / 100)
+}
+
+/// Shrink priority of a section, judged by its `## ` heading
+fn section_priority(heading: &str) -> Option<Priority> {
+    let heading = heading.to_lowercase();
+    if heading.contains("conversation") {
+        Some(Priority::OldTurns)
+    } else if heading.contains("dependenc") {
+        Some(Priority::DependencyOutputs)
+    } else if heading.contains("context") || heading.contains("file content") {
+        Some(Priority::FileContext)
+    } else {
+        None
+    }
+}
+
+/// Split text at `## ` headings into heading and body segments
+///
+/// Text before the first heading gets the `leading` priority.
+fn split_sections(text: &str, leading: Option<Priority>) -> Vec<Segment> {
+    let keep = |priority: Option<Priority>| match priority {
+        Some(Priority::OldTurns) => Keep::Tail,
+        _ => Keep::Head,
+    };
+
+    let mut segments = Vec::new();
+    let mut priority = leading;
+    let mut body = String::new();
+    for line in text.split_inclusive('\n') {
+        if line.starts_with("## ") {
+            segments.push(Segment::new(std::mem::take(&mut body), priority, keep(priority)));
+            segments.push(Segment::new(line, None, Keep::Head));
+            priority = section_priority(line);
+        } else {
+            body.push_str(line);
+        }
+    }
+    segments.push(Segment::new(body, priority, keep(priority)));
+    segments
+}
+
+fn join(segments: &[Segment]) -> String {
+    segments.iter().map(|segment| segment.text.as_str()).collect()
+}
+
+/// Shorten text to at most `max_tokens`, marking where it was cut
+///
+/// Finds the longest cut that fits by binary search over the characters kept.
+fn shorten(tokenizer: &dyn Tokenizer, text: &str, max_tokens: usize, keep: Keep) -> String {
+    if tokenizer.count_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    let chars = boundaries.len() - 1;
+    let render = |kept: usize| {
+        let marker = format!("\n[... {} characters trimmed to fit the context window ...]\n", chars - kept);
+        match keep {
+            Keep::Head => format!("{}{}", &text[..boundaries[kept]], marker),
+            Keep::Tail => format!("{}{}", marker, &text[boundaries[chars - kept]..]),
+            Keep::Ends => {
+                let head = kept / 2;
+                format!("{}{}{}", &text[..boundaries[head]], marker, &text[boundaries[chars - (kept - head)]..])
+            }
+        }
+    };
+
+    let (mut low, mut high) = (0, chars);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if tokenizer.count_tokens(&render(mid)) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let shortened = render(low);
+    if tokenizer.count_tokens(&shortened) <= max_tokens {
+        shortened
+    } else {
+        String::new()
+    }
+}
+
+/// Provider wrapper that fits every request into the model's context window
+///
+/// Context lengths come from the provider's model list, read once from the
+/// model registry. Models missing from the list are sent unchanged, but still
+/// get the retry.
+pub struct FittingProvider {
+    inner: Box<dyn LlmProvider>,
+    registry: Option<Arc<ModelRegistry>>,
+    models: OnceCell<HashMap<String, ModelInfo>>,
+}
+
+impl FittingProvider {
+    /// Wrap a provider, reading its models from the shared model registry
+    pub fn new(inner: Box<dyn LlmProvider>) -> Self {
+        Self {
+            inner,
+            registry: ModelRegistry::global(),
+            models: OnceCell::new(),
+        }
+    }
+
+    /// Read models from `registry` instead of the shared model registry
+    pub fn with_model_registry(mut self, registry: Arc<ModelRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Fitter for a request to `model`
+    async fn fitter(&self, model: &str, config: Option<&GenerationConfig>) -> ContextFitter {
+        let models = self
+            .models
+            .get_or_init(|| async {
+                let listing = match &self.registry {
+                    Some(registry) => registry.catalog(self.inner.as_ref()).await.map(|catalog| catalog.models),
+                    None => self.inner.list_models().await,
+                };
+                match listing {
+                    Ok(models) => models.into_iter().map(|info| (info.id.clone(), info)).collect(),
+                    Err(e) => {
+                        debug!("No model list for context fitting: {}", e);
+                        HashMap::new()
+                    }
+                }
+            })
+            .await;
+
+        let info = models.get(model);
+        let tokenizer = match info {
+            Some(info) => TokenizerRegistry::global().for_model_info(info),
+            None => TokenizerRegistry::global().for_model(model),
+        };
+        match info.and_then(|info| info.context_length) {
+            Some(context_length) => {
+                let output_reserve = config
+                    .and_then(|config| config.max_tokens)
+                    .or_else(|| info.and_then(|info| info.max_output_tokens))
+                    .map(|tokens| tokens as usize)
+                    .unwrap_or(DEFAULT_OUTPUT_RESERVE);
+                ContextFitter::for_window(tokenizer, context_length as usize, output_reserve)
+            }
+            None => ContextFitter::unlimited(tokenizer),
+        }
+    }
+
+    /// Fit a request and send it, retrying once with a smaller budget on overflow
+    ///
+    /// `fit` returns the fitted request and its size in tokens.
+    async fn with_fitting<R, T, Fut>(
+        &self,
+        model: &str,
+        config: Option<&GenerationConfig>,
+        fit: impl Fn(&ContextFitter) -> Result<(R, usize), LlmError>,
+        send: impl Fn(R) -> Fut,
+    ) -> Result<T, LlmError>
+    where
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let mut fitter = self.fitter(model, config).await;
+        loop {
+            let (request, tokens) = fit(&fitter)?;
+            match send(request).await {
+                Err(error) => match fitter.retry_after(&error, tokens) {
+                    Some(smaller) => {
+                        warn!(
+                            "{} rejected a {}-token request as too long; retrying with at most {} tokens",
+                            model, tokens, smaller.budget()
+                        );
+                        fitter = smaller;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn inner_streaming(&self) -> Result<&dyn StreamingLlmProvider, LlmError> {
+        self.inner.as_streaming().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support streaming", self.inner.provider_name()),
+        })
+    }
+}
+
+/// Fitted messages and their size
+fn fit_messages(fitter: &ContextFitter, messages: &[Message]) -> Result<(Vec<Message>, usize), LlmError> {
+    let fitted = fitter.fit_messages(messages)?;
+    let tokens = fitter.count_messages(&fitted);
+    Ok((fitted, tokens))
+}
+
+fn fit_plan_context(fitter: &ContextFitter, prompt: &str, context: &str) -> Result<(String, usize), LlmError> {
+    fitter.fit_context(context, |context| PlanGenerationMessageBuilder::create(prompt, context))
+}
+
+fn fit_content_context(fitter: &ContextFitter, prompt: &str, context: &str) -> Result<(String, usize), LlmError> {
+    fitter.fit_context(context, |context| {
+        Ok(vec![Message {
+            role: MessageRole::User,
+            content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }])
+    })
+}
+
+#[async_trait]
+impl LlmProvider for FittingProvider {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        self.inner.list_models().await
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        self.with_fitting(
+            model,
+            config,
+            |fitter| fit_messages(fitter, messages),
+            |messages| async move { self.inner.generate(&messages, model, tools, config).await },
+        )
+        .await
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fit_plan_context(fitter, prompt, context),
+            |context| async move { self.inner.generate_plan(prompt, &context, model).await },
+        )
+        .await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        self.with_fitting(
+            model,
+            config,
+            |fitter| fit_content_context(fitter, prompt, context),
+            |context| async move { self.inner.generate_content(prompt, &context, model, config).await },
+        )
+        .await
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fitter.fit_refinement(task, context),
+            |context| async move { self.inner.refine_task_for_execution(task, &context, model).await },
+        )
+        .await
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fitter.fit_execution_result(task, execution_result, expected_outcome),
+            |result| async move {
+                self.inner
+                    .analyze_task_result(task, &result, expected_outcome, model)
+                    .await
+            },
+        )
+        .await
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        self.inner.validate_model(model).await
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        self.inner.as_streaming().map(|_| self as &dyn StreamingLlmProvider)
+    }
+
+    /// Embedding inputs are not fitted; callers chunk their texts themselves
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.inner.as_embedding()
+    }
+}
+
+/// Only the opening request is retried; an overflow reported mid-stream is passed on
+#[async_trait]
+impl StreamingLlmProvider for FittingProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        self.with_fitting(
+            model,
+            config,
+            |fitter| fit_messages(fitter, messages),
+            |messages| async move { inner.generate_stream(&messages, model, tools, config).await },
+        )
+        .await
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fit_plan_context(fitter, prompt, context),
+            |context| async move { inner.generate_plan_stream(prompt, &context, model).await },
+        )
+        .await
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fitter.fit_refinement(task, context),
+            |context| async move { inner.refine_task_stream(task, &context, model).await },
+        )
+        .await
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        self.with_fitting(
+            model,
+            None,
+            |fitter| fitter.fit_execution_result(task, execution_result, expected_outcome),
+            |result| async move {
+                inner
+                    .analyze_task_result_stream(task, &result, expected_outcome, model)
+                    .await
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::{text_reply, ScriptedProvider};
+    use crate::llm::tokenizer::HeuristicTokenizer;
+    use crate::utils::http::parse_http_error;
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }
+    }
+
+    fn fitter(budget: usize) -> ContextFitter {
+        ContextFitter::new(Arc::new(HeuristicTokenizer), budget)
+    }
+
+    #[test]
+    fn test_overflow_errors_are_recognised() {
+        let openai = r#"{"error":{"message":"This model's maximum context length is 8192 tokens. However, your messages resulted in 9001 tokens.","type":"invalid_request_error","code":"context_length_exceeded"}}"#;
+        assert!(matches!(
+            parse_http_error(400, openai, Some("gpt-4")),
+            LlmError::ContextLengthExceeded { limit: Some(8192), .. }
+        ));
+
+        let anthropic = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#;
+        assert!(matches!(
+            parse_http_error(400, anthropic, None),
+            LlmError::ContextLengthExceeded { limit: Some(200000), .. }
+        ));
+
+        assert!(matches!(parse_http_error(400, "model not found", Some("x")), LlmError::InvalidModel { .. }));
+        assert!(matches!(parse_http_error(500, openai, None), LlmError::RequestFailed { status: 500, .. }));
+    }
+
+    #[test]
+    fn test_fit_messages_trims_old_turns_before_recent_ones() {
+        let long = "word ".repeat(2000);
+        let messages = vec![
+            message(MessageRole::System, "You are helpful."),
+            message(MessageRole::User, &format!("first request {}", long)),
+            message(MessageRole::Assistant, &format!("first answer {}", long)),
+            message(MessageRole::User, "now do the same for the tests folder"),
+        ];
+        let full = fitter(usize::MAX).count_messages(&messages);
+        let budget = full - fitter(usize::MAX).count(&long) / 2;
+        let fitter = fitter(budget);
+
+        let fitted = fitter.fit_messages(&messages).unwrap();
+        assert!(fitter.count_messages(&fitted) <= budget);
+        assert_eq!(fitted[0].content, "You are helpful.");
+        assert!(fitted[1].content.starts_with("first request word"));
+        assert!(fitted[1].content.contains("characters trimmed to fit the context window"));
+        assert_eq!(fitted[3].content, "now do the same for the tests folder");
+        // The first old turn already made room, so the second is untouched
+        assert_eq!(fitted[2].content, messages[2].content);
+
+        assert!(matches!(
+            ContextFitter::new(Arc::new(HeuristicTokenizer), 3).fit_messages(&messages[..1]),
+            Err(LlmError::ContextLengthExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fit_context_shrinks_conversation_before_file_context() {
+        let files: String = (0..200).map(|i| format!("- src/module_{}.rs: module {}\n", i, i)).collect();
+        let turns: String = (0..200).map(|i| format!("You: request number {}\n", i)).collect();
+        let context = format!("Working Directory: /project\nFile Structure:\n{}\n## Conversation So Far\n{}", files, turns);
+        let render = |context: &str| Ok(vec![message(MessageRole::User, context)]);
+
+        let full = fitter(usize::MAX).count(&context);
+        let turns_tokens = fitter(usize::MAX).count(&turns);
+        let (fitted, tokens) = fitter(full - turns_tokens / 2).fit_context(&context, render).unwrap();
+        assert!(tokens <= full - turns_tokens / 2);
+        assert!(fitted.contains(&files));
+        assert!(fitted.contains("You: request number 199"));
+        assert!(!fitted.contains("You: request number 0\n"));
+
+        // Once the conversation is gone the file list is cut from its end
+        let (fitted, _) = fitter(full / 3).fit_context(&context, render).unwrap();
+        assert!(fitted.starts_with("Working Directory: /project\nFile Structure:\n- src/module_0.rs"));
+        assert!(!fitted.contains("module_199"));
+    }
+
+    /// Provider that rejects requests over a fixed size
+    fn small_window_provider(limit: usize) -> ScriptedProvider {
+        ScriptedProvider::new("small").on_generate(move |messages| {
+            if fitter(usize::MAX).count_messages(messages) > limit {
+                return Err(LlmError::ContextLengthExceeded { message: "too long".to_string(), limit: None });
+            }
+            Ok(text_reply("ok"))
+        })
+    }
+
+    fn sent_sizes(provider: &ScriptedProvider) -> Vec<usize> {
+        provider
+            .requests()
+            .iter()
+            .map(|request| fitter(usize::MAX).count_messages(&request.messages))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_provider_retries_once_after_overflow() {
+        let messages = vec![
+            message(MessageRole::User, &"old turn ".repeat(500)),
+            message(MessageRole::Assistant, "done"),
+            message(MessageRole::User, "and again"),
+        ];
+        let size = fitter(usize::MAX).count_messages(&messages);
+
+        // Trimming the old turn brings the retry under the provider's limit
+        let inner = small_window_provider(size / 2);
+        let provider = FittingProvider::new(Box::new(inner.clone()));
+        let response = provider.generate(&messages, "unknown-model", None, None).await.unwrap();
+        assert_eq!(response.content.as_deref(), Some("ok"));
+        let sent_sizes = sent_sizes(&inner);
+        assert_eq!(sent_sizes.len(), 2);
+        assert_eq!(sent_sizes[0], size);
+        assert!(sent_sizes[1] <= size / 2);
+
+        // A request that cannot be trimmed enough fails after a single retry
+        let inner = small_window_provider(5);
+        let provider = FittingProvider::new(Box::new(inner.clone()));
+        let error = provider.generate(&messages, "unknown-model", None, None).await.unwrap_err();
+        assert!(matches!(error, LlmError::ContextLengthExceeded { .. }));
+        assert_eq!(inner.calls_to("generate"), 2);
+    }
+}
@@ -1,23 +1,34 @@
 //! Google Gemini LLM provider implementation
 
+use super::content::InlinePart;
+use super::embeddings::{embed_in_batches, EmbeddingLlmProvider, EmbeddingResponse};
+use super::tokenizer::tokenizer_name_for_model;
+use super::schema;
+use super::usage;
 use super::{
     GenerationConfig, LlmError, LlmProvider, LlmResponse, Message,
-    MessageRole, ModelInfo, TokenUsage, ToolCall, ToolDefinition,
+    MessageRole, ModelCapabilities, ModelInfo, ResponseFormat, TokenUsage, ToolCall, ToolDefinition,
     TaskAnalysis, TaskExecutionResult, TaskRefinementContext,
 };
+use super::streaming::{sse_stream, LlmStream, SseDecoder, SseEvent, StreamChunk, StreamingLlmProvider};
 use async_trait::async_trait;
 use reqwest::Client;
+use crate::utils::http::{
+    execute_with_retry, HttpClientConfig, RetryConfig, DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_DELAY,
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
 use std::time::Duration;
 
+/// Most texts `batchEmbedContents` accepts in one request
+const MAX_EMBEDDING_BATCH_SIZE: usize = 100;
+
 /// Google Gemini API provider
 pub struct GeminiProvider {
     client: Client,
     api_key: String,
     base_url: String,
-    retry_attempts: usize,
-    retry_delay: Duration,
+    retry: RetryConfig,
 }
 
 /// Gemini-specific request structures
@@ -32,8 +43,9 @@ struct GeminiRequest {
     system_instruction: Option<GeminiContent>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct GeminiContent {
+    #[serde(default)]
     parts: Vec<GeminiPart>,
     #[serde(skip_serializing_if = "Option::is_none")]
     role: Option<String>,
@@ -51,6 +63,18 @@ enum GeminiPart {
         #[serde(rename = "functionResponse")]
         function_response: GeminiFunctionResponse,
     },
+    InlineData {
+        #[serde(rename = "inlineData")]
+        inline_data: GeminiInlineData,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeminiInlineData {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    /// Base64-encoded bytes
+    data: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,11 +112,17 @@ struct GeminiGenerationConfig {
     top_p: Option<f32>,
     #[serde(rename = "stopSequences", skip_serializing_if = "Option::is_none")]
     stop_sequences: Option<Vec<String>>,
+    #[serde(rename = "responseMimeType", skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(rename = "responseJsonSchema", skip_serializing_if = "Option::is_none")]
+    response_json_schema: Option<serde_json::Value>,
 }
 
 /// Gemini API response structures
 #[derive(Debug, Clone, Deserialize)]
 struct GeminiResponse {
+    // Streamed chunks may carry only usage metadata
+    #[serde(default)]
     candidates: Vec<GeminiCandidate>,
     #[serde(rename = "usageMetadata", skip_serializing_if = "Option::is_none")]
     usage_metadata: Option<GeminiUsageMetadata>,
@@ -100,6 +130,7 @@ struct GeminiResponse {
 
 #[derive(Debug, Clone, Deserialize)]
 struct GeminiCandidate {
+    #[serde(default)]
     content: GeminiContent,
     #[serde(rename = "finishReason", skip_serializing_if = "Option::is_none")]
     finish_reason: Option<String>,
@@ -116,6 +147,29 @@ struct GeminiUsageMetadata {
     total_token_count: u32,
 }
 
+/// Embedding request and response structures
+#[derive(Debug, Clone, Serialize)]
+struct GeminiBatchEmbedRequest {
+    requests: Vec<GeminiEmbedRequest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct GeminiEmbedRequest {
+    model: String,
+    content: GeminiContent,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GeminiBatchEmbedResponse {
+    #[serde(default)]
+    embeddings: Vec<GeminiEmbedding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GeminiEmbedding {
+    values: Vec<f32>,
+}
+
 /// Models response structure
 #[derive(Debug, Clone, Deserialize)]
 struct GeminiModelsResponse {
@@ -146,8 +200,7 @@ impl GeminiProvider {
                 .unwrap_or_else(|_| Client::new()),
             api_key,
             base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
-            retry_attempts: 3,
-            retry_delay: Duration::from_millis(1000),
+            retry: HttpClientConfig::default().retry_config(),
         }
     }
 
@@ -165,11 +218,30 @@ impl GeminiProvider {
                 .unwrap_or_else(|_| Client::new()),
             api_key,
             base_url: base_url.unwrap_or_else(|| "https://generativelanguage.googleapis.com/v1beta".to_string()),
-            retry_attempts: retry_attempts.unwrap_or(3),
-            retry_delay: retry_delay.unwrap_or_else(|| Duration::from_millis(1000)),
+            retry: HttpClientConfig {
+                retry_attempts: retry_attempts.unwrap_or(DEFAULT_RETRY_ATTEMPTS),
+                retry_delay: retry_delay.unwrap_or(DEFAULT_RETRY_DELAY),
+                ..HttpClientConfig::default()
+            }
+            .retry_config(),
         }
     }
 
+    /// Create a provider from factory settings
+    ///
+    /// Recognised keys: `base_url`, `timeout_secs`, `retry_attempts`,
+    /// `retry_delay_ms` and `retry_rate_limits`.
+    pub fn from_settings(api_key: String, settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let client_config = HttpClientConfig::default().with_settings(settings)?;
+        let mut provider = Self::with_config(api_key, settings.get("base_url").cloned(), None, None);
+        provider.client = Client::builder()
+            .timeout(client_config.timeout)
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        provider.retry = client_config.retry_config();
+        Ok(provider)
+    }
+
     /// Convert our Message format to Gemini's format
     fn convert_messages(
         messages: &[Message],
@@ -188,10 +260,18 @@ impl GeminiProvider {
                     });
                 }
                 MessageRole::User => {
+                    let parts = message
+                        .inline_parts()
+                        .into_iter()
+                        .map(|part| match part {
+                            InlinePart::Text(text) => GeminiPart::Text { text },
+                            InlinePart::Image { mime_type, data } => GeminiPart::InlineData {
+                                inline_data: GeminiInlineData { mime_type, data },
+                            },
+                        })
+                        .collect();
                     contents.push(GeminiContent {
-                        parts: vec![GeminiPart::Text {
-                            text: message.content.clone(),
-                        }],
+                        parts,
                         role: Some("user".to_string()),
                     });
                 }
@@ -270,6 +350,8 @@ impl GeminiProvider {
             max_output_tokens: config.max_tokens,
             top_p: config.top_p,
             stop_sequences: config.stop_sequences.clone(),
+            response_mime_type: config.response_format.as_ref().map(|_| "application/json".to_string()),
+            response_json_schema: config.response_format.as_ref().map(|format| format.schema.clone()),
         }
     }
 
@@ -279,42 +361,290 @@ impl GeminiProvider {
         F: Fn() -> Fut,
         Fut: std::future::Future<Output = Result<T, LlmError>> + Send,
     {
-        let mut last_error = None;
+        execute_with_retry(operation, &self.retry).await
+    }
+
+    /// Build a generateContent request
+    fn build_request(
+        messages: &[Message],
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<GeminiRequest, LlmError> {
+        let (system_instruction, contents) = Self::convert_messages(messages)?;
         
-        for attempt in 0..=self.retry_attempts {
-            match operation().await {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    last_error = Some(e);
-                    
-                    if attempt < self.retry_attempts {
-                        // Check if error is retryable
-                        if let Some(ref error) = last_error {
-                            match error {
-                                LlmError::RateLimit { .. } => {
-                                    // Wait longer for rate limits
-                                    tokio::time::sleep(self.retry_delay * (attempt as u32 + 1) * 2).await;
-                                }
-                                LlmError::Network(_) => {
-                                    tokio::time::sleep(self.retry_delay * (attempt as u32 + 1)).await;
-                                }
-                                LlmError::RequestFailed { status, .. } if *status >= 500 => {
-                                    tokio::time::sleep(self.retry_delay * (attempt as u32 + 1)).await;
-                                }
-                                _ => {
-                                    // Non-retryable error, break immediately
-                                    break;
-                                }
-                            }
-                        }
+        let mut request = GeminiRequest {
+            contents,
+            tools: None,
+            generation_config: config.map(Self::convert_generation_config),
+            system_instruction,
+        };
+
+        if let Some(tools) = tools {
+            request.tools = Some(Self::convert_tools(tools)?);
+        }
+
+        Ok(request)
+    }
+
+    /// Seconds to wait from the `RetryInfo` detail of a 429 response, e.g. `"retryDelay": "37s"`
+    fn retry_delay(text: &str) -> Option<u64> {
+        let json = serde_json::from_str::<serde_json::Value>(text).ok()?;
+        json.pointer("/error/details")?
+            .as_array()?
+            .iter()
+            .filter_map(|detail| detail.get("retryDelay")?.as_str())
+            .find_map(|delay| delay.trim_end_matches('s').parse::<f64>().ok())
+            .map(|seconds| seconds.ceil() as u64)
+    }
+
+    /// Map an unsuccessful generation response to an LlmError
+    fn generation_error(status: u16, text: String, model: &str) -> LlmError {
+        if let Some(error) = crate::utils::http::context_length_error(status, &text) {
+            return error;
+        }
+
+        match status {
+            429 => LlmError::RateLimit { retry_after: Self::retry_delay(&text) },
+            401 | 403 => LlmError::Authentication {
+                message: "Invalid API key".to_string(),
+            },
+            400 => {
+                if text.contains("model not found") || text.contains("model does not exist") {
+                    LlmError::InvalidModel {
+                        model: model.to_string(),
                     }
+                } else {
+                    LlmError::RequestFailed { status, message: text }
                 }
             }
+            _ => LlmError::RequestFailed { status, message: text },
+        }
+    }
+
+    /// Build plan generation messages
+    fn plan_messages(prompt: &str, context: &str) -> Vec<Message> {
+        let system_message = r#"
+You are a task planning AI. Generate a structured plan to accomplish the user's request.
+Output your response as a JSON object with this exact structure:
+{
+    "description": "Brief description of what the plan accomplishes",
+    "tasks": [
+        {
+            "id": "unique_task_id",
+            "description": "Human readable task description",
+            "task_type": "read_file|write_file|execute_command|generate_content|analyze_code|list_files|create_directory|delete",
+            "parameters": {
+                // Task-specific parameters as key-value pairs
+            },
+            "dependencies": ["task_id_1", "task_id_2"]
+        }
+    ]
+}
+
+Available task types:
+- read_file: Read content from a file (parameters: {"path": "file/path"})
+- write_file: Write content to a file (parameters: {"path": "file/path", "content": "..."})
+- execute_command: Run a shell command (parameters: {"command": "...", "args": [...]})
+- generate_content: Generate code/text (parameters: {"prompt": "...", "output_file": "optional"})
+- analyze_code: Analyze existing code (parameters: {"path": "file/path", "focus": "what to analyze"})
+- list_files: List files in a directory (parameters: {"path": "directory/path", "pattern": "optional_glob"})
+- create_directory: Create a directory (parameters: {"path": "directory/path"})
+- delete: Delete a file or directory (parameters: {"path": "file/directory/path"})
+
+Ensure all tasks have unique IDs and proper dependencies.
+"#;
+
+        vec![
+            Message {
+                role: MessageRole::System,
+                content: system_message.to_string(),
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+        ]
+    }
+
+    /// Build task refinement messages from the prompt template
+    fn refinement_messages(task: &crate::planning::Task, context: &TaskRefinementContext) -> Result<Vec<Message>, LlmError> {
+        let template = super::prompts::PromptTemplates::task_refinement();
+        let prompt_context = super::prompts::PromptContext::new()
+            .with_variable("plan_description", &context.plan_description)
+            .with_variable("task_id", &task.id)
+            .with_variable("task_type", &format!("{:?}", task.task_type))
+            .with_variable("task_description", &task.description)
+            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
+            .with_variable("global_context", &context.global_context)
+            .with_variable("plan_context", &context.plan_context)
+            .with_variable("dependency_outputs", &serde_json::to_string_pretty(&context.dependency_outputs).unwrap_or_default());
+        
+        let (system_message, user_message) = template.fill(&prompt_context)
+            .map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to fill task refinement template: {}", e),
+            })?;
+
+        Ok(Self::system_and_user(system_message, user_message))
+    }
+
+    /// Generation settings for task refinement
+    fn refinement_config() -> GenerationConfig {
+        GenerationConfig {
+            temperature: Some(0.3), // Lower temperature for focused refinement
+            max_tokens: Some(4000),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: None,
         }
+    }
+
+    /// Build execution analysis messages from the prompt template
+    fn analysis_messages(
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+    ) -> Result<Vec<Message>, LlmError> {
+        let template = super::prompts::PromptTemplates::execution_analysis();
+        let prompt_context = super::prompts::PromptContext::new()
+            .with_variable("task_id", &task.id)
+            .with_variable("task_type", &format!("{:?}", task.task_type))
+            .with_variable("task_description", &task.description)
+            .with_variable("expected_outcome", expected_outcome)
+            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
+            .with_variable("success", &execution_result.success.to_string())
+            .with_variable("exit_code", &execution_result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "N/A".to_string()))
+            .with_variable("execution_time_ms", &execution_result.execution_time_ms.to_string())
+            .with_variable("stdout", execution_result.stdout.as_deref().unwrap_or(""))
+            .with_variable("stderr", execution_result.stderr.as_deref().unwrap_or(""))
+            .with_variable("output_data", &serde_json::to_string_pretty(&execution_result.output).unwrap_or_else(|_| "null".to_string()))
+            .with_variable("error_message", execution_result.error.as_deref().unwrap_or(""))
+            .with_variable("plan_description", "")
+            .with_variable("plan_context", "")
+            .with_variable("task_dependencies", &serde_json::to_string(&task.dependencies).unwrap_or_default());
         
-        Err(last_error.unwrap_or_else(|| LlmError::Unknown {
-            message: "Retry operation failed with no error".to_string(),
-        }))
+        let (system_message, user_message) = template.fill(&prompt_context)
+            .map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to fill execution analysis template: {}", e),
+            })?;
+
+        Ok(Self::system_and_user(system_message, user_message))
+    }
+
+    /// Generation settings for execution analysis
+    fn analysis_config() -> GenerationConfig {
+        GenerationConfig {
+            temperature: Some(0.1),
+            max_tokens: Some(2048),
+            top_p: Some(0.8),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
+        }
+    }
+
+    fn system_and_user(system_message: String, user_message: String) -> Vec<Message> {
+        vec![
+            Message {
+                role: MessageRole::System,
+                content: system_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: user_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+        ]
+    }
+}
+
+/// Decodes `streamGenerateContent?alt=sse` events, each of which is a partial response
+///
+/// Text and function calls are emitted as they arrive; the finish reason and the
+/// cumulative usage are emitted once the stream ends.
+#[derive(Default)]
+struct GeminiStreamDecoder {
+    finish_reason: Option<String>,
+    usage: Option<TokenUsage>,
+}
+
+impl SseDecoder for GeminiStreamDecoder {
+    fn decode(&mut self, event: &SseEvent) -> Result<Vec<StreamChunk>, LlmError> {
+        let value: serde_json::Value = serde_json::from_str(&event.data)?;
+
+        // Errors after the stream started arrive as a regular data event
+        if let Some(error) = value.get("error") {
+            return Err(LlmError::RequestFailed {
+                status: error["code"].as_u64().unwrap_or(500) as u16,
+                message: error["message"].as_str().map(|m| m.to_string()).unwrap_or_else(|| error.to_string()),
+            });
+        }
+
+        let response: GeminiResponse = serde_json::from_value(value)?;
+
+        if let Some(usage) = response.usage_metadata {
+            self.usage = Some(TokenUsage {
+                prompt_tokens: usage.prompt_token_count,
+                completion_tokens: usage.candidates_token_count,
+                total_tokens: usage.total_token_count,
+            });
+        }
+
+        let mut chunks = Vec::new();
+        if let Some(candidate) = response.candidates.into_iter().next() {
+            let mut tool_calls = Vec::new();
+
+            for part in candidate.content.parts {
+                match part {
+                    GeminiPart::Text { text } => {
+                        if !text.is_empty() {
+                            chunks.push(StreamChunk::content(text));
+                        }
+                    }
+                    GeminiPart::FunctionCall { function_call } => {
+                        tool_calls.push(ToolCall {
+                            id: format!("call_{}", uuid::Uuid::new_v4()),
+                            r#type: "function".to_string(),
+                            function: crate::llm::FunctionCall {
+                                name: function_call.name,
+                                arguments: function_call.args,
+                            },
+                        });
+                    }
+                    GeminiPart::FunctionResponse { .. } | GeminiPart::InlineData { .. } => {}
+                }
+            }
+
+            if !tool_calls.is_empty() {
+                chunks.push(StreamChunk::tool_calls(tool_calls));
+            }
+
+            if let Some(finish_reason) = candidate.finish_reason {
+                self.finish_reason = Some(finish_reason);
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    fn finish(&mut self) -> Vec<StreamChunk> {
+        vec![StreamChunk::finish(
+            self.finish_reason.take().unwrap_or_else(|| "stop".to_string()),
+            self.usage.take(),
+        )]
     }
 }
 
@@ -338,7 +668,7 @@ impl LlmProvider for GeminiProvider {
                     let text = response.text().await.unwrap_or_default();
                     
                     return match status {
-                        429 => Err(LlmError::RateLimit { retry_after: Some(60) }),
+                        429 => Err(LlmError::RateLimit { retry_after: Self::retry_delay(&text) }),
                         401 | 403 => Err(LlmError::Authentication {
                             message: "Invalid API key".to_string(),
                         }),
@@ -368,6 +698,9 @@ impl LlmProvider for GeminiProvider {
                         .map(|methods| methods.contains(&"generateContent".to_string()))
                         .unwrap_or(false)
                     {
+                        let tokenizer = tokenizer_name_for_model(&model_id);
+                        // Gemini models call tools, read images and emit JSON; Gemma models served by the same API do not
+                        let gemini = model_id.starts_with("gemini-").then_some(true);
                         models.push(ModelInfo {
                             id: model_id,
                             name: model.display_name,
@@ -375,6 +708,13 @@ impl LlmProvider for GeminiProvider {
                             context_length: model.input_token_limit,
                             max_output_tokens: model.output_token_limit,
                             pricing: None, // Gemini doesn't provide pricing in model info
+                            tokenizer,
+                            capabilities: ModelCapabilities {
+                                tools: gemini,
+                                vision: gemini,
+                                json_mode: gemini,
+                                streaming: Some(true),
+                            },
                         });
                     }
                 }
@@ -393,18 +733,7 @@ impl LlmProvider for GeminiProvider {
         config: Option<&GenerationConfig>,
     ) -> Result<LlmResponse, LlmError> {
         let operation = || async {
-                let (system_instruction, contents) = Self::convert_messages(messages)?;
-                
-                let mut request = GeminiRequest {
-                    contents,
-                    tools: None,
-                    generation_config: config.map(Self::convert_generation_config),
-                    system_instruction,
-                };
-
-                if let Some(tools) = tools {
-                    request.tools = Some(Self::convert_tools(tools)?);
-                }
+                let request = Self::build_request(messages, tools, config)?;
 
                 let url = format!(
                     "{}/models/{}:generateContent?key={}",
@@ -422,23 +751,7 @@ impl LlmProvider for GeminiProvider {
                 if !response.status().is_success() {
                     let status = response.status().as_u16();
                     let text = response.text().await.unwrap_or_default();
-                    
-                    return match status {
-                        429 => Err(LlmError::RateLimit { retry_after: Some(60) }),
-                        401 | 403 => Err(LlmError::Authentication {
-                            message: "Invalid API key".to_string(),
-                        }),
-                        400 => {
-                            if text.contains("model not found") || text.contains("model does not exist") {
-                                Err(LlmError::InvalidModel {
-                                    model: model.to_string(),
-                                })
-                            } else {
-                                Err(LlmError::RequestFailed { status, message: text })
-                            }
-                        }
-                        _ => Err(LlmError::RequestFailed { status, message: text }),
-                    };
+                    return Err(Self::generation_error(status, text, model));
                 }
 
                 let gemini_response: GeminiResponse = response.json().await
@@ -475,8 +788,8 @@ impl LlmProvider for GeminiProvider {
                                 },
                             });
                         }
-                        GeminiPart::FunctionResponse { .. } => {
-                            // Function responses shouldn't appear in model outputs
+                        GeminiPart::FunctionResponse { .. } | GeminiPart::InlineData { .. } => {
+                            // Function responses and inline data shouldn't appear in model outputs
                             // They're used for inputs when continuing conversations
                         }
                     }
@@ -495,10 +808,13 @@ impl LlmProvider for GeminiProvider {
                         .clone()
                         .unwrap_or_else(|| "stop".to_string()),
                     usage,
+                    backend: None,
                 })
         };
         
-        self.execute_with_retry(operation).await
+        self.execute_with_retry(operation)
+            .await
+            .inspect(|response| usage::report_generate(self.provider_name(), model, messages, response))
     }
 
     async fn generate_plan(
@@ -507,69 +823,10 @@ impl LlmProvider for GeminiProvider {
         context: &str,
         model: &str,
     ) -> Result<crate::planning::Plan, LlmError> {
-        let system_message = r#"
-You are a task planning AI. Generate a structured plan to accomplish the user's request.
-Output your response as a JSON object with this exact structure:
-{
-    "description": "Brief description of what the plan accomplishes",
-    "tasks": [
-        {
-            "id": "unique_task_id",
-            "description": "Human readable task description",
-            "task_type": "read_file|write_file|execute_command|generate_content|analyze_code|list_files|create_directory|delete",
-            "parameters": {
-                // Task-specific parameters as key-value pairs
-            },
-            "dependencies": ["task_id_1", "task_id_2"]
-        }
-    ]
-}
+        let messages = Self::plan_messages(prompt, context);
 
-Available task types:
-- read_file: Read content from a file (parameters: {"path": "file/path"})
-- write_file: Write content to a file (parameters: {"path": "file/path", "content": "..."})
-- execute_command: Run a shell command (parameters: {"command": "...", "args": [...]})
-- generate_content: Generate code/text (parameters: {"prompt": "...", "output_file": "optional"})
-- analyze_code: Analyze existing code (parameters: {"path": "file/path", "focus": "what to analyze"})
-- list_files: List files in a directory (parameters: {"path": "directory/path", "pattern": "optional_glob"})
-- create_directory: Create a directory (parameters: {"path": "directory/path"})
-- delete: Delete a file or directory (parameters: {"path": "file/directory/path"})
-
-Ensure all tasks have unique IDs and proper dependencies.
-"#;
-
-        let messages = vec![
-            Message {
-                role: MessageRole::System,
-                content: system_message.to_string(),
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: MessageRole::User,
-                content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
-
-        let response = self.generate(&messages, model, None, None).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in planning response".to_string(),
-            })?;
-
-        // Parse the JSON response into a Plan
-        let plan_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse plan JSON: {}", e),
-            })?;
-
-        crate::planning::Plan::from_json(&plan_json)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Invalid plan structure: {}", e),
-            })
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
     }
 
     async fn generate_content(
@@ -585,6 +842,7 @@ Ensure all tasks have unique IDs and proper dependencies.
                 content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ];
 
@@ -612,47 +870,8 @@ Ensure all tasks have unique IDs and proper dependencies.
         context: &TaskRefinementContext,
         model: &str,
     ) -> Result<String, LlmError> {
-        // Use the task refinement prompt template
-        let template = super::prompts::PromptTemplates::task_refinement();
-        let prompt_context = super::prompts::PromptContext::new()
-            .with_variable("plan_description", &context.plan_description)
-            .with_variable("task_id", &task.id)
-            .with_variable("task_type", &format!("{:?}", task.task_type))
-            .with_variable("task_description", &task.description)
-            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
-            .with_variable("global_context", &context.global_context)
-            .with_variable("plan_context", &context.plan_context)
-            .with_variable("dependency_outputs", &serde_json::to_string_pretty(&context.dependency_outputs).unwrap_or_default());
-        
-        let (system_message, user_message) = template.fill(&prompt_context)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to fill task refinement template: {}", e),
-            })?;
-
-        let messages = vec![
-            Message {
-                role: MessageRole::System,
-                content: system_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: MessageRole::User,
-                content: user_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
-
-        // Use focused configuration for refinement
-        let config = GenerationConfig {
-            temperature: Some(0.3), // Lower temperature for focused refinement
-            max_tokens: Some(4000),
-            top_p: Some(0.9),
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop_sequences: None,
-        };
+        let messages = Self::refinement_messages(task, context)?;
+        let config = Self::refinement_config();
 
         let response = self.generate(&messages, model, None, Some(&config)).await?;
         
@@ -669,106 +888,239 @@ Ensure all tasks have unique IDs and proper dependencies.
         expected_outcome: &str,
         model: &str,
     ) -> Result<TaskAnalysis, LlmError> {
-        // Use the execution analysis prompt template
-        let template = super::prompts::PromptTemplates::execution_analysis();
-        let prompt_context = super::prompts::PromptContext::new()
-            .with_variable("task_id", &task.id)
-            .with_variable("task_type", &format!("{:?}", task.task_type))
-            .with_variable("task_description", &task.description)
-            .with_variable("expected_outcome", expected_outcome)
-            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
-            .with_variable("success", &execution_result.success.to_string())
-            .with_variable("exit_code", &execution_result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "N/A".to_string()))
-            .with_variable("execution_time_ms", &execution_result.execution_time_ms.to_string())
-            .with_variable("stdout", execution_result.stdout.as_deref().unwrap_or(""))
-            .with_variable("stderr", execution_result.stderr.as_deref().unwrap_or(""))
-            .with_variable("output_data", &serde_json::to_string_pretty(&execution_result.output).unwrap_or_else(|_| "null".to_string()))
-            .with_variable("error_message", execution_result.error.as_deref().unwrap_or(""))
-            .with_variable("plan_description", "")
-            .with_variable("plan_context", "")
-            .with_variable("task_dependencies", &serde_json::to_string(&task.dependencies).unwrap_or_default());
-        
-        let (system_message, user_message) = template.fill(&prompt_context)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to fill execution analysis template: {}", e),
-            })?;
+        let messages = Self::analysis_messages(task, execution_result, expected_outcome)?;
+        let config = Self::analysis_config();
 
-        let messages = vec![
-            Message {
-                role: MessageRole::System,
-                content: system_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: MessageRole::User,
-                content: user_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
+    }
 
-        // Use lower temperature for consistent analysis
-        let config = GenerationConfig {
-            temperature: Some(0.1),
-            max_tokens: Some(2048),
-            top_p: Some(0.8),
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop_sequences: None,
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        Some(self)
+    }
+
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        Some(self)
+    }
+}
+
+/// The embedding endpoints do not report token usage; the metering wrapper estimates it
+#[async_trait]
+impl EmbeddingLlmProvider for GeminiProvider {
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingResponse, LlmError> {
+        embed_in_batches(texts, MAX_EMBEDDING_BATCH_SIZE, |batch| async move {
+            let request = GeminiBatchEmbedRequest {
+                requests: batch
+                    .iter()
+                    .map(|text| GeminiEmbedRequest {
+                        model: format!("models/{}", model),
+                        content: GeminiContent {
+                            parts: vec![GeminiPart::Text { text: text.clone() }],
+                            role: None,
+                        },
+                    })
+                    .collect(),
+            };
+
+            let operation = || async {
+                let url = format!(
+                    "{}/models/{}:batchEmbedContents?key={}",
+                    self.base_url, model, self.api_key
+                );
+
+                let response = self
+                    .client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+                    .send()
+                    .await?;
+
+                if !response.status().is_success() {
+                    let status = response.status().as_u16();
+                    let text = response.text().await.unwrap_or_default();
+                    return Err(Self::generation_error(status, text, model));
+                }
+
+                let embed_response: GeminiBatchEmbedResponse = response.json().await
+                    .map_err(|e| LlmError::InvalidResponse {
+                        message: format!("Failed to parse embeddings response: {}", e),
+                    })?;
+
+                Ok(EmbeddingResponse {
+                    embeddings: embed_response.embeddings.into_iter().map(|e| e.values).collect(),
+                    usage: None,
+                })
+            };
+
+            self.execute_with_retry(operation).await
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl StreamingLlmProvider for GeminiProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let request = Self::build_request(messages, tools, config)?;
+
+        // Only establishing the stream is retried; a broken stream surfaces as an error chunk
+        let operation = || async {
+            let url = format!(
+                "{}/models/{}:streamGenerateContent?alt=sse&key={}",
+                self.base_url, model, self.api_key
+            );
+
+            let response = self
+                .client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let text = response.text().await.unwrap_or_default();
+                return Err(Self::generation_error(status, text, model));
+            }
+
+            Ok(response)
         };
 
-        let response = self.generate(&messages, model, None, Some(&config)).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in task analysis response".to_string(),
-            })?;
+        let response = self.execute_with_retry(operation).await?;
+        Ok(sse_stream(response, GeminiStreamDecoder::default()))
+    }
 
-        // Parse the JSON response into TaskAnalysis
-        let analysis_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse task analysis JSON: {}. Content: {}", e, content),
-            })?;
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::plan_messages(prompt, context);
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        self.generate_stream(&messages, model, None, Some(&config)).await
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::refinement_messages(task, context)?;
+        self.generate_stream(&messages, model, None, Some(&Self::refinement_config())).await
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::analysis_messages(task, execution_result, expected_outcome)?;
+        self.generate_stream(&messages, model, None, Some(&Self::analysis_config())).await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+    use crate::llm::streaming::utils::collect_plan_stream;
+
+    #[tokio::test]
+    async fn test_generate_plan_stream() {
+        let events = [
+            r#"{"candidates":[{"content":{"parts":[{"text":"{\"description\": \"List "}],"role":"model"},"index":0}]}"#,
+            r#"{"candidates":[{"content":{"parts":[{"text":"files\", \"tasks\": []}"}],"role":"model"},"finishReason":"STOP","index":0}],"usageMetadata":{"promptTokenCount":20,"candidatesTokenCount":8,"totalTokenCount":28}}"#,
+        ];
+        // Gemini separates events with CRLF
+        let body: String = events.iter().map(|e| format!("data: {}\r\n\r\n", e)).collect();
+        let (server_url, captured) = mock_server::spawn(vec![(200, body)]).await;
+        let provider = GeminiProvider::with_config("secret".to_string(), Some(server_url), Some(0), None);
+
+        let stream = provider.generate_plan_stream("list files", "", "gemini-test").await.unwrap();
+        let plan = collect_plan_stream(stream).await.unwrap();
+
+        assert_eq!(plan.description, "List files");
+        let requests = captured.lock().unwrap();
+        assert_eq!(
+            requests[0].request_line,
+            "POST /models/gemini-test:streamGenerateContent?alt=sse&key=secret HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_reports_finish_and_usage() {
+        let body = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]},\"finishReason\":\"STOP\"}],\
+                    \"usageMetadata\":{\"promptTokenCount\":3,\"candidatesTokenCount\":1,\"totalTokenCount\":4}}\n\n".to_string();
+        let (server_url, _) = mock_server::spawn(vec![(200, body)]).await;
+        let provider = GeminiProvider::with_config("secret".to_string(), Some(server_url), Some(0), None);
+
+        let stream = provider.generate_stream(&[], "gemini-test", None, None).await.unwrap();
+        let response = crate::llm::StreamCollector::collect_stream(stream).await.unwrap();
 
-        // Extract fields with robust defaults
-        let success = analysis_json["success"].as_bool().unwrap_or(false);
-        let summary = analysis_json["summary"].as_str().unwrap_or("Analysis unavailable").to_string();
-        let details = analysis_json["details"].as_str().unwrap_or("").to_string();
-        let extracted_data = analysis_json.get("extracted_data").cloned();
-        let next_steps = analysis_json["next_steps"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                .collect::<Vec<String>>());
-        let context_updates = analysis_json["context_updates"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect());
-        let modified_files = analysis_json["modified_files"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| PathBuf::from(s)))
-                .collect::<Vec<PathBuf>>());
-        let error = if !success {
-            Some(analysis_json["error"].as_str().unwrap_or("Task failed").to_string())
-        } else {
-            None
+        assert_eq!(response.content.as_deref(), Some("Hi"));
+        assert_eq!(response.finish_reason, "STOP");
+        assert_eq!(response.usage.unwrap().total_tokens, 4);
+    }
+
+    #[tokio::test]
+    async fn test_embed_splits_into_batches_of_one_hundred() {
+        let body = |count: usize| {
+            let embeddings: Vec<_> = (0..count).map(|i| serde_json::json!({"values": [i as f32, 1.0]})).collect();
+            serde_json::json!({"embeddings": embeddings}).to_string()
         };
-        let metadata = analysis_json["metadata"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect())
-            .unwrap_or_default();
-
-        Ok(TaskAnalysis {
-            success,
-            summary,
-            details,
-            extracted_data,
-            next_steps,
-            context_updates,
-            modified_files,
-            error,
-            metadata,
-        })
+        let (server_url, captured) = mock_server::spawn(vec![(200, body(100)), (200, body(5))]).await;
+        let provider = GeminiProvider::with_config("secret".to_string(), Some(server_url), Some(0), None);
+
+        let texts: Vec<String> = (0..105).map(|i| format!("chunk {}", i)).collect();
+        let response = provider.embed(&texts, "text-embedding-004").await.unwrap();
+
+        assert_eq!(response.embeddings.len(), 105);
+        assert_eq!(response.embeddings[100], vec![0.0, 1.0]);
+        assert!(response.usage.is_none());
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].request_line,
+            "POST /models/text-embedding-004:batchEmbedContents?key=secret HTTP/1.1"
+        );
+        let first = requests[0].json();
+        assert_eq!(first["requests"].as_array().unwrap().len(), 100);
+        assert_eq!(first["requests"][0]["model"], "models/text-embedding-004");
+        assert_eq!(first["requests"][0]["content"]["parts"][0]["text"], "chunk 0");
+        assert_eq!(requests[1].json()["requests"].as_array().unwrap().len(), 5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_convert_messages_sends_images_as_inline_data() {
+        let message = Message {
+            role: MessageRole::User,
+            content: "Why is the header cut off?".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: vec![crate::llm::ContentPart::image("image/png", b"Man".to_vec())],
+        };
+
+        let (_, contents) = GeminiProvider::convert_messages(&[message]).unwrap();
+        assert_eq!(
+            serde_json::to_value(&contents[0]).unwrap(),
+            serde_json::json!({
+                "parts": [
+                    { "text": "Why is the header cut off?" },
+                    { "inlineData": { "mimeType": "image/png", "data": "TWFu" } }
+                ],
+                "role": "user"
+            })
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Minimal HTTP mock server for provider tests
+//!
+//! Each accepted connection is answered with the next canned response and then
+//! closed, so every request made by a provider maps to exactly one response.
+
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Request captured by the mock server
+#[derive(Debug, Clone)]
+pub struct CapturedRequest {
+    pub request_line: String,
+    pub headers: String,
+    pub body: String,
+}
+
+impl CapturedRequest {
+    /// Get a header value by case-insensitive name
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    }
+
+    /// Parse the request body as JSON
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+/// Start a mock server answering with `responses` in order
+///
+/// Returns the server root URL (without trailing slash) and the captured requests.
+pub async fn spawn(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<CapturedRequest>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let captured_clone = captured.clone();
+
+    tokio::spawn(async move {
+        for (status, body) in responses {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(_) => return,
+            };
+
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let (head, content_length) = loop {
+                let read = socket.read(&mut chunk).await.unwrap_or(0);
+                if read == 0 {
+                    break (String::from_utf8_lossy(&buffer).to_string(), 0);
+                }
+                buffer.extend_from_slice(&chunk[..read]);
+                if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&buffer[..pos]).to_string();
+                    let content_length = head
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    buffer.drain(..pos + 4);
+                    break (head, content_length);
+                }
+            };
+
+            while buffer.len() < content_length {
+                let read = socket.read(&mut chunk).await.unwrap_or(0);
+                if read == 0 {
+                    break;
+                }
+                buffer.extend_from_slice(&chunk[..read]);
+            }
+
+            let (request_line, headers) = head.split_once("\r\n").unwrap_or((head.as_str(), ""));
+            captured_clone.lock().unwrap().push(CapturedRequest {
+                request_line: request_line.to_string(),
+                headers: headers.to_string(),
+                body: String::from_utf8_lossy(&buffer).to_string(),
+            });
+
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+
+    (format!("http://{}", addr), captured)
+}
@@ -8,17 +8,39 @@ use thiserror::Error;
 
 pub mod openrouter;
 pub mod gemini;
+pub mod anthropic;
+pub mod cache;
+pub mod cancellation;
+pub mod cassette;
+pub mod content;
+pub mod embeddings;
+pub mod fallback;
+pub mod fitting;
+pub mod openai_compatible;
+pub mod ollama;
+pub mod prompt_overrides;
 pub mod prompts;
+pub mod rate_limit;
+pub mod registry;
+pub mod schema;
 pub mod streaming;
+pub mod tokenizer;
+pub mod usage;
 pub mod utils;
 
 #[cfg(test)]
 pub mod examples;
+#[cfg(test)]
+pub(crate) mod mock_server;
+#[cfg(test)]
+pub(crate) mod scripted;
 
 // Re-export commonly used types for convenience
+pub use content::ContentPart;
+pub use embeddings::{EmbeddingLlmProvider, EmbeddingResponse};
 pub use prompts::{PromptContext, PromptTemplate, PromptTemplates};
-pub use streaming::{LlmStream, StreamChunk, StreamCollector, StreamingLlmProvider};
-pub use utils::{CostBreakdown, CostEstimator, TokenCounter, UsageTracker};
+pub use streaming::{LlmStream, StreamChunk, StreamCollector, StreamOutput, StreamStage, StreamingLlmProvider};
+pub use utils::{CostBreakdown, CostEstimator, PriceSource, TokenCounter, UsageTracker};
 
 
 // Re-export agentic loop types (defined below)
@@ -42,6 +64,10 @@ pub enum LlmError {
     #[error("Invalid response format: {message}")]
     InvalidResponse { message: String },
 
+    /// The request does not fit the model's context window
+    #[error("Context length exceeded: {message}")]
+    ContextLengthExceeded { message: String, limit: Option<u32> },
+
     #[error("Tool execution error: {message}")]
     ToolExecution { message: String },
 
@@ -51,6 +77,10 @@ pub enum LlmError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
+    /// The request was aborted because its plan or task was cancelled
+    #[error("Request cancelled")]
+    Cancelled,
+
     #[error("Unknown error: {message}")]
     Unknown { message: String },
 }
@@ -63,7 +93,9 @@ impl Clone for LlmError {
             LlmError::InvalidModel { model } => LlmError::InvalidModel { model: model.clone() },
             LlmError::RequestFailed { status, message } => LlmError::RequestFailed { status: *status, message: message.clone() },
             LlmError::InvalidResponse { message } => LlmError::InvalidResponse { message: message.clone() },
+            LlmError::ContextLengthExceeded { message, limit } => LlmError::ContextLengthExceeded { message: message.clone(), limit: *limit },
             LlmError::ToolExecution { message } => LlmError::ToolExecution { message: message.clone() },
+            LlmError::Cancelled => LlmError::Cancelled,
             LlmError::Unknown { message } => LlmError::Unknown { message: message.clone() },
             // For non-cloneable errors, create a new error with the display string
             LlmError::Network(e) => LlmError::Unknown { message: format!("Network error: {}", e) },
@@ -79,6 +111,9 @@ pub struct Message {
     pub content: String,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub tool_call_id: Option<String>,
+    /// Images and file references sent after the text content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ContentPart>,
 }
 
 /// Role of a message in the conversation
@@ -121,6 +156,43 @@ pub struct FunctionDefinition {
     pub parameters: serde_json::Value,
 }
 
+/// JSON Schema the response must conform to, for providers with structured output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFormat {
+    /// Short identifier for the schema, sent to providers that require one
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+impl ResponseFormat {
+    /// Format for generated plans
+    pub fn plan() -> Self {
+        Self {
+            name: "plan".to_string(),
+            schema: schema::plan_schema().clone(),
+        }
+    }
+
+    /// Format for task result analyses
+    pub fn task_analysis() -> Self {
+        Self {
+            name: "task_analysis".to_string(),
+            schema: schema::task_analysis_schema().clone(),
+        }
+    }
+
+    /// The `response_format` object used by OpenAI-style chat completion APIs
+    pub fn to_openai(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": self.name,
+                "schema": self.schema,
+            }
+        })
+    }
+}
+
 /// Configuration for LLM generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenerationConfig {
@@ -130,6 +202,24 @@ pub struct GenerationConfig {
     pub frequency_penalty: Option<f32>,
     pub presence_penalty: Option<f32>,
     pub stop_sequences: Option<Vec<String>>,
+    /// Schema to request structured output with, where the provider supports it
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+}
+
+impl GenerationConfig {
+    /// Provider defaults with structured output constrained to `format`
+    pub fn structured(format: ResponseFormat) -> Self {
+        Self {
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(format),
+        }
+    }
 }
 
 impl Default for GenerationConfig {
@@ -141,6 +231,7 @@ impl Default for GenerationConfig {
             frequency_penalty: None,
             presence_penalty: None,
             stop_sequences: None,
+            response_format: None,
         }
     }
 }
@@ -152,6 +243,16 @@ pub struct LlmResponse {
     pub tool_calls: Option<Vec<ToolCall>>,
     pub finish_reason: String,
     pub usage: Option<TokenUsage>,
+    /// Backend that answered, set when the request went through a fallback chain
+    #[serde(default)]
+    pub backend: Option<ResponseBackend>,
+}
+
+/// Provider and model that produced a response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseBackend {
+    pub provider: String,
+    pub model: String,
 }
 
 /// Token usage information
@@ -206,6 +307,53 @@ pub struct TaskAnalysis {
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
+impl TaskAnalysis {
+    /// Build an analysis from the JSON object returned by the model
+    ///
+    /// Missing fields fall back to defaults; callers that need strict input
+    /// validate against `schema::task_analysis_schema()` first.
+    pub fn from_json(analysis_json: &serde_json::Value) -> Self {
+        let success = analysis_json["success"].as_bool().unwrap_or(false);
+        let summary = analysis_json["summary"].as_str().unwrap_or("Analysis unavailable").to_string();
+        let details = analysis_json["details"].as_str().unwrap_or("").to_string();
+        let extracted_data = analysis_json.get("extracted_data").cloned();
+        let next_steps = analysis_json["next_steps"].as_array()
+            .map(|arr| arr.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect::<Vec<String>>());
+        let context_updates = analysis_json["context_updates"].as_object()
+            .map(|obj| obj.iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect());
+        let modified_files = analysis_json["modified_files"].as_array()
+            .map(|arr| arr.iter()
+                .filter_map(|v| v.as_str().map(PathBuf::from))
+                .collect::<Vec<PathBuf>>());
+        let error = if !success {
+            Some(analysis_json["error"].as_str().unwrap_or("Task failed").to_string())
+        } else {
+            None
+        };
+        let metadata = analysis_json["metadata"].as_object()
+            .map(|obj| obj.iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+            .unwrap_or_default();
+
+        Self {
+            success,
+            summary,
+            details,
+            extracted_data,
+            next_steps,
+            context_updates,
+            modified_files,
+            error,
+            metadata,
+        }
+    }
+}
+
 /// Context for task refinement
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskRefinementContext {
@@ -228,10 +376,29 @@ pub struct ModelInfo {
     pub context_length: Option<u32>,
     pub max_output_tokens: Option<u32>,
     pub pricing: Option<ModelPricing>,
+    /// Vocabulary used for token counting (see `tokenizer::TokenizerRegistry`)
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    /// Features the model supports, as far as the provider reports them
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
 }
 
-/// Model pricing information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Features a model supports; `None` means the provider did not say
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// Function calling
+    pub tools: Option<bool>,
+    /// Image input
+    pub vision: Option<bool>,
+    /// Constrained JSON output
+    pub json_mode: Option<bool>,
+    /// Incremental responses
+    pub streaming: Option<bool>,
+}
+
+/// Model pricing information in USD
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ModelPricing {
     pub prompt: Option<f64>, // per 1M tokens
     pub completion: Option<f64>, // per 1M tokens
@@ -260,6 +427,7 @@ pub struct ModelPricing {
 ///         content: "Hello, how are you?".to_string(),
 ///         tool_calls: None,
 ///         tool_call_id: None,
+///         parts: Vec::new(),
 ///     }];
 ///     
 ///     let response = provider.generate(
@@ -352,6 +520,16 @@ pub trait LlmProvider: Send + Sync {
 
     /// Validate that a model is available
     async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError>;
+
+    /// Access token-by-token streaming, if this provider supports it
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        None
+    }
+
+    /// Access text embeddings, if this provider supports them
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        None
+    }
 }
 
 /// Factory for creating LLM providers
@@ -397,7 +575,8 @@ impl LlmProviderFactory {
                     state
                 });
                 
-                Ok(Box::new(openrouter::OpenRouterProvider::new(api_key.clone())) as Box<dyn LlmProvider>)
+                openrouter::OpenRouterProvider::from_settings(api_key.clone(), &config)
+                    .map(|provider| Box::new(provider) as Box<dyn LlmProvider>)
             }
             "gemini" => {
                 debug_checkpoint!(&mut flow_context, "creating_gemini_provider");
@@ -416,7 +595,94 @@ impl LlmProviderFactory {
                     state
                 });
                 
-                Ok(Box::new(gemini::GeminiProvider::new(api_key.clone())) as Box<dyn LlmProvider>)
+                gemini::GeminiProvider::from_settings(api_key.clone(), &config)
+                    .map(|provider| Box::new(provider) as Box<dyn LlmProvider>)
+            }
+            "anthropic" => {
+                debug_checkpoint!(&mut flow_context, "creating_anthropic_provider");
+                let provider = anthropic::AnthropicProvider::from_settings(&config)
+                    .inspect_err(|error| {
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "anthropic_config_invalid");
+                    })?;
+                
+                debug_checkpoint!(&mut flow_context, "anthropic_provider_created", {
+                    let mut state = std::collections::HashMap::new();
+                    state.insert("api_key_length".to_string(), serde_json::Value::Number(serde_json::Number::from(config.get("api_key").map(|k| k.len()).unwrap_or(0) as u64)));
+                    state
+                });
+                
+                Ok(Box::new(provider) as Box<dyn LlmProvider>)
+            }
+            "openai_compatible" => {
+                debug_checkpoint!(&mut flow_context, "creating_openai_compatible_provider");
+                let provider = openai_compatible::OpenAiCompatibleProvider::from_settings(&config)
+                    .inspect_err(|error| {
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "openai_compatible_config_invalid");
+                    })?;
+                
+                debug_checkpoint!(&mut flow_context, "openai_compatible_provider_created", {
+                    let mut state = std::collections::HashMap::new();
+                    state.insert("base_url".to_string(), serde_json::Value::String(provider.base_url().to_string()));
+                    state
+                });
+                
+                Ok(Box::new(provider) as Box<dyn LlmProvider>)
+            }
+            "ollama" => {
+                debug_checkpoint!(&mut flow_context, "creating_ollama_provider");
+                let provider = ollama::OllamaProvider::from_settings(&config)
+                    .inspect_err(|error| {
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "ollama_config_invalid");
+                    })?;
+                
+                debug_checkpoint!(&mut flow_context, "ollama_provider_created", {
+                    let mut state = std::collections::HashMap::new();
+                    state.insert("base_url".to_string(), serde_json::Value::String(provider.base_url().to_string()));
+                    state
+                });
+                
+                Ok(Box::new(provider) as Box<dyn LlmProvider>)
+            }
+            "record" => {
+                debug_checkpoint!(&mut flow_context, "creating_record_provider");
+                let inner_name = config.get("record_provider")
+                    .filter(|name| !matches!(name.to_lowercase().as_str(), "record" | "replay"))
+                    .ok_or_else(|| {
+                        let error = LlmError::Unknown {
+                            message: "Recording requires 'record_provider' naming the provider to wrap".to_string(),
+                        };
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "record_inner_provider_missing");
+                        error
+                    })?;
+                let inner = Self::create_provider(inner_name, config.clone())?;
+                let provider = cassette::RecordingProvider::from_settings(inner, &config)
+                    .inspect_err(|error| {
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "record_config_invalid");
+                    })?;
+                
+                debug_checkpoint!(&mut flow_context, "record_provider_created", {
+                    let mut state = std::collections::HashMap::new();
+                    state.insert("inner_provider".to_string(), serde_json::Value::String(inner_name.clone()));
+                    state.insert("cassette".to_string(), serde_json::Value::String(provider.cassette_path().display().to_string()));
+                    state
+                });
+                
+                Ok(Box::new(provider) as Box<dyn LlmProvider>)
+            }
+            "replay" => {
+                debug_checkpoint!(&mut flow_context, "creating_replay_provider");
+                let provider = cassette::ReplayProvider::from_settings(&config)
+                    .inspect_err(|error| {
+                        crate::debug_error!(&mut flow_context, &crate::utils::errors::KaiError::Llm(error.clone()), "replay_config_invalid");
+                    })?;
+                
+                debug_checkpoint!(&mut flow_context, "replay_provider_created", {
+                    let mut state = std::collections::HashMap::new();
+                    state.insert("cassette".to_string(), serde_json::Value::String(provider.cassette_path().display().to_string()));
+                    state
+                });
+                
+                Ok(Box::new(provider) as Box<dyn LlmProvider>)
             }
             _ => {
                 debug_checkpoint!(&mut flow_context, "unknown_provider_error");
@@ -437,8 +703,106 @@ impl LlmProviderFactory {
         result
     }
 
+    /// Create the active provider from configuration, wrapped in its fallback chain if one is configured
+    ///
+    /// Each `fallback_chain` entry is `provider` or `provider:model`; entries
+    /// without a model use the provider's configured default model, or the
+    /// requested model if it has none. Each provider is held to the rate
+    /// limits in its settings, requests are fitted into the context window of
+    /// the model each backend uses, usage is recorded in the shared ledger and
+    /// the result is served through the response cache unless `cache.enabled`
+    /// is off.
+    pub fn create_from_config(config: &crate::config::Config) -> Result<Box<dyn LlmProvider>, LlmError> {
+        use crate::utils::config::ConfigAccessPattern;
+        
+        // Inside a chain a failure moves on to the next backend instead of retrying or waiting
+        let in_chain = !config.fallback_chain.is_empty();
+        let primary = Self::create_rate_limited(
+            &config.active_provider,
+            config.get_provider_settings(&config.active_provider),
+            in_chain,
+        )?;
+        let primary = Box::new(fitting::FittingProvider::new(primary));
+        if config.fallback_chain.is_empty() {
+            return Ok(Self::with_response_cache(Self::with_usage_ledger(primary, config), config));
+        }
+        
+        let mut chain = fallback::FallbackProvider::new(primary);
+        for entry in &config.fallback_chain {
+            let (provider_name, model) = fallback::parse_fallback_entry(entry);
+            let provider = Self::create_rate_limited(&provider_name, config.get_provider_settings(&provider_name), in_chain)?;
+            let model = model.or_else(|| {
+                config.providers.get(&provider_name).and_then(|p| p.default_model.clone())
+            });
+            // Fitted per backend, so a pinned model is fitted to its own window
+            chain = chain.with_fallback(Box::new(fitting::FittingProvider::new(provider)), model);
+        }
+        
+        Ok(Self::with_response_cache(Self::with_usage_ledger(Box::new(chain), config), config))
+    }
+
+    /// Create a provider that waits on the shared limiter for its name
+    ///
+    /// Limits come from `requests_per_minute`, `tokens_per_minute` and
+    /// `max_in_flight` in the provider's settings. Providers without limits,
+    /// and replayed cassettes, are returned unwrapped. Providers in a fallback
+    /// chain make no HTTP retries and fail fast on rate limits so the chain
+    /// can move on.
+    fn create_rate_limited(
+        provider_name: &str,
+        mut settings: HashMap<String, String>,
+        in_chain: bool,
+    ) -> Result<Box<dyn LlmProvider>, LlmError> {
+        let limits = rate_limit::RateLimits::from_settings(&settings)?;
+        if in_chain {
+            settings.insert("retry_attempts".to_string(), "0".to_string());
+        }
+        if limits.is_unlimited() || provider_name == "replay" {
+            return Self::create_provider(provider_name, settings);
+        }
+        // The limiter owns the 429 policy, so the HTTP layer must not retry them too
+        settings.insert("retry_rate_limits".to_string(), "false".to_string());
+        let provider = Self::create_provider(provider_name, settings)?;
+        let limiter = rate_limit::RateLimiter::shared(provider.provider_name(), limits);
+        let provider = rate_limit::RateLimitedProvider::new(provider, limiter);
+        Ok(Box::new(if in_chain { provider.fail_fast() } else { provider }))
+    }
+
+    /// Record the provider's usage in the shared ledger
+    ///
+    /// Replayed cassettes cost nothing and are not recorded. The ledger sits
+    /// inside the response cache, so cache hits are not counted either.
+    fn with_usage_ledger(provider: Box<dyn LlmProvider>, config: &crate::config::Config) -> Box<dyn LlmProvider> {
+        if config.active_provider == "replay" {
+            return provider;
+        }
+        match usage::UsageLedger::global() {
+            Some(ledger) => Box::new(usage::MeteredProvider::new(provider, ledger)),
+            None => {
+                tracing::warn!("No data directory available; LLM usage will not be recorded");
+                provider
+            }
+        }
+    }
+
+    /// Wrap a provider in the on-disk response cache when caching is enabled
+    ///
+    /// Cassette providers are never cached, so recording sees every request.
+    fn with_response_cache(provider: Box<dyn LlmProvider>, config: &crate::config::Config) -> Box<dyn LlmProvider> {
+        if !config.cache.enabled || matches!(config.active_provider.as_str(), "record" | "replay") {
+            return provider;
+        }
+        match cache::ResponseCache::from_config(&config.cache) {
+            Some(cache) => Box::new(cache::CachingProvider::new(provider, cache)),
+            None => {
+                tracing::warn!("No data directory available; LLM response caching is disabled");
+                provider
+            }
+        }
+    }
+
     /// List all available provider names
     pub fn list_providers() -> Vec<&'static str> {
-        vec!["openrouter", "gemini"]
+        vec!["openrouter", "gemini", "anthropic", "openai_compatible", "ollama", "record", "replay"]
     }
 }
\ No newline at end of file
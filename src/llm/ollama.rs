@@ -0,0 +1,801 @@
+//! Ollama provider implementation for fully offline use
+//!
+//! Talks to a local Ollama daemon through `/api/chat`, `/api/tags`, `/api/show`
+//! and `/api/ps`. No API key is required.
+
+use super::tokenizer::tokenizer_name_for_model;
+use super::content::InlinePart;
+use super::schema;
+use super::usage;
+use super::{
+    FunctionCall, GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, MessageRole,
+    ModelCapabilities, ModelInfo, ResponseFormat, TaskAnalysis, TaskExecutionResult, TaskRefinementContext, TokenUsage, ToolCall,
+    ToolDefinition,
+};
+use crate::utils::http::headers::HeaderBuilder;
+use crate::utils::http::{
+    execute_with_retry, parse_http_error, parse_setting, HttpClient, HttpClientConfig,
+};
+use crate::utils::templates::handlers::{StandardTemplateHandler, TemplateHandler};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default Ollama daemon address
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+/// Context window Ollama loads models with when neither the request nor the Modelfile sets `num_ctx`
+const OLLAMA_DEFAULT_NUM_CTX: u32 = 2048;
+
+/// Ollama local model provider
+pub struct OllamaProvider {
+    http_client: HttpClient,
+    base_url: String,
+    /// Context window requested for every chat call (`options.num_ctx`)
+    num_ctx: Option<u32>,
+    /// How long the daemon keeps the model loaded after a request (e.g. "5m")
+    keep_alive: Option<String>,
+    template_handler: StandardTemplateHandler,
+}
+
+impl OllamaProvider {
+    /// Create a new Ollama provider talking to the default local daemon
+    pub fn new() -> Result<Self, LlmError> {
+        Self::with_config(None, None, None)
+    }
+
+    /// Create a new Ollama provider with custom configuration
+    pub fn with_config(
+        base_url: Option<String>,
+        num_ctx: Option<u32>,
+        client_config: Option<HttpClientConfig>,
+    ) -> Result<Self, LlmError> {
+        // Local models can take a long time to load and generate
+        let client_config = client_config.unwrap_or_else(|| HttpClientConfig {
+            timeout: Duration::from_secs(300),
+            ..HttpClientConfig::default()
+        });
+        let http_client = HttpClient::new(client_config)?;
+
+        Ok(Self {
+            http_client,
+            base_url: base_url
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            num_ctx,
+            keep_alive: None,
+            template_handler: StandardTemplateHandler,
+        })
+    }
+
+    /// Create a provider from factory settings
+    ///
+    /// Recognised keys: `base_url`, `num_ctx`, `keep_alive`, `timeout_secs`,
+    /// `retry_attempts` and `retry_delay_ms`.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let client_config = HttpClientConfig {
+            timeout: Duration::from_secs(300),
+            ..HttpClientConfig::default()
+        }
+        .with_settings(settings)?;
+
+        let mut provider = Self::with_config(
+            settings.get("base_url").cloned(),
+            parse_setting::<u32>(settings, "num_ctx")?,
+            Some(client_config),
+        )?;
+        provider.keep_alive = settings.get("keep_alive").cloned();
+
+        Ok(provider)
+    }
+
+    /// Get the daemon URL this provider talks to
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn create_headers(&self) -> reqwest::header::HeaderMap {
+        HeaderBuilder::new().json_content_type().user_agent("KAI-X/1.0").build()
+    }
+
+    /// Map HTTP errors; Ollama answers unknown models with a 404
+    fn parse_error_response(status: u16, body: &str, model: Option<&str>) -> LlmError {
+        if status == 404 && body.contains("not found") {
+            return LlmError::InvalidModel {
+                model: model.unwrap_or("unknown").to_string(),
+            };
+        }
+        parse_http_error(status, body, model)
+    }
+
+    /// Whether a requested model name refers to a pulled model (`llama3` means `llama3:latest`)
+    fn model_matches(requested: &str, pulled: &str) -> bool {
+        requested == pulled || (!requested.contains(':') && pulled == format!("{}:latest", requested))
+    }
+
+    /// Convert messages to Ollama's chat format
+    ///
+    /// Tool calls carry object arguments and tool results are tagged with the
+    /// name of the tool they answer.
+    fn convert_messages(messages: &[Message]) -> Vec<serde_json::Value> {
+        let mut tool_names: HashMap<&str, &str> = HashMap::new();
+
+        messages
+            .iter()
+            .map(|message| {
+                let mut wire = serde_json::json!({
+                    "role": message.role,
+                    "content": message.content,
+                });
+
+                if let Some(tool_calls) = &message.tool_calls {
+                    wire["tool_calls"] = tool_calls
+                        .iter()
+                        .map(|call| {
+                            tool_names.insert(call.id.as_str(), call.function.name.as_str());
+                            let arguments = match &call.function.arguments {
+                                serde_json::Value::String(raw) => {
+                                    serde_json::from_str(raw).unwrap_or_else(|_| serde_json::json!({}))
+                                }
+                                other => other.clone(),
+                            };
+                            serde_json::json!({
+                                "function": {
+                                    "name": call.function.name,
+                                    "arguments": arguments,
+                                }
+                            })
+                        })
+                        .collect();
+                }
+
+                // Ollama takes images as a separate list of base64 strings
+                if !message.parts.is_empty() {
+                    let mut text = Vec::new();
+                    let mut images = Vec::new();
+                    for part in message.inline_parts() {
+                        match part {
+                            InlinePart::Text(part_text) => text.push(part_text),
+                            InlinePart::Image { data, .. } => images.push(data),
+                        }
+                    }
+                    wire["content"] = text.join("\n\n").into();
+                    if !images.is_empty() {
+                        wire["images"] = images.into();
+                    }
+                }
+
+                if matches!(message.role, MessageRole::Tool) {
+                    if let Some(name) = message
+                        .tool_call_id
+                        .as_deref()
+                        .and_then(|id| tool_names.get(id))
+                    {
+                        wire["tool_name"] = (*name).into();
+                    }
+                }
+
+                wire
+            })
+            .collect()
+    }
+
+    /// Build the `/api/chat` request body
+    fn build_request_body(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> serde_json::Value {
+        let mut request_body = serde_json::json!({
+            "model": model,
+            "messages": Self::convert_messages(messages),
+            "stream": false,
+        });
+
+        let mut options = serde_json::Map::new();
+        if let Some(num_ctx) = self.num_ctx {
+            options.insert("num_ctx".to_string(), num_ctx.into());
+        }
+        if let Some(config) = config {
+            if let Some(temp) = config.temperature {
+                options.insert("temperature".to_string(), temp.into());
+            }
+            if let Some(max_tokens) = config.max_tokens {
+                options.insert("num_predict".to_string(), max_tokens.into());
+            }
+            if let Some(top_p) = config.top_p {
+                options.insert("top_p".to_string(), top_p.into());
+            }
+            if let Some(freq_penalty) = config.frequency_penalty {
+                options.insert("frequency_penalty".to_string(), freq_penalty.into());
+            }
+            if let Some(pres_penalty) = config.presence_penalty {
+                options.insert("presence_penalty".to_string(), pres_penalty.into());
+            }
+            if let Some(stop) = &config.stop_sequences {
+                options.insert("stop".to_string(), stop.clone().into());
+            }
+            // Ollama constrains output to a JSON Schema passed as `format`
+            if let Some(format) = &config.response_format {
+                request_body["format"] = format.schema.clone();
+            }
+        }
+        if !options.is_empty() {
+            request_body["options"] = serde_json::Value::Object(options);
+        }
+
+        if let Some(keep_alive) = &self.keep_alive {
+            request_body["keep_alive"] = keep_alive.clone().into();
+        }
+
+        if let Some(tools) = tools {
+            if !tools.is_empty() {
+                request_body["tools"] = serde_json::to_value(tools).unwrap_or(serde_json::Value::Null);
+            }
+        }
+
+        request_body
+    }
+
+    /// Parse an `/api/chat` response body
+    fn parse_response(body: &serde_json::Value) -> Result<LlmResponse, LlmError> {
+        if let Some(error) = body["error"].as_str() {
+            return Err(LlmError::RequestFailed {
+                status: 400,
+                message: error.to_string(),
+            });
+        }
+
+        let message = body["message"].as_object().ok_or_else(|| LlmError::InvalidResponse {
+            message: "No message in response".to_string(),
+        })?;
+
+        let content = message
+            .get("content")
+            .and_then(|c| c.as_str())
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_string());
+
+        // Ollama does not assign tool call IDs, so synthesise stable ones
+        let tool_calls = message
+            .get("tool_calls")
+            .and_then(|calls| calls.as_array())
+            .filter(|calls| !calls.is_empty())
+            .map(|calls| {
+                calls
+                    .iter()
+                    .enumerate()
+                    .map(|(index, call)| ToolCall {
+                        id: call["id"]
+                            .as_str()
+                            .map(|id| id.to_string())
+                            .unwrap_or_else(|| format!("call_{}", index)),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                            arguments: call["function"]["arguments"].clone(),
+                        },
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+        let finish_reason = body["done_reason"].as_str().unwrap_or("stop").to_string();
+
+        let prompt_tokens = body["prompt_eval_count"].as_u64();
+        let completion_tokens = body["eval_count"].as_u64();
+        let usage = if prompt_tokens.is_some() || completion_tokens.is_some() {
+            let prompt_tokens = prompt_tokens.unwrap_or(0) as u32;
+            let completion_tokens = completion_tokens.unwrap_or(0) as u32;
+            Some(TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            })
+        } else {
+            None
+        };
+
+        Ok(LlmResponse {
+            content,
+            tool_calls,
+            finish_reason,
+            usage,
+            backend: None,
+        })
+    }
+
+    /// Fetch `/api/show` details for a model
+    async fn show_model(&self, model: &str) -> Result<serde_json::Value, LlmError> {
+        let url = format!("{}/api/show", self.base_url);
+        let response = self
+            .http_client
+            .client()
+            .post(&url)
+            .headers(self.create_headers())
+            .json(&serde_json::json!({ "model": model }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            return Err(Self::parse_error_response(status, &body, Some(model)));
+        }
+
+        response.json().await.map_err(|e| LlmError::InvalidResponse {
+            message: format!("Failed to parse model details: {}", e),
+        })
+    }
+
+    /// Context lengths of currently loaded models from `/api/ps`
+    ///
+    /// Older daemons do not report `context_length`; failures are treated as "nothing loaded".
+    async fn loaded_context_lengths(&self) -> HashMap<String, u32> {
+        let url = format!("{}/api/ps", self.base_url);
+        let response = match self.http_client.client().get(&url).headers(self.create_headers()).send().await {
+            Ok(response) if response.status().is_success() => response,
+            _ => return HashMap::new(),
+        };
+
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        body["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| {
+                        let name = m["name"].as_str().or_else(|| m["model"].as_str())?;
+                        let context_length = m["context_length"].as_u64()?;
+                        Some((name.to_string(), context_length as u32))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// `num_ctx` parameter baked into the Modelfile, if any
+    fn modelfile_num_ctx(details: &serde_json::Value) -> Option<u32> {
+        details["parameters"].as_str()?.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Maximum context the model was trained for (`<arch>.context_length`)
+    fn trained_context_length(details: &serde_json::Value) -> Option<u64> {
+        details["model_info"].as_object()?.iter().find_map(|(key, value)| {
+            if key.ends_with(".context_length") {
+                value.as_u64()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Capabilities listed by `/api/show`; older daemons do not report them
+    fn capabilities(details: Option<&serde_json::Value>) -> ModelCapabilities {
+        let listed = details
+            .and_then(|details| details["capabilities"].as_array())
+            .map(|items| items.iter().filter_map(|item| item.as_str()).collect::<Vec<_>>());
+        ModelCapabilities {
+            tools: listed.as_ref().map(|items| items.contains(&"tools")),
+            vision: listed.as_ref().map(|items| items.contains(&"vision")),
+            json_mode: Some(true),
+            streaming: Some(true),
+        }
+    }
+
+    /// Build model info from a `/api/tags` entry and its `/api/show` details
+    ///
+    /// `context_length` is the window the model runs with: what the daemon reports for a
+    /// loaded model, else the configured `num_ctx`, the Modelfile `num_ctx`, or Ollama's default.
+    fn build_model_info(
+        &self,
+        tag: &serde_json::Value,
+        details: Option<&serde_json::Value>,
+        loaded_context: Option<u32>,
+    ) -> Option<ModelInfo> {
+        let name = tag["name"].as_str().or_else(|| tag["model"].as_str())?;
+
+        let context_length = loaded_context
+            .or(self.num_ctx)
+            .or_else(|| details.and_then(Self::modelfile_num_ctx))
+            .unwrap_or(OLLAMA_DEFAULT_NUM_CTX);
+
+        let tag_details = &tag["details"];
+        let mut description_parts: Vec<String> = [
+            tag_details["family"].as_str(),
+            tag_details["parameter_size"].as_str(),
+            tag_details["quantization_level"].as_str(),
+        ]
+        .iter()
+        .flatten()
+        .map(|s| s.to_string())
+        .collect();
+        if let Some(trained) = details.and_then(Self::trained_context_length) {
+            description_parts.push(format!("trained context {}", trained));
+        }
+
+        Some(ModelInfo {
+            id: name.to_string(),
+            name: name.to_string(),
+            description: if description_parts.is_empty() {
+                None
+            } else {
+                Some(description_parts.join(", "))
+            },
+            context_length: Some(context_length),
+            max_output_tokens: None,
+            pricing: None,
+            tokenizer: tokenizer_name_for_model(name),
+            capabilities: Self::capabilities(details),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn provider_name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        let operation = || async {
+            let url = format!("{}/api/tags", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .get(&url)
+                .headers(self.create_headers())
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(Self::parse_error_response(status, &body, None));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse tags response: {}", e),
+            })?;
+
+            body["models"].as_array().cloned().ok_or_else(|| LlmError::InvalidResponse {
+                message: "Expected 'models' array in response".to_string(),
+            })
+        };
+
+        let tags = execute_with_retry(operation, &self.http_client.retry_config()).await?;
+        let loaded = self.loaded_context_lengths().await;
+
+        let mut models = Vec::new();
+        for tag in &tags {
+            let Some(name) = tag["name"].as_str().or_else(|| tag["model"].as_str()) else {
+                continue;
+            };
+            // Details only refine the context length; a failing /api/show must not hide the model
+            let details = self.show_model(name).await.ok();
+            if let Some(info) = self.build_model_info(tag, details.as_ref(), loaded.get(name).copied()) {
+                models.push(info);
+            }
+        }
+
+        Ok(models)
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let request_body = self.build_request_body(messages, model, tools, config);
+
+        let operation = || async {
+            let url = format!("{}/api/chat", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .post(&url)
+                .headers(self.create_headers())
+                .json(&request_body)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(Self::parse_error_response(status, &body, Some(model)));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse chat response: {}", e),
+            })?;
+
+            Self::parse_response(&body)
+        };
+
+        execute_with_retry(operation, &self.http_client.retry_config())
+            .await
+            .inspect(|response| usage::report_generate(self.provider_name(), model, messages, response))
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let messages = self.template_handler.plan_generation_messages(prompt, context)?;
+
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let response = self.generate(&messages, model, None, config).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in response".to_string(),
+        })
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        let models = self.list_models().await?;
+        models
+            .into_iter()
+            .find(|m| Self::model_matches(model, &m.id))
+            .ok_or_else(|| LlmError::InvalidModel {
+                model: model.to_string(),
+            })
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let messages = self.template_handler.task_refinement_messages(
+            task,
+            &context.plan_description,
+            &context.global_context,
+            &context.plan_context,
+            &context.dependency_outputs,
+        )?;
+
+        // Use focused configuration for refinement
+        let config = GenerationConfig {
+            temperature: Some(0.3),
+            max_tokens: Some(4000),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: None,
+        };
+
+        let response = self.generate(&messages, model, None, Some(&config)).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in task refinement response".to_string(),
+        })
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let messages = self
+            .template_handler
+            .execution_analysis_messages(task, execution_result, expected_outcome)?;
+
+        // Use lower temperature for consistent analysis
+        let config = GenerationConfig {
+            temperature: Some(0.1),
+            max_tokens: Some(2048),
+            top_p: Some(0.8),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
+        };
+
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+
+    fn test_provider(server_url: String, num_ctx: Option<&str>) -> OllamaProvider {
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), server_url);
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        if let Some(num_ctx) = num_ctx {
+            settings.insert("num_ctx".to_string(), num_ctx.to_string());
+        }
+        OllamaProvider::from_settings(&settings).unwrap()
+    }
+
+    fn tags_response() -> String {
+        serde_json::json!({
+            "models": [
+                {"name": "llama3.2:latest", "details": {"family": "llama", "parameter_size": "3.2B", "quantization_level": "Q4_K_M"}},
+                {"name": "qwen2.5-coder:7b", "details": {"family": "qwen2"}}
+            ]
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_list_models_reports_context_lengths() {
+        let ps = serde_json::json!({
+            "models": [{"name": "qwen2.5-coder:7b", "context_length": 32768}]
+        });
+        let llama_show = serde_json::json!({
+            "parameters": "num_ctx 8192\nstop \"<|eot_id|>\"",
+            "model_info": {"general.architecture": "llama", "llama.context_length": 131072},
+            "capabilities": ["completion", "tools"]
+        });
+        let qwen_show = serde_json::json!({
+            "model_info": {"qwen2.context_length": 32768}
+        });
+        let (server_url, captured) = mock_server::spawn(vec![
+            (200, tags_response()),
+            (200, ps.to_string()),
+            (200, llama_show.to_string()),
+            (200, qwen_show.to_string()),
+        ]).await;
+        let provider = test_provider(server_url, None);
+
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(models.len(), 2);
+
+        // Modelfile num_ctx applies to the model that is not loaded
+        assert_eq!(models[0].id, "llama3.2:latest");
+        assert_eq!(models[0].context_length, Some(8192));
+        assert!(models[0].description.as_deref().unwrap().contains("trained context 131072"));
+        assert_eq!(models[0].capabilities.tools, Some(true));
+        assert_eq!(models[0].capabilities.vision, Some(false));
+
+        // Loaded models report the window they are actually running with
+        assert_eq!(models[1].context_length, Some(32768));
+        assert_eq!(models[1].capabilities.tools, None);
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests[0].request_line, "GET /api/tags HTTP/1.1");
+        assert_eq!(requests[1].request_line, "GET /api/ps HTTP/1.1");
+        assert_eq!(requests[2].json()["model"], "llama3.2:latest");
+        assert!(requests.iter().all(|r| r.header("authorization").is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_validate_model_uses_pulled_models() {
+        let (server_url, _) = mock_server::spawn(vec![
+            (200, tags_response()),
+            (500, "ps unavailable".to_string()),
+            (404, r#"{"error":"model not found"}"#.to_string()),
+            (404, r#"{"error":"model not found"}"#.to_string()),
+            (200, tags_response()),
+            (500, "ps unavailable".to_string()),
+            (404, r#"{"error":"model not found"}"#.to_string()),
+            (404, r#"{"error":"model not found"}"#.to_string()),
+        ]).await;
+        let provider = test_provider(server_url, Some("16384"));
+
+        // An untagged name resolves to `:latest`; configured num_ctx wins over defaults
+        let info = provider.validate_model("llama3.2").await.unwrap();
+        assert_eq!(info.id, "llama3.2:latest");
+        assert_eq!(info.context_length, Some(16384));
+
+        let missing = provider.validate_model("mistral").await;
+        assert!(matches!(missing, Err(LlmError::InvalidModel { model }) if model == "mistral"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_tools_and_usage() {
+        let response = serde_json::json!({
+            "model": "llama3.2",
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{"function": {"name": "list_files", "arguments": {"path": "src"}}}]
+            },
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 26,
+            "eval_count": 9
+        });
+        let (server_url, captured) = mock_server::spawn(vec![(200, response.to_string())]).await;
+        let provider = test_provider(server_url, Some("8192"));
+
+        let messages = vec![
+            Message {
+                role: MessageRole::Assistant,
+                content: String::new(),
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_0".to_string(),
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "read_file".to_string(),
+                        arguments: serde_json::Value::String("{\"path\":\"a.rs\"}".to_string()),
+                    },
+                }]),
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::Tool,
+                content: "fn a() {}".to_string(),
+                tool_calls: None,
+                tool_call_id: Some("call_0".to_string()),
+                parts: Vec::new(),
+            },
+        ];
+        let config = GenerationConfig {
+            max_tokens: Some(256),
+            ..GenerationConfig::default()
+        };
+
+        let result = provider.generate(&messages, "llama3.2", None, Some(&config)).await.unwrap();
+        assert!(result.content.is_none());
+        let tool_calls = result.tool_calls.unwrap();
+        assert_eq!(tool_calls[0].id, "call_0");
+        assert_eq!(tool_calls[0].function.arguments["path"], "src");
+        assert_eq!(result.usage.unwrap().total_tokens, 35);
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests[0].request_line, "POST /api/chat HTTP/1.1");
+        let body = requests[0].json();
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"]["num_ctx"], 8192);
+        assert_eq!(body["options"]["num_predict"], 256);
+        assert_eq!(body["messages"][0]["tool_calls"][0]["function"]["arguments"]["path"], "a.rs");
+        assert_eq!(body["messages"][1]["tool_name"], "read_file");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_is_invalid_model() {
+        let (server_url, _) = mock_server::spawn(vec![
+            (404, r#"{"error":"model \"nope\" not found, try pulling it first"}"#.to_string()),
+        ]).await;
+        let provider = test_provider(server_url, None);
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: "hi".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let result = provider.generate(&messages, "nope", None, None).await;
+        assert!(matches!(result, Err(LlmError::InvalidModel { model }) if model == "nope"));
+    }
+}
@@ -0,0 +1,769 @@
+//! Generic OpenAI-compatible chat-completions provider
+//!
+//! Talks to any server exposing the OpenAI `/v1/chat/completions` and `/v1/models`
+//! endpoints (vLLM, llama.cpp server, LM Studio, LocalAI, ...). The provider is
+//! entirely driven by `ProviderConfig.base_url` and `ProviderConfig.settings`.
+//! Servers that also expose `/v1/embeddings` can embed text through it.
+
+use super::embeddings::{embed_in_batches, EmbeddingLlmProvider, EmbeddingResponse};
+use super::tokenizer::tokenizer_name_for_model;
+use super::schema;
+use super::usage;
+use super::{
+    FunctionCall, GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, MessageRole,
+    ModelCapabilities, ModelInfo, ResponseFormat, TaskAnalysis, TaskExecutionResult, TaskRefinementContext, TokenUsage, ToolCall,
+    ToolDefinition,
+};
+use crate::utils::http::headers::HeaderBuilder;
+use crate::utils::http::{
+    execute_with_retry, parse_http_error, parse_setting, HttpClient, HttpClientConfig,
+};
+use crate::utils::templates::handlers::{StandardTemplateHandler, TemplateHandler};
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// Provider name used in configuration and by the factory
+pub const PROVIDER_NAME: &str = "openai_compatible";
+
+/// Texts sent per embeddings request unless `embedding_batch_size` is set
+pub const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 128;
+
+/// Provider for any OpenAI-compatible chat-completions endpoint
+pub struct OpenAiCompatibleProvider {
+    http_client: HttpClient,
+    api_key: Option<String>,
+    base_url: String,
+    embedding_batch_size: usize,
+    template_handler: StandardTemplateHandler,
+}
+
+impl OpenAiCompatibleProvider {
+    /// Create a new provider for the given base URL (e.g. `http://localhost:8000/v1`)
+    pub fn new(base_url: String, api_key: Option<String>) -> Result<Self, LlmError> {
+        Self::with_config(base_url, api_key, None)
+    }
+
+    /// Create a new provider with custom HTTP client configuration
+    pub fn with_config(
+        base_url: String,
+        api_key: Option<String>,
+        client_config: Option<HttpClientConfig>,
+    ) -> Result<Self, LlmError> {
+        let http_client = HttpClient::new(client_config.unwrap_or_default())?;
+
+        Ok(Self {
+            http_client,
+            // Self-hosted servers frequently run without authentication
+            api_key: api_key.filter(|key| !key.trim().is_empty()),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            embedding_batch_size: DEFAULT_EMBEDDING_BATCH_SIZE,
+            template_handler: StandardTemplateHandler,
+        })
+    }
+
+    /// Create a provider from factory settings
+    ///
+    /// Recognised keys: `base_url` (required), `api_key`, `embedding_batch_size`,
+    /// `timeout_secs`, `retry_attempts` and `retry_delay_ms`.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let base_url = settings
+            .get("base_url")
+            .filter(|url| !url.trim().is_empty())
+            .ok_or_else(|| LlmError::Unknown {
+                message: "OpenAI-compatible provider requires a base_url".to_string(),
+            })?;
+
+        let client_config = HttpClientConfig::default().with_settings(settings)?;
+
+        let mut provider =
+            Self::with_config(base_url.clone(), settings.get("api_key").cloned(), Some(client_config))?;
+        if let Some(batch_size) = parse_setting::<usize>(settings, "embedding_batch_size")? {
+            provider.embedding_batch_size = batch_size.max(1);
+        }
+
+        Ok(provider)
+    }
+
+    /// Base URL requests are sent to
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Parse an embeddings response, ordering the vectors by their `index`
+    fn parse_embeddings(body: &serde_json::Value) -> Result<EmbeddingResponse, LlmError> {
+        let data = body["data"].as_array().ok_or_else(|| LlmError::InvalidResponse {
+            message: "Expected 'data' array in embeddings response".to_string(),
+        })?;
+
+        let mut indexed = data
+            .iter()
+            .enumerate()
+            .map(|(position, item)| {
+                let vector = item["embedding"]
+                    .as_array()
+                    .ok_or_else(|| LlmError::InvalidResponse {
+                        message: "Embedding is not a list of numbers".to_string(),
+                    })?
+                    .iter()
+                    .map(|value| value.as_f64().map(|n| n as f32))
+                    .collect::<Option<Vec<f32>>>()
+                    .ok_or_else(|| LlmError::InvalidResponse {
+                        message: "Embedding is not a list of numbers".to_string(),
+                    })?;
+                let index = item["index"].as_u64().map_or(position, |n| n as usize);
+                Ok((index, vector))
+            })
+            .collect::<Result<Vec<_>, LlmError>>()?;
+        indexed.sort_by_key(|(index, _)| *index);
+
+        // Embedding models only consume input, so there are no completion tokens
+        let usage = body["usage"]["prompt_tokens"].as_u64().map(|prompt| TokenUsage {
+            prompt_tokens: prompt as u32,
+            completion_tokens: 0,
+            total_tokens: body["usage"]["total_tokens"].as_u64().unwrap_or(prompt) as u32,
+        });
+
+        Ok(EmbeddingResponse {
+            embeddings: indexed.into_iter().map(|(_, vector)| vector).collect(),
+            usage,
+        })
+    }
+
+    /// Create request headers, adding Bearer auth only when a key is configured
+    fn create_headers(&self) -> reqwest::header::HeaderMap {
+        let builder = HeaderBuilder::new().json_content_type().user_agent("KAI-X/1.0");
+
+        match &self.api_key {
+            Some(api_key) => match builder.bearer_auth(api_key) {
+                Ok(builder) => builder.build(),
+                Err(_) => HeaderBuilder::new().json_content_type().user_agent("KAI-X/1.0").build(),
+            },
+            None => builder.build(),
+        }
+    }
+
+    /// Convert messages to the chat-completions wire format
+    ///
+    /// Tool call arguments travel as JSON-encoded strings on the wire.
+    fn to_wire_messages(messages: &[Message]) -> Vec<serde_json::Value> {
+        messages
+            .iter()
+            .map(|message| {
+                let mut wire = serde_json::json!({
+                    "role": message.role,
+                    "content": message.openai_content(),
+                });
+
+                if let Some(tool_calls) = &message.tool_calls {
+                    wire["tool_calls"] = tool_calls
+                        .iter()
+                        .map(|call| {
+                            let arguments = match &call.function.arguments {
+                                serde_json::Value::String(raw) => raw.clone(),
+                                other => other.to_string(),
+                            };
+                            serde_json::json!({
+                                "id": call.id,
+                                "type": call.r#type,
+                                "function": {
+                                    "name": call.function.name,
+                                    "arguments": arguments,
+                                }
+                            })
+                        })
+                        .collect();
+                }
+
+                if matches!(message.role, MessageRole::Tool) {
+                    if let Some(tool_call_id) = &message.tool_call_id {
+                        wire["tool_call_id"] = tool_call_id.clone().into();
+                    }
+                }
+
+                wire
+            })
+            .collect()
+    }
+
+    /// Parse tool calls from a response message, decoding string arguments into JSON
+    fn parse_tool_calls(calls: &[serde_json::Value]) -> Result<Vec<ToolCall>, LlmError> {
+        calls
+            .iter()
+            .map(|call| {
+                let function = &call["function"];
+                let name = function["name"].as_str().ok_or_else(|| LlmError::InvalidResponse {
+                    message: format!("Tool call without function name: {}", call),
+                })?;
+
+                let arguments = match &function["arguments"] {
+                    serde_json::Value::String(raw) => serde_json::from_str(raw)
+                        .unwrap_or_else(|_| serde_json::Value::String(raw.clone())),
+                    serde_json::Value::Null => serde_json::json!({}),
+                    other => other.clone(),
+                };
+
+                Ok(ToolCall {
+                    id: call["id"].as_str().unwrap_or_default().to_string(),
+                    r#type: call["type"].as_str().unwrap_or("function").to_string(),
+                    function: FunctionCall {
+                        name: name.to_string(),
+                        arguments,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Build the chat-completions request body
+    fn build_request_body(
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<serde_json::Value, LlmError> {
+        let mut request_body = serde_json::json!({
+            "model": model,
+            "messages": Self::to_wire_messages(messages),
+        });
+
+        // Apply generation configuration
+        if let Some(config) = config {
+            if let Some(temp) = config.temperature {
+                request_body["temperature"] = temp.into();
+            }
+            if let Some(max_tokens) = config.max_tokens {
+                request_body["max_tokens"] = max_tokens.into();
+            }
+            if let Some(top_p) = config.top_p {
+                request_body["top_p"] = top_p.into();
+            }
+            if let Some(freq_penalty) = config.frequency_penalty {
+                request_body["frequency_penalty"] = freq_penalty.into();
+            }
+            if let Some(pres_penalty) = config.presence_penalty {
+                request_body["presence_penalty"] = pres_penalty.into();
+            }
+            if let Some(stop) = &config.stop_sequences {
+                request_body["stop"] = serde_json::to_value(stop)?;
+            }
+            if let Some(format) = &config.response_format {
+                request_body["response_format"] = format.to_openai();
+            }
+        }
+
+        // Add tools if provided
+        if let Some(tools) = tools {
+            if !tools.is_empty() {
+                request_body["tools"] = serde_json::to_value(tools)?;
+                request_body["tool_choice"] = "auto".into();
+            }
+        }
+
+        Ok(request_body)
+    }
+
+    /// Parse a chat-completions response body
+    fn parse_response(body: &serde_json::Value) -> Result<LlmResponse, LlmError> {
+        // Some servers report errors with a 200 status
+        if let Some(error) = body.get("error") {
+            return Err(LlmError::RequestFailed {
+                status: 400,
+                message: error.to_string(),
+            });
+        }
+
+        let choice = body["choices"][0].as_object().ok_or_else(|| LlmError::InvalidResponse {
+            message: "No choices in response".to_string(),
+        })?;
+
+        let message = &choice["message"];
+        let content = message["content"].as_str().map(|s| s.to_string());
+
+        let tool_calls = match message["tool_calls"].as_array() {
+            Some(calls) if !calls.is_empty() => Some(Self::parse_tool_calls(calls)?),
+            _ => None,
+        };
+
+        let finish_reason = choice["finish_reason"].as_str().unwrap_or("unknown").to_string();
+
+        let usage = body["usage"].as_object().map(|u| TokenUsage {
+            prompt_tokens: u["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+            completion_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
+            total_tokens: u["total_tokens"].as_u64().unwrap_or(0) as u32,
+        });
+
+        Ok(LlmResponse {
+            content,
+            tool_calls,
+            finish_reason,
+            usage,
+            backend: None,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleProvider {
+    fn provider_name(&self) -> &str {
+        PROVIDER_NAME
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        let operation = || async {
+            let url = format!("{}/models", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .get(&url)
+                .headers(self.create_headers())
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(parse_http_error(status, &body, None));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse models response: {}", e),
+            })?;
+
+            let data = body["data"].as_array().ok_or_else(|| LlmError::InvalidResponse {
+                message: "Expected 'data' array in response".to_string(),
+            })?;
+
+            let models = data
+                .iter()
+                .filter_map(|model| {
+                    let id = model["id"].as_str()?;
+                    Some(ModelInfo {
+                        id: id.to_string(),
+                        name: model["name"].as_str().unwrap_or(id).to_string(),
+                        description: model["description"].as_str().map(|s| s.to_string()),
+                        // vLLM reports `max_model_len`, others use `context_length`
+                        context_length: model["context_length"]
+                            .as_u64()
+                            .or_else(|| model["max_model_len"].as_u64())
+                            .map(|n| n as u32),
+                        max_output_tokens: model["max_completion_tokens"].as_u64().map(|n| n as u32),
+                        pricing: None,
+                        tokenizer: tokenizer_name_for_model(id),
+                        // The OpenAI-style listing does not describe model features
+                        capabilities: ModelCapabilities::default(),
+                    })
+                })
+                .collect();
+
+            Ok(models)
+        };
+
+        execute_with_retry(operation, &self.http_client.retry_config()).await
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let request_body = Self::build_request_body(messages, model, tools, config)?;
+
+        let operation = || async {
+            let url = format!("{}/chat/completions", self.base_url);
+            let response = self
+                .http_client
+                .client()
+                .post(&url)
+                .headers(self.create_headers())
+                .json(&request_body)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(parse_http_error(status, &body, Some(model)));
+            }
+
+            let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to parse generation response: {}", e),
+            })?;
+
+            Self::parse_response(&body)
+        };
+
+        execute_with_retry(operation, &self.http_client.retry_config())
+            .await
+            .inspect(|response| usage::report_generate(self.provider_name(), model, messages, response))
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let messages = self.template_handler.plan_generation_messages(prompt, context)?;
+
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let response = self.generate(&messages, model, None, config).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in response".to_string(),
+        })
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        let models = self.list_models().await?;
+        models
+            .into_iter()
+            .find(|m| m.id == model)
+            .ok_or_else(|| LlmError::InvalidModel {
+                model: model.to_string(),
+            })
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let messages = self.template_handler.task_refinement_messages(
+            task,
+            &context.plan_description,
+            &context.global_context,
+            &context.plan_context,
+            &context.dependency_outputs,
+        )?;
+
+        // Use focused configuration for refinement
+        let config = GenerationConfig {
+            temperature: Some(0.3),
+            max_tokens: Some(4000),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: None,
+        };
+
+        let response = self.generate(&messages, model, None, Some(&config)).await?;
+
+        response.content.ok_or_else(|| LlmError::InvalidResponse {
+            message: "No content in task refinement response".to_string(),
+        })
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let messages = self
+            .template_handler
+            .execution_analysis_messages(task, execution_result, expected_outcome)?;
+
+        // Use lower temperature for consistent analysis
+        let config = GenerationConfig {
+            temperature: Some(0.1),
+            max_tokens: Some(2048),
+            top_p: Some(0.8),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
+        };
+
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
+    }
+
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl EmbeddingLlmProvider for OpenAiCompatibleProvider {
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingResponse, LlmError> {
+        embed_in_batches(texts, self.embedding_batch_size, |batch| async move {
+            let request_body = serde_json::json!({
+                "model": model,
+                "input": batch,
+            });
+
+            let operation = || async {
+                let url = format!("{}/embeddings", self.base_url);
+                let response = self
+                    .http_client
+                    .client()
+                    .post(&url)
+                    .headers(self.create_headers())
+                    .json(&request_body)
+                    .send()
+                    .await?;
+
+                if !response.status().is_success() {
+                    let status = response.status().as_u16();
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(parse_http_error(status, &body, Some(model)));
+                }
+
+                let body: serde_json::Value = response.json().await.map_err(|e| LlmError::InvalidResponse {
+                    message: format!("Failed to parse embeddings response: {}", e),
+                })?;
+
+                Self::parse_embeddings(&body)
+            };
+
+            execute_with_retry(operation, &self.http_client.retry_config()).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+    use crate::llm::FunctionDefinition;
+
+    fn test_provider(server_url: String, api_key: Option<&str>) -> OpenAiCompatibleProvider {
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), format!("{}/v1", server_url));
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        if let Some(key) = api_key {
+            settings.insert("api_key".to_string(), key.to_string());
+        }
+        OpenAiCompatibleProvider::from_settings(&settings).unwrap()
+    }
+
+    #[test]
+    fn test_from_settings_requires_base_url() {
+        let result = OpenAiCompatibleProvider::from_settings(&HashMap::new());
+        assert!(result.is_err());
+
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), "http://localhost:8000/v1/".to_string());
+        settings.insert("retry_attempts".to_string(), "many".to_string());
+        assert!(OpenAiCompatibleProvider::from_settings(&settings).is_err());
+
+        settings.insert("retry_attempts".to_string(), "2".to_string());
+        let provider = OpenAiCompatibleProvider::from_settings(&settings).unwrap();
+        assert_eq!(provider.base_url(), "http://localhost:8000/v1");
+        assert_eq!(provider.provider_name(), "openai_compatible");
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_tool_calls() {
+        let response = serde_json::json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "read_file", "arguments": "{\"path\":\"src/main.rs\"}"}
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17}
+        });
+        let (server_url, captured) = mock_server::spawn(vec![(200, response.to_string())]).await;
+        let provider = test_provider(server_url, Some("secret"));
+
+        let tools = vec![ToolDefinition {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "read_file".to_string(),
+                description: "Read a file".to_string(),
+                parameters: serde_json::json!({"type": "object"}),
+            },
+        }];
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: "Show me main.rs".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let result = provider.generate(&messages, "local-model", Some(&tools), None).await.unwrap();
+        let tool_calls = result.tool_calls.unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "read_file");
+        assert_eq!(tool_calls[0].function.arguments["path"], "src/main.rs");
+        assert_eq!(result.finish_reason, "tool_calls");
+        assert_eq!(result.usage.unwrap().total_tokens, 17);
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests[0].request_line, "POST /v1/chat/completions HTTP/1.1");
+        assert_eq!(requests[0].header("authorization").as_deref(), Some("Bearer secret"));
+        let body = requests[0].json();
+        assert_eq!(body["model"], "local-model");
+        assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+        assert_eq!(body["tool_choice"], "auto");
+    }
+
+    #[tokio::test]
+    async fn test_tool_results_are_sent_in_wire_format() {
+        let response = serde_json::json!({
+            "choices": [{"message": {"content": "done"}, "finish_reason": "stop"}]
+        });
+        let (server_url, captured) = mock_server::spawn(vec![(200, response.to_string())]).await;
+        let provider = test_provider(server_url, None);
+
+        let messages = vec![
+            Message {
+                role: MessageRole::Assistant,
+                content: String::new(),
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_1".to_string(),
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "list_files".to_string(),
+                        arguments: serde_json::json!({"path": "."}),
+                    },
+                }]),
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: MessageRole::Tool,
+                content: "Cargo.toml".to_string(),
+                tool_calls: None,
+                tool_call_id: Some("call_1".to_string()),
+                parts: Vec::new(),
+            },
+        ];
+
+        let result = provider.generate(&messages, "local-model", None, None).await.unwrap();
+        assert_eq!(result.content.as_deref(), Some("done"));
+
+        let requests = captured.lock().unwrap();
+        assert!(requests[0].header("authorization").is_none());
+        let body = requests[0].json();
+        assert_eq!(body["messages"][0]["tool_calls"][0]["function"]["arguments"], "{\"path\":\".\"}");
+        assert_eq!(body["messages"][1]["role"], "tool");
+        assert_eq!(body["messages"][1]["tool_call_id"], "call_1");
+        assert!(body.get("tools").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_validate_models() {
+        let models = serde_json::json!({
+            "object": "list",
+            "data": [
+                {"id": "llama-3-8b", "object": "model", "max_model_len": 8192},
+                {"id": "qwen2.5-coder", "object": "model"}
+            ]
+        });
+        let (server_url, captured) = mock_server::spawn(vec![
+            (200, models.to_string()),
+            (200, models.to_string()),
+        ]).await;
+        let provider = test_provider(server_url, None);
+
+        let listed = provider.list_models().await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].id, "llama-3-8b");
+        assert_eq!(listed[0].context_length, Some(8192));
+        assert_eq!(listed[1].context_length, None);
+
+        let missing = provider.validate_model("gpt-4").await;
+        assert!(matches!(missing, Err(LlmError::InvalidModel { model }) if model == "gpt-4"));
+
+        assert_eq!(captured.lock().unwrap()[0].request_line, "GET /v1/models HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn test_http_errors_are_mapped() {
+        let (server_url, _) = mock_server::spawn(vec![
+            (401, r#"{"error": {"message": "bad key"}}"#.to_string()),
+            (400, r#"{"error": {"message": "The model `foo` does not exist"}}"#.to_string()),
+        ]).await;
+        let provider = test_provider(server_url, Some("wrong"));
+
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: "hi".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+
+        let auth = provider.generate(&messages, "foo", None, None).await;
+        assert!(matches!(auth, Err(LlmError::Authentication { .. })));
+
+        let model = provider.generate(&messages, "foo", None, None).await;
+        assert!(matches!(model, Err(LlmError::InvalidModel { model }) if model == "foo"));
+    }
+
+    #[tokio::test]
+    async fn test_embed_sends_batches_and_orders_vectors_by_index() {
+        let first = serde_json::json!({
+            "data": [
+                {"index": 1, "embedding": [0.0, 1.0]},
+                {"index": 0, "embedding": [1.0, 0.0]}
+            ],
+            "usage": {"prompt_tokens": 6, "total_tokens": 6}
+        });
+        let second = serde_json::json!({
+            "data": [{"index": 0, "embedding": [0.5, 0.5]}],
+            "usage": {"prompt_tokens": 2, "total_tokens": 2}
+        });
+        let (server_url, captured) = mock_server::spawn(vec![
+            (200, first.to_string()),
+            (200, second.to_string()),
+        ]).await;
+        let mut settings = HashMap::new();
+        settings.insert("base_url".to_string(), format!("{}/v1", server_url));
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        settings.insert("embedding_batch_size".to_string(), "2".to_string());
+        let provider = OpenAiCompatibleProvider::from_settings(&settings).unwrap();
+
+        let texts = vec!["fn main".to_string(), "struct Plan".to_string(), "mod llm".to_string()];
+        let response = provider
+            .as_embedding()
+            .unwrap()
+            .embed(&texts, "nomic-embed-text")
+            .await
+            .unwrap();
+
+        assert_eq!(response.embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]]);
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 8);
+        assert_eq!(usage.completion_tokens, 0);
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request_line, "POST /v1/embeddings HTTP/1.1");
+        assert_eq!(requests[0].json()["model"], "nomic-embed-text");
+        assert_eq!(requests[0].json()["input"], serde_json::json!(["fn main", "struct Plan"]));
+        assert_eq!(requests[1].json()["input"], serde_json::json!(["mod llm"]));
+    }
+}
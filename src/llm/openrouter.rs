@@ -1,13 +1,21 @@
 //! OpenRouter LLM provider implementation
 
+use super::tokenizer::tokenizer_name_for_model;
+use super::schema;
+use super::usage;
 use super::{
     GenerationConfig, LlmError, LlmProvider, LlmResponse, Message,
-    ModelInfo, TokenUsage, ToolCall, ToolDefinition, TaskAnalysis, TaskExecutionResult, TaskRefinementContext,
+    ModelCapabilities, ModelInfo, ResponseFormat, TokenUsage, ToolCall, ToolDefinition, TaskAnalysis, TaskExecutionResult, TaskRefinementContext,
 };
+use super::streaming::{sse_stream, LlmStream, SseDecoder, SseEvent, StreamChunk, StreamingLlmProvider};
 use async_trait::async_trait;
 use reqwest::Client;
 use std::time::Duration;
 use crate::utils::debug::DEBUG_TRACER;
+use crate::utils::http::{
+    execute_with_retry, extract_retry_after, HttpClientConfig, RetryConfig, DEFAULT_RETRY_ATTEMPTS,
+    DEFAULT_RETRY_DELAY,
+};
 use crate::{debug_checkpoint, debug_error};
 use std::collections::HashMap;
 
@@ -16,8 +24,7 @@ pub struct OpenRouterProvider {
     client: Client,
     api_key: String,
     base_url: String,
-    retry_attempts: usize,
-    retry_delay: Duration,
+    retry: RetryConfig,
 }
 
 impl OpenRouterProvider {
@@ -30,8 +37,7 @@ impl OpenRouterProvider {
                 .unwrap_or_else(|_| Client::new()),
             api_key,
             base_url: "https://openrouter.ai/api/v1".to_string(),
-            retry_attempts: 3,
-            retry_delay: Duration::from_millis(1000),
+            retry: HttpClientConfig::default().retry_config(),
         }
     }
 
@@ -49,11 +55,30 @@ impl OpenRouterProvider {
                 .unwrap_or_else(|_| Client::new()),
             api_key,
             base_url: base_url.unwrap_or_else(|| "https://openrouter.ai/api/v1".to_string()),
-            retry_attempts: retry_attempts.unwrap_or(3),
-            retry_delay: retry_delay.unwrap_or_else(|| Duration::from_millis(1000)),
+            retry: HttpClientConfig {
+                retry_attempts: retry_attempts.unwrap_or(DEFAULT_RETRY_ATTEMPTS),
+                retry_delay: retry_delay.unwrap_or(DEFAULT_RETRY_DELAY),
+                ..HttpClientConfig::default()
+            }
+            .retry_config(),
         }
     }
 
+    /// Create a provider from factory settings
+    ///
+    /// Recognised keys: `base_url`, `timeout_secs`, `retry_attempts`,
+    /// `retry_delay_ms` and `retry_rate_limits`.
+    pub fn from_settings(api_key: String, settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        let client_config = HttpClientConfig::default().with_settings(settings)?;
+        let mut provider = Self::with_config(api_key, settings.get("base_url").cloned(), None, None);
+        provider.client = Client::builder()
+            .timeout(client_config.timeout)
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        provider.retry = client_config.retry_config();
+        Ok(provider)
+    }
+
     /// Create OpenRouter request headers
     fn create_headers(&self) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
@@ -82,72 +107,19 @@ impl OpenRouterProvider {
         F: Fn() -> Fut,
         Fut: std::future::Future<Output = Result<T, LlmError>> + Send,
     {
-        let mut last_error = None;
-        
-        for attempt in 0..=self.retry_attempts {
-            match operation().await {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    last_error = Some(e);
-                    
-                    if attempt < self.retry_attempts {
-                        // Check if error is retryable
-                        if let Some(ref error) = last_error {
-                            match error {
-                                LlmError::RateLimit { retry_after } => {
-                                    // Use retry_after if provided, otherwise use exponential backoff
-                                    let delay = if let Some(retry_after) = retry_after {
-                                        Duration::from_secs(*retry_after)
-                                    } else {
-                                        self.retry_delay * (attempt as u32 + 1) * 2
-                                    };
-                                    tokio::time::sleep(delay).await;
-                                }
-                                LlmError::Network(_) => {
-                                    tokio::time::sleep(self.retry_delay * (attempt as u32 + 1)).await;
-                                }
-                                LlmError::RequestFailed { status, .. } if *status >= 500 => {
-                                    tokio::time::sleep(self.retry_delay * (attempt as u32 + 1)).await;
-                                }
-                                _ => {
-                                    // Non-retryable error, break immediately
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        Err(last_error.unwrap_or_else(|| LlmError::Unknown {
-            message: "Retry operation failed with no error".to_string(),
-        }))
+        execute_with_retry(operation, &self.retry).await
     }
 
     /// Parse error response and return appropriate LlmError
     fn parse_error_response(status: u16, body: &str) -> LlmError {
+        if let Some(error) = crate::utils::http::context_length_error(status, body) {
+            return error;
+        }
+
         match status {
-            429 => {
-                // Try to extract retry-after from the response
-                let retry_after = if body.contains("retry_after") {
-                    // Try to parse retry_after from JSON response
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
-                        json.get("retry_after")
-                            .and_then(|v| v.as_u64())
-                            .or_else(|| {
-                                json.get("error")
-                                    .and_then(|e| e.get("retry_after"))
-                                    .and_then(|v| v.as_u64())
-                            })
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
-                LlmError::RateLimit { retry_after }
-            }
+            429 => LlmError::RateLimit {
+                retry_after: extract_retry_after(body),
+            },
             401 | 403 => LlmError::Authentication {
                 message: "Invalid API key or insufficient permissions".to_string(),
             },
@@ -169,6 +141,295 @@ impl OpenRouterProvider {
             },
         }
     }
+
+    /// Map an unsuccessful generation response, naming the requested model where possible
+    fn generation_error(status: u16, body: &str, model: &str) -> LlmError {
+        // Special handling for model not found errors
+        if status == 400 && (body.contains("model") && body.contains("not found")) {
+            return LlmError::InvalidModel {
+                model: model.to_string(),
+            };
+        }
+
+        Self::parse_error_response(status, body)
+    }
+
+    /// Build the chat completions request body
+    fn build_request_body(
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<serde_json::Value, LlmError> {
+        let mut request_body = serde_json::json!({
+            "model": model,
+            "messages": super::content::openai_messages(messages)?
+        });
+
+        // Apply generation configuration
+        if let Some(config) = config {
+            if let Some(temp) = config.temperature {
+                request_body["temperature"] = temp.into();
+            }
+            if let Some(max_tokens) = config.max_tokens {
+                request_body["max_tokens"] = max_tokens.into();
+            }
+            if let Some(top_p) = config.top_p {
+                request_body["top_p"] = top_p.into();
+            }
+            if let Some(freq_penalty) = config.frequency_penalty {
+                request_body["frequency_penalty"] = freq_penalty.into();
+            }
+            if let Some(pres_penalty) = config.presence_penalty {
+                request_body["presence_penalty"] = pres_penalty.into();
+            }
+            if let Some(stop) = &config.stop_sequences {
+                request_body["stop"] = serde_json::to_value(stop)?;
+            }
+            if let Some(format) = &config.response_format {
+                request_body["response_format"] = format.to_openai();
+            }
+        }
+
+        // Add tools if provided
+        if let Some(tools) = tools {
+            request_body["tools"] = serde_json::to_value(tools)?;
+            request_body["tool_choice"] = "auto".into();
+        }
+
+        Ok(request_body)
+    }
+
+    /// Build plan generation messages from the structured prompt template
+    fn plan_messages(prompt: &str, context: &str) -> Result<Vec<Message>, LlmError> {
+        let template = super::prompts::PromptTemplates::plan_generation();
+        let prompt_context = super::prompts::PromptContext::new()
+            .with_variable("context", context)
+            .with_variable("request", prompt)
+            .with_variable("working_directory", "/Users/dovcaspi/develop/KAI-X") // TODO: Pass actual working directory
+            .with_variable("project_type", "Rust application") // TODO: Detect project type
+            .with_variable("current_state", "Initial state"); // TODO: Get actual current state
+        
+        let (system_message, user_message) = template.fill(&prompt_context)
+            .map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to fill prompt template: {}", e),
+            })?;
+
+        Ok(Self::system_and_user(system_message, user_message))
+    }
+
+    /// Build task refinement messages from the prompt template
+    fn refinement_messages(task: &crate::planning::Task, context: &TaskRefinementContext) -> Result<Vec<Message>, LlmError> {
+        let template = super::prompts::PromptTemplates::task_refinement();
+        let prompt_context = super::prompts::PromptContext::new()
+            .with_variable("plan_description", &context.plan_description)
+            .with_variable("task_id", &task.id)
+            .with_variable("task_type", &format!("{:?}", task.task_type))
+            .with_variable("task_description", &task.description)
+            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
+            .with_variable("global_context", &context.global_context)
+            .with_variable("plan_context", &context.plan_context)
+            .with_variable("dependency_outputs", &serde_json::to_string_pretty(&context.dependency_outputs).unwrap_or_default());
+        
+        let (system_message, user_message) = template.fill(&prompt_context)
+            .map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to fill task refinement template: {}", e),
+            })?;
+
+        Ok(Self::system_and_user(system_message, user_message))
+    }
+
+    /// Generation settings for task refinement
+    fn refinement_config() -> GenerationConfig {
+        GenerationConfig {
+            temperature: Some(0.3), // Lower temperature for more focused refinement
+            max_tokens: Some(4000),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: None,
+        }
+    }
+
+    /// Build execution analysis messages from the prompt template
+    fn analysis_messages(
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+    ) -> Result<Vec<Message>, LlmError> {
+        let template = super::prompts::PromptTemplates::execution_analysis();
+        let prompt_context = super::prompts::PromptContext::new()
+            .with_variable("task_id", &task.id)
+            .with_variable("task_type", &format!("{:?}", task.task_type))
+            .with_variable("task_description", &task.description)
+            .with_variable("expected_outcome", expected_outcome)
+            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
+            .with_variable("success", &execution_result.success.to_string())
+            .with_variable("exit_code", &execution_result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "N/A".to_string()))
+            .with_variable("execution_time_ms", &execution_result.execution_time_ms.to_string())
+            .with_variable("stdout", execution_result.stdout.as_deref().unwrap_or(""))
+            .with_variable("stderr", execution_result.stderr.as_deref().unwrap_or(""))
+            .with_variable("output_data", &serde_json::to_string_pretty(&execution_result.output).unwrap_or_else(|_| "null".to_string()))
+            .with_variable("error_message", execution_result.error.as_deref().unwrap_or(""))
+            .with_variable("plan_description", "")
+            .with_variable("plan_context", "")
+            .with_variable("task_dependencies", &serde_json::to_string(&task.dependencies).unwrap_or_default());
+        
+        let (system_message, user_message) = template.fill(&prompt_context)
+            .map_err(|e| LlmError::InvalidResponse {
+                message: format!("Failed to fill execution analysis template: {}", e),
+            })?;
+
+        Ok(Self::system_and_user(system_message, user_message))
+    }
+
+    /// Generation settings for execution analysis
+    fn analysis_config() -> GenerationConfig {
+        GenerationConfig {
+            temperature: Some(0.1),
+            max_tokens: Some(2048),
+            top_p: Some(0.8),
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
+        }
+    }
+
+    fn system_and_user(system_message: String, user_message: String) -> Vec<Message> {
+        vec![
+            Message {
+                role: super::MessageRole::System,
+                content: system_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+            Message {
+                role: super::MessageRole::User,
+                content: user_message,
+                tool_calls: None,
+                tool_call_id: None,
+                parts: Vec::new(),
+            },
+        ]
+    }
+}
+
+/// Capabilities of a `/models` entry from its supported parameters and input modalities
+pub(crate) fn model_capabilities(model: &serde_json::Value) -> ModelCapabilities {
+    let has = |list: &serde_json::Value, name: &str| {
+        list.as_array().map(|items| items.iter().any(|item| item.as_str() == Some(name)))
+    };
+    let parameters = &model["supported_parameters"];
+    let json_mode = match (has(parameters, "response_format"), has(parameters, "structured_outputs")) {
+        (Some(format), Some(structured)) => Some(format || structured),
+        _ => None,
+    };
+    ModelCapabilities {
+        tools: has(parameters, "tools"),
+        vision: has(&model["architecture"]["input_modalities"], "image"),
+        json_mode,
+        streaming: Some(true),
+    }
+}
+
+/// Decodes OpenRouter's OpenAI-style `chat.completion.chunk` events
+///
+/// Tool call fragments are accumulated by index and emitted once the stream ends,
+/// together with the finish reason and usage (which arrive in the last chunks).
+#[derive(Default)]
+struct OpenRouterStreamDecoder {
+    tool_calls: Vec<(String, String, String)>,
+    finish_reason: Option<String>,
+    usage: Option<TokenUsage>,
+}
+
+impl SseDecoder for OpenRouterStreamDecoder {
+    fn decode(&mut self, event: &SseEvent) -> Result<Vec<StreamChunk>, LlmError> {
+        if event.data == "[DONE]" {
+            return Ok(Vec::new());
+        }
+
+        let body: serde_json::Value = serde_json::from_str(&event.data)?;
+
+        // Errors after the stream started arrive as a regular data event
+        if let Some(error) = body.get("error") {
+            return Err(LlmError::RequestFailed {
+                status: error["code"].as_u64().unwrap_or(500) as u16,
+                message: error["message"].as_str().map(|m| m.to_string()).unwrap_or_else(|| error.to_string()),
+            });
+        }
+
+        if let Some(usage) = body["usage"].as_object() {
+            self.usage = Some(TokenUsage {
+                prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+                completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0) as u32,
+                total_tokens: usage["total_tokens"].as_u64().unwrap_or(0) as u32,
+            });
+        }
+
+        let mut chunks = Vec::new();
+        let choice = &body["choices"][0];
+
+        if let Some(content) = choice["delta"]["content"].as_str() {
+            if !content.is_empty() {
+                chunks.push(StreamChunk::content(content));
+            }
+        }
+
+        if let Some(calls) = choice["delta"]["tool_calls"].as_array() {
+            for call in calls {
+                let index = call["index"].as_u64().unwrap_or(0) as usize;
+                if self.tool_calls.len() <= index {
+                    self.tool_calls.resize(index + 1, Default::default());
+                }
+                let (id, name, arguments) = &mut self.tool_calls[index];
+                if let Some(call_id) = call["id"].as_str() {
+                    *id = call_id.to_string();
+                }
+                if let Some(function_name) = call["function"]["name"].as_str() {
+                    name.push_str(function_name);
+                }
+                if let Some(fragment) = call["function"]["arguments"].as_str() {
+                    arguments.push_str(fragment);
+                }
+            }
+        }
+
+        if let Some(finish_reason) = choice["finish_reason"].as_str() {
+            self.finish_reason = Some(finish_reason.to_string());
+        }
+
+        Ok(chunks)
+    }
+
+    fn finish(&mut self) -> Vec<StreamChunk> {
+        let mut chunks = Vec::new();
+
+        if !self.tool_calls.is_empty() {
+            // Arguments stay a JSON string, matching the non-streaming response
+            let tool_calls = std::mem::take(&mut self.tool_calls)
+                .into_iter()
+                .map(|(id, name, arguments)| ToolCall {
+                    id,
+                    r#type: "function".to_string(),
+                    function: super::FunctionCall {
+                        name,
+                        arguments: serde_json::Value::String(arguments),
+                    },
+                })
+                .collect();
+            chunks.push(StreamChunk::tool_calls(tool_calls));
+        }
+
+        chunks.push(StreamChunk::finish(
+            self.finish_reason.take().unwrap_or_else(|| "stop".to_string()),
+            self.usage.take(),
+        ));
+        chunks
+    }
 }
 
 #[async_trait]
@@ -206,12 +467,14 @@ impl LlmProvider for OpenRouterProvider {
                 let mut models = Vec::new();
                 for model in data {
                     if let Some(id) = model["id"].as_str() {
-                        // Extract pricing information if available
+                        // Extract pricing information if available; OpenRouter quotes USD per token
                         let pricing = model.get("pricing").and_then(|p| {
-                            let prompt = p.get("prompt").and_then(|v| v.as_str())
-                                .and_then(|s| s.parse::<f64>().ok());
-                            let completion = p.get("completion").and_then(|v| v.as_str())
-                                .and_then(|s| s.parse::<f64>().ok());
+                            let per_million = |key: &str| p.get(key).and_then(|v| v.as_str())
+                                .and_then(|s| s.parse::<f64>().ok())
+                                .filter(|price| *price >= 0.0) // -1 marks routers with variable prices
+                                .map(|price| price * 1_000_000.0);
+                            let prompt = per_million("prompt");
+                            let completion = per_million("completion");
                             
                             if prompt.is_some() || completion.is_some() {
                                 Some(super::ModelPricing { prompt, completion })
@@ -231,6 +494,8 @@ impl LlmProvider for OpenRouterProvider {
                                     .and_then(|tp| tp["max_completion_tokens"].as_u64()))
                                 .map(|n| n as u32),
                             pricing,
+                            tokenizer: tokenizer_name_for_model(id),
+                            capabilities: model_capabilities(model),
                         });
                     }
                 }
@@ -262,40 +527,7 @@ impl LlmProvider for OpenRouterProvider {
         
         let operation = || async {
                 let url = format!("{}/chat/completions", self.base_url);
-                // Debug removed due to closure capture constraints
-                
-                let mut request_body = serde_json::json!({
-                    "model": model,
-                    "messages": messages
-                });
-
-                // Apply generation configuration
-                if let Some(config) = config {
-                    if let Some(temp) = config.temperature {
-                        request_body["temperature"] = temp.into();
-                    }
-                    if let Some(max_tokens) = config.max_tokens {
-                        request_body["max_tokens"] = max_tokens.into();
-                    }
-                    if let Some(top_p) = config.top_p {
-                        request_body["top_p"] = top_p.into();
-                    }
-                    if let Some(freq_penalty) = config.frequency_penalty {
-                        request_body["frequency_penalty"] = freq_penalty.into();
-                    }
-                    if let Some(pres_penalty) = config.presence_penalty {
-                        request_body["presence_penalty"] = pres_penalty.into();
-                    }
-                    if let Some(stop) = &config.stop_sequences {
-                        request_body["stop"] = serde_json::to_value(stop)?;
-                    }
-                }
-
-                // Add tools if provided
-                if let Some(tools) = tools {
-                    request_body["tools"] = serde_json::to_value(tools)?;
-                    request_body["tool_choice"] = "auto".into();
-                }
+                let request_body = Self::build_request_body(messages, model, tools, config)?;
 
                 // Debug removed due to closure capture constraints
 
@@ -312,15 +544,7 @@ impl LlmProvider for OpenRouterProvider {
                 if !response.status().is_success() {
                     let status = response.status().as_u16();
                     let body = response.text().await.unwrap_or_default();
-                    
-                    // Special handling for model not found errors
-                    if status == 400 && (body.contains("model") && body.contains("not found")) {
-                        return Err(LlmError::InvalidModel {
-                            model: model.to_string(),
-                        });
-                    }
-                    
-                    return Err(Self::parse_error_response(status, &body));
+                    return Err(Self::generation_error(status, &body, model));
                 }
 
                 let body: serde_json::Value = response.json().await
@@ -366,6 +590,7 @@ impl LlmProvider for OpenRouterProvider {
                     tool_calls,
                     finish_reason,
                     usage,
+                    backend: None,
                 })
         };
         
@@ -373,6 +598,7 @@ impl LlmProvider for OpenRouterProvider {
         
         match &result {
             Ok(response) => {
+                usage::report_generate(self.provider_name(), model, messages, response);
                 debug_checkpoint!(&mut flow_context, "generate_request_success", {
                     let mut state = HashMap::new();
                     state.insert("has_content".to_string(), serde_json::Value::Bool(response.content.is_some()));
@@ -402,51 +628,10 @@ impl LlmProvider for OpenRouterProvider {
         model: &str,
     ) -> Result<crate::planning::Plan, LlmError> {
         // Use the structured prompt template for plan generation
-        let template = super::prompts::PromptTemplates::plan_generation();
-        let prompt_context = super::prompts::PromptContext::new()
-            .with_variable("context", context)
-            .with_variable("request", prompt)
-            .with_variable("working_directory", "/Users/dovcaspi/develop/KAI-X") // TODO: Pass actual working directory
-            .with_variable("project_type", "Rust application") // TODO: Detect project type
-            .with_variable("current_state", "Initial state"); // TODO: Get actual current state
-        
-        let (system_message, user_message) = template.fill(&prompt_context)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to fill prompt template: {}", e),
-            })?;
+        let messages = Self::plan_messages(prompt, context)?;
 
-        let messages = vec![
-            Message {
-                role: super::MessageRole::System,
-                content: system_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: super::MessageRole::User,
-                content: user_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
-
-        let response = self.generate(&messages, model, None, None).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in planning response".to_string(),
-            })?;
-
-        // Parse the JSON response into a Plan
-        let plan_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse plan JSON: {}", e),
-            })?;
-
-        crate::planning::Plan::from_json(&plan_json)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Invalid plan structure: {}", e),
-            })
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
     }
 
     async fn generate_content(
@@ -462,6 +647,7 @@ impl LlmProvider for OpenRouterProvider {
                 content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ];
 
@@ -489,47 +675,8 @@ impl LlmProvider for OpenRouterProvider {
         context: &TaskRefinementContext,
         model: &str,
     ) -> Result<String, LlmError> {
-        // Use the task refinement prompt template
-        let template = super::prompts::PromptTemplates::task_refinement();
-        let prompt_context = super::prompts::PromptContext::new()
-            .with_variable("plan_description", &context.plan_description)
-            .with_variable("task_id", &task.id)
-            .with_variable("task_type", &format!("{:?}", task.task_type))
-            .with_variable("task_description", &task.description)
-            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
-            .with_variable("global_context", &context.global_context)
-            .with_variable("plan_context", &context.plan_context)
-            .with_variable("dependency_outputs", &serde_json::to_string_pretty(&context.dependency_outputs).unwrap_or_default());
-        
-        let (system_message, user_message) = template.fill(&prompt_context)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to fill task refinement template: {}", e),
-            })?;
-
-        let messages = vec![
-            Message {
-                role: super::MessageRole::System,
-                content: system_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: super::MessageRole::User,
-                content: user_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
-
-        // Use high temperature for creativity in refinement
-        let config = GenerationConfig {
-            temperature: Some(0.3), // Lower temperature for more focused refinement
-            max_tokens: Some(4000),
-            top_p: Some(0.9),
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop_sequences: None,
-        };
+        let messages = Self::refinement_messages(task, context)?;
+        let config = Self::refinement_config();
 
         let response = self.generate(&messages, model, None, Some(&config)).await?;
         
@@ -546,106 +693,166 @@ impl LlmProvider for OpenRouterProvider {
         expected_outcome: &str,
         model: &str,
     ) -> Result<TaskAnalysis, LlmError> {
-        // Use the execution analysis prompt template
-        let template = super::prompts::PromptTemplates::execution_analysis();
-        let prompt_context = super::prompts::PromptContext::new()
-            .with_variable("task_id", &task.id)
-            .with_variable("task_type", &format!("{:?}", task.task_type))
-            .with_variable("task_description", &task.description)
-            .with_variable("expected_outcome", expected_outcome)
-            .with_variable("task_parameters", &serde_json::to_string_pretty(&task.parameters).unwrap_or_default())
-            .with_variable("success", &execution_result.success.to_string())
-            .with_variable("exit_code", &execution_result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "N/A".to_string()))
-            .with_variable("execution_time_ms", &execution_result.execution_time_ms.to_string())
-            .with_variable("stdout", execution_result.stdout.as_deref().unwrap_or(""))
-            .with_variable("stderr", execution_result.stderr.as_deref().unwrap_or(""))
-            .with_variable("output_data", &serde_json::to_string_pretty(&execution_result.output).unwrap_or_else(|_| "null".to_string()))
-            .with_variable("error_message", execution_result.error.as_deref().unwrap_or(""))
-            .with_variable("plan_description", "")
-            .with_variable("plan_context", "")
-            .with_variable("task_dependencies", &serde_json::to_string(&task.dependencies).unwrap_or_default());
-        
-        let (system_message, user_message) = template.fill(&prompt_context)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to fill execution analysis template: {}", e),
-            })?;
+        let messages = Self::analysis_messages(task, execution_result, expected_outcome)?;
+        let config = Self::analysis_config();
 
-        let messages = vec![
-            Message {
-                role: super::MessageRole::System,
-                content: system_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            Message {
-                role: super::MessageRole::User,
-                content: user_message,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-        ];
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
+    }
 
-        // Use lower temperature for more consistent analysis
-        let config = GenerationConfig {
-            temperature: Some(0.1),
-            max_tokens: Some(2048),
-            top_p: Some(0.8),
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop_sequences: None,
-        };
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        Some(self)
+    }
+}
 
-        let response = self.generate(&messages, model, None, Some(&config)).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in task analysis response".to_string(),
-            })?;
+#[async_trait]
+impl StreamingLlmProvider for OpenRouterProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let mut request_body = Self::build_request_body(messages, model, tools, config)?;
+        request_body["stream"] = true.into();
+        request_body["stream_options"] = serde_json::json!({ "include_usage": true });
 
-        // Parse the JSON response into TaskAnalysis
-        let analysis_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse task analysis JSON: {}. Content: {}", e, content),
-            })?;
+        // Only establishing the stream is retried; a broken stream surfaces as an error chunk
+        let operation = || async {
+            let url = format!("{}/chat/completions", self.base_url);
+            let response = self
+                .client
+                .post(&url)
+                .headers(self.create_headers())
+                .header(reqwest::header::ACCEPT, "text/event-stream")
+                .json(&request_body)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(Self::generation_error(status, &body, model));
+            }
 
-        // Extract fields with defaults
-        let success = analysis_json["success"].as_bool().unwrap_or(false);
-        let summary = analysis_json["summary"].as_str().unwrap_or("Analysis unavailable").to_string();
-        let details = analysis_json["details"].as_str().unwrap_or("").to_string();
-        let extracted_data = analysis_json.get("extracted_data").cloned();
-        let next_steps = analysis_json["next_steps"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                .collect::<Vec<String>>());
-        let context_updates = analysis_json["context_updates"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect());
-        let modified_files = analysis_json["modified_files"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| std::path::PathBuf::from(s)))
-                .collect::<Vec<std::path::PathBuf>>());
-        let error = if !success {
-            Some(analysis_json["error"].as_str().unwrap_or("Task failed").to_string())
-        } else {
-            None
+            Ok(response)
         };
-        let metadata = analysis_json["metadata"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect())
-            .unwrap_or_default();
-
-        Ok(TaskAnalysis {
-            success,
-            summary,
-            details,
-            extracted_data,
-            next_steps,
-            context_updates,
-            modified_files,
-            error,
-            metadata,
-        })
-    }
-}
\ No newline at end of file
+
+        let response = self.execute_with_retry(operation).await?;
+        Ok(sse_stream(response, OpenRouterStreamDecoder::default()))
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::plan_messages(prompt, context)?;
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        self.generate_stream(&messages, model, None, Some(&config)).await
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::refinement_messages(task, context)?;
+        self.generate_stream(&messages, model, None, Some(&Self::refinement_config())).await
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let messages = Self::analysis_messages(task, execution_result, expected_outcome)?;
+        self.generate_stream(&messages, model, None, Some(&Self::analysis_config())).await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+    use crate::llm::streaming::StreamCollector;
+
+    #[tokio::test]
+    async fn test_generate_stream_collects_content_and_tool_calls() {
+        let events = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hel"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_file","arguments":"{\"path\":"}}]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"a.rs\"}"}}]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":5,"total_tokens":17}}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        let (server_url, captured) = mock_server::spawn(vec![(200, body)]).await;
+        let provider = OpenRouterProvider::with_config("secret".to_string(), Some(server_url), Some(0), None);
+
+        let messages = vec![Message {
+            role: crate::llm::MessageRole::User,
+            content: "hi".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }];
+        let stream = provider.generate_stream(&messages, "test/model", None, None).await.unwrap();
+        let response = StreamCollector::collect_stream(stream).await.unwrap();
+
+        assert_eq!(response.content.as_deref(), Some("Hello"));
+        assert_eq!(response.finish_reason, "tool_calls");
+        assert_eq!(response.usage.unwrap().total_tokens, 17);
+        let tool_calls = response.tool_calls.unwrap();
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.name, "read_file");
+        assert_eq!(tool_calls[0].function.arguments, serde_json::json!(r#"{"path":"a.rs"}"#));
+
+        let requests = captured.lock().unwrap();
+        assert_eq!(requests[0].request_line, "POST /chat/completions HTTP/1.1");
+        assert_eq!(requests[0].json()["stream"], true);
+    }
+
+    #[test]
+    fn test_request_body_sends_images_as_image_url_parts() {
+        let messages = vec![Message {
+            role: crate::llm::MessageRole::User,
+            content: "Match this mockup".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: vec![crate::llm::ContentPart::image("image/webp", b"Ma".to_vec())],
+        }];
+
+        let body = OpenRouterProvider::build_request_body(&messages, "test/model", None, None).unwrap();
+        assert_eq!(
+            body["messages"][0],
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "Match this mockup" },
+                    { "type": "image_url", "image_url": { "url": "data:image/webp;base64,TWE=" } }
+                ],
+                "tool_calls": null,
+                "tool_call_id": null
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_surfaces_mid_stream_errors() {
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"partial\"}}]}\n\n\
+                    data: {\"error\":{\"code\":502,\"message\":\"upstream failed\"}}\n\n".to_string();
+        let (server_url, _) = mock_server::spawn(vec![(200, body)]).await;
+        let provider = OpenRouterProvider::with_config("secret".to_string(), Some(server_url), Some(0), None);
+
+        let stream = provider.generate_stream(&[], "test/model", None, None).await.unwrap();
+        let result = StreamCollector::collect_stream(stream).await;
+
+        assert!(matches!(result, Err(LlmError::RequestFailed { status: 502, .. })));
+    }
+}
@@ -1,8 +1,10 @@
 //! Refactored OpenRouter LLM provider implementation using shared utilities
 
+use super::tokenizer::tokenizer_name_for_model;
+use super::schema;
 use super::{
     FunctionDefinition, GenerationConfig, LlmError, LlmProvider, LlmResponse, Message,
-    ModelInfo, TokenUsage, ToolCall, ToolDefinition, TaskAnalysis, TaskExecutionResult, TaskRefinementContext,
+    ModelInfo, ResponseFormat, TokenUsage, ToolCall, ToolDefinition, TaskAnalysis, TaskExecutionResult, TaskRefinementContext,
 };
 use crate::utils::http::{HttpClient, HttpClientConfig, execute_with_retry, parse_http_error};
 use crate::utils::http::headers::{OpenRouterHeaders, ProviderHeaders};
@@ -128,6 +130,8 @@ impl LlmProvider for OpenRouterProvider {
                                     .and_then(|tp| tp["max_completion_tokens"].as_u64()))
                                 .map(|n| n as u32),
                             pricing,
+                            tokenizer: tokenizer_name_for_model(id),
+                            capabilities: super::openrouter::model_capabilities(model),
                         });
                     }
                 }
@@ -157,7 +161,7 @@ impl LlmProvider for OpenRouterProvider {
             Box::pin(async move {
                 let mut request_body = serde_json::json!({
                     "model": model,
-                    "messages": messages
+                    "messages": super::content::openai_messages(&messages)?
                 });
 
                 // Apply generation configuration
@@ -180,6 +184,9 @@ impl LlmProvider for OpenRouterProvider {
                     if let Some(stop) = &config.stop_sequences {
                         request_body["stop"] = serde_json::to_value(stop).unwrap_or(serde_json::Value::Null);
                     }
+                    if let Some(format) = &config.response_format {
+                        request_body["response_format"] = format.to_openai();
+                    }
                 }
 
                 // Add tools if provided
@@ -244,6 +251,7 @@ impl LlmProvider for OpenRouterProvider {
                     tool_calls,
                     finish_reason,
                     usage,
+                    backend: None,
                 })
             })
         };
@@ -260,23 +268,8 @@ impl LlmProvider for OpenRouterProvider {
         // Use shared template handler
         let messages = self.template_handler.plan_generation_messages(prompt, context)?;
 
-        let response = self.generate(&messages, model, None, None).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in planning response".to_string(),
-            })?;
-
-        // Parse the JSON response into a Plan
-        let plan_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse plan JSON: {}", e),
-            })?;
-
-        crate::planning::Plan::from_json(&plan_json)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Invalid plan structure: {}", e),
-            })
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+        schema::generate_validated(self, messages, model, &config, schema::parse_plan).await
     }
 
     async fn generate_content(
@@ -292,6 +285,7 @@ impl LlmProvider for OpenRouterProvider {
                 content: format!("Context:\n{}\n\nRequest:\n{}", context, prompt),
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ];
 
@@ -336,6 +330,7 @@ impl LlmProvider for OpenRouterProvider {
             frequency_penalty: None,
             presence_penalty: None,
             stop_sequences: None,
+            response_format: None,
         };
 
         let response = self.generate(&messages, model, None, Some(&config)).await?;
@@ -368,17 +363,10 @@ impl LlmProvider for OpenRouterProvider {
             frequency_penalty: None,
             presence_penalty: None,
             stop_sequences: None,
+            response_format: Some(ResponseFormat::task_analysis()),
         };
 
-        let response = self.generate(&messages, model, None, Some(&config)).await?;
-        
-        let content = response.content
-            .ok_or_else(|| LlmError::InvalidResponse {
-                message: "No content in task analysis response".to_string(),
-            })?;
-
-        // Use shared template handler for parsing
-        self.template_handler.parse_task_analysis(&content)
+        schema::generate_validated(self, messages, model, &config, schema::parse_task_analysis).await
     }
 }
 
@@ -0,0 +1,359 @@
+//! User and project overrides for the built-in prompt templates
+//!
+//! An override is a TOML file named after the template it replaces, for
+//! example `plan_generation.toml`, with a `system_message` and/or a
+//! `user_template` key. A missing key keeps the text of the layer below.
+//! Overrides are read from the user config directory first and then from the
+//! project's `.kai/prompts/` directory, so project overrides win.
+
+use super::prompts::{PromptContext, PromptTemplate, PromptTemplates};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::debug;
+
+/// Directory holding project prompt overrides, relative to the working directory
+pub const PROJECT_PROMPTS_DIR: &str = ".kai/prompts";
+
+lazy_static::lazy_static! {
+    static ref ACTIVE_OVERRIDES: RwLock<PromptOverrides> = RwLock::new(PromptOverrides::default());
+}
+
+/// Where the effective version of a template comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateSource {
+    BuiltIn,
+    User(PathBuf),
+    Project(PathBuf),
+}
+
+impl fmt::Display for TemplateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateSource::BuiltIn => write!(f, "built-in"),
+            TemplateSource::User(path) => write!(f, "user ({})", path.display()),
+            TemplateSource::Project(path) => write!(f, "project ({})", path.display()),
+        }
+    }
+}
+
+/// Contents of an override file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    system_message: Option<String>,
+    user_template: Option<String>,
+}
+
+/// A validated override of one template
+#[derive(Debug, Clone)]
+pub struct TemplateOverride {
+    pub template: PromptTemplate,
+    pub source: TemplateSource,
+    /// Placeholders that no caller fills; they are sent to the model as written
+    pub warnings: Vec<String>,
+}
+
+/// An override file that failed to load and is ignored
+#[derive(Debug, Clone)]
+pub struct RejectedOverride {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Loaded prompt overrides, keyed by template name
+#[derive(Debug, Clone, Default)]
+pub struct PromptOverrides {
+    overrides: HashMap<String, TemplateOverride>,
+    rejected: Vec<RejectedOverride>,
+}
+
+impl PromptOverrides {
+    /// Default user override location, next to the kai-x config file
+    pub fn default_user_dir() -> Option<PathBuf> {
+        dirs::data_local_dir()
+            .or_else(dirs::config_dir)
+            .map(|dir| dir.join("kai-x").join("prompts"))
+    }
+
+    /// Directory for project overrides inside a working directory
+    pub fn project_dir(working_dir: &Path) -> PathBuf {
+        working_dir.join(PROJECT_PROMPTS_DIR)
+    }
+
+    /// Load overrides from the user directory, then the project directory
+    ///
+    /// Missing directories are skipped. Invalid files are recorded in
+    /// `rejected()` and leave the previous layer in effect.
+    pub fn load(user_dir: Option<&Path>, project_dir: Option<&Path>) -> Self {
+        let mut overrides = Self::default();
+        if let Some(dir) = user_dir {
+            overrides.load_dir(dir, TemplateSource::User);
+        }
+        if let Some(dir) = project_dir {
+            overrides.load_dir(dir, TemplateSource::Project);
+        }
+        overrides
+    }
+
+    fn load_dir(&mut self, dir: &Path, source: fn(PathBuf) -> TemplateSource) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match self.load_file(&path) {
+                Ok((name, template, warnings)) => {
+                    debug!("Loaded prompt override '{}' from {}", name, path.display());
+                    self.overrides.insert(name, TemplateOverride {
+                        template,
+                        source: source(path),
+                        warnings,
+                    });
+                }
+                Err(error) => self.rejected.push(RejectedOverride { path, error }),
+            }
+        }
+    }
+
+    fn load_file(&self, path: &Path) -> Result<(String, PromptTemplate, Vec<String>), String> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let builtin = PromptTemplates::builtin(&name).ok_or_else(|| {
+            format!(
+                "unknown template '{}', expected one of: {}",
+                name,
+                PromptTemplates::list_templates().join(", ")
+            )
+        })?;
+
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("failed to read: {}", e))?;
+        let file: TemplateFile = toml::from_str(&contents).map_err(|e| format!("invalid TOML: {}", e))?;
+
+        // Fields missing from the file keep the layer below, which may itself be an override
+        let base = self
+            .overrides
+            .get(&name)
+            .map(|o| o.template.clone())
+            .unwrap_or_else(|| builtin.clone());
+        let template = PromptTemplate {
+            system_message: file.system_message.unwrap_or(base.system_message),
+            user_template: file.user_template.unwrap_or(base.user_template),
+            variables: builtin.variables.clone(),
+        };
+
+        let warnings = validate_override(&builtin, &template)?;
+        Ok((name, template, warnings))
+    }
+
+    /// Override for a template, if one was loaded
+    pub fn get(&self, name: &str) -> Option<&TemplateOverride> {
+        self.overrides.get(name)
+    }
+
+    /// Override files that failed validation
+    pub fn rejected(&self) -> &[RejectedOverride] {
+        &self.rejected
+    }
+
+    /// Effective template and its source
+    pub fn effective(&self, name: &str) -> Option<(PromptTemplate, TemplateSource)> {
+        match self.overrides.get(name) {
+            Some(o) => Some((o.template.clone(), o.source.clone())),
+            None => PromptTemplates::builtin(name).map(|t| (t, TemplateSource::BuiltIn)),
+        }
+    }
+
+    /// Make these overrides the ones returned by `PromptTemplates`
+    pub fn install(self) {
+        if let Ok(mut active) = ACTIVE_OVERRIDES.write() {
+            *active = self;
+        }
+    }
+
+    /// Installed override for a template
+    pub fn lookup(name: &str) -> Option<PromptTemplate> {
+        ACTIVE_OVERRIDES
+            .read()
+            .ok()
+            .and_then(|active| active.overrides.get(name).map(|o| o.template.clone()))
+    }
+}
+
+/// Check an override against the variables its built-in template is filled with
+///
+/// Every variable the built-in declares must still appear, otherwise the
+/// request data it carries would be silently dropped. Placeholders that no
+/// caller fills are returned as warnings, since they reach the model verbatim.
+pub fn validate_override(builtin: &PromptTemplate, template: &PromptTemplate) -> Result<Vec<String>, String> {
+    let missing: Vec<&str> = builtin
+        .variables
+        .iter()
+        .filter(|variable| {
+            let placeholder = format!("{{{{{}}}}}", variable);
+            !template.system_message.contains(&placeholder) && !template.user_template.contains(&placeholder)
+        })
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("missing required variables: {}", missing.join(", ")));
+    }
+
+    let mut context = PromptContext::new();
+    for variable in &builtin.variables {
+        context.set_variable(variable, "");
+    }
+    let (system_message, user_message) = template.fill(&context)?;
+
+    let placeholder = regex::Regex::new(r"\{\{\s*(\w+)\s*\}\}").map_err(|e| e.to_string())?;
+    let mut unknown: Vec<&str> = placeholder
+        .captures_iter(&system_message)
+        .chain(placeholder.captures_iter(&user_message))
+        .filter_map(|captures| captures.get(1).map(|m| m.as_str()))
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+
+    Ok(unknown
+        .into_iter()
+        .map(|name| format!("{{{{{}}}}} is not a template variable and is sent as written", name))
+        .collect())
+}
+
+/// One line of a line-by-line comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line diff of two texts based on their longest common subsequence
+pub fn line_diff<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lengths[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_project_overrides_layer_on_user_overrides() {
+        let user = TempDir::new().unwrap();
+        let project = TempDir::new().unwrap();
+        std::fs::write(
+            user.path().join("plan_generation.toml"),
+            "system_message = \"Plan like a Rust team lead.\"",
+        )
+        .unwrap();
+        std::fs::write(
+            user.path().join("task_refinement.toml"),
+            "system_message = \"Refine tasks tersely.\"",
+        )
+        .unwrap();
+        std::fs::write(
+            project.path().join("plan_generation.toml"),
+            "user_template = \"{{request}} in {{working_directory}}\\n{{context}} {{project_type}} {{current_state}}\"",
+        )
+        .unwrap();
+
+        let overrides = PromptOverrides::load(Some(user.path()), Some(project.path()));
+        assert!(overrides.rejected().is_empty());
+
+        let (plan, source) = overrides.effective("plan_generation").unwrap();
+        assert_eq!(source, TemplateSource::Project(project.path().join("plan_generation.toml")));
+        assert_eq!(plan.system_message, "Plan like a Rust team lead.");
+        assert!(plan.user_template.starts_with("{{request}} in"));
+
+        let (_, source) = overrides.effective("task_refinement").unwrap();
+        assert!(matches!(source, TemplateSource::User(_)));
+        let (analysis, source) = overrides.effective("execution_analysis").unwrap();
+        assert_eq!(source, TemplateSource::BuiltIn);
+        assert_eq!(analysis.user_template, PromptTemplates::builtin("execution_analysis").unwrap().user_template);
+    }
+
+    #[test]
+    fn test_invalid_overrides_are_rejected() {
+        let project = TempDir::new().unwrap();
+        std::fs::write(project.path().join("plan_generation.toml"), "user_template = \"Just {{request}}\"").unwrap();
+        std::fs::write(project.path().join("planner.toml"), "system_message = \"Hi\"").unwrap();
+        std::fs::write(project.path().join("schema_repair.toml"), "system = \"typo\"").unwrap();
+        std::fs::write(project.path().join("notes.md"), "not an override").unwrap();
+
+        let overrides = PromptOverrides::load(None, Some(project.path()));
+        let errors: Vec<&str> = overrides.rejected().iter().map(|r| r.error.as_str()).collect();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("missing required variables: context, working_directory"));
+        assert!(errors[1].starts_with("unknown template 'planner'"));
+        assert!(errors[2].starts_with("invalid TOML"));
+        assert!(overrides.get("plan_generation").is_none());
+
+        let builtin = PromptTemplates::builtin("schema_repair").unwrap();
+        let mut template = builtin.clone();
+        template.user_template.push_str("\nTeam rules: {{ team_rules }}");
+        assert_eq!(
+            validate_override(&builtin, &template).unwrap(),
+            vec!["{{team_rules}} is not a template variable and is sent as written".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_line_diff_marks_changed_lines() {
+        let diff = line_diff("a\nb\nc", "a\nB\nc\nd");
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("a"),
+                DiffLine::Removed("b"),
+                DiffLine::Added("B"),
+                DiffLine::Same("c"),
+                DiffLine::Added("d"),
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! Comprehensive prompt templates for different LLM use cases
 
+use super::prompt_overrides::PromptOverrides;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -78,7 +79,7 @@ pub struct PromptTemplates;
 
 impl PromptTemplates {
     /// Template for generating structured execution plans with tool-use integration
-    pub fn plan_generation() -> PromptTemplate {
+    fn builtin_plan_generation() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are a sophisticated task planning AI with access to a comprehensive toolkit. Your role is to analyze user requests and generate detailed, structured execution plans that leverage available tools effectively.
@@ -226,7 +227,7 @@ Analyze the user's request in the context of the current project state. Generate
     }
 
     /// Template for pre-execution task refinement
-    pub fn task_refinement() -> PromptTemplate {
+    fn builtin_task_refinement() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are a task refinement specialist. Your job is to take high-level task descriptions and convert them into precise, executable instructions.
@@ -335,7 +336,7 @@ Output only the refined, executable instruction:"#.to_string(),
     }
 
     /// Template for post-execution analysis
-    pub fn execution_analysis() -> PromptTemplate {
+    fn builtin_execution_analysis() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are an execution analysis specialist. Your role is to interpret task execution results and provide comprehensive structured analysis.
@@ -477,7 +478,7 @@ Analyze these execution results comprehensively. Determine if the task achieved
     }
 
     /// Template for code summarization and context generation
-    pub fn code_summarization() -> PromptTemplate {
+    fn builtin_code_summarization() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are a code analysis and summarization expert. Your role is to read code files and generate concise, informative summaries for context management.
@@ -538,7 +539,7 @@ Generate a comprehensive summary of this code file for use in context management
     }
 
     /// Template for general content generation
-    pub fn content_generation() -> PromptTemplate {
+    fn builtin_content_generation() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are a versatile content generation specialist. You excel at creating various types of content based on user requirements and project context.
@@ -620,7 +621,7 @@ Generate the requested content following all specified requirements and maintain
     }
 
     /// Template for code analysis and review
-    pub fn code_analysis() -> PromptTemplate {
+    fn builtin_code_analysis() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are a code analysis expert specializing in comprehensive code review and analysis. Your role is to examine code and provide detailed insights.
@@ -683,7 +684,7 @@ Perform a comprehensive analysis focusing on the specified areas and provide det
     }
 
     /// Template for interactive conversation and assistance
-    pub fn conversation() -> PromptTemplate {
+    fn builtin_conversation() -> PromptTemplate {
         PromptTemplate {
             system_message: r#"
 You are KAI-X, a sophisticated AI coding assistant. You're designed to help developers with all aspects of software development, from planning to implementation to maintenance.
@@ -747,6 +748,128 @@ Provide a helpful, informative response that addresses the user's message while
         }
     }
 
+    /// Template for executing a task through native tool calls
+    fn builtin_tool_execution() -> PromptTemplate {
+        PromptTemplate {
+            system_message: r#"
+You are the execution agent of KAI-X. You complete one task at a time by calling the tools you are given: read_file, write_file, list_files, execute_command, create_directory and delete.
+
+## How to Work
+1. Inspect before you change: read files and list directories you are unsure about
+2. Make changes with complete file contents; write_file replaces the whole file
+3. Verify your work when a command can check it (build, test, lint)
+4. When the task is done, reply with a short summary of what you did and stop calling tools
+
+## Working Directory Constraints
+**CRITICAL**: ALL paths must be relative to the working directory
+- Absolute paths and paths that escape the working directory are rejected
+- Commands run without a shell: no pipes, redirects, `&&`, `;` or subshells
+- Deleting requires `force: true`; protected paths (source roots, .git) are refused
+
+## Handling Tool Errors
+- A failed tool call returns `"success": false` with an error message
+- Read the error, adjust the arguments, and try a different approach if needed
+- If the task cannot be completed, say so plainly in your final reply
+            "#.to_string(),
+            user_template: r#"## Task
+**Type**: {{task_type}}
+**Description**: {{task_description}}
+
+**Parameters**:
+```json
+{{task_parameters}}
+```
+
+## Context
+{{context}}
+
+Complete this task using the available tools."#.to_string(),
+            variables: vec![
+                "task_description".to_string(),
+                "task_type".to_string(),
+                "task_parameters".to_string(),
+                "context".to_string(),
+            ],
+        }
+    }
+
+    /// Template for asking the model to fix a response that failed schema validation
+    ///
+    /// The repair request continues the original conversation, so callers send
+    /// only the user message and keep the original system prompt.
+    fn builtin_schema_repair() -> PromptTemplate {
+        PromptTemplate {
+            system_message: r#"
+You return structured JSON for KAI-X. Every response must be a single JSON object that validates against the schema you are given.
+            "#.to_string(),
+            user_template: r#"Your previous response did not match the required JSON schema.
+
+## Validation Errors
+{{errors}}
+
+## Required Schema
+```json
+{{schema}}
+```
+
+Reply again with the corrected JSON object only. Keep the content of your previous answer where it was valid, and do not add any explanation or markdown outside the JSON."#.to_string(),
+            variables: vec![
+                "errors".to_string(),
+                "schema".to_string(),
+            ],
+        }
+    }
+
+    /// Effective template: an installed override, or else the built-in version
+    fn effective(name: &str, builtin: fn() -> PromptTemplate) -> PromptTemplate {
+        PromptOverrides::lookup(name).unwrap_or_else(builtin)
+    }
+
+    /// Template for generating structured execution plans
+    pub fn plan_generation() -> PromptTemplate {
+        Self::effective("plan_generation", Self::builtin_plan_generation)
+    }
+
+    /// Template for pre-execution task refinement
+    pub fn task_refinement() -> PromptTemplate {
+        Self::effective("task_refinement", Self::builtin_task_refinement)
+    }
+
+    /// Template for post-execution analysis
+    pub fn execution_analysis() -> PromptTemplate {
+        Self::effective("execution_analysis", Self::builtin_execution_analysis)
+    }
+
+    /// Template for summarizing code files
+    pub fn code_summarization() -> PromptTemplate {
+        Self::effective("code_summarization", Self::builtin_code_summarization)
+    }
+
+    /// Template for generating file content
+    pub fn content_generation() -> PromptTemplate {
+        Self::effective("content_generation", Self::builtin_content_generation)
+    }
+
+    /// Template for analyzing code
+    pub fn code_analysis() -> PromptTemplate {
+        Self::effective("code_analysis", Self::builtin_code_analysis)
+    }
+
+    /// Template for conversational replies
+    pub fn conversation() -> PromptTemplate {
+        Self::effective("conversation", Self::builtin_conversation)
+    }
+
+    /// Template for tool execution guidance
+    pub fn tool_execution() -> PromptTemplate {
+        Self::effective("tool_execution", Self::builtin_tool_execution)
+    }
+
+    /// Template for repairing responses that failed schema validation
+    pub fn schema_repair() -> PromptTemplate {
+        Self::effective("schema_repair", Self::builtin_schema_repair)
+    }
+
     /// Get all available template names
     pub fn list_templates() -> Vec<&'static str> {
         vec![
@@ -757,19 +880,28 @@ Provide a helpful, informative response that addresses the user's message while
             "content_generation",
             "code_analysis",
             "conversation",
+            "tool_execution",
+            "schema_repair",
         ]
     }
 
-    /// Get a template by name
+    /// Get a template by name, with any installed override applied
     pub fn get_template(name: &str) -> Option<PromptTemplate> {
+        PromptOverrides::lookup(name).or_else(|| Self::builtin(name))
+    }
+
+    /// Get the built-in version of a template, ignoring overrides
+    pub fn builtin(name: &str) -> Option<PromptTemplate> {
         match name {
-            "plan_generation" => Some(Self::plan_generation()),
-            "task_refinement" => Some(Self::task_refinement()),
-            "execution_analysis" => Some(Self::execution_analysis()),
-            "code_summarization" => Some(Self::code_summarization()),
-            "content_generation" => Some(Self::content_generation()),
-            "code_analysis" => Some(Self::code_analysis()),
-            "conversation" => Some(Self::conversation()),
+            "plan_generation" => Some(Self::builtin_plan_generation()),
+            "task_refinement" => Some(Self::builtin_task_refinement()),
+            "execution_analysis" => Some(Self::builtin_execution_analysis()),
+            "code_summarization" => Some(Self::builtin_code_summarization()),
+            "content_generation" => Some(Self::builtin_content_generation()),
+            "code_analysis" => Some(Self::builtin_code_analysis()),
+            "conversation" => Some(Self::builtin_conversation()),
+            "tool_execution" => Some(Self::builtin_tool_execution()),
+            "schema_repair" => Some(Self::builtin_schema_repair()),
             _ => None,
         }
     }
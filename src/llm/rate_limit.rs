@@ -0,0 +1,699 @@
+//! Client-side rate limiting per provider
+//!
+//! Summarising a large repository sends one request per file as fast as the
+//! provider answers, which quickly runs into 429 responses. `RateLimiter`
+//! holds requests back so they stay under the requests-per-minute,
+//! tokens-per-minute and in-flight limits set in the provider's settings.
+//! Limiters are shared by provider name, so every provider instance created
+//! through the factory draws from the same budget. When the provider still
+//! answers with a rate limit error, all requests to it pause for the
+//! `retry_after` it asked for.
+
+use super::cancellation;
+use super::embeddings::{EmbeddingLlmProvider, EmbeddingResponse};
+use super::streaming::{LlmStream, StreamingLlmProvider};
+use super::tokenizer::TokenizerRegistry;
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use crate::utils::http::parse_setting;
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Period that per-minute limits are counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Pause after a rate limit error that does not say how long to wait
+pub const DEFAULT_PAUSE: Duration = Duration::from_secs(10);
+
+/// Times a rate-limited request is sent again after waiting out the pause
+pub const MAX_RATE_LIMIT_RETRIES: usize = 2;
+
+lazy_static::lazy_static! {
+    static ref SHARED_LIMITERS: Mutex<HashMap<String, Arc<RateLimiter>>> = Mutex::new(HashMap::new());
+}
+
+/// Limits read from a provider's settings; unset limits are not enforced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub max_in_flight: Option<usize>,
+}
+
+impl RateLimits {
+    /// Read `requests_per_minute`, `tokens_per_minute` and `max_in_flight`
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        Ok(Self {
+            requests_per_minute: parse_setting(settings, "requests_per_minute")?,
+            tokens_per_minute: parse_setting(settings, "tokens_per_minute")?,
+            max_in_flight: parse_setting(settings, "max_in_flight")?,
+        })
+    }
+
+    /// Whether no limit is set
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A request counted against the per-minute limits
+#[derive(Debug)]
+struct Call {
+    id: u64,
+    at: Instant,
+    tokens: u32,
+}
+
+/// Requests of the last minute and any pause the provider asked for
+#[derive(Debug, Default)]
+struct Window {
+    calls: VecDeque<Call>,
+    paused_until: Option<Instant>,
+    next_id: u64,
+}
+
+impl Window {
+    /// How long a request of `tokens` has to wait before it may start
+    fn delay(&mut self, now: Instant, tokens: u32, limits: &RateLimits) -> Duration {
+        while self.calls.front().is_some_and(|call| now.duration_since(call.at) >= WINDOW) {
+            self.calls.pop_front();
+        }
+
+        let mut delay = self
+            .paused_until
+            .map_or(Duration::ZERO, |until| until.saturating_duration_since(now));
+
+        if let Some(rpm) = limits.requests_per_minute {
+            let rpm = rpm.max(1) as usize;
+            if self.calls.len() >= rpm {
+                // Wait until enough of the oldest requests leave the window
+                let freeing = &self.calls[self.calls.len() - rpm];
+                delay = delay.max((freeing.at + WINDOW).saturating_duration_since(now));
+            }
+        }
+
+        if let Some(tpm) = limits.tokens_per_minute {
+            let mut used: u64 = self.calls.iter().map(|call| u64::from(call.tokens)).sum();
+            // A request larger than the whole budget goes once the window is empty
+            for call in &self.calls {
+                if used + u64::from(tokens) <= u64::from(tpm) {
+                    break;
+                }
+                used -= u64::from(call.tokens);
+                delay = delay.max((call.at + WINDOW).saturating_duration_since(now));
+            }
+        }
+
+        delay
+    }
+
+    /// Count a request that starts now
+    fn admit(&mut self, now: Instant, tokens: u32) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.calls.push_back(Call { id, at: now, tokens });
+        id
+    }
+
+    /// Replace the estimated size of a request with what it actually used
+    fn settle(&mut self, id: u64, tokens: u32) {
+        if let Some(call) = self.calls.iter_mut().find(|call| call.id == id) {
+            call.tokens = tokens;
+        }
+    }
+
+    fn pause(&mut self, until: Instant) {
+        if self.paused_until.is_none_or(|paused| paused < until) {
+            self.paused_until = Some(until);
+        }
+    }
+}
+
+/// Limits requests to one provider
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    window: Mutex<Window>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter of its own, not shared with other providers
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            window: Mutex::new(Window::default()),
+            in_flight: limits.max_in_flight.map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        }
+    }
+
+    /// Limiter shared by every provider instance called `provider`
+    ///
+    /// A limiter with different limits is replaced, so changed settings take
+    /// effect for providers created afterwards.
+    pub fn shared(provider: &str, limits: RateLimits) -> Arc<RateLimiter> {
+        let mut limiters = SHARED_LIMITERS.lock().unwrap();
+        match limiters.get(provider) {
+            Some(limiter) if limiter.limits == limits => limiter.clone(),
+            _ => {
+                let limiter = Arc::new(RateLimiter::new(limits));
+                limiters.insert(provider.to_string(), limiter.clone());
+                limiter
+            }
+        }
+    }
+
+    /// Limits this limiter enforces
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Wait until a request of about `tokens` may start
+    ///
+    /// The request counts as in flight until the permit is dropped.
+    pub async fn acquire(self: &Arc<Self>, tokens: u32) -> RatePermit {
+        let in_flight = match &self.in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("in-flight semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        loop {
+            let delay = {
+                let mut window = self.window.lock().unwrap();
+                let now = Instant::now();
+                let delay = window.delay(now, tokens, &self.limits);
+                if delay.is_zero() {
+                    let id = window.admit(now, tokens);
+                    return RatePermit {
+                        limiter: self.clone(),
+                        id,
+                        _in_flight: in_flight,
+                    };
+                }
+                delay
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Hold back every request for `duration`
+    pub fn pause(&self, duration: Duration) {
+        self.window.lock().unwrap().pause(Instant::now() + duration);
+    }
+
+    /// Time left of a pause the provider asked for, if one is in effect
+    pub fn paused_for(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.window
+            .lock()
+            .unwrap()
+            .paused_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+/// A request admitted by a `RateLimiter`
+pub struct RatePermit {
+    limiter: Arc<RateLimiter>,
+    id: u64,
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl RatePermit {
+    /// Count the tokens the request actually used instead of the estimate
+    pub fn settle(&self, tokens: u32) {
+        self.limiter.window.lock().unwrap().settle(self.id, tokens);
+    }
+}
+
+/// Provider wrapper that keeps requests within a `RateLimiter`
+///
+/// Requests are sized by the model's tokenizer plus the requested output
+/// tokens, and corrected with the usage the provider reports. Rate limit
+/// errors pause the limiter and are retried up to `MAX_RATE_LIMIT_RETRIES`
+/// times; for streams only the opening request is retried. A provider in a
+/// fallback chain fails fast instead (see `fail_fast`).
+pub struct RateLimitedProvider {
+    inner: Box<dyn LlmProvider>,
+    limiter: Arc<RateLimiter>,
+    fail_fast: bool,
+}
+
+impl RateLimitedProvider {
+    /// Wrap a provider with the given limiter
+    pub fn new(inner: Box<dyn LlmProvider>, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner,
+            limiter,
+            fail_fast: false,
+        }
+    }
+
+    /// Return rate limit errors instead of waiting them out
+    ///
+    /// For providers in a fallback chain, which can move on to the next backend.
+    /// A rate limit error still pauses the limiter, but is returned without a
+    /// retry, and requests made while the pause lasts fail at once with
+    /// `RateLimit`. The requests-per-minute and token limits are waited for as usual.
+    pub fn fail_fast(mut self) -> Self {
+        self.fail_fast = true;
+        self
+    }
+
+    /// Limiter this provider waits on
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    /// Estimated size of a request made of `texts`
+    fn estimate(model: &str, texts: &[&str], config: Option<&GenerationConfig>) -> u32 {
+        let tokenizer = TokenizerRegistry::global().for_model(model);
+        let input: usize = texts.iter().map(|text| tokenizer.count_tokens(text)).sum();
+        let output = config.and_then(|config| config.max_tokens).unwrap_or(0);
+        (input as u32).saturating_add(output)
+    }
+
+    /// Send a request once the limiter admits it, retrying after rate limit errors
+    ///
+    /// `used` reads the actual token usage from a response, if it has one.
+    async fn limited<T, Fut>(
+        &self,
+        tokens: u32,
+        call: impl Fn() -> Fut,
+        used: impl Fn(&T) -> Option<u32>,
+    ) -> Result<(T, RatePermit), LlmError>
+    where
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let max_retries = if self.fail_fast { 0 } else { MAX_RATE_LIMIT_RETRIES };
+        let mut retries = 0;
+        loop {
+            if let Some(remaining) = self.limiter.paused_for().filter(|_| self.fail_fast) {
+                return Err(LlmError::RateLimit {
+                    retry_after: Some(remaining.as_secs().max(1)),
+                });
+            }
+            let permit = cancellation::cancellable(async { Ok(self.limiter.acquire(tokens).await) }).await?;
+            match call().await {
+                Ok(response) => {
+                    if let Some(tokens) = used(&response) {
+                        permit.settle(tokens);
+                    }
+                    return Ok((response, permit));
+                }
+                Err(LlmError::RateLimit { retry_after }) => {
+                    let pause = retry_after.map_or(DEFAULT_PAUSE, Duration::from_secs);
+                    self.limiter.pause(pause);
+                    if retries >= max_retries {
+                        return Err(LlmError::RateLimit { retry_after });
+                    }
+                    retries += 1;
+                    warn!(
+                        "{} is rate limited; retrying in {}s ({} of {})",
+                        self.inner.provider_name(),
+                        pause.as_secs(),
+                        retries,
+                        max_retries
+                    );
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Send a request that returns a plain value
+    async fn request<T, Fut>(&self, tokens: u32, call: impl Fn() -> Fut) -> Result<T, LlmError>
+    where
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let (response, _permit) = self.limited(tokens, call, |_| None).await?;
+        Ok(response)
+    }
+
+    /// Open a stream, keeping it in flight until it ends
+    async fn stream<Fut>(&self, tokens: u32, call: impl Fn() -> Fut) -> Result<LlmStream, LlmError>
+    where
+        Fut: Future<Output = Result<LlmStream, LlmError>>,
+    {
+        let (stream, permit) = self.limited(tokens, call, |_| None).await?;
+        Ok(Box::pin(stream.map(move |chunk| {
+            if let Some(usage) = chunk.as_ref().ok().and_then(|chunk| chunk.usage.as_ref()) {
+                permit.settle(usage.total_tokens);
+            }
+            chunk
+        })))
+    }
+
+    fn inner_streaming(&self) -> Result<&dyn StreamingLlmProvider, LlmError> {
+        self.inner.as_streaming().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support streaming", self.inner.provider_name()),
+        })
+    }
+}
+
+/// Text of a message list, for sizing
+fn message_texts(messages: &[Message]) -> Vec<&str> {
+    messages.iter().map(|message| message.content.as_str()).collect()
+}
+
+/// Text of a refinement request, for sizing
+fn refinement_text(task: &crate::planning::Task, context: &TaskRefinementContext) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        task.description,
+        context.plan_description,
+        context.global_context,
+        context.plan_context,
+        serde_json::to_string(&context.dependency_outputs).unwrap_or_default()
+    )
+}
+
+/// Text of an analysis request, for sizing
+fn analysis_text(task: &crate::planning::Task, result: &TaskExecutionResult, expected_outcome: &str) -> String {
+    format!(
+        "{}\n{}\n{}",
+        task.description,
+        expected_outcome,
+        serde_json::to_string(result).unwrap_or_default()
+    )
+}
+
+#[async_trait]
+impl LlmProvider for RateLimitedProvider {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        self.request(0, || self.inner.list_models()).await
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let tokens = Self::estimate(model, &message_texts(messages), config);
+        let (response, _permit) = self
+            .limited(
+                tokens,
+                || self.inner.generate(messages, model, tools, config),
+                |response: &LlmResponse| response.usage.as_ref().map(|usage| usage.total_tokens),
+            )
+            .await?;
+        Ok(response)
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        let tokens = Self::estimate(model, &[prompt, context], None);
+        self.request(tokens, || self.inner.generate_plan(prompt, context, model)).await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        let tokens = Self::estimate(model, &[prompt, context], config);
+        self.request(tokens, || self.inner.generate_content(prompt, context, model, config)).await
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        let tokens = Self::estimate(model, &[&refinement_text(task, context)], None);
+        self.request(tokens, || self.inner.refine_task_for_execution(task, context, model)).await
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let tokens = Self::estimate(model, &[&analysis_text(task, execution_result, expected_outcome)], None);
+        self.request(tokens, || {
+            self.inner.analyze_task_result(task, execution_result, expected_outcome, model)
+        })
+        .await
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        self.request(0, || self.inner.validate_model(model)).await
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        self.inner.as_streaming().map(|_| self as &dyn StreamingLlmProvider)
+    }
+
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.inner.as_embedding().map(|_| self as &dyn EmbeddingLlmProvider)
+    }
+}
+
+/// A whole `embed` call is admitted as one request, however many batches it sends
+#[async_trait]
+impl EmbeddingLlmProvider for RateLimitedProvider {
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingResponse, LlmError> {
+        let embedding = self.inner.as_embedding().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support embeddings", self.inner.provider_name()),
+        })?;
+        let inputs: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let tokens = Self::estimate(model, &inputs, None);
+        let (response, _permit) = self
+            .limited(
+                tokens,
+                || embedding.embed(texts, model),
+                |response: &EmbeddingResponse| response.usage.as_ref().map(|usage| usage.total_tokens),
+            )
+            .await?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl StreamingLlmProvider for RateLimitedProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        let tokens = Self::estimate(model, &message_texts(messages), config);
+        self.stream(tokens, || inner.generate_stream(messages, model, tools, config)).await
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        let tokens = Self::estimate(model, &[prompt, context], None);
+        self.stream(tokens, || inner.generate_plan_stream(prompt, context, model)).await
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        let tokens = Self::estimate(model, &[&refinement_text(task, context)], None);
+        self.stream(tokens, || inner.refine_task_stream(task, context, model)).await
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let inner = self.inner_streaming()?;
+        let tokens = Self::estimate(model, &[&analysis_text(task, execution_result, expected_outcome)], None);
+        self.stream(tokens, || {
+            inner.analyze_task_result_stream(task, execution_result, expected_outcome, model)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server;
+
+    fn limits(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> RateLimits {
+        RateLimits {
+            requests_per_minute,
+            tokens_per_minute,
+            max_in_flight: None,
+        }
+    }
+
+    #[test]
+    fn test_limits_are_read_from_settings() {
+        let settings: HashMap<String, String> = [
+            ("requests_per_minute", "60"),
+            ("tokens_per_minute", "100000"),
+            ("api_key", "sk-test"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let limits = RateLimits::from_settings(&settings).unwrap();
+        assert_eq!(limits.requests_per_minute, Some(60));
+        assert_eq!(limits.tokens_per_minute, Some(100_000));
+        assert_eq!(limits.max_in_flight, None);
+        assert!(RateLimits::from_settings(&HashMap::new()).unwrap().is_unlimited());
+
+        let invalid = HashMap::from([("max_in_flight".to_string(), "many".to_string())]);
+        assert!(RateLimits::from_settings(&invalid).is_err());
+
+        let shared = RateLimiter::shared("rate-limit-test", limits);
+        assert!(Arc::ptr_eq(&shared, &RateLimiter::shared("rate-limit-test", limits)));
+        assert!(!Arc::ptr_eq(&shared, &RateLimiter::shared("rate-limit-test", RateLimits::default())));
+    }
+
+    #[test]
+    fn test_window_delays_requests_over_the_limits() {
+        let start = Instant::now();
+        let mut window = Window::default();
+
+        // Two requests a minute: the third waits for the first to leave the window
+        let rpm = limits(Some(2), None);
+        window.admit(start, 10);
+        window.admit(start + Duration::from_secs(20), 10);
+        assert_eq!(window.delay(start + Duration::from_secs(30), 10, &rpm), Duration::from_secs(30));
+        assert_eq!(window.delay(start + WINDOW, 10, &rpm), Duration::ZERO);
+
+        // 1000 tokens a minute: settling the first request frees room sooner
+        let mut window = Window::default();
+        let tpm = limits(None, Some(1000));
+        let first = window.admit(start, 800);
+        window.admit(start + Duration::from_secs(10), 100);
+        assert_eq!(window.delay(start + Duration::from_secs(15), 300, &tpm), Duration::from_secs(45));
+        window.settle(first, 500);
+        assert_eq!(window.delay(start + Duration::from_secs(15), 300, &tpm), Duration::ZERO);
+
+        // A request larger than the budget waits for an empty window instead of forever
+        assert_eq!(window.delay(start + Duration::from_secs(15), 5000, &tpm), Duration::from_secs(55));
+
+        // A provider's retry_after holds back every request
+        window.pause(start + Duration::from_secs(120));
+        assert_eq!(window.delay(start + Duration::from_secs(100), 1, &RateLimits::default()), Duration::from_secs(20));
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_returns_rate_limits_without_waiting() {
+        use crate::llm::openai_compatible::OpenAiCompatibleProvider;
+
+        let (url, requests) = mock_server::spawn(vec![(429, r#"{"error": {"message": "slow down"}}"#.to_string())]).await;
+        let settings = HashMap::from([
+            ("base_url".to_string(), url),
+            ("retry_attempts".to_string(), "0".to_string()),
+        ]);
+        let inner = Box::new(OpenAiCompatibleProvider::from_settings(&settings).unwrap());
+        let limiter = Arc::new(RateLimiter::new(limits(Some(100), None)));
+        let provider = RateLimitedProvider::new(inner, limiter.clone()).fail_fast();
+
+        let message = || Message {
+            role: crate::llm::MessageRole::User,
+            content: "hello".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        };
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            provider.generate(&[message()], "model", None, None),
+        )
+        .await
+        .expect("a chained provider must not wait out the pause");
+        assert!(matches!(result, Err(LlmError::RateLimit { .. })));
+        assert!(limiter.paused_for().is_some());
+
+        // While the pause lasts, requests fail without reaching the provider
+        let result = provider.generate(&[message()], "model", None, None).await;
+        assert!(matches!(result, Err(LlmError::RateLimit { retry_after: Some(_) })));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_providers_leave_429s_to_the_limiter() {
+        let body = r#"{"error": {"message": "slow down", "retry_after": 1}}"#.to_string();
+        let (url, requests) = mock_server::spawn(vec![(429, body.clone()), (429, body)]).await;
+        // Default HTTP retry settings; a distinct limit keeps the shared limiter to this test
+        let settings = HashMap::from([
+            ("base_url".to_string(), url),
+            ("requests_per_minute".to_string(), "97".to_string()),
+        ]);
+        let provider = crate::llm::LlmProviderFactory::create_rate_limited("openai_compatible", settings, true).unwrap();
+
+        let message = Message {
+            role: crate::llm::MessageRole::User,
+            content: "hello".to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        };
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            provider.generate(&[message], "model", None, None),
+        )
+        .await
+        .expect("the rate limit must reach the caller without retrying");
+        assert!(matches!(result, Err(LlmError::RateLimit { .. })));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_are_capped() {
+        let limiter = Arc::new(RateLimiter::new(RateLimits {
+            max_in_flight: Some(1),
+            ..RateLimits::default()
+        }));
+
+        let first = limiter.acquire(10).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(50), limiter.acquire(10)).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_millis(50), limiter.acquire(10)).await;
+        assert!(second.is_ok());
+    }
+}
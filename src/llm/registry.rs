@@ -0,0 +1,321 @@
+//! On-disk registry of the models each provider offers
+//!
+//! Listing models is a network call, and some providers return hundreds of
+//! entries. `ModelRegistry` keeps the last listing of every provider on disk,
+//! together with pricing and capabilities, and lists again once it is older
+//! than its TTL. Planners check the active model against the features a plan
+//! needs before using it, and `/model` only offers models that have them.
+
+use super::{LlmError, LlmProvider, ModelInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Current catalog format version; catalogs with another version are ignored
+///
+/// Version 2 stores prices per million tokens for every provider.
+pub const REGISTRY_VERSION: u32 = 2;
+
+/// Default age after which a provider's models are listed again
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+lazy_static::lazy_static! {
+    static ref GLOBAL_REGISTRY: Option<Arc<ModelRegistry>> =
+        ModelRegistry::default_dir().map(|dir| Arc::new(ModelRegistry::new(dir)));
+}
+
+/// Features a request needs from the model that serves it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelRequirements {
+    pub tools: bool,
+    pub vision: bool,
+    pub json_mode: bool,
+    pub streaming: bool,
+    /// Smallest context window that is enough
+    pub min_context: Option<u32>,
+}
+
+impl ModelRequirements {
+    /// Whether nothing is required
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Required features the model is known to lack
+    ///
+    /// Features the provider did not report are assumed to be there, so a
+    /// model is only ruled out on what is known about it.
+    pub fn unmet_by(&self, model: &ModelInfo) -> Vec<String> {
+        let capabilities = &model.capabilities;
+        let mut unmet: Vec<String> = [
+            (self.tools, capabilities.tools, "tool calling"),
+            (self.vision, capabilities.vision, "image input"),
+            (self.json_mode, capabilities.json_mode, "JSON mode"),
+            (self.streaming, capabilities.streaming, "streaming"),
+        ]
+        .into_iter()
+        .filter(|(required, supported, _)| *required && *supported == Some(false))
+        .map(|(_, _, feature)| feature.to_string())
+        .collect();
+
+        if let (Some(min_context), Some(context_length)) = (self.min_context, model.context_length) {
+            if context_length < min_context {
+                unmet.push(format!("a context window of {} tokens (has {})", min_context, context_length));
+            }
+        }
+        unmet
+    }
+}
+
+/// Models listed by one provider, as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    version: u32,
+    pub provider: String,
+    /// Unix time in milliseconds when the provider was asked
+    pub fetched_at: i64,
+    pub models: Vec<ModelInfo>,
+}
+
+impl ModelCatalog {
+    /// Look up a model by id
+    pub fn model(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|model| model.id == id)
+    }
+
+    /// Time since the provider was asked
+    pub fn age(&self) -> Duration {
+        let age_ms = chrono::Utc::now().timestamp_millis().saturating_sub(self.fetched_at);
+        Duration::from_millis(age_ms.max(0) as u64)
+    }
+}
+
+/// Directory of model catalogs, one JSON file per provider
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ModelRegistry {
+    /// Open a registry in the given directory with the default TTL
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Default registry location under the kai-x data directory
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_local_dir()
+            .or_else(dirs::data_dir)
+            .map(|dir| dir.join("kai-x").join("cache").join("models"))
+    }
+
+    /// Registry shared by every planner and command in this process
+    ///
+    /// Returns `None` when the platform has no data directory.
+    pub fn global() -> Option<Arc<ModelRegistry>> {
+        GLOBAL_REGISTRY.clone()
+    }
+
+    /// Set the age after which models are listed again
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Directory holding the catalogs
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn catalog_path(&self, provider: &str) -> PathBuf {
+        let name: String = provider
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Last stored catalog of a provider, however old
+    pub fn cached(&self, provider: &str) -> Option<ModelCatalog> {
+        let content = std::fs::read_to_string(self.catalog_path(provider)).ok()?;
+        serde_json::from_str::<ModelCatalog>(&content)
+            .ok()
+            .filter(|catalog| catalog.version == REGISTRY_VERSION)
+    }
+
+    /// Write a catalog, replacing the provider's previous one
+    pub fn store(&self, catalog: &ModelCatalog) -> Result<(), LlmError> {
+        let content = serde_json::to_string_pretty(catalog)?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| LlmError::Unknown {
+            message: format!("Failed to create model registry {}: {}", self.dir.display(), e),
+        })?;
+        // Write then rename so concurrent readers never see a partial catalog
+        let path = self.catalog_path(&catalog.provider);
+        let temp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_path);
+                LlmError::Unknown {
+                    message: format!("Failed to write model catalog {}: {}", path.display(), e),
+                }
+            })
+    }
+
+    /// List the provider's models now and store the result
+    ///
+    /// Providers rarely say whether a model streams, so models without that
+    /// capability take it from whether the provider streams at all.
+    pub async fn refresh(&self, provider: &dyn LlmProvider) -> Result<ModelCatalog, LlmError> {
+        let mut models = provider.list_models().await?;
+        let streams = provider.as_streaming().is_some();
+        for model in &mut models {
+            model.capabilities.streaming.get_or_insert(streams);
+        }
+
+        let catalog = ModelCatalog {
+            version: REGISTRY_VERSION,
+            provider: provider.provider_name().to_string(),
+            fetched_at: chrono::Utc::now().timestamp_millis(),
+            models,
+        };
+        if let Err(error) = self.store(&catalog) {
+            warn!("Failed to store models of {}: {}", catalog.provider, error);
+        }
+        Ok(catalog)
+    }
+
+    /// Catalog of a provider, listing its models again once the stored one expires
+    ///
+    /// An expired catalog is still used when the provider cannot be reached.
+    pub async fn catalog(&self, provider: &dyn LlmProvider) -> Result<ModelCatalog, LlmError> {
+        let cached = self.cached(provider.provider_name());
+        if let Some(catalog) = cached.as_ref().filter(|catalog| catalog.age() < self.ttl) {
+            return Ok(catalog.clone());
+        }
+
+        match (self.refresh(provider).await, cached) {
+            (Ok(catalog), _) => Ok(catalog),
+            (Err(error), Some(catalog)) => {
+                warn!(
+                    "Failed to list models of {}, using the list from {} minutes ago: {}",
+                    catalog.provider,
+                    catalog.age().as_secs() / 60,
+                    error
+                );
+                Ok(catalog)
+            }
+            (Err(error), None) => Err(error),
+        }
+    }
+
+    /// Required features that a model of the provider is known to lack
+    ///
+    /// Models the registry knows nothing about, including when the provider
+    /// cannot list its models, are not ruled out.
+    pub async fn unmet_requirements(
+        &self,
+        provider: &dyn LlmProvider,
+        model: &str,
+        requirements: &ModelRequirements,
+    ) -> Vec<String> {
+        if requirements.is_empty() {
+            return Vec::new();
+        }
+        match self.catalog(provider).await {
+            Ok(catalog) => catalog
+                .model(model)
+                .map(|info| requirements.unmet_by(info))
+                .unwrap_or_default(),
+            Err(error) => {
+                warn!("Cannot check capabilities of {}: {}", model, error);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::ScriptedProvider;
+    use crate::llm::ModelCapabilities;
+
+    fn model(id: &str, tools: Option<bool>, context_length: Option<u32>) -> ModelInfo {
+        ModelInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: None,
+            context_length,
+            max_output_tokens: None,
+            pricing: None,
+            tokenizer: None,
+            capabilities: ModelCapabilities {
+                tools,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_requirements_only_rule_out_known_gaps() {
+        let tool_plan = ModelRequirements {
+            tools: true,
+            min_context: Some(32_000),
+            ..Default::default()
+        };
+
+        assert!(tool_plan.unmet_by(&model("agent", Some(true), Some(128_000))).is_empty());
+        assert!(tool_plan.unmet_by(&model("unknown", None, None)).is_empty());
+        assert_eq!(
+            tool_plan.unmet_by(&model("chat-only", Some(false), Some(8_192))),
+            vec![
+                "tool calling".to_string(),
+                "a context window of 32000 tokens (has 8192)".to_string(),
+            ]
+        );
+        assert!(ModelRequirements::default().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_catalog_is_served_from_disk_until_it_expires() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ModelRegistry::new(dir.path());
+        // The model list can be changed or broken between calls
+        let provider = ScriptedProvider::new("listing/test").with_models(vec![model("chat-only", Some(false), None)]);
+
+        let catalog = registry.catalog(&provider).await.unwrap();
+        assert_eq!(catalog.models.len(), 1);
+        // The provider does not stream, and the registry records that
+        assert_eq!(catalog.models[0].capabilities.streaming, Some(false));
+        assert!(dir.path().join("listing_test.json").exists());
+
+        // A fresh catalog is not listed again
+        provider.set_models(Some(Vec::new()));
+        registry.catalog(&provider).await.unwrap();
+        assert_eq!(provider.calls_to("list_models"), 1);
+
+        let requirements = ModelRequirements { tools: true, ..Default::default() };
+        assert_eq!(
+            registry.unmet_requirements(&provider, "chat-only", &requirements).await,
+            vec!["tool calling".to_string()]
+        );
+        assert!(registry.unmet_requirements(&provider, "other", &requirements).await.is_empty());
+
+        // An expired catalog is listed again, and kept if the provider is unreachable
+        let expired = ModelRegistry::new(dir.path()).with_ttl(Duration::ZERO);
+        provider.set_models(None);
+        assert_eq!(expired.catalog(&provider).await.unwrap().models.len(), 1);
+        assert_eq!(provider.calls_to("list_models"), 2);
+
+        provider.set_models(Some(Vec::new()));
+        assert!(expired.catalog(&provider).await.unwrap().models.is_empty());
+        assert!(registry.cached("listing/test").unwrap().models.is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+//! JSON Schemas for structured LLM output and a bounded repair loop
+//!
+//! Plans and task analyses are requested with a JSON Schema, sent as the
+//! provider's structured-output setting where it has one. Responses are
+//! validated against the same schema. An invalid response is not returned as
+//! an error straight away; instead the validation errors are sent back to the
+//! model so it can correct itself, up to `MAX_REPAIR_ATTEMPTS` times.
+
+use super::prompts::{PromptContext, PromptTemplates};
+use super::{GenerationConfig, LlmError, LlmProvider, Message, MessageRole, ResponseFormat, TaskAnalysis};
+use crate::planning::Plan;
+use serde_json::{json, Value};
+use tracing::warn;
+
+/// Number of times a model is asked to fix an invalid response before giving up
+pub const MAX_REPAIR_ATTEMPTS: usize = 2;
+
+/// Task types accepted in generated plans
+pub const TASK_TYPES: [&str; 8] = [
+    "read_file",
+    "write_file",
+    "execute_command",
+    "generate_content",
+    "analyze_code",
+    "list_files",
+    "create_directory",
+    "delete",
+];
+
+lazy_static::lazy_static! {
+    static ref PLAN_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "description": { "type": "string" },
+            "tasks": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string" },
+                        "description": { "type": "string" },
+                        "task_type": { "type": "string", "enum": TASK_TYPES },
+                        "parameters": { "type": "object" },
+                        "dependencies": { "type": "array", "items": { "type": "string" } }
+                    },
+                    "required": ["id", "description", "task_type"]
+                }
+            }
+        },
+        "required": ["description", "tasks"]
+    });
+
+    static ref TASK_ANALYSIS_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "success": { "type": "boolean" },
+            "summary": { "type": "string" },
+            "details": { "type": "string" },
+            "extracted_data": {},
+            "next_steps": { "type": "array", "items": { "type": "string" } },
+            "context_updates": { "type": "object" },
+            "modified_files": { "type": "array", "items": { "type": "string" } },
+            "error": { "type": ["string", "null"] },
+            "metadata": { "type": "object" }
+        },
+        "required": ["success", "summary"]
+    });
+}
+
+/// Schema for plans returned by plan generation
+pub fn plan_schema() -> &'static Value {
+    &PLAN_SCHEMA
+}
+
+/// Schema for task analyses returned by result analysis
+pub fn task_analysis_schema() -> &'static Value {
+    &TASK_ANALYSIS_SCHEMA
+}
+
+/// Validate a value against a schema, returning one message per violation
+///
+/// Supports the subset of JSON Schema used here: `type`, `enum`, `properties`,
+/// `required`, `items` and `additionalProperties: false`.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at("$", value, schema, &mut errors);
+    errors
+}
+
+fn validate_at(path: &str, value: &Value, schema: &Value, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+            errors.push(format!("{}: expected {}, got {}", path, allowed.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(Value::to_string).collect();
+            errors.push(format!("{}: {} is not one of {}", path, value, options.join(", ")));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        for field in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(field) = field.as_str() {
+                if !object.contains_key(field) {
+                    errors.push(format!("{}: missing required field '{}'", path, field));
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, field_value) in object {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(field_schema) => validate_at(&format!("{}.{}", path, key), field_value, field_schema, errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{}: unexpected field '{}'", path, key));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(&format!("{}[{}]", path, index), item, item_schema, errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Parse the JSON object in a response, ignoring surrounding text such as code fences
+fn parse_json(content: &str) -> Result<Value, Vec<String>> {
+    let json_content = match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => content,
+    };
+    serde_json::from_str(json_content).map_err(|e| vec![format!("response is not valid JSON: {}", e)])
+}
+
+/// Parse and validate a plan response, including the checks of `Plan::validate`
+pub fn parse_plan(content: &str) -> Result<Plan, Vec<String>> {
+    let value = parse_json(content)?;
+    let errors = validate(&value, plan_schema());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let plan = Plan::from_json(&value).map_err(|e| vec![e.to_string()])?;
+    let diagnostics = plan.validate();
+    if !diagnostics.is_empty() {
+        return Err(diagnostics.iter().map(ToString::to_string).collect());
+    }
+    Ok(plan)
+}
+
+/// Parse and validate a task analysis response
+pub fn parse_task_analysis(content: &str) -> Result<TaskAnalysis, Vec<String>> {
+    let value = parse_json(content)?;
+    let errors = validate(&value, task_analysis_schema());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(TaskAnalysis::from_json(&value))
+}
+
+/// Message asking the model to correct a response that failed validation
+fn repair_message(format: &ResponseFormat, errors: &[String]) -> Result<Message, LlmError> {
+    let context = PromptContext::new()
+        .with_variable("errors", errors.iter().map(|e| format!("- {}", e)).collect::<Vec<_>>().join("\n"))
+        .with_variable("schema", serde_json::to_string_pretty(&format.schema)?);
+    let (_, user_message) = PromptTemplates::schema_repair()
+        .fill(&context)
+        .map_err(|e| LlmError::InvalidResponse {
+            message: format!("Failed to fill schema repair template: {}", e),
+        })?;
+
+    Ok(Message {
+        role: MessageRole::User,
+        content: user_message,
+        tool_calls: None,
+        tool_call_id: None,
+        parts: Vec::new(),
+    })
+}
+
+/// Generate a structured response, asking the model to repair invalid output
+///
+/// `config.response_format` names the schema sent to the provider and quoted
+/// back in repair requests. After `MAX_REPAIR_ATTEMPTS` failed repairs the last
+/// validation errors are returned as `LlmError::InvalidResponse`.
+pub async fn generate_validated<T>(
+    provider: &dyn LlmProvider,
+    messages: Vec<Message>,
+    model: &str,
+    config: &GenerationConfig,
+    parse: fn(&str) -> Result<T, Vec<String>>,
+) -> Result<T, LlmError> {
+    let content = request_content(provider, &messages, model, config).await?;
+    repair_validated(provider, messages, model, config, parse, content).await
+}
+
+/// Validate `content`, a response already received for `messages`, asking the
+/// model to repair it like `generate_validated` does
+///
+/// Used for output that arrived some other way, such as a stream, so an
+/// invalid response is corrected instead of requested again from scratch.
+pub async fn repair_validated<T>(
+    provider: &dyn LlmProvider,
+    mut messages: Vec<Message>,
+    model: &str,
+    config: &GenerationConfig,
+    parse: fn(&str) -> Result<T, Vec<String>>,
+    mut content: String,
+) -> Result<T, LlmError> {
+    let format = response_format(config)?;
+
+    let mut repairs = 0;
+    loop {
+        let errors = match parse(&content) {
+            Ok(parsed) => return Ok(parsed),
+            Err(errors) => errors,
+        };
+        if repairs == MAX_REPAIR_ATTEMPTS {
+            return Err(LlmError::InvalidResponse {
+                message: format!(
+                    "Invalid {} after {} repair attempts: {}",
+                    format.name,
+                    repairs,
+                    errors.join("; ")
+                ),
+            });
+        }
+
+        repairs += 1;
+        warn!(
+            "Invalid {} from {} (repair attempt {}/{}): {}",
+            format.name,
+            model,
+            repairs,
+            MAX_REPAIR_ATTEMPTS,
+            errors.join("; ")
+        );
+        messages.push(Message {
+            role: MessageRole::Assistant,
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        });
+        messages.push(repair_message(format, &errors)?);
+        content = request_content(provider, &messages, model, config).await?;
+    }
+}
+
+/// Schema a structured request is validated against
+fn response_format(config: &GenerationConfig) -> Result<&ResponseFormat, LlmError> {
+    config.response_format.as_ref().ok_or_else(|| LlmError::Unknown {
+        message: "Structured generation requires a response format".to_string(),
+    })
+}
+
+/// Send `messages` and return the text of the reply
+async fn request_content(
+    provider: &dyn LlmProvider,
+    messages: &[Message],
+    model: &str,
+    config: &GenerationConfig,
+) -> Result<String, LlmError> {
+    let format = response_format(config)?;
+    let response = provider.generate(messages, model, None, Some(config)).await?;
+    response.content.ok_or_else(|| LlmError::InvalidResponse {
+        message: format!("No content in {} response", format.name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::ScriptedProvider;
+
+    const INVALID_PLAN: &str = r#"{"description": "Build", "tasks": [{"id": "t1", "task_type": "compile"}]}"#;
+    const VALID_PLAN: &str = r#"```json
+{"description": "Build", "tasks": [{"id": "t1", "description": "Run cargo", "task_type": "execute_command", "parameters": {"command": "cargo"}}]}
+```"#;
+
+    #[test]
+    fn test_validate_reports_paths_of_violations() {
+        let value: Value = serde_json::from_str(INVALID_PLAN).unwrap();
+        let errors = validate(&value, plan_schema());
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|e| e == "$.tasks[0]: missing required field 'description'"));
+        assert!(errors.iter().any(|e| e.starts_with("$.tasks[0].task_type: \"compile\" is not one of")));
+
+        let analysis = json!({ "success": "yes", "summary": "ok", "error": null });
+        assert_eq!(
+            validate(&analysis, task_analysis_schema()),
+            vec!["$.success: expected boolean, got string".to_string()]
+        );
+
+        assert!(parse_plan(VALID_PLAN).is_ok());
+        assert!(parse_task_analysis("not json").unwrap_err()[0].starts_with("response is not valid JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_plan_is_repaired_with_validation_errors() {
+        let provider = ScriptedProvider::new("scripted").with_text_replies(vec![INVALID_PLAN, VALID_PLAN]);
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+
+        let plan = generate_validated(&provider, vec![], "model", &config, parse_plan).await.unwrap();
+        assert_eq!(plan.tasks.len(), 1);
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        let repair = &requests[1].messages;
+        assert_eq!(repair[0].content, INVALID_PLAN);
+        assert!(repair[1].content.contains("missing required field 'description'"));
+        assert!(repair[1].content.contains("\"execute_command\""));
+    }
+
+    #[tokio::test]
+    async fn test_unrunnable_plan_is_repaired_with_diagnostics() {
+        const CYCLIC_PLAN: &str = r#"{"description": "Build", "tasks": [
+            {"id": "t1", "description": "Write", "task_type": "write_file", "dependencies": ["t2"]},
+            {"id": "t2", "description": "Run", "task_type": "execute_command", "dependencies": ["t1"]}]}"#;
+        let provider = ScriptedProvider::new("scripted").with_text_replies(vec![CYCLIC_PLAN, VALID_PLAN]);
+        let config = GenerationConfig::structured(ResponseFormat::plan());
+
+        let plan = generate_validated(&provider, vec![], "model", &config, parse_plan).await.unwrap();
+        assert_eq!(plan.tasks[0].id, "t1");
+
+        let requests = provider.requests();
+        let repair = &requests[1].messages[1].content;
+        assert!(repair.contains("task 't1' of type write_file is missing the required string parameter 'path'"));
+        assert!(repair.contains("cycle: t1 -> t2 -> t1"));
+    }
+
+    #[tokio::test]
+    async fn test_repair_gives_up_after_max_attempts() {
+        let provider = ScriptedProvider::new("scripted").with_text_replies(vec!["{}"; MAX_REPAIR_ATTEMPTS + 1]);
+        let config = GenerationConfig::structured(ResponseFormat::task_analysis());
+
+        let error = generate_validated(&provider, vec![], "model", &config, parse_task_analysis)
+            .await
+            .unwrap_err();
+        assert_eq!(provider.requests().len(), MAX_REPAIR_ATTEMPTS + 1);
+        match error {
+            LlmError::InvalidResponse { message } => {
+                assert!(message.starts_with("Invalid task_analysis after 2 repair attempts"));
+                assert!(message.contains("missing required field 'success'"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Scripted LLM provider for unit tests
+//!
+//! Every method answers with `LlmError::Unknown` unless the test scripts it,
+//! so a test only spells out the calls it expects. Clones share their script
+//! and call log, which lets a test hand a clone to a wrapper and inspect the
+//! original afterwards.
+
+use super::embeddings::{EmbeddingLlmProvider, EmbeddingResponse};
+use super::streaming::{LlmStream, StreamChunk, StreamingLlmProvider};
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, ToolDefinition,
+};
+use crate::planning::{Plan, Task};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+type GenerateFn = dyn Fn(&[Message]) -> Result<LlmResponse, LlmError> + Send + Sync;
+type ContentFn = dyn Fn(&str, &str, &str) -> Result<String, LlmError> + Send + Sync;
+type PlanFn = dyn Fn(&str) -> Result<Plan, LlmError> + Send + Sync;
+type RefineFn = dyn Fn(&Task) -> Result<String, LlmError> + Send + Sync;
+type AnalysisFn = dyn Fn(&Task) -> Result<TaskAnalysis, LlmError> + Send + Sync;
+type StreamFn = dyn Fn(&str) -> Vec<StreamChunk> + Send + Sync;
+
+/// A `generate` request as the provider received it
+#[derive(Debug, Clone)]
+pub(crate) struct ScriptedRequest {
+    pub messages: Vec<Message>,
+    /// Number of tool definitions offered, if any
+    pub tools: Option<usize>,
+}
+
+#[derive(Default)]
+struct Script {
+    replies: Mutex<VecDeque<Result<LlmResponse, LlmError>>>,
+    models: Mutex<Option<Vec<ModelInfo>>>,
+    calls: Mutex<Vec<&'static str>>,
+    requests: Mutex<Vec<ScriptedRequest>>,
+}
+
+/// Provider answering from a script and recording every call it receives
+#[derive(Clone)]
+pub(crate) struct ScriptedProvider {
+    name: String,
+    generate: Option<Arc<GenerateFn>>,
+    content: Option<Arc<ContentFn>>,
+    plan: Option<Arc<PlanFn>>,
+    refine: Option<Arc<RefineFn>>,
+    analysis: Option<Arc<AnalysisFn>>,
+    stream: Option<Arc<StreamFn>>,
+    embeddings: bool,
+    script: Arc<Script>,
+}
+
+impl ScriptedProvider {
+    /// Create a provider that has nothing scripted yet
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            generate: None,
+            content: None,
+            plan: None,
+            refine: None,
+            analysis: None,
+            stream: None,
+            embeddings: false,
+            script: Arc::new(Script::default()),
+        }
+    }
+
+    /// Answer `generate` calls with `replies`, in order
+    pub fn with_replies(self, replies: Vec<LlmResponse>) -> Self {
+        self.script.replies.lock().unwrap().extend(replies.into_iter().map(Ok));
+        self
+    }
+
+    /// Answer `generate` calls with these text replies, in order
+    pub fn with_text_replies(self, replies: Vec<&str>) -> Self {
+        self.with_replies(replies.into_iter().map(text_reply).collect())
+    }
+
+    /// Answer every `generate` call with `respond` once scripted replies run out
+    pub fn on_generate(
+        mut self,
+        respond: impl Fn(&[Message]) -> Result<LlmResponse, LlmError> + Send + Sync + 'static,
+    ) -> Self {
+        self.generate = Some(Arc::new(respond));
+        self
+    }
+
+    /// Answer `generate_content` with `respond(prompt, context, model)`
+    pub fn on_content(
+        mut self,
+        respond: impl Fn(&str, &str, &str) -> Result<String, LlmError> + Send + Sync + 'static,
+    ) -> Self {
+        self.content = Some(Arc::new(respond));
+        self
+    }
+
+    /// Answer `generate_plan` with `respond(prompt)`
+    pub fn on_plan(mut self, respond: impl Fn(&str) -> Result<Plan, LlmError> + Send + Sync + 'static) -> Self {
+        self.plan = Some(Arc::new(respond));
+        self
+    }
+
+    /// Answer `refine_task_for_execution` with `respond(task)`
+    pub fn on_refine(mut self, respond: impl Fn(&Task) -> Result<String, LlmError> + Send + Sync + 'static) -> Self {
+        self.refine = Some(Arc::new(respond));
+        self
+    }
+
+    /// Answer `analyze_task_result` with `respond(task)`
+    pub fn on_analysis(
+        mut self,
+        respond: impl Fn(&Task) -> Result<TaskAnalysis, LlmError> + Send + Sync + 'static,
+    ) -> Self {
+        self.analysis = Some(Arc::new(respond));
+        self
+    }
+
+    /// Support streaming, answering `generate_stream` and `generate_plan_stream`
+    /// with the chunks for the last message or the prompt
+    pub fn with_stream(mut self, chunks: impl Fn(&str) -> Vec<StreamChunk> + Send + Sync + 'static) -> Self {
+        self.stream = Some(Arc::new(chunks));
+        self
+    }
+
+    /// Support embeddings, embedding each text as its length
+    pub fn with_embeddings(mut self) -> Self {
+        self.embeddings = true;
+        self
+    }
+
+    /// Serve `models` from `list_models`
+    pub fn with_models(self, models: Vec<ModelInfo>) -> Self {
+        self.set_models(Some(models));
+        self
+    }
+
+    /// Change the model list, or make listing fail with `None`
+    pub fn set_models(&self, models: Option<Vec<ModelInfo>>) {
+        *self.script.models.lock().unwrap() = models;
+    }
+
+    /// Number of calls received, whether scripted or not
+    pub fn calls(&self) -> usize {
+        self.script.calls.lock().unwrap().len()
+    }
+
+    /// Number of calls received by one method
+    pub fn calls_to(&self, method: &str) -> usize {
+        self.script.calls.lock().unwrap().iter().filter(|call| **call == method).count()
+    }
+
+    /// Every `generate` request received so far
+    pub fn requests(&self) -> Vec<ScriptedRequest> {
+        self.script.requests.lock().unwrap().clone()
+    }
+
+    fn record(&self, method: &'static str) {
+        self.script.calls.lock().unwrap().push(method);
+    }
+}
+
+/// A plain text `generate` reply
+pub(crate) fn text_reply(content: &str) -> LlmResponse {
+    LlmResponse {
+        content: Some(content.to_string()),
+        tool_calls: None,
+        finish_reason: "stop".to_string(),
+        usage: None,
+        backend: None,
+    }
+}
+
+fn unscripted<T>(method: &str) -> Result<T, LlmError> {
+    Err(LlmError::Unknown {
+        message: format!("{} is not scripted", method),
+    })
+}
+
+#[async_trait]
+impl LlmProvider for ScriptedProvider {
+    fn provider_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        self.record("list_models");
+        match self.script.models.lock().unwrap().clone() {
+            Some(models) => Ok(models),
+            None => unscripted("list_models"),
+        }
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        _model: &str,
+        tools: Option<&[ToolDefinition]>,
+        _config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        self.record("generate");
+        self.script.requests.lock().unwrap().push(ScriptedRequest {
+            messages: messages.to_vec(),
+            tools: tools.map(|tools| tools.len()),
+        });
+        if let Some(reply) = self.script.replies.lock().unwrap().pop_front() {
+            return reply;
+        }
+        match &self.generate {
+            Some(respond) => respond(messages),
+            None => unscripted("generate"),
+        }
+    }
+
+    async fn generate_plan(&self, prompt: &str, _context: &str, _model: &str) -> Result<Plan, LlmError> {
+        self.record("generate_plan");
+        match &self.plan {
+            Some(respond) => respond(prompt),
+            None => unscripted("generate_plan"),
+        }
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        _config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        self.record("generate_content");
+        match &self.content {
+            Some(respond) => respond(prompt, context, model),
+            None => unscripted("generate_content"),
+        }
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &Task,
+        _context: &TaskRefinementContext,
+        _model: &str,
+    ) -> Result<String, LlmError> {
+        self.record("refine_task_for_execution");
+        match &self.refine {
+            Some(respond) => respond(task),
+            None => unscripted("refine_task_for_execution"),
+        }
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &Task,
+        _execution_result: &TaskExecutionResult,
+        _expected_outcome: &str,
+        _model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        self.record("analyze_task_result");
+        match &self.analysis {
+            Some(respond) => respond(task),
+            None => unscripted("analyze_task_result"),
+        }
+    }
+
+    async fn validate_model(&self, _model: &str) -> Result<ModelInfo, LlmError> {
+        self.record("validate_model");
+        unscripted("validate_model")
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        self.stream.as_ref().map(|_| self as &dyn StreamingLlmProvider)
+    }
+
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.embeddings.then_some(self as &dyn EmbeddingLlmProvider)
+    }
+}
+
+impl ScriptedProvider {
+    fn scripted_stream(&self, method: &'static str, input: &str) -> Result<LlmStream, LlmError> {
+        self.record(method);
+        match &self.stream {
+            Some(chunks) => Ok(Box::pin(futures::stream::iter(chunks(input).into_iter().map(Ok)))),
+            None => unscripted(method),
+        }
+    }
+}
+
+#[async_trait]
+impl StreamingLlmProvider for ScriptedProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        _model: &str,
+        _tools: Option<&[ToolDefinition]>,
+        _config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let last = messages.last().map(|message| message.content.as_str()).unwrap_or_default();
+        self.scripted_stream("generate_stream", last)
+    }
+
+    async fn generate_plan_stream(&self, prompt: &str, _context: &str, _model: &str) -> Result<LlmStream, LlmError> {
+        self.scripted_stream("generate_plan_stream", prompt)
+    }
+
+    async fn refine_task_stream(
+        &self,
+        _task: &Task,
+        _context: &TaskRefinementContext,
+        _model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        self.record("refine_task_stream");
+        unscripted("refine_task_stream")
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        _task: &Task,
+        _execution_result: &TaskExecutionResult,
+        _expected_outcome: &str,
+        _model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        self.record("analyze_task_result_stream");
+        unscripted("analyze_task_result_stream")
+    }
+}
+
+#[async_trait]
+impl EmbeddingLlmProvider for ScriptedProvider {
+    async fn embed(&self, texts: &[String], _model: &str) -> Result<EmbeddingResponse, LlmError> {
+        self.record("embed");
+        if !self.embeddings {
+            return unscripted("embed");
+        }
+        Ok(EmbeddingResponse {
+            embeddings: texts.iter().map(|text| vec![text.len() as f32]).collect(),
+            usage: None,
+        })
+    }
+}
@@ -4,6 +4,7 @@ use super::{LlmError, TokenUsage, ToolCall, TaskRefinementContext};
 use async_trait::async_trait;
 use futures::stream::Stream;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::pin::Pin;
 
 /// Represents a chunk of streaming response data
@@ -74,9 +75,24 @@ impl StreamChunk {
 /// Stream of chunks from an LLM response
 pub type LlmStream = Pin<Box<dyn Stream<Item = Result<StreamChunk, LlmError>> + Send>>;
 
+/// Agentic operation that produced a piece of streamed output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamStage {
+    Planning,
+    Refinement,
+    Analysis,
+}
+
+/// Streamed text forwarded to the user interfaces
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamOutput {
+    pub stage: StreamStage,
+    pub content: String,
+}
+
 /// Trait for LLM providers that support streaming responses
 #[async_trait]
-pub trait StreamingLlmProvider {
+pub trait StreamingLlmProvider: Send + Sync {
     /// Generate a streaming response
     async fn generate_stream(
         &self,
@@ -126,6 +142,9 @@ pub struct PlanStreamCollector {
     is_json_complete: bool,
     brace_count: i32,
     bracket_count: i32,
+    json_started: bool,
+    in_string: bool,
+    escaped: bool,
 }
 
 /// Specialized collector for streaming task refinement
@@ -140,6 +159,8 @@ pub struct TaskAnalysisCollector {
     json_buffer: String,
     in_json_block: bool,
     brace_count: i32,
+    in_string: bool,
+    escaped: bool,
 }
 
 /// Sections of task refinement streaming
@@ -201,6 +222,7 @@ impl StreamCollector {
             },
             finish_reason: self.finish_reason.unwrap_or_else(|| "stop".to_string()),
             usage: self.usage,
+            backend: None,
         }
     }
 
@@ -232,6 +254,9 @@ impl PlanStreamCollector {
             is_json_complete: false,
             brace_count: 0,
             bracket_count: 0,
+            json_started: false,
+            in_string: false,
+            escaped: false,
         }
     }
 
@@ -240,10 +265,23 @@ impl PlanStreamCollector {
         if let Some(content) = &chunk.content {
             self.plan_buffer.push_str(content);
             
-            // Track JSON structure completion
+            // Track JSON structure completion, ignoring braces inside string literals
             for ch in content.chars() {
+                if self.in_string {
+                    match ch {
+                        _ if self.escaped => self.escaped = false,
+                        '\\' => self.escaped = true,
+                        '"' => self.in_string = false,
+                        _ => {}
+                    }
+                    continue;
+                }
                 match ch {
-                    '{' => self.brace_count += 1,
+                    '"' if self.json_started => self.in_string = true,
+                    '{' => {
+                        self.json_started = true;
+                        self.brace_count += 1;
+                    }
                     '}' => self.brace_count -= 1,
                     '[' => self.bracket_count += 1,
                     ']' => self.bracket_count -= 1,
@@ -251,8 +289,8 @@ impl PlanStreamCollector {
                 }
             }
             
-            // Check if JSON is complete
-            if self.brace_count == 0 && self.bracket_count == 0 && !self.plan_buffer.trim().is_empty() {
+            // Check if JSON is complete (text before the opening brace, e.g. a code fence, is ignored)
+            if self.json_started && self.brace_count == 0 && self.bracket_count == 0 {
                 self.is_json_complete = true;
             }
         }
@@ -272,15 +310,10 @@ impl PlanStreamCollector {
 
     /// Parse the final plan
     pub fn into_plan(self) -> Result<crate::planning::Plan, LlmError> {
-        let plan_json: serde_json::Value = serde_json::from_str(&self.plan_buffer)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse streamed plan JSON: {}", e),
-            })?;
-
-        crate::planning::Plan::from_json(&plan_json)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Invalid streamed plan structure: {}", e),
-            })
+        // Models sometimes wrap the plan in a markdown code fence; the schema parser skips it
+        super::schema::parse_plan(&self.plan_buffer).map_err(|errors| LlmError::InvalidResponse {
+            message: format!("Invalid streamed plan: {}", errors.join("; ")),
+        })
     }
 }
 
@@ -340,6 +373,8 @@ impl TaskAnalysisCollector {
             json_buffer: String::new(),
             in_json_block: false,
             brace_count: 0,
+            in_string: false,
+            escaped: false,
         }
     }
 
@@ -350,6 +385,21 @@ impl TaskAnalysisCollector {
             
             // Track JSON blocks in the response
             for ch in content.chars() {
+                if self.in_string {
+                    self.json_buffer.push(ch);
+                    match ch {
+                        _ if self.escaped => self.escaped = false,
+                        '\\' => self.escaped = true,
+                        '"' => self.in_string = false,
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                if ch == '"' && self.in_json_block {
+                    self.in_string = true;
+                }
+
                 if ch == '{' {
                     if self.brace_count == 0 {
                         self.in_json_block = true;
@@ -388,65 +438,15 @@ impl TaskAnalysisCollector {
 
     /// Parse the final analysis
     pub fn into_analysis(self) -> Result<crate::llm::TaskAnalysis, LlmError> {
-        // Try to parse the JSON buffer first, fall back to analysis buffer
-        let json_content = if !self.json_buffer.trim().is_empty() {
+        // Prefer the JSON buffer, fall back to extracting JSON from the analysis buffer
+        let content = if !self.json_buffer.trim().is_empty() {
             &self.json_buffer
         } else {
-            // Try to extract JSON from the analysis buffer
-            if let Some(start) = self.analysis_buffer.find('{') {
-                if let Some(end) = self.analysis_buffer.rfind('}') {
-                    &self.analysis_buffer[start..=end]
-                } else {
-                    &self.analysis_buffer
-                }
-            } else {
-                &self.analysis_buffer
-            }
+            &self.analysis_buffer
         };
 
-        let analysis_json: serde_json::Value = serde_json::from_str(json_content)
-            .map_err(|e| LlmError::InvalidResponse {
-                message: format!("Failed to parse streamed analysis JSON: {}. Content: {}", e, json_content),
-            })?;
-
-        // Parse into TaskAnalysis (similar to the provider implementations)
-        let success = analysis_json["success"].as_bool().unwrap_or(false);
-        let summary = analysis_json["summary"].as_str().unwrap_or("Analysis unavailable").to_string();
-        let details = analysis_json["details"].as_str().unwrap_or("").to_string();
-        let extracted_data = analysis_json.get("extracted_data").cloned();
-        let next_steps = analysis_json["next_steps"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                .collect::<Vec<String>>());
-        let context_updates = analysis_json["context_updates"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect());
-        let modified_files = analysis_json["modified_files"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| std::path::PathBuf::from(s)))
-                .collect());
-        let error = if !success {
-            Some(analysis_json["error"].as_str().unwrap_or("Task failed").to_string())
-        } else {
-            None
-        };
-        let metadata = analysis_json["metadata"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect())
-            .unwrap_or_default();
-
-        Ok(crate::llm::TaskAnalysis {
-            success,
-            summary,
-            details,
-            extracted_data,
-            next_steps,
-            context_updates,
-            modified_files,
-            error,
-            metadata,
+        super::schema::parse_task_analysis(content).map_err(|errors| LlmError::InvalidResponse {
+            message: format!("Invalid streamed analysis: {}", errors.join("; ")),
         })
     }
 }
@@ -491,6 +491,10 @@ impl SseParser {
     /// Parse incoming data and extract complete SSE events
     pub fn parse(&mut self, data: &str) -> Vec<SseEvent> {
         self.buffer.push_str(data);
+        // Some servers (e.g. Gemini) separate events with CRLF
+        if self.buffer.contains("\r\n") {
+            self.buffer = self.buffer.replace("\r\n", "\n");
+        }
         let mut events = Vec::new();
         
         while let Some(event_end) = self.buffer.find("\n\n") {
@@ -505,6 +509,12 @@ impl SseParser {
         events
     }
 
+    /// Flush a trailing event that was not terminated by a blank line
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let remaining = std::mem::take(&mut self.buffer);
+        Self::parse_event(&remaining).into_iter().collect()
+    }
+
     fn parse_event(data: &str) -> Option<SseEvent> {
         let mut event_type = None;
         let mut event_data = String::new();
@@ -568,6 +578,92 @@ impl SseEvent {
     }
 }
 
+/// Provider-specific decoding of SSE events into stream chunks
+pub trait SseDecoder: Send + 'static {
+    /// Decode one event into zero or more chunks
+    fn decode(&mut self, event: &SseEvent) -> Result<Vec<StreamChunk>, LlmError>;
+
+    /// Emit buffered state once the response body ends; must include the final chunk
+    fn finish(&mut self) -> Vec<StreamChunk>;
+}
+
+/// Turn a successful streaming HTTP response into an `LlmStream`
+///
+/// The stream ends after the first error or after the final chunk. Streams
+/// opened inside `cancellation::with_cancellation` end with
+/// `LlmError::Cancelled` once that token is cancelled.
+pub fn sse_stream<D: SseDecoder>(response: reqwest::Response, decoder: D) -> LlmStream {
+    struct SseState<D> {
+        response: reqwest::Response,
+        parser: SseParser,
+        decoder: D,
+        // Bytes of a UTF-8 sequence split across network chunks
+        partial_utf8: Vec<u8>,
+        pending: VecDeque<Result<StreamChunk, LlmError>>,
+        finished: bool,
+    }
+
+    impl<D: SseDecoder> SseState<D> {
+        fn push_events(&mut self, events: Vec<SseEvent>) {
+            for event in events {
+                match self.decoder.decode(&event) {
+                    Ok(chunks) => self.pending.extend(chunks.into_iter().map(Ok)),
+                    Err(e) => self.pending.push_back(Err(e)),
+                }
+            }
+        }
+    }
+
+    let state = SseState {
+        response,
+        parser: SseParser::new(),
+        decoder,
+        partial_utf8: Vec::new(),
+        pending: VecDeque::new(),
+        finished: false,
+    };
+
+    super::cancellation::cancellable_stream(Box::pin(futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                if item.as_ref().map_or(true, StreamChunk::is_final) {
+                    state.pending.clear();
+                    state.finished = true;
+                }
+                return Some((item, state));
+            }
+
+            if state.finished {
+                return None;
+            }
+
+            match state.response.chunk().await {
+                Ok(Some(bytes)) => {
+                    state.partial_utf8.extend_from_slice(&bytes);
+                    let valid_len = match std::str::from_utf8(&state.partial_utf8) {
+                        Ok(text) => text.len(),
+                        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                        Err(_) => state.partial_utf8.len(),
+                    };
+                    let text = String::from_utf8_lossy(&state.partial_utf8[..valid_len]).into_owned();
+                    state.partial_utf8.drain(..valid_len);
+
+                    let events = state.parser.parse(&text);
+                    state.push_events(events);
+                }
+                Ok(None) => {
+                    let events = state.parser.finish();
+                    state.push_events(events);
+                    let final_chunks = state.decoder.finish();
+                    state.pending.extend(final_chunks.into_iter().map(Ok));
+                    state.finished = true;
+                }
+                Err(e) => state.pending.push_back(Err(e.into())),
+            }
+        }
+    })))
+}
+
 /// Utility functions for working with streaming responses
 pub mod utils {
     use super::*;
@@ -601,6 +697,7 @@ pub mod utils {
                 tool_calls: chunk.tool_calls,
                 finish_reason: chunk.finish_reason.unwrap_or_else(|| "stop".to_string()),
                 usage: chunk.usage,
+                backend: None,
             })
         } else {
             Err(LlmError::InvalidResponse {
@@ -616,7 +713,12 @@ pub mod utils {
     }
 
     /// Collect a plan from a streaming response
-    pub async fn collect_plan_stream(mut stream: LlmStream) -> Result<crate::planning::Plan, LlmError> {
+    pub async fn collect_plan_stream(stream: LlmStream) -> Result<crate::planning::Plan, LlmError> {
+        read_plan_stream(stream).await?.into_plan()
+    }
+
+    /// Read a streamed plan up to the end of its JSON without parsing it
+    async fn read_plan_stream(mut stream: LlmStream) -> Result<PlanStreamCollector, LlmError> {
         use futures::StreamExt;
         
         let mut collector = PlanStreamCollector::new();
@@ -630,7 +732,7 @@ pub mod utils {
             }
         }
         
-        collector.into_plan()
+        Ok(collector)
     }
 
     /// Collect refined task instruction from a streaming response
@@ -668,6 +770,99 @@ pub mod utils {
         
         collector.into_analysis()
     }
+
+    /// Pass each piece of streamed content to `on_content` as it arrives
+    pub fn observe_content<F>(stream: LlmStream, mut on_content: F) -> LlmStream
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        Box::pin(stream.inspect(move |chunk_result| {
+            if let Ok(StreamChunk { content: Some(content), .. }) = chunk_result {
+                on_content(content);
+            }
+        }))
+    }
+
+    /// Generate a plan, streaming its text to `on_content` when the provider supports it
+    pub async fn generate_plan_observed<F>(
+        provider: &dyn crate::llm::LlmProvider,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        on_content: F,
+    ) -> Result<crate::planning::Plan, LlmError>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        match provider.as_streaming() {
+            Some(streaming) => {
+                let stream = streaming.generate_plan_stream(prompt, context, model).await?;
+                let streamed = read_plan_stream(observe_content(stream, on_content)).await?;
+                // An invalid plan is repaired from what was streamed rather than generated again
+                let messages = crate::utils::templates::builders::PlanGenerationMessageBuilder::create(prompt, context)?;
+                let config = crate::llm::GenerationConfig::structured(crate::llm::ResponseFormat::plan());
+                crate::llm::schema::repair_validated(
+                    provider,
+                    messages,
+                    model,
+                    &config,
+                    crate::llm::schema::parse_plan,
+                    streamed.get_current_plan().to_string(),
+                )
+                .await
+            }
+            None => provider.generate_plan(prompt, context, model).await,
+        }
+    }
+
+    /// Refine a task, streaming the instruction to `on_content` when the provider supports it
+    pub async fn refine_task_observed<F>(
+        provider: &dyn crate::llm::LlmProvider,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+        on_content: F,
+    ) -> Result<String, LlmError>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        match provider.as_streaming() {
+            Some(streaming) => {
+                let stream = streaming.refine_task_stream(task, context, model).await?;
+                collect_task_refinement_stream(observe_content(stream, on_content)).await
+            }
+            None => provider.refine_task_for_execution(task, context, model).await,
+        }
+    }
+
+    /// Analyze a task result, streaming the analysis to `on_content` when the provider supports it
+    pub async fn analyze_task_result_observed<F>(
+        provider: &dyn crate::llm::LlmProvider,
+        task: &crate::planning::Task,
+        execution_result: &crate::llm::TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+        on_content: F,
+    ) -> Result<crate::llm::TaskAnalysis, LlmError>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        match provider.as_streaming() {
+            Some(streaming) => {
+                let stream = streaming
+                    .analyze_task_result_stream(task, execution_result, expected_outcome, model)
+                    .await?;
+                match collect_task_analysis_stream(observe_content(stream, on_content)).await {
+                    Err(LlmError::InvalidResponse { message }) => {
+                        tracing::warn!("Streamed analysis was invalid, regenerating with repair: {}", message);
+                        provider.analyze_task_result(task, execution_result, expected_outcome, model).await
+                    }
+                    result => result,
+                }
+            }
+            None => provider.analyze_task_result(task, execution_result, expected_outcome, model).await,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -766,6 +961,80 @@ mod tests {
         assert!(events[1].is_completion());
     }
 
+    #[test]
+    fn test_sse_parser_crlf_and_trailing_event() {
+        let mut parser = SseParser::new();
+
+        let events = parser.parse("data: {\"a\":1}\r\n\r\ndata: {\"b\"");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, r#"{"a":1}"#);
+
+        assert!(parser.parse(":2}").is_empty());
+        let events = parser.finish();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, r#"{"b":2}"#);
+    }
+
+    #[test]
+    fn test_plan_stream_collector_ignores_fences_and_string_braces() {
+        let mut collector = PlanStreamCollector::new();
+
+        let chunks = vec![
+            StreamChunk::content("```json\n"),
+            StreamChunk::content("{\"description\": \"Write main\", \"tasks\": [{\"id\": \"t1\", "),
+            StreamChunk::content("\"description\": \"Write\", \"task_type\": \"write_file\", "),
+            StreamChunk::content("\"parameters\": {\"path\": \"main.rs\", \"content\": \"fn main() { \\\"}\\\" }\"}}]"),
+        ];
+
+        for chunk in &chunks {
+            assert!(!collector.process_chunk(chunk).unwrap());
+        }
+        assert!(collector.process_chunk(&StreamChunk::content("}\n```")).unwrap());
+
+        let plan = collector.into_plan().unwrap();
+        assert_eq!(plan.description, "Write main");
+        assert_eq!(plan.tasks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_observe_content() {
+        let chunks = vec![
+            Ok(StreamChunk::content("a")),
+            Ok(StreamChunk::content("b")),
+            Ok(StreamChunk::finish("stop", None)),
+        ];
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+        let seen_clone = seen.clone();
+
+        let stream = utils::observe_content(Box::pin(stream::iter(chunks)), move |content| {
+            seen_clone.lock().unwrap().push_str(content);
+        });
+        let response = StreamCollector::collect_stream(stream).await.unwrap();
+
+        assert_eq!(response.content.as_deref(), Some("ab"));
+        assert_eq!(*seen.lock().unwrap(), "ab");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_streamed_plan_is_repaired_from_the_stream() {
+        let provider = crate::llm::scripted::ScriptedProvider::new("scripted")
+            .with_stream(|_| vec![StreamChunk::content(r#"{"description": "Build", "tasks": [{"id": "t1"}]}"#)])
+            .with_text_replies(vec![
+                r#"{"description": "Build", "tasks": [{"id": "t1", "description": "Run cargo", "task_type": "execute_command", "parameters": {"command": "cargo"}}]}"#,
+            ]);
+
+        let plan = utils::generate_plan_observed(&provider, "Build it", "", "test-model", |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(plan.tasks[0].description, "Run cargo");
+        assert_eq!(provider.calls_to("generate_plan"), 0);
+        // The repair request quotes the streamed plan back to the model
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].messages.iter().any(|m| m.content.contains(r#"[{"id": "t1"}]"#)));
+    }
+
     #[test]
     fn test_chunk_types() {
         let content_chunk = StreamChunk::content("Hello");
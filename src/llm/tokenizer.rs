@@ -0,0 +1,459 @@
+//! Offline tokenizers for accurate token counting
+//!
+//! Vocabulary files are read from the kai-x data directory
+//! (`~/.local/share/kai-x/tokenizers` on Linux) and never downloaded:
+//!
+//! - `<name>.tiktoken`: byte-level BPE ranks in tiktoken format (one
+//!   `<base64 token> <rank>` pair per line), e.g. `cl100k_base` or `o200k_base`
+//! - `<name>.vocab`: a SentencePiece unigram vocabulary (one `<piece>\t<score>`
+//!   pair per line, as written next to the `.model` file by `spm_train`)
+//!
+//! Each model names its tokenizer through `ModelInfo::tokenizer`. When no
+//! vocabulary file is available the character heuristics in `TokenCounter` are
+//! used instead, so counting always works.
+
+use super::utils::TokenCounter;
+use super::ModelInfo;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+/// Pre-tokenizer pattern for cl100k-style vocabularies
+///
+/// tiktoken's `\s+(?!\S)` branch uses a lookahead the `regex` crate does not
+/// support; `pre_tokenize` emulates it when a whitespace run is matched.
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+";
+
+/// Pre-tokenizer pattern for o200k-style vocabularies
+const O200K_PATTERN: &str = r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+";
+
+/// SentencePiece word boundary marker
+const SPACE_MARKER: char = '\u{2581}';
+
+/// Counts tokens the way a model's tokenizer would
+pub trait Tokenizer: Send + Sync {
+    /// Name of the vocabulary, or "heuristic" for the fallback estimator
+    fn name(&self) -> &str;
+
+    /// Number of tokens `text` encodes to
+    fn count_tokens(&self, text: &str) -> usize;
+}
+
+/// Character-based estimate used when no vocabulary file is available
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        TokenCounter::estimate_tokens(text) as usize
+    }
+}
+
+/// Byte-level BPE tokenizer in the style of tiktoken
+pub struct BpeTokenizer {
+    name: String,
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl BpeTokenizer {
+    /// Build a tokenizer from merge ranks and a pre-tokenizer pattern
+    pub fn new(name: impl Into<String>, ranks: HashMap<Vec<u8>, u32>, pattern: &str) -> Result<Self, String> {
+        let pattern = Regex::new(pattern).map_err(|e| format!("Invalid pre-tokenizer pattern: {}", e))?;
+        Ok(Self {
+            name: name.into(),
+            ranks,
+            pattern,
+        })
+    }
+
+    /// Load a `.tiktoken` rank file
+    ///
+    /// The o200k pre-tokenizer is used for vocabularies whose name starts with
+    /// `o200k`; everything else uses the cl100k pattern.
+    pub fn load(name: &str, path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let mut ranks = HashMap::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line
+                .split_once(' ')
+                .and_then(|(token, rank)| Some((STANDARD.decode(token).ok()?, rank.trim().parse::<u32>().ok()?)));
+            match parsed {
+                Some((token, rank)) => {
+                    ranks.insert(token, rank);
+                }
+                None => {
+                    return Err(format!("Malformed line {} in {}", line_number + 1, path.display()));
+                }
+            }
+        }
+
+        let pattern = if name.starts_with("o200k") { O200K_PATTERN } else { CL100K_PATTERN };
+        Self::new(name, ranks, pattern)
+    }
+
+    /// Split text into the pieces BPE is applied to
+    pub fn pre_tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut pieces = Vec::new();
+        let mut position = 0;
+
+        while position < text.len() {
+            let Some(found) = self.pattern.find_at(text, position) else {
+                break;
+            };
+            let mut end = found.end();
+            let piece = &text[position..end];
+
+            // Emulate `\s+(?!\S)`: a whitespace run followed by a word leaves
+            // its last character to be attached to that word
+            if piece.chars().all(char::is_whitespace) && text[end..].chars().next().is_some_and(|c| !c.is_whitespace()) {
+                if let Some((last_start, _)) = piece.char_indices().last().filter(|(start, _)| *start > 0) {
+                    end = position + last_start;
+                }
+            }
+
+            pieces.push(&text[position..end]);
+            position = end;
+        }
+
+        pieces
+    }
+
+    /// Number of tokens a single pre-tokenized piece merges into
+    fn count_piece(&self, piece: &[u8]) -> usize {
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return 1;
+        }
+
+        // Boundaries between the current parts; merge the lowest-ranked pair until none is known
+        let mut boundaries: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..boundaries.len().saturating_sub(2))
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[boundaries[i]..boundaries[i + 2]])
+                        .map(|rank| (*rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    boundaries.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+
+        boundaries.len() - 1
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.pre_tokenize(text)
+            .into_iter()
+            .map(|piece| self.count_piece(piece.as_bytes()))
+            .sum()
+    }
+}
+
+/// SentencePiece unigram tokenizer
+pub struct SentencePieceTokenizer {
+    name: String,
+    scores: HashMap<String, f32>,
+    /// Longest piece in characters, bounding the Viterbi search window
+    max_piece_chars: usize,
+    /// Whether unknown characters are spelled out as `<0xNN>` byte pieces
+    byte_fallback: bool,
+    /// Score given to characters no piece covers
+    unknown_score: f32,
+}
+
+impl SentencePieceTokenizer {
+    /// Build a tokenizer from piece scores
+    pub fn new(name: impl Into<String>, scores: HashMap<String, f32>) -> Self {
+        let max_piece_chars = scores.keys().map(|piece| piece.chars().count()).max().unwrap_or(1);
+        let byte_fallback = scores.contains_key("<0x00>");
+        let min_score = scores.values().copied().fold(0.0f32, f32::min);
+        Self {
+            name: name.into(),
+            scores,
+            max_piece_chars,
+            byte_fallback,
+            unknown_score: min_score - 10.0,
+        }
+    }
+
+    /// Load a SentencePiece `.vocab` file
+    pub fn load(name: &str, path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let mut scores = HashMap::new();
+        for (line_number, line) in content.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let (piece, score) = line
+                .split_once('\t')
+                .and_then(|(piece, score)| Some((piece, score.trim().parse::<f32>().ok()?)))
+                .ok_or_else(|| format!("Malformed line {} in {}", line_number + 1, path.display()))?;
+            scores.insert(piece.to_string(), score);
+        }
+
+        Ok(Self::new(name, scores))
+    }
+}
+
+impl Tokenizer for SentencePieceTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+
+        // Default normalization: a leading dummy space and spaces as the boundary marker
+        let normalized: Vec<char> = std::iter::once(SPACE_MARKER)
+            .chain(text.chars().map(|c| if c == ' ' { SPACE_MARKER } else { c }))
+            .collect();
+
+        // Viterbi over character positions: best (score, token count) reaching each position
+        let mut best: Vec<Option<(f32, usize)>> = vec![None; normalized.len() + 1];
+        best[0] = Some((0.0, 0));
+        let mut piece = String::new();
+
+        for start in 0..normalized.len() {
+            let Some((score, count)) = best[start] else {
+                continue;
+            };
+
+            piece.clear();
+            let mut matched_single = false;
+            for end in start + 1..=normalized.len().min(start + self.max_piece_chars) {
+                piece.push(normalized[end - 1]);
+                if let Some(piece_score) = self.scores.get(piece.as_str()) {
+                    matched_single |= end == start + 1;
+                    let candidate = (score + piece_score, count + 1);
+                    if best[end].is_none_or(|current| candidate.0 > current.0) {
+                        best[end] = Some(candidate);
+                    }
+                }
+            }
+
+            // Characters outside the vocabulary become byte pieces or a single unknown token
+            if !matched_single {
+                let tokens = if self.byte_fallback { normalized[start].len_utf8() } else { 1 };
+                let candidate = (score + self.unknown_score * tokens as f32, count + tokens);
+                if best[start + 1].is_none_or(|current| candidate.0 > current.0) {
+                    best[start + 1] = Some(candidate);
+                }
+            }
+        }
+
+        best[normalized.len()].map(|(_, count)| count).unwrap_or(0)
+    }
+}
+
+/// Default tokenizer name for a model id, when one is known
+///
+/// Provider prefixes such as `openai/` are ignored. Returns `None` for models
+/// whose tokenizer is not published, which fall back to the heuristic counter.
+pub fn tokenizer_name_for_model(model_id: &str) -> Option<String> {
+    let model = model_id.rsplit('/').next().unwrap_or(model_id).to_lowercase();
+
+    let name = if model.starts_with("gpt-4o")
+        || model.starts_with("gpt-4.1")
+        || model.starts_with("gpt-5")
+        || model.starts_with("o1")
+        || model.starts_with("o3")
+        || model.starts_with("o4")
+    {
+        "o200k_base"
+    } else if model.starts_with("gpt-4") || model.starts_with("gpt-3.5") || model.starts_with("text-embedding") {
+        "cl100k_base"
+    } else if model.contains("llama-3") || model.contains("llama3") {
+        "llama3"
+    } else if model.contains("llama-2") || model.contains("llama2") || model.contains("mistral") || model.contains("mixtral") {
+        "llama"
+    } else if model.starts_with("gemma") {
+        "gemma"
+    } else {
+        return None;
+    };
+    Some(name.to_string())
+}
+
+/// Loads tokenizers from a vocabulary directory and caches them by name
+pub struct TokenizerRegistry {
+    dir: Option<PathBuf>,
+    loaded: Mutex<HashMap<String, Arc<dyn Tokenizer>>>,
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL_REGISTRY: TokenizerRegistry = TokenizerRegistry::new(TokenizerRegistry::default_dir());
+}
+
+impl TokenizerRegistry {
+    /// Create a registry reading vocabularies from `dir`
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Shared registry over the default vocabulary directory
+    pub fn global() -> &'static TokenizerRegistry {
+        &GLOBAL_REGISTRY
+    }
+
+    /// Default vocabulary directory under the kai-x data directory
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_local_dir()
+            .or_else(dirs::data_dir)
+            .map(|dir| dir.join("kai-x").join("tokenizers"))
+    }
+
+    /// Tokenizer for a vocabulary name, or the heuristic if its file is missing or invalid
+    pub fn get(&self, name: &str) -> Arc<dyn Tokenizer> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tokenizer) = loaded.get(name) {
+            return tokenizer.clone();
+        }
+
+        let tokenizer = self.load(name).unwrap_or_else(|| Arc::new(HeuristicTokenizer));
+        loaded.insert(name.to_string(), tokenizer.clone());
+        tokenizer
+    }
+
+    /// Tokenizer named by a model's `ModelInfo`, falling back to its id
+    pub fn for_model_info(&self, model: &ModelInfo) -> Arc<dyn Tokenizer> {
+        match &model.tokenizer {
+            Some(name) => self.get(name),
+            None => self.for_model(&model.id),
+        }
+    }
+
+    /// Tokenizer for a model id
+    pub fn for_model(&self, model_id: &str) -> Arc<dyn Tokenizer> {
+        match tokenizer_name_for_model(model_id) {
+            Some(name) => self.get(&name),
+            None => Arc::new(HeuristicTokenizer),
+        }
+    }
+
+    fn load(&self, name: &str) -> Option<Arc<dyn Tokenizer>> {
+        let dir = self.dir.as_ref()?;
+
+        let bpe_path = dir.join(format!("{}.tiktoken", name));
+        let sentencepiece_path = dir.join(format!("{}.vocab", name));
+        let loaded: Result<Arc<dyn Tokenizer>, String> = if bpe_path.is_file() {
+            BpeTokenizer::load(name, &bpe_path).map(|t| Arc::new(t) as Arc<dyn Tokenizer>)
+        } else if sentencepiece_path.is_file() {
+            SentencePieceTokenizer::load(name, &sentencepiece_path).map(|t| Arc::new(t) as Arc<dyn Tokenizer>)
+        } else {
+            debug!("No vocabulary for tokenizer '{}' in {}; using heuristic counts", name, dir.display());
+            return None;
+        };
+
+        loaded
+            .inspect_err(|error| warn!("Failed to load tokenizer '{}': {}", name, error))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rank file with every byte plus a few merges
+    fn write_vocab(dir: &Path) {
+        let mut tokens: Vec<Vec<u8>> = (0u8..=255).map(|b| vec![b]).collect();
+        for merge in ["he", "ll", "hell", "hello", " w", "or", " wor", " world", "!!"] {
+            tokens.push(merge.as_bytes().to_vec());
+        }
+        let lines: Vec<String> = tokens
+            .iter()
+            .enumerate()
+            .map(|(rank, token)| format!("{} {}", STANDARD.encode(token), rank))
+            .collect();
+        std::fs::write(dir.join("tiny.tiktoken"), lines.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn test_bpe_counts_merged_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        write_vocab(dir.path());
+        let tokenizer = BpeTokenizer::load("tiny", &dir.path().join("tiny.tiktoken")).unwrap();
+
+        assert_eq!(
+            tokenizer.pre_tokenize("hello   world!!\n"),
+            vec!["hello", "  ", " world", "!!\n"]
+        );
+        assert_eq!(tokenizer.count_tokens("hello"), 1);
+        assert_eq!(tokenizer.count_tokens("hello world"), 2);
+        // "!!\n" merges to "!!" plus the newline byte; the two spaces stay separate
+        assert_eq!(tokenizer.count_tokens("hello   world!!\n"), 6);
+        assert_eq!(tokenizer.count_tokens("xyz"), 3);
+        assert_eq!(tokenizer.count_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_sentencepiece_prefers_high_scoring_pieces() {
+        let dir = tempfile::tempdir().unwrap();
+        let vocab = "<unk>\t0\n\u{2581}hello\t-1\n\u{2581}world\t-1.5\n\u{2581}\t-3\nh\t-4\ne\t-4\nl\t-4\no\t-4\n";
+        std::fs::write(dir.path().join("sp.vocab"), vocab).unwrap();
+        let tokenizer = SentencePieceTokenizer::load("sp", &dir.path().join("sp.vocab")).unwrap();
+
+        assert_eq!(tokenizer.count_tokens("hello world"), 2);
+        assert_eq!(tokenizer.count_tokens("hell"), 5);
+        // Unknown characters count as one token each without byte fallback
+        assert_eq!(tokenizer.count_tokens("hello zz"), 4);
+    }
+
+    #[test]
+    fn test_registry_selects_by_model_and_falls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        write_vocab(dir.path());
+        std::fs::rename(dir.path().join("tiny.tiktoken"), dir.path().join("cl100k_base.tiktoken")).unwrap();
+        let registry = TokenizerRegistry::new(Some(dir.path().to_path_buf()));
+
+        assert_eq!(registry.for_model("openai/gpt-4-turbo").name(), "cl100k_base");
+        // o200k vocabulary is not installed, so counts fall back to the heuristic
+        assert_eq!(registry.for_model("openai/gpt-4o").name(), "heuristic");
+        assert_eq!(registry.for_model("some-unknown-model").name(), "heuristic");
+
+        let model = ModelInfo {
+            id: "custom".to_string(),
+            name: "Custom".to_string(),
+            description: None,
+            context_length: None,
+            max_output_tokens: None,
+            pricing: None,
+            tokenizer: Some("cl100k_base".to_string()),
+            capabilities: Default::default(),
+        };
+        assert_eq!(registry.for_model_info(&model).name(), "cl100k_base");
+        assert_eq!(tokenizer_name_for_model("meta-llama/llama-3.1-70b-instruct").as_deref(), Some("llama3"));
+    }
+}
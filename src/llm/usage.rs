@@ -0,0 +1,975 @@
+//! Persistent usage ledger and spending budgets
+//!
+//! `MeteredProvider` wraps any provider and appends one record per successful
+//! call to a JSON-lines ledger under the kai-x data directory, so spend
+//! survives restarts and can be reported by model and by day. Providers that
+//! do not report token usage are metered with the model's tokenizer instead.
+//! The ledger also answers whether the next piece of work would push the
+//! session, daily or monthly spend past the caps in `[budget]`.
+//!
+//! Plans, refinements, analyses and content are built by the provider from
+//! `generate` calls of its own, which it reports with [`report_generate`].
+//! Each of those calls is recorded with the usage the backend returned, so a
+//! plan that needed a schema repair is billed for both requests.
+//!
+//! Embedding calls are recorded like any other call, with their input as
+//! prompt tokens, so they show up in the same usage reports.
+//!
+//! Calls are costed with the prices from the provider's model listing, as
+//! cached by the model registry, falling back to the built-in price table.
+//! Every record notes which prices it used.
+
+use super::embeddings::{EmbeddingLlmProvider, EmbeddingResponse};
+use super::fallback;
+use super::streaming::{LlmStream, StreamChunk, StreamingLlmProvider};
+use super::registry::{ModelCatalog, ModelRegistry};
+use super::utils::{CostEstimator, PriceSource, TokenCounter, UsageTracker};
+use super::{
+    GenerationConfig, LlmError, LlmProvider, LlmResponse, Message, ModelInfo, ResponseBackend, TaskAnalysis,
+    TaskExecutionResult, TaskRefinementContext, TokenUsage, ToolDefinition,
+};
+use crate::config::BudgetConfig;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How often a metered provider looks for a newer cached model listing
+const PRICING_RECHECK: Duration = Duration::from_secs(60 * 60);
+
+tokio::task_local! {
+    static REPORTED_CALLS: Arc<Mutex<Vec<ReportedCall>>>;
+}
+
+/// A `generate` call a provider made while serving a higher-level request
+struct ReportedCall {
+    provider: String,
+    model: String,
+    usage: TokenUsage,
+    estimated: bool,
+}
+
+/// Report a completed `generate` call to the metered request it was made for
+///
+/// Providers call this from `generate`, because the calls they make for plans,
+/// refinements, analyses and content never pass through `MeteredProvider`.
+/// Outside a metered request it does nothing.
+pub fn report_generate(provider: &str, model: &str, messages: &[Message], response: &LlmResponse) {
+    let _ = REPORTED_CALLS.try_with(|calls| {
+        let (usage, estimated) = match &response.usage {
+            Some(usage) => (usage.clone(), false),
+            None => {
+                let output = response.content.as_deref().unwrap_or_default();
+                (estimate_usage(model, &messages_text(messages), output), true)
+            }
+        };
+        calls.lock().unwrap().push(ReportedCall {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            usage,
+            estimated,
+        });
+    });
+}
+
+/// One metered LLM call as stored in the ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Process-wide id of the kai-x session that made the call
+    pub session_id: String,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// Cost in USD, zero when the model has no known pricing
+    pub cost: f64,
+    /// Whether token counts were estimated locally rather than reported by the provider
+    #[serde(default)]
+    pub estimated: bool,
+    /// Prices the cost was calculated with, `None` when the model has no known pricing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_source: Option<PriceSource>,
+}
+
+/// Window a spending cap applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Session,
+    Day,
+    Month,
+}
+
+impl fmt::Display for BudgetPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetPeriod::Session => write!(f, "session"),
+            BudgetPeriod::Day => write!(f, "daily"),
+            BudgetPeriod::Month => write!(f, "monthly"),
+        }
+    }
+}
+
+/// A spending cap that the next piece of work would exceed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetExceeded {
+    pub period: BudgetPeriod,
+    /// Configured cap in USD
+    pub limit: f64,
+    /// Spend so far in the period in USD
+    pub spent: f64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} spending cap of ${:.2} would be exceeded (${:.4} spent)",
+            self.period, self.limit, self.spent
+        )
+    }
+}
+
+/// Append-only ledger of LLM usage, one JSON record per line
+pub struct UsageLedger {
+    path: PathBuf,
+    session_id: String,
+    estimator: RwLock<CostEstimator>,
+    /// Spend recorded by this session; the lock also serializes appends
+    session_cost: Mutex<f64>,
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL_LEDGER: Option<Arc<UsageLedger>> =
+        UsageLedger::default_path().map(|path| Arc::new(UsageLedger::new(path)));
+}
+
+impl UsageLedger {
+    /// Open a ledger at `path` for a new session
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            session_id: uuid::Uuid::new_v4().to_string(),
+            estimator: RwLock::new(CostEstimator::new()),
+            session_cost: Mutex::new(0.0),
+        }
+    }
+
+    /// Default ledger location under the kai-x data directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_local_dir()
+            .or_else(dirs::data_dir)
+            .map(|dir| dir.join("kai-x").join("usage.jsonl"))
+    }
+
+    /// Ledger shared by every provider and coordinator in this process
+    ///
+    /// Returns `None` when the platform has no data directory.
+    pub fn global() -> Option<Arc<UsageLedger>> {
+        GLOBAL_LEDGER.clone()
+    }
+
+    /// File backing this ledger
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Id stamped on the records of this session
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Spend recorded by this session so far
+    pub fn session_cost(&self) -> f64 {
+        *self.session_cost.lock().unwrap()
+    }
+
+    /// Use the pricing a provider reports for a model when costing its calls
+    pub fn learn_pricing(&self, provider: &str, model: &ModelInfo) {
+        let Some(pricing) = &model.pricing else {
+            return;
+        };
+        if pricing.prompt.is_none() && pricing.completion.is_none() {
+            return;
+        }
+        self.estimator.write().unwrap().set_model_pricing(
+            &model.id,
+            pricing.clone(),
+            PriceSource::listing(provider, Utc::now()),
+        );
+    }
+
+    /// Use the prices of a cached model listing where they are newer than the known ones
+    pub fn sync_pricing(&self, catalog: &ModelCatalog) -> usize {
+        self.estimator.write().unwrap().sync_catalog(catalog)
+    }
+
+    /// Cost a call and append it to the ledger
+    pub fn record(
+        &self,
+        provider: &str,
+        model: &str,
+        usage: &TokenUsage,
+        estimated: bool,
+    ) -> Result<UsageRecord, LlmError> {
+        let cost = self.estimator.read().unwrap().calculate_cost(model, usage);
+        let record = UsageRecord {
+            timestamp: Utc::now(),
+            session_id: self.session_id.clone(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost: cost.total_cost.unwrap_or(0.0),
+            estimated,
+            price_source: cost.price_source,
+        };
+
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        let mut session_cost = self.session_cost.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| ledger_error(&self.path, e))?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| ledger_error(&self.path, e))?;
+        *session_cost += record.cost;
+
+        Ok(record)
+    }
+
+    /// All records in the ledger, skipping lines that cannot be parsed
+    pub fn records(&self) -> Result<Vec<UsageRecord>, LlmError> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(ledger_error(&self.path, e)),
+        };
+
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    warn!("Skipping unreadable usage record in {}: {}", self.path.display(), e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Aggregate spend by model and by day, optionally from a date onwards
+    pub fn report(&self, since: Option<NaiveDate>) -> Result<UsageTracker, LlmError> {
+        let mut tracker = UsageTracker::new();
+        for record in self.records()? {
+            let date = record.timestamp.date_naive();
+            if since.is_some_and(|since| date < since) {
+                continue;
+            }
+            let cost = super::utils::CostBreakdown {
+                prompt_tokens: record.prompt_tokens,
+                completion_tokens: record.completion_tokens,
+                total_tokens: record.prompt_tokens + record.completion_tokens,
+                prompt_cost: None,
+                completion_cost: None,
+                total_cost: Some(record.cost),
+                currency: Some("USD".to_string()),
+                price_source: record.price_source,
+            };
+            tracker.record_usage_on(&date.format("%Y-%m-%d").to_string(), &record.model, &cost);
+        }
+        Ok(tracker)
+    }
+
+    /// Caps that spending `projected_cost` more would reach or exceed
+    ///
+    /// Session spend comes from this process; daily and monthly spend are read
+    /// from the ledger so they include other sessions.
+    pub fn check_budget(
+        &self,
+        budget: &BudgetConfig,
+        projected_cost: f64,
+    ) -> Result<Vec<BudgetExceeded>, LlmError> {
+        let mut exceeded = Vec::new();
+        let mut check = |period, limit: Option<f64>, spent: f64| {
+            if let Some(limit) = limit {
+                if spent + projected_cost >= limit {
+                    exceeded.push(BudgetExceeded { period, limit, spent });
+                }
+            }
+        };
+
+        check(BudgetPeriod::Session, budget.session_limit, self.session_cost());
+        if budget.daily_limit.is_none() && budget.monthly_limit.is_none() {
+            return Ok(exceeded);
+        }
+
+        let now = Utc::now();
+        let today = now.format("%Y-%m-%d").to_string();
+        let month = now.format("%Y-%m").to_string();
+        let (mut daily, mut monthly) = (0.0, 0.0);
+        for record in self.records()? {
+            let date = record.timestamp.format("%Y-%m-%d").to_string();
+            if date.starts_with(&month) {
+                monthly += record.cost;
+                if date == today {
+                    daily += record.cost;
+                }
+            }
+        }
+        check(BudgetPeriod::Day, budget.daily_limit, daily);
+        check(BudgetPeriod::Month, budget.monthly_limit, monthly);
+
+        Ok(exceeded)
+    }
+}
+
+fn ledger_error(path: &Path, error: std::io::Error) -> LlmError {
+    LlmError::Unknown {
+        message: format!("Failed to access usage ledger {}: {}", path.display(), error),
+    }
+}
+
+/// Token usage estimated from the request and response text
+fn estimate_usage(model: &str, input: &str, output: &str) -> TokenUsage {
+    let prompt_tokens = TokenCounter::count_tokens(input, model);
+    let completion_tokens = TokenCounter::count_tokens(output, model);
+    TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+fn messages_text(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Accumulates a streamed response and records it when the stream is dropped
+///
+/// Callers often stop reading once the content they need has arrived, so
+/// recording on drop meters partially consumed streams too.
+struct StreamMeter {
+    ledger: Arc<UsageLedger>,
+    provider: String,
+    model: String,
+    input: String,
+    output: String,
+    usage: Option<TokenUsage>,
+    received: bool,
+}
+
+impl StreamMeter {
+    fn observe(&mut self, chunk: &StreamChunk) {
+        self.received = true;
+        if let Some(content) = &chunk.content {
+            self.output.push_str(content);
+        }
+        if let Some(usage) = &chunk.usage {
+            self.usage = Some(usage.clone());
+        }
+    }
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        if !self.received {
+            return;
+        }
+        let (usage, estimated) = match self.usage.take() {
+            Some(usage) => (usage, false),
+            None => (estimate_usage(&self.model, &self.input, &self.output), true),
+        };
+        if let Err(e) = self.ledger.record(&self.provider, &self.model, &usage, estimated) {
+            warn!("Failed to record streamed usage: {}", e);
+        }
+    }
+}
+
+/// Provider wrapper that records every successful call in a usage ledger
+pub struct MeteredProvider {
+    inner: Box<dyn LlmProvider>,
+    ledger: Arc<UsageLedger>,
+    /// Cached model listings the prices are taken from
+    registry: Option<Arc<ModelRegistry>>,
+    /// When a cached listing was last applied to the ledger's prices
+    pricing_checked_at: Mutex<Option<Instant>>,
+}
+
+impl MeteredProvider {
+    /// Wrap a provider, recording its usage in `ledger`
+    pub fn new(inner: Box<dyn LlmProvider>, ledger: Arc<UsageLedger>) -> Self {
+        Self {
+            inner,
+            ledger,
+            registry: ModelRegistry::global(),
+            pricing_checked_at: Mutex::new(None),
+        }
+    }
+
+    /// Take prices from the listings in `registry` instead of the shared model registry
+    pub fn with_model_registry(mut self, registry: Arc<ModelRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Ledger this provider records into
+    pub fn ledger(&self) -> &Arc<UsageLedger> {
+        &self.ledger
+    }
+
+    /// Apply the provider's cached model listing to the ledger's prices
+    ///
+    /// Listings are fetched by whoever needs them first, so until one is
+    /// cached every call looks again; after that, once per `PRICING_RECHECK`.
+    fn sync_pricing(&self) {
+        let Some(registry) = &self.registry else {
+            return;
+        };
+        let mut checked_at = self.pricing_checked_at.lock().unwrap();
+        if checked_at.is_some_and(|checked_at| checked_at.elapsed() < PRICING_RECHECK) {
+            return;
+        }
+        if let Some(catalog) = registry.cached(self.inner.provider_name()) {
+            self.ledger.sync_pricing(&catalog);
+            *checked_at = Some(Instant::now());
+        }
+    }
+
+    fn meter(&self, provider: &str, model: &str, usage: &TokenUsage, estimated: bool) {
+        self.sync_pricing();
+        if let Err(e) = self.ledger.record(provider, model, usage, estimated) {
+            warn!("Failed to record usage: {}", e);
+        }
+    }
+
+    /// Record a call whose provider does not report token usage
+    fn meter_estimated(&self, provider: &str, model: &str, input: &str, output: &str) {
+        let usage = estimate_usage(model, input, output);
+        self.meter(provider, model, &usage, true);
+    }
+
+    /// Provider and model to bill, preferring the fallback backend that answered
+    fn billed_to<'a>(&'a self, backend: &'a Option<ResponseBackend>, model: &'a str) -> (&'a str, &'a str) {
+        match backend {
+            Some(backend) => (backend.provider.as_str(), backend.model.as_str()),
+            None => (self.inner.provider_name(), model),
+        }
+    }
+
+    /// Run a higher-level request and record every `generate` call it reported
+    ///
+    /// Attempts are recorded even when the request fails in the end, since
+    /// they were billed. A request that reported nothing, such as a replayed
+    /// cassette, is estimated from the `(input, output)` that `describe` gives.
+    async fn metered<T, Fut>(
+        &self,
+        model: &str,
+        request: Fut,
+        describe: impl FnOnce(&T) -> (String, String),
+    ) -> Result<T, LlmError>
+    where
+        Fut: std::future::Future<Output = Result<T, LlmError>>,
+    {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (result, backend) = fallback::answered_by(REPORTED_CALLS.scope(calls.clone(), request)).await;
+        let calls = std::mem::take(&mut *calls.lock().unwrap());
+        for call in &calls {
+            self.meter(&call.provider, &call.model, &call.usage, call.estimated);
+        }
+
+        let response = result?;
+        if calls.is_empty() {
+            let (input, output) = describe(&response);
+            let (provider, model) = self.billed_to(&backend, model);
+            self.meter_estimated(provider, model, &input, &output);
+        }
+        Ok(response)
+    }
+
+    fn metered_stream(
+        &self,
+        stream: LlmStream,
+        backend: Option<ResponseBackend>,
+        model: &str,
+        input: String,
+    ) -> LlmStream {
+        self.sync_pricing();
+        let (provider, model) = self.billed_to(&backend, model);
+        let mut meter = StreamMeter {
+            ledger: self.ledger.clone(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input,
+            output: String::new(),
+            usage: None,
+            received: false,
+        };
+        Box::pin(stream.map(move |chunk| {
+            if let Ok(chunk) = &chunk {
+                meter.observe(chunk);
+            }
+            chunk
+        }))
+    }
+
+    fn inner_streaming(&self) -> Result<&dyn StreamingLlmProvider, LlmError> {
+        self.inner.as_streaming().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support streaming", self.inner.provider_name()),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MeteredProvider {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, LlmError> {
+        let models = self.inner.list_models().await?;
+        for model in &models {
+            self.ledger.learn_pricing(self.inner.provider_name(), model);
+        }
+        Ok(models)
+    }
+
+    async fn generate(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmResponse, LlmError> {
+        let response = self.inner.generate(messages, model, tools, config).await?;
+
+        // A fallback chain reports which backend actually answered
+        let (provider, model) = self.billed_to(&response.backend, model);
+        match &response.usage {
+            Some(usage) => self.meter(provider, model, usage, false),
+            None => {
+                let output = response.content.as_deref().unwrap_or_default();
+                let usage = estimate_usage(model, &messages_text(messages), output);
+                self.meter(provider, model, &usage, true);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn generate_plan(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<crate::planning::Plan, LlmError> {
+        self.metered(model, self.inner.generate_plan(prompt, context, model), |plan| {
+            let output = serde_json::to_string(plan).unwrap_or_default();
+            (format!("{}\n{}", prompt, context), output)
+        })
+        .await
+    }
+
+    async fn generate_content(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+        config: Option<&GenerationConfig>,
+    ) -> Result<String, LlmError> {
+        self.metered(model, self.inner.generate_content(prompt, context, model, config), |content| {
+            (format!("{}\n{}", prompt, context), content.clone())
+        })
+        .await
+    }
+
+    async fn refine_task_for_execution(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<String, LlmError> {
+        self.metered(model, self.inner.refine_task_for_execution(task, context, model), |instruction| {
+            (refinement_input(task, context), instruction.clone())
+        })
+        .await
+    }
+
+    async fn analyze_task_result(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<TaskAnalysis, LlmError> {
+        let request = self.inner.analyze_task_result(task, execution_result, expected_outcome, model);
+        self.metered(model, request, |analysis| {
+            let output = serde_json::to_string(analysis).unwrap_or_default();
+            (analysis_input(task, execution_result, expected_outcome), output)
+        })
+        .await
+    }
+
+    async fn validate_model(&self, model: &str) -> Result<ModelInfo, LlmError> {
+        let info = self.inner.validate_model(model).await?;
+        self.ledger.learn_pricing(self.inner.provider_name(), &info);
+        Ok(info)
+    }
+
+    fn as_streaming(&self) -> Option<&dyn StreamingLlmProvider> {
+        self.inner.as_streaming().map(|_| self as &dyn StreamingLlmProvider)
+    }
+
+    fn as_embedding(&self) -> Option<&dyn EmbeddingLlmProvider> {
+        self.inner.as_embedding().map(|_| self as &dyn EmbeddingLlmProvider)
+    }
+}
+
+#[async_trait]
+impl EmbeddingLlmProvider for MeteredProvider {
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingResponse, LlmError> {
+        let embedding = self.inner.as_embedding().ok_or_else(|| LlmError::Unknown {
+            message: format!("{} does not support embeddings", self.inner.provider_name()),
+        })?;
+        let response = embedding.embed(texts, model).await?;
+
+        match &response.usage {
+            Some(usage) => self.meter(self.inner.provider_name(), model, usage, false),
+            None => self.meter_estimated(self.inner.provider_name(), model, &texts.join("\n"), ""),
+        }
+        Ok(response)
+    }
+}
+
+fn refinement_input(task: &crate::planning::Task, context: &TaskRefinementContext) -> String {
+    format!(
+        "{}\n{}",
+        task.description,
+        serde_json::to_string(context).unwrap_or_default()
+    )
+}
+
+fn analysis_input(
+    task: &crate::planning::Task,
+    execution_result: &TaskExecutionResult,
+    expected_outcome: &str,
+) -> String {
+    format!(
+        "{}\n{}\n{}",
+        task.description,
+        serde_json::to_string(execution_result).unwrap_or_default(),
+        expected_outcome
+    )
+}
+
+#[async_trait]
+impl StreamingLlmProvider for MeteredProvider {
+    async fn generate_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        config: Option<&GenerationConfig>,
+    ) -> Result<LlmStream, LlmError> {
+        let (stream, backend) =
+            fallback::answered_by(self.inner_streaming()?.generate_stream(messages, model, tools, config)).await;
+        let stream = stream?;
+        Ok(self.metered_stream(stream, backend, model, messages_text(messages)))
+    }
+
+    async fn generate_plan_stream(
+        &self,
+        prompt: &str,
+        context: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let (stream, backend) =
+            fallback::answered_by(self.inner_streaming()?.generate_plan_stream(prompt, context, model)).await;
+        let stream = stream?;
+        Ok(self.metered_stream(stream, backend, model, format!("{}\n{}", prompt, context)))
+    }
+
+    async fn refine_task_stream(
+        &self,
+        task: &crate::planning::Task,
+        context: &TaskRefinementContext,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let (stream, backend) =
+            fallback::answered_by(self.inner_streaming()?.refine_task_stream(task, context, model)).await;
+        let stream = stream?;
+        Ok(self.metered_stream(stream, backend, model, refinement_input(task, context)))
+    }
+
+    async fn analyze_task_result_stream(
+        &self,
+        task: &crate::planning::Task,
+        execution_result: &TaskExecutionResult,
+        expected_outcome: &str,
+        model: &str,
+    ) -> Result<LlmStream, LlmError> {
+        let request = self
+            .inner_streaming()?
+            .analyze_task_result_stream(task, execution_result, expected_outcome, model);
+        let (stream, backend) = fallback::answered_by(request).await;
+        let stream = stream?;
+        let input = analysis_input(task, execution_result, expected_outcome);
+        Ok(self.metered_stream(stream, backend, model, input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::scripted::{text_reply, ScriptedProvider};
+    use crate::llm::ModelPricing;
+
+    /// Provider that echoes prompts and streams a fixed reply
+    fn echo_provider() -> ScriptedProvider {
+        ScriptedProvider::new("echo")
+            .on_generate(|_messages| {
+                Ok(LlmResponse {
+                    usage: Some(usage(1_000_000)),
+                    ..text_reply("done")
+                })
+            })
+            .on_content(|prompt, _context, _model| Ok(format!("summary of {}", prompt)))
+            .with_stream(|_message| {
+                vec![
+                    StreamChunk::content("first part, "),
+                    StreamChunk::content("second part"),
+                    StreamChunk::finish("stop", None),
+                ]
+            })
+            .with_embeddings()
+    }
+
+    fn user_message(content: &str) -> Message {
+        Message {
+            role: crate::llm::MessageRole::User,
+            content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }
+    }
+
+    fn usage(prompt_tokens: u32) -> TokenUsage {
+        TokenUsage { prompt_tokens, completion_tokens: 0, total_tokens: prompt_tokens }
+    }
+
+    #[test]
+    fn test_ledger_persists_and_reports_by_model_and_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.jsonl");
+        let ledger = UsageLedger::new(&path);
+        // $30 per million prompt tokens
+        ledger.record("openrouter", "openai/gpt-4", &usage(100_000), false).unwrap();
+        ledger.record("openrouter", "openai/gpt-4", &usage(50_000), false).unwrap();
+        ledger.record("ollama", "llama3.2", &usage(10_000), true).unwrap();
+        assert!((ledger.session_cost() - 4.5).abs() < 1e-9);
+
+        // A later session reads the same records but starts its own session spend
+        let reopened = UsageLedger::new(&path);
+        assert_eq!(reopened.records().unwrap().len(), 3);
+        assert_eq!(reopened.session_cost(), 0.0);
+
+        let report = reopened.report(None).unwrap();
+        assert_eq!(report.total_requests, 3);
+        assert_eq!(report.get_model_usage("openai/gpt-4").unwrap().requests, 2);
+        assert_eq!(report.get_model_usage("llama3.2").unwrap().total_cost, 0.0);
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        assert!((report.get_daily_usage(&today).unwrap().total_cost - 4.5).abs() < 1e-9);
+
+        let tomorrow = Utc::now().date_naive().succ_opt().unwrap();
+        assert_eq!(reopened.report(Some(tomorrow)).unwrap().total_requests, 0);
+    }
+
+    #[test]
+    fn test_check_budget_reports_caps_that_would_be_exceeded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.jsonl");
+        UsageLedger::new(&path).record("openrouter", "openai/gpt-4", &usage(100_000), false).unwrap();
+
+        let ledger = UsageLedger::new(&path);
+        ledger.estimator.write().unwrap().set_model_pricing(
+            "cheap",
+            ModelPricing { prompt: Some(1.0), completion: None },
+            PriceSource::built_in(),
+        );
+        ledger.record("openrouter", "cheap", &usage(500_000), false).unwrap();
+
+        let budget = BudgetConfig {
+            session_limit: Some(1.0),
+            daily_limit: Some(5.0),
+            monthly_limit: Some(100.0),
+        };
+        assert!(ledger.check_budget(&budget, 0.0).unwrap().is_empty());
+
+        let exceeded = ledger.check_budget(&budget, 1.5).unwrap();
+        let periods: Vec<_> = exceeded.iter().map(|e| e.period).collect();
+        assert_eq!(periods, vec![BudgetPeriod::Session, BudgetPeriod::Day]);
+        assert!((exceeded[1].spent - 3.5).abs() < 1e-9);
+        assert!(exceeded[1].to_string().starts_with("daily spending cap of $5.00"));
+
+        assert!(ledger.check_budget(&BudgetConfig::default(), 1000.0).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_metered_provider_records_calls_and_partial_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        let provider = MeteredProvider::new(Box::new(echo_provider()), ledger.clone());
+
+        provider.generate(&[user_message("hi")], "openai/gpt-4", None, None).await.unwrap();
+        provider.generate_content("main.rs", "fn main() {}", "gemini-pro", None).await.unwrap();
+
+        // Only the first chunk is read before the stream is dropped
+        let mut stream = provider
+            .as_streaming()
+            .unwrap()
+            .generate_stream(&[user_message("hi")], "gemini-pro", None, None)
+            .await
+            .unwrap();
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        let records = ledger.records().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].provider, "echo");
+        assert!(!records[0].estimated);
+        assert!((records[0].cost - 30.0).abs() < 1e-9);
+        assert!(records[1].estimated && records[1].prompt_tokens > 0);
+        assert!(records[2].estimated && records[2].completion_tokens > 0);
+        assert!(records.iter().all(|r| r.session_id == ledger.session_id()));
+    }
+
+    #[tokio::test]
+    async fn test_metered_provider_bills_the_fallback_backend_that_answered() {
+        let primary = ScriptedProvider::new("primary")
+            .on_content(|_prompt, _context, _model| Err(LlmError::RateLimit { retry_after: None }));
+        let chain = fallback::FallbackProvider::new(Box::new(primary))
+            .with_fallback(Box::new(echo_provider()), Some("backup-model".to_string()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        let provider = MeteredProvider::new(Box::new(chain), ledger.clone());
+        provider.generate_content("main.rs", "fn main() {}", "main-model", None).await.unwrap();
+
+        let records = ledger.records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].provider.as_str(), records[0].model.as_str()), ("echo", "backup-model"));
+    }
+
+    #[tokio::test]
+    async fn test_metered_provider_records_every_attempt_of_a_plan() {
+        let reply = |content: &str, prompt_tokens: u32| {
+            serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }],
+                "usage": { "prompt_tokens": prompt_tokens, "completion_tokens": 10, "total_tokens": prompt_tokens + 10 },
+            })
+            .to_string()
+        };
+        let valid_plan = r#"{"description": "Build", "tasks": [{"id": "t1", "description": "Run cargo", "task_type": "execute_command", "parameters": {"command": "cargo"}}]}"#;
+        let (server_url, _) = crate::llm::mock_server::spawn(vec![
+            (200, reply(r#"{"description": "Build"}"#, 100)),
+            (200, reply(valid_plan, 200)),
+        ])
+        .await;
+        let mut settings = std::collections::HashMap::new();
+        settings.insert("base_url".to_string(), format!("{}/v1", server_url));
+        settings.insert("retry_attempts".to_string(), "0".to_string());
+        let inner = crate::llm::openai_compatible::OpenAiCompatibleProvider::from_settings(&settings).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        let provider = MeteredProvider::new(Box::new(inner), ledger.clone());
+        provider.generate_plan("Build", "ctx", "local-model").await.unwrap();
+
+        // The rejected first reply and its repair are both billed as reported
+        let records = ledger.records().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| !r.estimated && r.provider == "openai_compatible"));
+        assert_eq!(records[0].prompt_tokens, 100);
+        assert_eq!(records[1].prompt_tokens, 200);
+    }
+
+    #[tokio::test]
+    async fn test_metered_provider_costs_calls_with_cached_listing_prices() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(ModelRegistry::new(dir.path().join("models")));
+        let catalog: ModelCatalog = serde_json::from_value(serde_json::json!({
+            "version": crate::llm::registry::REGISTRY_VERSION,
+            "provider": "echo",
+            "fetched_at": Utc::now().timestamp_millis(),
+            "models": [{
+                "id": "openai/gpt-4",
+                "name": "GPT-4",
+                "description": null,
+                "context_length": null,
+                "max_output_tokens": null,
+                "pricing": { "prompt": 10.0, "completion": 20.0 },
+            }],
+        }))
+        .unwrap();
+        registry.store(&catalog).unwrap();
+
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        let provider = MeteredProvider::new(Box::new(echo_provider()), ledger.clone())
+            .with_model_registry(registry);
+        provider.generate(&[user_message("hi")], "openai/gpt-4", None, None).await.unwrap();
+        provider.generate_content("main.rs", "fn main() {}", "gemini-pro", None).await.unwrap();
+
+        let records = ledger.records().unwrap();
+        // The listing replaces the built-in $30 per million prompt tokens
+        assert!((records[0].cost - 10.0).abs() < 1e-9);
+        assert_eq!(records[0].price_source.as_ref().unwrap().name, "echo");
+        // Models missing from the listing keep the built-in prices
+        assert!(records[1].price_source.as_ref().unwrap().is_built_in());
+
+        let report = ledger.report(None).unwrap();
+        let source = report.get_model_usage("openai/gpt-4").unwrap().price_source.clone();
+        assert_eq!(source.unwrap().name, "echo");
+    }
+
+    #[tokio::test]
+    async fn test_metered_provider_reports_embedding_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        let provider = MeteredProvider::new(Box::new(echo_provider()), ledger.clone());
+
+        let texts = vec!["fn main() {}".to_string(), "struct Plan;".to_string()];
+        let response = provider
+            .as_embedding()
+            .unwrap()
+            .embed(&texts, "text-embedding-004")
+            .await
+            .unwrap();
+        assert_eq!(response.embeddings.len(), 2);
+
+        // The provider reported no usage, so the input is counted with the tokenizer
+        let records = ledger.records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].estimated && records[0].prompt_tokens > 0);
+        assert_eq!(records[0].completion_tokens, 0);
+
+        let report = ledger.report(None).unwrap();
+        assert_eq!(report.get_model_usage("text-embedding-004").unwrap().requests, 1);
+    }
+}
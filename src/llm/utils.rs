@@ -1,13 +1,25 @@
 //! Utility functions for LLM operations including token counting and cost estimation
 
-use super::TokenUsage;
+use super::registry::ModelCatalog;
+use super::tokenizer::TokenizerRegistry;
+use super::{ModelPricing, TokenUsage};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
 /// Token counting utility for estimating token usage
 pub struct TokenCounter;
 
 impl TokenCounter {
+    /// Count tokens with the model's own tokenizer
+    ///
+    /// Uses the vocabulary installed for the model (see `TokenizerRegistry`)
+    /// and falls back to `estimate_tokens` when none is available.
+    pub fn count_tokens(text: &str, model_id: &str) -> u32 {
+        TokenizerRegistry::global().for_model(model_id).count_tokens(text) as u32
+    }
+
     /// Rough estimation of tokens in text using simple heuristics
     /// This is an approximation - actual token counts vary by model and tokenizer
     pub fn estimate_tokens(text: &str) -> u32 {
@@ -114,17 +126,60 @@ impl TokenCounter {
 }
 
 /// Cost estimation utility for calculating API costs
+///
+/// Starts from a built-in price table and takes newer prices from provider
+/// model listings (see `sync_catalog`), remembering where each price came from.
 #[derive(Debug, Clone)]
 pub struct CostEstimator {
     model_pricing: HashMap<String, ModelPricing>,
+    price_sources: HashMap<String, PriceSource>,
 }
 
-/// Detailed pricing information for a model
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ModelPricing {
-    pub prompt_cost_per_million: Option<f64>,
-    pub completion_cost_per_million: Option<f64>,
-    pub currency: String,
+/// When the built-in price table was last revised
+const BUILT_IN_PRICES_AS_OF: &str = "2024-01-01T00:00:00Z";
+
+/// Where the prices used for a cost came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceSource {
+    /// Provider whose model listing supplied the prices, or `built-in`
+    pub name: String,
+    /// When the listing was fetched, or when the built-in table was revised
+    pub as_of: DateTime<Utc>,
+}
+
+impl PriceSource {
+    /// The price table shipped with kai-x
+    pub fn built_in() -> Self {
+        Self {
+            name: "built-in".to_string(),
+            as_of: DateTime::parse_from_rfc3339(BUILT_IN_PRICES_AS_OF)
+                .map(|date| date.with_timezone(&Utc))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Prices from a provider's model listing fetched at `as_of`
+    pub fn listing(provider: &str, as_of: DateTime<Utc>) -> Self {
+        Self {
+            name: provider.to_string(),
+            as_of,
+        }
+    }
+
+    /// Whether the prices come from the built-in table
+    pub fn is_built_in(&self) -> bool {
+        self.name == "built-in"
+    }
+}
+
+impl fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_built_in() {
+            write!(f, "built-in prices of {}", self.as_of.format("%Y-%m-%d"))
+        } else {
+            write!(f, "{} prices of {}", self.name, self.as_of.format("%Y-%m-%d"))
+        }
+    }
 }
 
 /// Cost breakdown for a request
@@ -137,63 +192,44 @@ pub struct CostBreakdown {
     pub completion_cost: Option<f64>,
     pub total_cost: Option<f64>,
     pub currency: Option<String>,
+    /// Prices the cost was calculated with
+    #[serde(default)]
+    pub price_source: Option<PriceSource>,
 }
 
 impl CostEstimator {
     /// Create a new cost estimator with default pricing data
     pub fn new() -> Self {
-        let mut model_pricing = HashMap::new();
-        
-        // OpenRouter pricing (approximate, as of 2024)
-        model_pricing.insert("openai/gpt-3.5-turbo".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(0.5),
-            completion_cost_per_million: Some(1.5),
-            currency: "USD".to_string(),
-        });
-        
-        model_pricing.insert("openai/gpt-4".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(30.0),
-            completion_cost_per_million: Some(60.0),
-            currency: "USD".to_string(),
-        });
-
-        model_pricing.insert("openai/gpt-4-turbo".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(10.0),
-            completion_cost_per_million: Some(30.0),
-            currency: "USD".to_string(),
-        });
-
-        model_pricing.insert("anthropic/claude-3-sonnet".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(3.0),
-            completion_cost_per_million: Some(15.0),
-            currency: "USD".to_string(),
-        });
-
-        model_pricing.insert("anthropic/claude-3-opus".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(15.0),
-            completion_cost_per_million: Some(75.0),
-            currency: "USD".to_string(),
-        });
-
-        // Google Gemini pricing (approximate)
-        model_pricing.insert("gemini-pro".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(0.5),
-            completion_cost_per_million: Some(1.5),
-            currency: "USD".to_string(),
-        });
-
-        model_pricing.insert("gemini-pro-1.5".to_string(), ModelPricing {
-            prompt_cost_per_million: Some(3.5),
-            completion_cost_per_million: Some(10.5),
-            currency: "USD".to_string(),
-        });
-
-        Self { model_pricing }
+        let built_in = [
+            // OpenRouter pricing (approximate, as of 2024)
+            ("openai/gpt-3.5-turbo", 0.5, 1.5),
+            ("openai/gpt-4", 30.0, 60.0),
+            ("openai/gpt-4-turbo", 10.0, 30.0),
+            ("anthropic/claude-3-sonnet", 3.0, 15.0),
+            ("anthropic/claude-3-opus", 15.0, 75.0),
+            // Google Gemini pricing (approximate)
+            ("gemini-pro", 0.5, 1.5),
+            ("gemini-pro-1.5", 3.5, 10.5),
+        ];
+
+        let mut estimator = Self {
+            model_pricing: HashMap::new(),
+            price_sources: HashMap::new(),
+        };
+        for (model_id, prompt, completion) in built_in {
+            let pricing = ModelPricing {
+                prompt: Some(prompt),
+                completion: Some(completion),
+            };
+            estimator.set_model_pricing(model_id, pricing, PriceSource::built_in());
+        }
+        estimator
     }
 
     /// Add or update pricing for a model
-    pub fn set_model_pricing(&mut self, model_id: &str, pricing: ModelPricing) {
+    pub fn set_model_pricing(&mut self, model_id: &str, pricing: ModelPricing, source: PriceSource) {
         self.model_pricing.insert(model_id.to_string(), pricing);
+        self.price_sources.insert(model_id.to_string(), source);
     }
 
     /// Get pricing for a model
@@ -201,15 +237,46 @@ impl CostEstimator {
         self.model_pricing.get(model_id)
     }
 
+    /// Where the pricing for a model came from
+    pub fn price_source(&self, model_id: &str) -> Option<&PriceSource> {
+        self.price_sources.get(model_id)
+    }
+
+    /// Take the prices from a provider's model listing
+    ///
+    /// Prices from an older listing than the one already known are ignored,
+    /// and models the listing has no price for keep their current pricing.
+    /// Returns the number of models whose pricing changed.
+    pub fn sync_catalog(&mut self, catalog: &ModelCatalog) -> usize {
+        let fetched_at = DateTime::from_timestamp_millis(catalog.fetched_at).unwrap_or_default();
+        let mut updated = 0;
+        for model in &catalog.models {
+            let Some(pricing) = &model.pricing else {
+                continue;
+            };
+            if pricing.prompt.is_none() && pricing.completion.is_none() {
+                continue;
+            }
+            let newer = self.price_sources.get(&model.id).is_none_or(|source| {
+                source.is_built_in() || source.as_of < fetched_at
+            });
+            if newer {
+                self.set_model_pricing(&model.id, pricing.clone(), PriceSource::listing(&catalog.provider, fetched_at));
+                updated += 1;
+            }
+        }
+        updated
+    }
+
     /// Calculate cost for a given token usage
     pub fn calculate_cost(&self, model_id: &str, usage: &TokenUsage) -> CostBreakdown {
         let pricing = self.model_pricing.get(model_id);
         
         let (prompt_cost, completion_cost, total_cost, currency) = if let Some(pricing) = pricing {
-            let prompt_cost = pricing.prompt_cost_per_million
+            let prompt_cost = pricing.prompt
                 .map(|rate| (usage.prompt_tokens as f64 / 1_000_000.0) * rate);
             
-            let completion_cost = pricing.completion_cost_per_million
+            let completion_cost = pricing.completion
                 .map(|rate| (usage.completion_tokens as f64 / 1_000_000.0) * rate);
             
             let total_cost = match (prompt_cost, completion_cost) {
@@ -219,7 +286,7 @@ impl CostEstimator {
                 (None, None) => None,
             };
             
-            (prompt_cost, completion_cost, total_cost, Some(pricing.currency.clone()))
+            (prompt_cost, completion_cost, total_cost, Some("USD".to_string()))
         } else {
             (None, None, None, None)
         };
@@ -232,12 +299,13 @@ impl CostEstimator {
             completion_cost,
             total_cost,
             currency,
+            price_source: pricing.and(self.price_sources.get(model_id).cloned()),
         }
     }
 
     /// Estimate cost for a prompt before sending to API
     pub fn estimate_cost(&self, model_id: &str, prompt_text: &str, estimated_completion_tokens: Option<u32>) -> CostBreakdown {
-        let prompt_tokens = TokenCounter::estimate_tokens(prompt_text);
+        let prompt_tokens = TokenCounter::count_tokens(prompt_text, model_id);
         let completion_tokens = estimated_completion_tokens.unwrap_or(150); // Default estimate
         let total_tokens = prompt_tokens + completion_tokens;
         
@@ -276,6 +344,9 @@ pub struct ModelUsage {
     pub prompt_tokens: u64,
     pub completion_tokens: u64,
     pub total_cost: f64,
+    /// Prices the most recent request was costed with
+    #[serde(default)]
+    pub price_source: Option<PriceSource>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -301,7 +372,11 @@ impl UsageTracker {
     /// Record a new API request
     pub fn record_usage(&mut self, model_id: &str, cost_breakdown: &CostBreakdown) {
         let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
-        
+        self.record_usage_on(&today, model_id, cost_breakdown);
+    }
+
+    /// Record an API request made on the given date (`%Y-%m-%d`)
+    pub fn record_usage_on(&mut self, date: &str, model_id: &str, cost_breakdown: &CostBreakdown) {
         // Update totals
         self.total_requests += 1;
         self.total_prompt_tokens += cost_breakdown.prompt_tokens as u64;
@@ -317,9 +392,13 @@ impl UsageTracker {
             prompt_tokens: 0,
             completion_tokens: 0,
             total_cost: 0.0,
+            price_source: None,
         });
         
         model_usage.requests += 1;
+        if cost_breakdown.price_source.is_some() {
+            model_usage.price_source = cost_breakdown.price_source.clone();
+        }
         model_usage.prompt_tokens += cost_breakdown.prompt_tokens as u64;
         model_usage.completion_tokens += cost_breakdown.completion_tokens as u64;
         
@@ -328,7 +407,7 @@ impl UsageTracker {
         }
 
         // Update daily usage
-        let daily_usage = self.daily_usage.entry(today).or_insert(DailyUsage {
+        let daily_usage = self.daily_usage.entry(date.to_string()).or_insert(DailyUsage {
             requests: 0,
             prompt_tokens: 0,
             completion_tokens: 0,
@@ -423,6 +502,44 @@ mod tests {
         let cost = estimator.calculate_cost("openai/gpt-3.5-turbo", &usage);
         assert!(cost.total_cost.is_some());
         assert!(cost.total_cost.unwrap() > 0.0);
+        assert!(cost.price_source.unwrap().is_built_in());
+    }
+
+    #[test]
+    fn test_sync_catalog_prefers_newer_listing_prices() {
+        let mut estimator = CostEstimator::new();
+        let listed = |id: &str, prompt: Option<f64>| crate::llm::ModelInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: None,
+            context_length: None,
+            max_output_tokens: None,
+            pricing: Some(ModelPricing { prompt, completion: prompt }),
+            tokenizer: None,
+            capabilities: Default::default(),
+        };
+        let fetched_at = Utc::now();
+        let catalog: ModelCatalog = serde_json::from_value(serde_json::json!({
+            "version": crate::llm::registry::REGISTRY_VERSION,
+            "provider": "openrouter",
+            "fetched_at": fetched_at.timestamp_millis(),
+            "models": [listed("openai/gpt-4", Some(20.0)), listed("unpriced", None)],
+        }))
+        .unwrap();
+
+        assert_eq!(estimator.sync_catalog(&catalog), 1);
+        assert_eq!(estimator.get_model_pricing("openai/gpt-4").unwrap().prompt, Some(20.0));
+        assert!(estimator.get_model_pricing("unpriced").is_none());
+        let source = estimator.price_source("openai/gpt-4").unwrap().clone();
+        assert_eq!(source.name, "openrouter");
+        assert!(source.to_string().starts_with(&format!("openrouter prices of {}", fetched_at.format("%Y-%m-%d"))));
+
+        // An older listing does not override newer prices
+        let mut stale = catalog.clone();
+        stale.fetched_at -= 60_000;
+        stale.models[0].pricing = Some(ModelPricing { prompt: Some(1.0), completion: None });
+        assert_eq!(estimator.sync_catalog(&stale), 0);
+        assert_eq!(estimator.get_model_pricing("openai/gpt-4").unwrap().prompt, Some(20.0));
     }
 
     #[test]
@@ -436,6 +553,7 @@ mod tests {
             completion_cost: Some(0.075),
             total_cost: Some(0.125),
             currency: Some("USD".to_string()),
+            price_source: None,
         };
         
         tracker.record_usage("test-model", &cost_breakdown);
@@ -4,9 +4,12 @@ use KAI_X::{
     config::ConfigManager,
     context::ContextManager,
     execution::{ExecutionEngine, TaskExecutor},
-    llm::{LlmProvider, LlmProviderFactory},
-    planning::manager::AgenticPlanningCoordinator,
-    ui::ConsoleChat,
+    llm::{prompt_overrides::PromptOverrides, registry::ModelRegistry, LlmProvider, LlmProviderFactory, PromptTemplates},
+    planning::manager::{AgenticPlanningCoordinator, CoordinatorConfig},
+    planning::store::PlanStore,
+    planning::TaskStatus,
+    ui::{slash_commands::format_model_table, ConsoleChat},
+    utils::config::ConfigAccessPattern,
     utils::debug::{DEBUG_TRACER, is_debug_enabled},
     debug_flow, debug_checkpoint, debug_error,
     Result,
@@ -39,6 +42,10 @@ struct Cli {
     #[arg(short, long)]
     non_interactive: bool,
 
+    /// Bypass the on-disk LLM response cache
+    #[arg(long)]
+    no_cache: bool,
+
     /// Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
@@ -69,6 +76,33 @@ enum Commands {
     Chat,
     /// Check configuration and system status
     Status,
+    /// Report LLM spend by model and by day
+    Usage {
+        /// Number of days to include, counting today
+        #[arg(long, default_value = "30")]
+        days: u32,
+    },
+    /// Inspect prompt templates and their overrides
+    Prompts {
+        #[command(subcommand)]
+        action: PromptsAction,
+    },
+    /// List the active provider's models with their capabilities
+    Models {
+        /// Ask the provider again instead of using the cached list
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Inspect plans saved in the working directory
+    Plans {
+        #[command(subcommand)]
+        action: PlansAction,
+    },
+    /// Continue a saved plan, skipping tasks that already completed
+    Resume {
+        /// Id of the plan, or an unambiguous prefix of it
+        plan_id: String,
+    },
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -85,6 +119,22 @@ enum ProviderAction {
     Set { name: String, model: Option<String> },
 }
 
+#[derive(Subcommand, Clone, Debug)]
+enum PromptsAction {
+    /// List templates and where each one comes from
+    List,
+    /// Show the effective version of a template
+    Show { name: String },
+    /// Compare a template override with the built-in version
+    Diff { name: String },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum PlansAction {
+    /// List saved plans with their progress
+    List,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let start_time = std::time::Instant::now();
@@ -109,6 +159,7 @@ async fn main() -> Result<()> {
             ));
             state.insert("log_level".to_string(), serde_json::Value::String(cli.log_level.clone()));
             state.insert("non_interactive".to_string(), serde_json::Value::Bool(cli.non_interactive));
+            state.insert("no_cache".to_string(), serde_json::Value::Bool(cli.no_cache));
             state.insert("command".to_string(), serde_json::Value::String(format!("{:?}", cli.command)));
             state
         });
@@ -203,9 +254,29 @@ async fn main() -> Result<()> {
                 debug_checkpoint!(&mut flow_context, "executing_status_command");
                 show_status(&mut flow_context).await
             },
+            Commands::Usage { days } => {
+                debug_checkpoint!(&mut flow_context, "executing_usage_command");
+                show_usage(days, &mut flow_context).await
+            },
+            Commands::Prompts { action } => {
+                debug_checkpoint!(&mut flow_context, "executing_prompts_command");
+                handle_prompts_command(action, cli.workdir, &mut flow_context).await
+            },
+            Commands::Models { refresh } => {
+                debug_checkpoint!(&mut flow_context, "executing_models_command");
+                show_models(refresh, &mut flow_context).await
+            },
+            Commands::Plans { action } => {
+                debug_checkpoint!(&mut flow_context, "executing_plans_command");
+                handle_plans_command(action, cli.workdir, &mut flow_context).await
+            },
+            Commands::Resume { plan_id } => {
+                debug_checkpoint!(&mut flow_context, "executing_resume_command");
+                resume_plan(plan_id, cli, &mut flow_context).await
+            },
             Commands::Prompt { prompt, format } => {
                 debug_checkpoint!(&mut flow_context, "executing_single_prompt_command");
-                run_single_prompt(prompt, format, cli.workdir, &mut flow_context).await
+                run_single_prompt(prompt, format, cli.workdir, cli.no_cache, &mut flow_context).await
             },
             Commands::Chat => {
                 debug_checkpoint!(&mut flow_context, "executing_interactive_mode");
@@ -309,7 +380,7 @@ fn validate_provider_configuration(
     
     // Check API key availability
     debug_checkpoint!(flow_context, "provider_validation_check_api_key");
-    let api_key_available = config.get_active_api_key().is_some();
+    let api_key_available = config.is_provider_ready(&config.active_provider);
     
     debug_checkpoint!(flow_context, "provider_validation_api_key_result", {
         let mut state = HashMap::new();
@@ -438,7 +509,7 @@ async fn handle_provider_command(action: ProviderAction, flow_context: &mut KAI_
 }
 
 /// Run a single prompt and exit
-async fn run_single_prompt(prompt: String, format: String, workdir: Option<PathBuf>, flow_context: &mut KAI_X::utils::debug::FlowContext) -> Result<()> {
+async fn run_single_prompt(prompt: String, format: String, workdir: Option<PathBuf>, no_cache: bool, flow_context: &mut KAI_X::utils::debug::FlowContext) -> Result<()> {
     debug_checkpoint!(flow_context, "single_prompt_start", {
         let mut state = HashMap::new();
         state.insert("prompt_length".to_string(), serde_json::Value::Number(serde_json::Number::from(prompt.len() as u64)));
@@ -455,31 +526,17 @@ async fn run_single_prompt(prompt: String, format: String, workdir: Option<PathB
     // Initialize system (provider validation already done in main)
     info!("🔧 [SINGLE-PROMPT] Initializing core systems");
     let core_init_start = std::time::Instant::now();
-    let (config_manager, context_manager, _execution_engine, _planning_manager) = initialize_core_systems(workdir).await?;
+    let (config_manager, context_manager, _execution_engine, _planning_manager) = initialize_core_systems(workdir, no_cache).await?;
     let core_init_time = core_init_start.elapsed();
     info!("✅ [SINGLE-PROMPT] Core systems initialized in {:?}", core_init_time);
 
     // Get LLM provider
     info!("🤖 [SINGLE-PROMPT] Setting up LLM provider");
     let provider_setup_start = std::time::Instant::now();
-    let config = config_manager.config();
+    let config = session_config(&config_manager, no_cache);
 
-    let mut provider_settings = std::collections::HashMap::new();
-    
-    // Get API key using centralized function
-    if let Some(api_key) = config.get_active_api_key() {
-        provider_settings.insert("api_key".to_string(), api_key);
-    }
-    
-    // Get base URL from hardcoded provider settings
-    if let Some(base_url) = KAI_X::config::ProviderConfig::get_base_url_for_provider(&config.active_provider) {
-        provider_settings.insert("base_url".to_string(), base_url);
-    }
-
-    let llm_provider = LlmProviderFactory::create_provider(
-        &config.active_provider,
-        provider_settings,
-    )?;
+    // Active provider and its fallback chain from config
+    let llm_provider = LlmProviderFactory::create_from_config(&config)?;
     let provider_setup_time = provider_setup_start.elapsed();
     info!("✅ [SINGLE-PROMPT] LLM provider '{}' ready in {:?}", config.active_provider, provider_setup_time);
 
@@ -523,45 +580,89 @@ async fn run_interactive_mode(cli: Cli, flow_context: &mut KAI_X::utils::debug::
     info!("Starting interactive mode");
     
     // Initialize core systems properly (following the spec)
-    let (config_manager, _context_manager, execution_engine, _planning_manager) = initialize_core_systems(cli.workdir).await?;
-    
+    let (config_manager, _context_manager, execution_engine, _planning_manager) = initialize_core_systems(cli.workdir, cli.no_cache).await?;
+    run_console_chat(config_manager, execution_engine, cli.no_cache, None).await
+}
+
+/// Continue a saved plan and keep the console chat open afterwards
+async fn resume_plan(plan_id: String, cli: Cli, flow_context: &mut KAI_X::utils::debug::FlowContext) -> Result<()> {
+    debug_checkpoint!(flow_context, "resume_command_start");
+    let (config_manager, _context_manager, execution_engine, _planning_manager) = initialize_core_systems(cli.workdir, cli.no_cache).await?;
+    let working_dir = config_manager.config().working_directory.clone()
+        .ok_or_else(|| KAI_X::utils::errors::KaiError::not_found("Working directory not set"))?;
+
+    let saved = PlanStore::for_project(&working_dir).load(&plan_id)?;
+    if saved.plan.tasks.iter().all(|task| task.status == TaskStatus::Completed) {
+        println!("✅ Plan {} already completed: {}", saved.plan.id, saved.plan.description);
+        return Ok(());
+    }
+    let plan = {
+        let engine = execution_engine.read().await;
+        engine.resume_plan(saved.plan, saved.context).await?;
+        engine.get_current_plan().await
+    };
+
+    run_console_chat(config_manager, execution_engine, cli.no_cache, plan).await
+}
+
+/// Start the execution engine and the console chat, following `resumed` first if given
+async fn run_console_chat(
+    config_manager: ConfigManager,
+    execution_engine: Arc<tokio::sync::RwLock<ExecutionEngine>>,
+    no_cache: bool,
+    resumed: Option<KAI_X::planning::Plan>,
+) -> Result<()> {
     // Get working directory from config (validated during initialization)
     let working_dir = config_manager.config().working_directory.clone()
         .ok_or_else(|| KAI_X::utils::errors::KaiError::not_found("Working directory not set"))?;
     
-    let config = config_manager.config();
+    let config = session_config(&config_manager, no_cache);
     
     // Create LLM provider
-    let mut provider_settings = std::collections::HashMap::new();
-    
-    if let Some(api_key) = config.get_active_api_key() {
-        provider_settings.insert("api_key".to_string(), api_key);
-    }
-    
-    if let Some(base_url) = KAI_X::config::ProviderConfig::get_base_url_for_provider(&config.active_provider) {
-        provider_settings.insert("base_url".to_string(), base_url);
-    }
-    
-    let provider_box = LlmProviderFactory::create_provider(
-        &config.active_provider,
-        provider_settings,
-    )?;
+    let provider_box = LlmProviderFactory::create_from_config(&config)?;
     let llm_provider: Arc<dyn LlmProvider> = Arc::from(provider_box);
     
     // Start the execution engine in the background
     let execution_engine_for_loop = execution_engine.clone();
-    let _execution_handle = tokio::spawn(async move {
+    let execution_handle = tokio::spawn(async move {
         let engine = execution_engine_for_loop.read().await;
         if let Err(e) = engine.start().await {
             eprintln!("Execution engine error: {}", e);
         }
     });
     
+    // Ctrl-C cancels the running plan, and ends the session when there is nothing to cancel
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let execution_engine_for_signal = execution_engine.clone();
+    let shutdown_on_signal = shutdown.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            let engine = execution_engine_for_signal.read().await;
+            if !engine.has_active_plan().await {
+                shutdown_on_signal.cancel();
+                break;
+            }
+            engine.cancel_plan().await;
+            println!("\n⏹ Plan cancelled");
+        }
+    });
+
     info!("🤖 KAI-X console chat ready with execution engine");
     
     // Run console chat with proper execution engine integration
-    let mut console_chat = ConsoleChat::new(llm_provider, execution_engine, working_dir);
-    console_chat.run().await
+    let mut console_chat = ConsoleChat::new(llm_provider, execution_engine.clone(), working_dir)
+        .with_shutdown(shutdown);
+    if let Some(plan) = resumed {
+        console_chat.follow_resumed_plan(&plan).await;
+    }
+    let result = console_chat.run().await;
+
+    // Let running tasks finish and the engine save its state before returning
+    execution_engine.read().await.stop().await;
+    if let Err(e) = execution_handle.await {
+        warn!("Execution engine task failed: {}", e);
+    }
+    result
 }
 
 /// Show comprehensive system status
@@ -643,13 +744,15 @@ async fn show_status(flow_context: &mut KAI_X::utils::debug::FlowContext) -> Res
                 "📁 File"
             } else if has_api_key {
                 "✅ Available"
+            } else if !KAI_X::utils::config::ApiKeyResolver::requires_api_key(name) {
+                "🔓 Not required"
             } else {
                 "❌ Missing"
             };
             
             println!("   {} {} ({})", status_icon, name, key_status);
             
-            if let Some(base_url) = KAI_X::config::ProviderConfig::get_base_url_for_provider(name) {
+            if let Some(base_url) = config.get_base_url_for_provider(name) {
                 println!("     URL: {}", base_url);
             }
             if let Some(ref default_model) = provider_config.default_model {
@@ -681,13 +784,256 @@ async fn show_status(flow_context: &mut KAI_X::utils::debug::FlowContext) -> Res
     println!("   kai --workdir <path>          - Set project directory");
     println!("   kai init --force              - Reinitialize configuration");
     println!("   kai chat                      - Start interactive mode");
+    println!("   kai plans list                - List saved plans");
+    println!("   kai resume <plan-id>          - Continue an interrupted plan");
+
+    Ok(())
+}
+
+async fn show_usage(days: u32, flow_context: &mut KAI_X::utils::debug::FlowContext) -> Result<()> {
+    debug_checkpoint!(flow_context, "usage_command_start");
+    let ledger = KAI_X::llm::usage::UsageLedger::global()
+        .ok_or_else(|| KAI_X::utils::errors::KaiError::not_found("data directory"))?;
+    let today = chrono::Utc::now().date_naive();
+    let since = today - chrono::Duration::days(i64::from(days.max(1)) - 1);
+    let report = ledger.report(Some(since))?;
+
+    println!("💰 KAI-X Usage (since {})", since);
+    println!("═══════════════════════════════");
+    println!("   Ledger: {}", ledger.path().display());
+    println!("   Requests: {}", report.total_requests);
+    println!("   Tokens: {} prompt, {} completion", report.total_prompt_tokens, report.total_completion_tokens);
+    println!("   Spend: ${:.4}", report.total_cost);
+
+    if report.total_requests == 0 {
+        println!("\n   No usage recorded in this period");
+    } else {
+        println!("\n🤖 By Model:");
+        let mut models: Vec<_> = report.model_usage.iter().collect();
+        models.sort_by(|(a_name, a), (b_name, b)| {
+            b.total_cost.total_cmp(&a.total_cost).then_with(|| a_name.cmp(b_name))
+        });
+        for (model, usage) in models {
+            let prices = usage.price_source
+                .as_ref()
+                .map(|source| source.to_string())
+                .unwrap_or_else(|| "no known prices".to_string());
+            println!("   {:<40} {:>6} req {:>10} tok  ${:.4}  ({})",
+                model, usage.requests, usage.prompt_tokens + usage.completion_tokens, usage.total_cost, prices);
+        }
+
+        println!("\n📅 By Day:");
+        let mut daily: Vec<_> = report.daily_usage.iter().collect();
+        daily.sort_by_key(|(date, _)| *date);
+        for (date, usage) in daily {
+            println!("   {:<40} {:>6} req {:>10} tok  ${:.4}",
+                date, usage.requests, usage.prompt_tokens + usage.completion_tokens, usage.total_cost);
+        }
+    }
+
+    // Caps are checked against today and this month regardless of --days
+    use chrono::Datelike;
+    let budget = ConfigManager::new()?.config().budget;
+    if budget.session_limit.is_some() || budget.daily_limit.is_some() || budget.monthly_limit.is_some() {
+        let current = ledger.report(Some(today.with_day(1).unwrap_or(today)))?;
+        let today_spend = current.get_daily_usage(&today.format("%Y-%m-%d").to_string())
+            .map(|usage| usage.total_cost)
+            .unwrap_or(0.0);
+        let format_limit = |limit: Option<f64>| limit
+            .map(|limit| format!("${:.2}", limit))
+            .unwrap_or_else(|| "none".to_string());
+
+        println!("\n🚦 Budget:");
+        println!("   Per session: {}", format_limit(budget.session_limit));
+        println!("   Today: ${:.4} of {}", today_spend, format_limit(budget.daily_limit));
+        println!("   This month: ${:.4} of {}", current.total_cost, format_limit(budget.monthly_limit));
+    }
+
+    Ok(())
+}
+
+async fn handle_plans_command(
+    action: PlansAction,
+    workdir: Option<PathBuf>,
+    flow_context: &mut KAI_X::utils::debug::FlowContext,
+) -> Result<()> {
+    debug_checkpoint!(flow_context, "plans_command_start");
+    let working_dir = workdir
+        .or_else(|| ConfigManager::new().ok().and_then(|manager| manager.config().working_directory))
+        .or_else(|| std::env::current_dir().ok())
+        .ok_or_else(|| KAI_X::utils::errors::KaiError::not_found("working directory"))?;
+    let store = PlanStore::for_project(&working_dir);
+
+    match action {
+        PlansAction::List => {
+            let plans = store.list()?;
+            println!("🗂  Saved Plans");
+            println!("═══════════════════════════════");
+            println!("   Store: {}", store.dir().display());
+            if plans.is_empty() {
+                println!("\n   No plans saved in this project");
+                return Ok(());
+            }
+            println!();
+            for plan in &plans {
+                println!("   {:<36} {:<10} {:>3}/{:<3} tasks  {}  {}",
+                    plan.id,
+                    format!("{:?}", plan.status),
+                    plan.completed_tasks,
+                    plan.total_tasks,
+                    plan.saved_at.format("%Y-%m-%d %H:%M"),
+                    plan.description);
+            }
+            println!("\n   Continue one with: kai resume <plan-id>");
+        }
+    }
+
+    Ok(())
+}
+
+async fn show_models(refresh: bool, flow_context: &mut KAI_X::utils::debug::FlowContext) -> Result<()> {
+    debug_checkpoint!(flow_context, "models_command_start");
+    let registry = ModelRegistry::global()
+        .ok_or_else(|| KAI_X::utils::errors::KaiError::not_found("data directory"))?;
+    let config_manager = ConfigManager::new()?;
+    let config = config_manager.config();
+    let provider = LlmProviderFactory::create_from_config(&config)?;
+
+    let catalog = if refresh {
+        registry.refresh(provider.as_ref()).await?
+    } else {
+        registry.catalog(provider.as_ref()).await?
+    };
+    let requirements = config.execution.mode.model_requirements();
+
+    println!("📋 Models from {} ({} listed {} minutes ago)", catalog.provider, catalog.models.len(), catalog.age().as_secs() / 60);
+    println!("═══════════════════════════════");
+    println!("{}", format_model_table(&catalog.models, &requirements));
+    if let Some(active) = catalog.model(&config.active_model) {
+        let unmet = requirements.unmet_by(active);
+        if !unmet.is_empty() {
+            println!("\n⚠️  Active model {} lacks {}, which {:?} execution needs", active.id, unmet.join(", "), config.execution.mode);
+        }
+    }
+    println!("\n📁 Registry: {}", registry.dir().display());
 
     Ok(())
 }
 
+async fn handle_prompts_command(
+    action: PromptsAction,
+    workdir: Option<PathBuf>,
+    flow_context: &mut KAI_X::utils::debug::FlowContext,
+) -> Result<()> {
+    use colored::Colorize;
+    use KAI_X::llm::prompt_overrides::{line_diff, DiffLine, TemplateSource};
+
+    debug_checkpoint!(flow_context, "prompts_command_start");
+    let working_dir = workdir
+        .or_else(|| ConfigManager::new().ok().and_then(|manager| manager.config().working_directory))
+        .or_else(|| std::env::current_dir().ok());
+    let overrides = load_prompt_overrides(working_dir.as_deref());
+
+    let effective = |name: &str| overrides.effective(name).ok_or_else(|| {
+        KAI_X::utils::errors::KaiError::not_found(format!(
+            "prompt template '{}' (available: {})",
+            name,
+            PromptTemplates::list_templates().join(", ")
+        ))
+    });
+
+    match action {
+        PromptsAction::List => {
+            println!("📝 Prompt Templates");
+            println!("═══════════════════════════════");
+            for name in PromptTemplates::list_templates() {
+                let (_, source) = effective(name)?;
+                println!("   {:<20} {}", name, source);
+                for warning in overrides.get(name).map(|o| o.warnings.as_slice()).unwrap_or_default() {
+                    println!("     ⚠️  {}", warning);
+                }
+            }
+            if !overrides.rejected().is_empty() {
+                println!("\n❌ Ignored Overrides:");
+                for rejected in overrides.rejected() {
+                    println!("   {}: {}", rejected.path.display(), rejected.error);
+                }
+            }
+            if let Some(dir) = PromptOverrides::default_user_dir() {
+                println!("\n📁 User overrides: {}", dir.display());
+            }
+            if let Some(dir) = working_dir.as_deref() {
+                println!("📁 Project overrides: {}", PromptOverrides::project_dir(dir).display());
+            }
+        }
+        PromptsAction::Show { name } => {
+            let (template, source) = effective(&name)?;
+            println!("📝 {} ({})", name, source);
+            println!("   Variables: {}", template.variables.join(", "));
+            for warning in overrides.get(&name).map(|o| o.warnings.as_slice()).unwrap_or_default() {
+                println!("   ⚠️  {}", warning);
+            }
+            println!("\n── System message ──\n{}", template.system_message.trim());
+            println!("\n── User template ──\n{}", template.user_template.trim());
+        }
+        PromptsAction::Diff { name } => {
+            let (template, source) = effective(&name)?;
+            if source == TemplateSource::BuiltIn {
+                println!("{} has no override and uses the built-in template", name);
+                return Ok(());
+            }
+            let builtin = PromptTemplates::builtin(&name).unwrap_or_else(|| template.clone());
+            println!("{}", "--- built-in".red());
+            println!("{}", format!("+++ {}", source).green());
+            let sections = [
+                ("system message", &builtin.system_message, &template.system_message),
+                ("user template", &builtin.user_template, &template.user_template),
+            ];
+            for (section, old, new) in sections {
+                println!("{}", format!("@@ {} @@", section).cyan());
+                if old.trim() == new.trim() {
+                    println!("  (unchanged)");
+                    continue;
+                }
+                for line in line_diff(old.trim(), new.trim()) {
+                    match line {
+                        DiffLine::Same(line) => println!(" {}", line),
+                        DiffLine::Removed(line) => println!("{}", format!("-{}", line).red()),
+                        DiffLine::Added(line) => println!("{}", format!("+{}", line).green()),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Load prompt overrides from the user config directory and the project's `.kai/prompts/`
+fn load_prompt_overrides(working_dir: Option<&std::path::Path>) -> PromptOverrides {
+    let overrides = PromptOverrides::load(
+        PromptOverrides::default_user_dir().as_deref(),
+        working_dir.map(PromptOverrides::project_dir).as_deref(),
+    );
+    for rejected in overrides.rejected() {
+        warn!("Ignoring prompt override {}: {}", rejected.path.display(), rejected.error);
+    }
+    overrides
+}
+
+/// Configuration for this session, with the response cache turned off by `--no-cache`
+fn session_config(config_manager: &ConfigManager, no_cache: bool) -> KAI_X::config::Config {
+    let mut config = config_manager.config();
+    if no_cache {
+        config.cache.enabled = false;
+    }
+    config
+}
+
 /// Initialize core systems
 async fn initialize_core_systems(
     workdir: Option<PathBuf>,
+    no_cache: bool,
 ) -> Result<(ConfigManager, Arc<tokio::sync::RwLock<ContextManager>>, Arc<tokio::sync::RwLock<ExecutionEngine>>, Arc<tokio::sync::RwLock<AgenticPlanningCoordinator>>)> {
     // Load configuration
     let mut config_manager = ConfigManager::new()?;
@@ -726,27 +1072,16 @@ async fn initialize_core_systems(
 
     info!("Working directory: {}", working_dir.display());
 
+    // Team and project prompt overrides replace the built-in templates
+    load_prompt_overrides(Some(&working_dir)).install();
+
     // Validate configuration
     config_manager.config().validate()?;
 
     // Get LLM provider (provider validation already done in main)
-    let config = config_manager.config();
-    let mut provider_settings = std::collections::HashMap::new();
-    
-    // Get API key using centralized function
-    if let Some(api_key) = config.get_active_api_key() {
-        provider_settings.insert("api_key".to_string(), api_key);
-    }
-    
-    // Get base URL from hardcoded provider settings
-    if let Some(base_url) = KAI_X::config::ProviderConfig::get_base_url_for_provider(&config.active_provider) {
-        provider_settings.insert("base_url".to_string(), base_url);
-    }
-
-    let provider_box = LlmProviderFactory::create_provider(
-        &config.active_provider,
-        provider_settings,
-    )?;
+    let config = session_config(&config_manager, no_cache);
+    // Active provider and its fallback chain from config
+    let provider_box = LlmProviderFactory::create_from_config(&config)?;
     let llm_provider: Arc<dyn LlmProvider> = Arc::from(provider_box);
 
     // Initialize context manager
@@ -763,11 +1098,11 @@ async fn initialize_core_systems(
         llm_provider.clone(),
         config.active_model.clone(),
         working_dir.clone(),
-        None,
-    )));
+        Some(config.execution.to_engine_config()),
+    ).with_budget(config.budget.clone())));
 
     // Initialize task executor for planning manager
-    let execution_config = KAI_X::execution::ExecutionConfig::default();
+    let execution_config = config.execution.to_engine_config();
     let task_executor = TaskExecutor::new(
         execution_config,
         working_dir.clone(),
@@ -789,7 +1124,13 @@ async fn initialize_core_systems(
         planning_context_manager,
         llm_provider.clone(),
         config.active_model.clone(),
-        None, // Use default config
+        Some(CoordinatorConfig {
+            budget: config.budget.clone(),
+            plan_review: config.execution.plan_review,
+            max_decomposition_depth: config.execution.sub_plans.max_depth,
+            max_subtasks_per_task: config.execution.sub_plans.max_fan_out,
+            ..Default::default()
+        }),
     );
 
     Ok((config_manager, context_manager, execution_engine, Arc::new(tokio::sync::RwLock::new(planning_manager))))
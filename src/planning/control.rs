@@ -0,0 +1,402 @@
+//! Plan lifecycle shared by the orchestrators
+//!
+//! `ExecutionEngine` and `AgenticPlanningCoordinator` run plans from different
+//! loops, but the rules around a plan are the same for both. `PlanControl`
+//! owns them: the cancellation token of the current plan's LLM requests, the
+//! generated plan waiting for review, the spending cap the plan is held on
+//! and the caps the user approved, and the decomposition of abstract tasks
+//! into sub-plans. Each orchestrator keeps its own queue and state and
+//! applies the outcome to them.
+//!
+//! `PlanControl` is cheap to clone; clones share the same state, so spawned
+//! tasks can record their spend and cycles can cancel the plan they run.
+
+use super::diff::merge_plans;
+use super::hierarchy::{decomposition_prompt, is_abstract_task};
+use super::{Plan, PlanDiff, PlanReviewPolicy, PlanStatus, SubPlanLimits, Task};
+use crate::config::BudgetConfig;
+use crate::llm::cancellation;
+use crate::llm::streaming::utils::generate_plan_observed;
+use crate::llm::usage::{BudgetExceeded, BudgetPeriod, UsageLedger};
+use crate::llm::LlmProvider;
+use crate::utils::errors::KaiError;
+use crate::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// A generated plan held back until the user reviews it
+#[derive(Debug, Clone)]
+struct PendingReview<D> {
+    plan: Plan,
+    /// Changes to the running plan, when the plan is a revision of it
+    diff: Option<PlanDiff>,
+    /// How the orchestrator starts the plan once it is approved
+    dispatch: D,
+}
+
+/// What `PlanControl::cancel` stopped
+#[derive(Debug, Clone, Default)]
+pub struct CancelledPlan {
+    /// Id of the plan that was marked cancelled, if there was one
+    pub plan_id: Option<String>,
+    /// Whether the plan was held on a spending cap, so the orchestrator should resume
+    pub released_budget_hold: bool,
+}
+
+/// Review, spending cap and cancellation state of the current plan
+///
+/// `D` is what the orchestrator needs to start a reviewed plan, such as the
+/// priority of the prompt it was generated for.
+pub struct PlanControl<D> {
+    /// Parent of every plan token, so stopping the orchestrator stops its plans
+    root_token: CancellationToken,
+    /// Cancelled when the current plan is cancelled or replaced
+    plan_token: Arc<RwLock<CancellationToken>>,
+    pending_review: Arc<RwLock<Option<PendingReview<D>>>>,
+    /// Spending caps checked before each task is started
+    budget: BudgetConfig,
+    /// Ledger the caps are checked against
+    usage_ledger: Option<Arc<UsageLedger>>,
+    /// Cap the plan is paused on until the user continues or cancels
+    budget_hold: Arc<RwLock<Option<BudgetExceeded>>>,
+    /// Caps the user agreed to exceed for the current plan
+    approved_budget_periods: Arc<RwLock<HashSet<BudgetPeriod>>>,
+    /// Spend of the last task, used to project the next one
+    last_task_cost: Arc<RwLock<f64>>,
+}
+
+impl<D> Clone for PlanControl<D> {
+    fn clone(&self) -> Self {
+        Self {
+            root_token: self.root_token.clone(),
+            plan_token: self.plan_token.clone(),
+            pending_review: self.pending_review.clone(),
+            budget: self.budget.clone(),
+            usage_ledger: self.usage_ledger.clone(),
+            budget_hold: self.budget_hold.clone(),
+            approved_budget_periods: self.approved_budget_periods.clone(),
+            last_task_cost: self.last_task_cost.clone(),
+        }
+    }
+}
+
+impl<D: Clone> PlanControl<D> {
+    /// Control whose plan tokens are children of `root_token`, checked against the shared usage ledger
+    pub fn new(root_token: CancellationToken) -> Self {
+        Self {
+            plan_token: Arc::new(RwLock::new(root_token.child_token())),
+            root_token,
+            pending_review: Arc::new(RwLock::new(None)),
+            budget: BudgetConfig::default(),
+            usage_ledger: UsageLedger::global(),
+            budget_hold: Arc::new(RwLock::new(None)),
+            approved_budget_periods: Arc::new(RwLock::new(HashSet::new())),
+            last_task_cost: Arc::new(RwLock::new(0.0)),
+        }
+    }
+
+    /// Hold plans before a task that would exceed one of these spending caps
+    pub fn with_budget(mut self, budget: BudgetConfig) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Check spending caps against `ledger` instead of the shared usage ledger
+    pub fn with_usage_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.usage_ledger = Some(ledger);
+        self
+    }
+
+    /// Token of the current plan's LLM requests and tasks
+    pub async fn plan_token(&self) -> CancellationToken {
+        self.plan_token.read().await.clone()
+    }
+
+    /// Abort the current plan's requests and start a fresh token for the next plan
+    pub async fn renew_plan_token(&self) {
+        let mut token = self.plan_token.write().await;
+        token.cancel();
+        *token = self.root_token.child_token();
+    }
+
+    /// Cancel the current plan
+    ///
+    /// Its requests are aborted, a plan waiting for review is discarded, the
+    /// spending cap hold is released and `current_plan` is marked cancelled.
+    /// Clearing queues is left to the orchestrator.
+    pub async fn cancel(&self, current_plan: &RwLock<Option<Plan>>) -> CancelledPlan {
+        self.renew_plan_token().await;
+        *self.pending_review.write().await = None;
+        let released_budget_hold = self.budget_hold.write().await.take().is_some();
+
+        let plan_id = match &mut *current_plan.write().await {
+            Some(plan) => {
+                plan.status = PlanStatus::Cancelled;
+                info!("Plan cancelled: {}", plan.description);
+                Some(plan.id.clone())
+            }
+            None => None,
+        };
+        CancelledPlan {
+            plan_id,
+            released_budget_hold,
+        }
+    }
+
+    /// Start budget tracking afresh for a new plan
+    ///
+    /// Approvals only cover the plan they were given for. Returns whether the
+    /// previous plan was held on a spending cap.
+    pub async fn start_plan(&self) -> bool {
+        self.approved_budget_periods.write().await.clear();
+        self.budget_hold.write().await.take().is_some()
+    }
+
+    /// First spending cap the next task would exceed that the user has not approved
+    ///
+    /// The next task is projected to cost as much as the last one. A ledger
+    /// that cannot be read holds nothing, so bookkeeping failures never stop work.
+    pub async fn check_budget(&self) -> Option<BudgetExceeded> {
+        let ledger = self.usage_ledger.as_ref()?;
+        let projected_cost = *self.last_task_cost.read().await;
+        let exceeded = match ledger.check_budget(&self.budget, projected_cost) {
+            Ok(exceeded) => exceeded,
+            Err(e) => {
+                warn!("Failed to check spending caps: {}", e);
+                return None;
+            }
+        };
+        let approved = self.approved_budget_periods.read().await;
+        exceeded.into_iter().find(|exceeded| !approved.contains(&exceeded.period))
+    }
+
+    /// Hold the plan on a spending cap until the user continues or cancels
+    pub async fn hold_for_budget(&self, exceeded: BudgetExceeded) {
+        warn!("Pausing plan before its next task: {}", exceeded);
+        *self.budget_hold.write().await = Some(exceeded);
+    }
+
+    /// Spending cap the current plan is held on, if any
+    pub async fn budget_hold(&self) -> Option<BudgetExceeded> {
+        self.budget_hold.read().await.clone()
+    }
+
+    /// Release the spending cap hold, approving that cap for the rest of the plan
+    ///
+    /// Returns the cap, or `None` when the plan was not held.
+    pub async fn continue_past_budget(&self) -> Option<BudgetExceeded> {
+        let exceeded = self.budget_hold.write().await.take()?;
+        info!("Continuing past the {}", exceeded);
+        self.approved_budget_periods.write().await.insert(exceeded.period);
+        Some(exceeded)
+    }
+
+    /// Session spend so far, to be passed to `record_task_cost` when a task ends
+    pub fn spend_so_far(&self) -> Option<f64> {
+        self.usage_ledger.as_ref().map(|ledger| ledger.session_cost())
+    }
+
+    /// Remember what the task that started at `spend_before` cost, to project the next one
+    ///
+    /// Spend of tasks running alongside is included, which errs on the side of holding.
+    pub async fn record_task_cost(&self, spend_before: Option<f64>) {
+        if let (Some(ledger), Some(spend_before)) = (&self.usage_ledger, spend_before) {
+            *self.last_task_cost.write().await = ledger.session_cost() - spend_before;
+        }
+    }
+
+    /// Hold a generated plan for review, or hand it back to be started
+    ///
+    /// A plan revising `running` is merged into it first and always held, so
+    /// the user sees the diff before it is applied. Other plans are held when
+    /// `policy` asks for it.
+    pub async fn review_or_run(
+        &self,
+        policy: &PlanReviewPolicy,
+        running: Option<&Plan>,
+        plan: Plan,
+        dispatch: D,
+    ) -> Option<(Plan, D)> {
+        let (plan, diff) = match running {
+            Some(current) => {
+                let merged = merge_plans(current, &plan);
+                let diff = PlanDiff::between(current, &merged);
+                (merged, Some(diff))
+            }
+            None => (plan, None),
+        };
+        if diff.is_none() && !policy.requires_review(&plan) {
+            return Some((plan, dispatch));
+        }
+
+        info!("Plan {} is waiting for review", plan.id);
+        *self.pending_review.write().await = Some(PendingReview { plan, diff, dispatch });
+        None
+    }
+
+    /// Plan waiting for the user to approve it, if any
+    pub async fn pending_review(&self) -> Option<Plan> {
+        self.pending_review.read().await.as_ref().map(|pending| pending.plan.clone())
+    }
+
+    /// How the plan under review changes the running plan, if it revises it
+    pub async fn pending_review_diff(&self) -> Option<PlanDiff> {
+        self.pending_review.read().await.as_ref().and_then(|pending| pending.diff.clone())
+    }
+
+    /// Whether a plan is waiting for review
+    pub async fn has_pending_review(&self) -> bool {
+        self.pending_review.read().await.is_some()
+    }
+
+    /// Take the plan under review with the user's edits, if it still validates
+    ///
+    /// If the edited plan fails validation it stays under review and the error
+    /// lists the problems.
+    pub async fn approve_plan(&self, plan: Plan) -> Result<(Plan, D)> {
+        let mut pending_review = self.pending_review.write().await;
+        let pending = pending_review
+            .as_mut()
+            .ok_or_else(|| KaiError::not_found("plan waiting for review"))?;
+        pending.plan = plan.clone();
+        plan.ensure_valid()?;
+
+        let dispatch = pending.dispatch.clone();
+        *pending_review = None;
+        info!("Plan {} approved", plan.id);
+        Ok((plan, dispatch))
+    }
+
+    /// Discard the plan under review without running it
+    pub async fn reject_plan(&self) -> Option<Plan> {
+        let pending = self.pending_review.write().await.take()?;
+        info!("Plan {} rejected", pending.plan.id);
+        Some(pending.plan)
+    }
+
+    /// Decompose an abstract task of the current plan into a sub-plan
+    ///
+    /// The sub-plan is generated under the current plan's token, with streamed
+    /// text passed to `on_output`, and its tasks join `current_plan` under the
+    /// task, which then completes through them instead of running itself.
+    /// Returns the new sub-tasks for the orchestrator to queue.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn decompose_task(
+        &self,
+        llm_provider: &dyn LlmProvider,
+        model: &str,
+        current_plan: &RwLock<Option<Plan>>,
+        task: &Task,
+        limits: &SubPlanLimits,
+        context: &str,
+        on_output: impl FnMut(&str) + Send + 'static,
+    ) -> Result<Vec<Task>> {
+        info!("Decomposing abstract task: {}", task.description);
+        let sub_plan = cancellation::with_cancellation(
+            self.plan_token().await,
+            generate_plan_observed(llm_provider, &decomposition_prompt(task, limits), context, model, on_output),
+        )
+        .await?;
+
+        let mut current_plan = current_plan.write().await;
+        let plan = current_plan
+            .as_mut()
+            .ok_or_else(|| KaiError::planning("No active plan"))?;
+        let ids = plan.add_subtasks(&task.id, sub_plan.tasks, limits)?;
+        info!("Decomposed task {} into {} sub-tasks: {}", task.id, ids.len(), ids.join(", "));
+        Ok(plan.tasks.iter().filter(|task| ids.contains(&task.id)).cloned().collect())
+    }
+}
+
+/// Whether a task is abstract and its sub-tasks would stay within the depth limit
+pub fn should_decompose(plan: &Plan, task: &Task, limits: &SubPlanLimits) -> bool {
+    is_abstract_task(task) && !plan.has_subtasks(&task.id) && plan.task_depth(&task.id) < limits.max_depth
+}
+
+/// Fold an approved revision into the running plan
+///
+/// The revision is merged again, so tasks that finished while it was under
+/// review keep their results. The merged plan keeps the running plan's id and
+/// must pass validation.
+pub fn merge_revision(current: &Plan, revision: &Plan) -> Result<Plan> {
+    let merged = merge_plans(current, revision);
+    merged.ensure_valid()?;
+    info!("Revised plan {}: {}", merged.id, merged.description);
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::TokenUsage;
+    use crate::planning::TaskType;
+
+    fn read_plan() -> Plan {
+        let mut plan = Plan::new("Read the sources");
+        plan.add_task(Task::new("read", "Read main.rs", TaskType::ReadFile).with_parameter("path", "src/main.rs"));
+        plan
+    }
+
+    #[tokio::test]
+    async fn test_revisions_wait_for_review_and_keep_invalid_edits() {
+        let control: PlanControl<bool> = PlanControl::new(CancellationToken::new());
+        let running = read_plan();
+
+        // A fresh plan the policy does not hold is handed straight back
+        let (plan, dispatch) = control
+            .review_or_run(&PlanReviewPolicy::Never, None, read_plan(), false)
+            .await
+            .unwrap();
+        assert_eq!(plan.tasks.len(), 1);
+        assert!(!dispatch);
+
+        // A revision is always held, with its diff against the running plan
+        let mut revision = read_plan();
+        revision.add_task(Task::new("lib", "Read lib.rs", TaskType::ReadFile).with_parameter("path", "src/lib.rs"));
+        assert!(control
+            .review_or_run(&PlanReviewPolicy::Never, Some(&running), revision, true)
+            .await
+            .is_none());
+        assert_eq!(control.pending_review().await.unwrap().id, running.id);
+        assert!(control.pending_review_diff().await.is_some());
+
+        // An edit that breaks the plan stays under review
+        let mut edited = control.pending_review().await.unwrap();
+        edited.tasks[1].dependencies.push("missing".to_string());
+        assert!(control.approve_plan(edited).await.is_err());
+        assert_eq!(control.pending_review().await.unwrap().tasks[1].dependencies, vec!["missing".to_string()]);
+
+        let current_plan = RwLock::new(Some(running));
+        let cancelled = control.cancel(&current_plan).await;
+        assert!(cancelled.plan_id.is_some());
+        assert!(!control.has_pending_review().await);
+        assert_eq!(current_plan.read().await.as_ref().unwrap().status, PlanStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_approved_caps_last_until_the_next_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Arc::new(UsageLedger::new(dir.path().join("usage.jsonl")));
+        // $30 per million prompt tokens
+        let usage = TokenUsage { prompt_tokens: 100_000, completion_tokens: 0, total_tokens: 100_000 };
+        ledger.record("openrouter", "openai/gpt-4", &usage, false).unwrap();
+        let control: PlanControl<bool> = PlanControl::new(CancellationToken::new())
+            .with_budget(BudgetConfig {
+                session_limit: Some(1.0),
+                ..BudgetConfig::default()
+            })
+            .with_usage_ledger(ledger);
+
+        let exceeded = control.check_budget().await.unwrap();
+        control.hold_for_budget(exceeded).await;
+        assert!(control.budget_hold().await.is_some());
+        assert_eq!(control.continue_past_budget().await.unwrap().period, BudgetPeriod::Session);
+        assert!(control.check_budget().await.is_none());
+
+        assert!(!control.start_plan().await);
+        assert!(control.check_budget().await.is_some());
+    }
+}
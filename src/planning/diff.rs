@@ -0,0 +1,272 @@
+//! Differences between a running plan and a revised one
+//!
+//! When a prompt changes a plan that is already executing, the model returns a
+//! whole new plan. `merge_plans` folds it into the running plan: tasks are
+//! matched by id, completed tasks are kept with their results whatever the
+//! revision did with them, and unchanged tasks keep their progress. The plan
+//! keeps its id, so its `PlanContext` and the outputs recorded there stay
+//! attached. `PlanDiff` describes the outcome so it can be shown to the user
+//! before it is applied.
+
+use super::{Plan, PlanStatus, Task, TaskStatus};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How a revised plan differs from the plan it replaces, task by task
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlanDiff {
+    /// Tasks only in the revised plan
+    pub added: Vec<Task>,
+    /// Tasks only in the old plan
+    pub removed: Vec<Task>,
+    /// Tasks in both plans whose definition differs
+    pub changed: Vec<TaskChange>,
+    /// Tasks in both plans with the same definition
+    pub unchanged: Vec<Task>,
+}
+
+/// A task whose definition differs between two plans
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskChange {
+    pub before: Task,
+    pub after: Task,
+    /// Names of the fields that differ, e.g. `parameters`
+    pub fields: Vec<&'static str>,
+}
+
+impl PlanDiff {
+    /// Compare two plans, matching tasks by id
+    pub fn between(old: &Plan, new: &Plan) -> Self {
+        let old_tasks: HashMap<&str, &Task> = old.tasks.iter().map(|task| (task.id.as_str(), task)).collect();
+        let new_ids: HashSet<&str> = new.tasks.iter().map(|task| task.id.as_str()).collect();
+
+        let mut diff = PlanDiff::default();
+        for task in &new.tasks {
+            match old_tasks.get(task.id.as_str()) {
+                None => diff.added.push(task.clone()),
+                Some(before) => {
+                    let fields = changed_fields(before, task);
+                    if fields.is_empty() {
+                        diff.unchanged.push(task.clone());
+                    } else {
+                        diff.changed.push(TaskChange {
+                            before: (*before).clone(),
+                            after: task.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+        diff.removed = old
+            .tasks
+            .iter()
+            .filter(|task| !new_ids.contains(task.id.as_str()))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// Whether the plans define the same tasks
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for PlanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for task in &self.added {
+            writeln!(f, "+ {}: {}", task.id, task.description)?;
+        }
+        for task in &self.removed {
+            writeln!(f, "- {}: {}", task.id, task.description)?;
+        }
+        for change in &self.changed {
+            writeln!(
+                f,
+                "~ {}: {} ({} changed)",
+                change.after.id,
+                change.after.description,
+                change.fields.join(", ")
+            )?;
+        }
+        for task in &self.unchanged {
+            let note = if task.status == TaskStatus::Completed { " (completed)" } else { "" };
+            writeln!(f, "= {}: {}{}", task.id, task.description, note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fold a revised plan into the running plan it is meant to replace
+///
+/// Completed tasks are never redone: they stay with their results, even if
+/// the revision dropped or rewrote them. Tasks the revision left unchanged
+/// keep their status, except that failed, skipped and cancelled ones are
+/// queued again. New and changed tasks start out pending.
+pub fn merge_plans(current: &Plan, revised: &Plan) -> Plan {
+    let previous: HashMap<&str, &Task> = current.tasks.iter().map(|task| (task.id.as_str(), task)).collect();
+    let revised_ids: HashSet<&str> = revised.tasks.iter().map(|task| task.id.as_str()).collect();
+
+    let mut tasks: Vec<Task> = current
+        .tasks
+        .iter()
+        .filter(|task| task.status == TaskStatus::Completed && !revised_ids.contains(task.id.as_str()))
+        .cloned()
+        .collect();
+    for task in &revised.tasks {
+        let merged = match previous.get(task.id.as_str()) {
+            Some(old) if old.status == TaskStatus::Completed => (*old).clone(),
+            Some(old) if changed_fields(old, task).is_empty() => {
+                let mut kept = (*old).clone();
+                if matches!(kept.status, TaskStatus::Failed | TaskStatus::Skipped | TaskStatus::Cancelled) {
+                    kept.status = TaskStatus::Pending;
+                    kept.result = None;
+                }
+                kept
+            }
+            previous_task => {
+                let mut fresh = task.clone();
+                fresh.status = TaskStatus::Pending;
+                fresh.result = None;
+                // Revisions come from the model, which does not see sub-plan links
+                fresh.parent_id = previous_task.and_then(|old| old.parent_id.clone());
+                fresh
+            }
+        };
+        tasks.push(merged);
+    }
+    // Sub-tasks whose parent was dropped become top-level tasks
+    let ids: HashSet<String> = tasks.iter().map(|task| task.id.clone()).collect();
+    for task in &mut tasks {
+        if task.parent_id.as_ref().is_some_and(|parent| !ids.contains(parent)) {
+            task.parent_id = None;
+        }
+    }
+
+    let mut merged = current.clone();
+    merged.description = revised.description.clone();
+    merged.status = if !tasks.is_empty() && tasks.iter().all(|task| task.status == TaskStatus::Completed) {
+        PlanStatus::Completed
+    } else {
+        PlanStatus::Executing
+    };
+    merged.tasks = tasks;
+    merged.updated_at = chrono::Utc::now();
+    merged
+}
+
+/// Planning prompt asking the model to revise `current` for a new request
+///
+/// The model sees the running plan's task ids and statuses, so the revision
+/// can reuse them and `merge_plans` can match tasks up.
+pub fn revision_prompt(current: &Plan, request: &str) -> String {
+    let tasks: Vec<String> = current
+        .tasks
+        .iter()
+        .map(|task| format!("- {} [{:?}] {}", task.id, task.status, task.description))
+        .collect();
+    format!(
+        "Modify the existing plan to incorporate the new user request. \
+        Return the whole plan. Keep the id of every task you keep; completed \
+        tasks are kept as they are and never run again.\n\n\
+        Current Plan: {}\nTasks:\n{}\nNew Request: {}",
+        current.description,
+        tasks.join("\n"),
+        request
+    )
+}
+
+/// Fields that define what a task does and differ between `before` and `after`
+fn changed_fields(before: &Task, after: &Task) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if before.description != after.description {
+        fields.push("description");
+    }
+    if before.task_type != after.task_type {
+        fields.push("task_type");
+    }
+    if before.parameters != after.parameters {
+        fields.push("parameters");
+    }
+    if before.dependencies != after.dependencies {
+        fields.push("dependencies");
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::{TaskResult, TaskType};
+
+    fn running_plan() -> Plan {
+        let mut plan = Plan::new("Add a config file");
+        plan.add_task(Task::new("read", "Read main.rs", TaskType::ReadFile).with_parameter("path", "src/main.rs"));
+        plan.add_task(
+            Task::new("write", "Write config.toml", TaskType::WriteFile)
+                .with_parameter("path", "config.toml")
+                .with_dependency("read"),
+        );
+        plan.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("write"));
+        plan.set_task_result("read", TaskResult::success(Some(serde_json::json!("fn main() {}")), 5)).unwrap();
+        plan
+    }
+
+    #[test]
+    fn test_merge_keeps_completed_work() {
+        let current = running_plan();
+
+        // The revision drops the completed read, rewrites the write and adds a task
+        let mut revised = Plan::new("Add a YAML config file");
+        revised.add_task(Task::new("write", "Write config.yaml", TaskType::WriteFile).with_parameter("path", "config.yaml"));
+        revised.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("write"));
+        revised.add_task(Task::new("docs", "Document the config", TaskType::WriteFile).with_parameter("path", "README.md"));
+
+        let merged = merge_plans(&current, &revised);
+        assert_eq!(merged.id, current.id);
+        assert_eq!(merged.description, "Add a YAML config file");
+        assert_eq!(merged.status, PlanStatus::Executing);
+        let ids: Vec<&str> = merged.tasks.iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(ids, vec!["read", "write", "test", "docs"]);
+        assert_eq!(merged.tasks[0].status, TaskStatus::Completed);
+        assert!(merged.tasks[0].result.is_some());
+        assert_eq!(merged.tasks[1].parameters["path"], "config.yaml");
+        assert!(merged.validate().is_empty());
+
+        let diff = PlanDiff::between(&current, &merged);
+        assert_eq!(diff.added.len(), 1);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].fields, vec!["description", "parameters", "dependencies"]);
+        assert_eq!(diff.unchanged.len(), 2);
+        assert_eq!(
+            diff.to_string(),
+            "+ docs: Document the config\n\
+             ~ write: Write config.yaml (description, parameters, dependencies changed)\n\
+             = read: Read main.rs (completed)\n\
+             = test: Run the tests\n"
+        );
+    }
+
+    #[test]
+    fn test_completed_tasks_cannot_be_rewritten() {
+        let mut current = running_plan();
+        current.update_task_status("write", TaskStatus::Failed).unwrap();
+
+        let mut revised = current.clone();
+        revised.tasks[0].description = "Read main.rs again".to_string();
+        revised.tasks.retain(|task| task.id != "test");
+
+        let merged = merge_plans(&current, &revised);
+        assert_eq!(merged.tasks[0].description, "Read main.rs");
+        assert_eq!(merged.tasks[1].status, TaskStatus::Pending);
+
+        let diff = PlanDiff::between(&current, &merged);
+        assert_eq!(diff.removed.iter().map(|task| task.id.as_str()).collect::<Vec<_>>(), vec!["test"]);
+        assert!(diff.changed.is_empty());
+        assert!(!diff.is_empty());
+        assert!(PlanDiff::between(&merged, &merged).is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+//! Sub-plans: abstract tasks decomposed into child tasks
+//!
+//! A task that is too abstract to run is broken into sub-tasks that live in
+//! the same `Plan`, linked to it through `Task::parent_id`. The parent never
+//! runs itself; `roll_up` completes it once every sub-task has completed (or
+//! fails it as soon as one fails), so tasks depending on the parent wait for
+//! the whole sub-plan. `SubPlanLimits` bounds how deep and how wide the tree
+//! can grow, and `task_tree` walks it for display with progress rolled up
+//! from the leaves.
+
+use super::validation::task_type_name;
+use super::{Plan, Task, TaskResult, TaskStatus};
+use crate::utils::errors::KaiError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How far abstract tasks may be decomposed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubPlanLimits {
+    /// Deepest level a sub-task may sit at; top-level tasks are at depth 0
+    pub max_depth: usize,
+    /// Maximum number of sub-tasks a single task may be split into
+    pub max_fan_out: usize,
+}
+
+impl Default for SubPlanLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_fan_out: 8,
+        }
+    }
+}
+
+/// Completed and total leaf tasks under a task or plan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub completed: usize,
+    pub total: usize,
+}
+
+impl TaskProgress {
+    fn add(&mut self, other: TaskProgress) {
+        self.completed += other.completed;
+        self.total += other.total;
+    }
+}
+
+impl fmt::Display for TaskProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.completed, self.total)
+    }
+}
+
+/// A task in depth-first tree order, as returned by `Plan::task_tree`
+#[derive(Debug, Clone)]
+pub struct TaskTreeNode<'a> {
+    pub task: &'a Task,
+    /// 0 for top-level tasks, 1 for their sub-tasks and so on
+    pub depth: usize,
+    /// Progress of the leaf tasks below this one, or of the task itself if it is a leaf
+    pub progress: TaskProgress,
+    /// Whether the task was decomposed into sub-tasks
+    pub has_subtasks: bool,
+}
+
+impl Plan {
+    /// Sub-tasks of a task, in plan order
+    pub fn subtasks(&self, task_id: &str) -> Vec<&Task> {
+        self.tasks
+            .iter()
+            .filter(|task| task.parent_id.as_deref() == Some(task_id))
+            .collect()
+    }
+
+    /// Whether a task was decomposed into sub-tasks
+    pub fn has_subtasks(&self, task_id: &str) -> bool {
+        self.tasks.iter().any(|task| task.parent_id.as_deref() == Some(task_id))
+    }
+
+    /// How many parents a task has above it
+    pub fn task_depth(&self, task_id: &str) -> usize {
+        let mut depth = 0;
+        let mut current = self.find_task(task_id).and_then(|task| task.parent_id.as_deref());
+        // Bounded by the task count so a malformed parent chain cannot loop forever
+        while let Some(parent_id) = current {
+            if depth >= self.tasks.len() {
+                break;
+            }
+            depth += 1;
+            current = self.find_task(parent_id).and_then(|task| task.parent_id.as_deref());
+        }
+        depth
+    }
+
+    /// Progress of the leaf tasks below a task, or of the task itself if it has none
+    pub fn task_progress(&self, task_id: &str) -> TaskProgress {
+        self.progress_below(task_id, self.tasks.len())
+    }
+
+    /// Progress of every leaf task in the plan
+    pub fn progress(&self) -> TaskProgress {
+        let mut progress = TaskProgress::default();
+        for task in self.tasks.iter().filter(|task| !self.has_subtasks(&task.id)) {
+            progress.add(leaf_progress(task));
+        }
+        progress
+    }
+
+    /// Tasks in depth-first order, each sub-plan right below its parent
+    ///
+    /// Tasks whose parent is missing are shown at the top level.
+    pub fn task_tree(&self) -> Vec<TaskTreeNode<'_>> {
+        let ids: HashSet<&str> = self.tasks.iter().map(|task| task.id.as_str()).collect();
+        let mut nodes = Vec::with_capacity(self.tasks.len());
+        let mut visited = HashSet::new();
+        for task in &self.tasks {
+            let is_root = task.parent_id.as_deref().is_none_or(|parent| !ids.contains(parent));
+            if is_root {
+                self.push_subtree(task, 0, &mut visited, &mut nodes);
+            }
+        }
+        // Tasks caught in a parent cycle are unreachable from any root
+        for task in &self.tasks {
+            if !visited.contains(task.id.as_str()) {
+                self.push_subtree(task, 0, &mut visited, &mut nodes);
+            }
+        }
+        nodes
+    }
+
+    /// Add the sub-tasks an abstract task was decomposed into
+    ///
+    /// Sub-task ids are prefixed with the parent's id (`build.tests`), and
+    /// dependencies between siblings are rewritten to match. Each sub-task also
+    /// waits for whatever the parent waits for. The sub-tasks are inserted right
+    /// after the parent, which is marked in progress until they finish. Returns
+    /// the new ids; the plan is left unchanged if the sub-plan breaks the limits
+    /// or would make the plan invalid.
+    pub fn add_subtasks(
+        &mut self,
+        parent_id: &str,
+        subtasks: Vec<Task>,
+        limits: &SubPlanLimits,
+    ) -> Result<Vec<String>, KaiError> {
+        let parent_index = self
+            .tasks
+            .iter()
+            .position(|task| task.id == parent_id)
+            .ok_or_else(|| KaiError::not_found(format!("task '{}'", parent_id)))?;
+        let parent = &self.tasks[parent_index];
+        if parent.status == TaskStatus::Completed {
+            return Err(KaiError::planning(format!("Task {} is already completed", parent_id)));
+        }
+        if self.has_subtasks(parent_id) {
+            return Err(KaiError::planning(format!("Task {} is already decomposed", parent_id)));
+        }
+        if subtasks.is_empty() {
+            return Err(KaiError::planning(format!("No sub-tasks given for task {}", parent_id)));
+        }
+        let depth = self.task_depth(parent_id) + 1;
+        if depth > limits.max_depth {
+            return Err(KaiError::validation(
+                "max_depth",
+                format!(
+                    "Sub-tasks of {} would be {} levels deep (max {})",
+                    parent_id, depth, limits.max_depth
+                ),
+            ));
+        }
+        if subtasks.len() > limits.max_fan_out {
+            return Err(KaiError::validation(
+                "max_fan_out",
+                format!(
+                    "Task {} was split into {} sub-tasks (max {})",
+                    parent_id,
+                    subtasks.len(),
+                    limits.max_fan_out
+                ),
+            ));
+        }
+
+        let sibling_ids: HashMap<String, String> = subtasks
+            .iter()
+            .map(|task| (task.id.clone(), format!("{}.{}", parent_id, task.id)))
+            .collect();
+        let inherited = parent.dependencies.clone();
+        let now = chrono::Utc::now();
+        let children: Vec<Task> = subtasks
+            .into_iter()
+            .map(|mut task| {
+                task.id = sibling_ids[&task.id].clone();
+                let mut dependencies: Vec<String> = task
+                    .dependencies
+                    .iter()
+                    .map(|dependency| sibling_ids.get(dependency).unwrap_or(dependency).clone())
+                    .collect();
+                for dependency in &inherited {
+                    if !dependencies.contains(dependency) {
+                        dependencies.push(dependency.clone());
+                    }
+                }
+                task.dependencies = dependencies;
+                task.parent_id = Some(parent_id.to_string());
+                task.status = TaskStatus::Pending;
+                task.result = None;
+                task.updated_at = now;
+                task
+            })
+            .collect();
+        let ids = children.iter().map(|task| task.id.clone()).collect();
+
+        let mut updated = self.clone();
+        updated.tasks.splice(parent_index + 1..parent_index + 1, children);
+        updated.tasks[parent_index].status = TaskStatus::InProgress;
+        updated.tasks[parent_index].updated_at = now;
+        updated.ensure_valid()?;
+        updated.updated_at = now;
+        *self = updated;
+        Ok(ids)
+    }
+
+    /// Bring the parents of a task in line with their sub-tasks
+    ///
+    /// A parent completes once all of its sub-tasks have completed and fails
+    /// as soon as one of them fails; the change is carried up to the top.
+    pub(crate) fn roll_up(&mut self, task_id: &str) {
+        let mut parent_id = self.find_task(task_id).and_then(|task| task.parent_id.clone());
+        let mut steps = 0;
+        while let Some(id) = parent_id {
+            if steps >= self.tasks.len() {
+                break;
+            }
+            steps += 1;
+
+            let subtasks = self.subtasks(&id);
+            let status = if subtasks.iter().all(|task| task.status == TaskStatus::Completed) {
+                Some(TaskStatus::Completed)
+            } else if subtasks.iter().any(|task| task.status == TaskStatus::Failed) {
+                Some(TaskStatus::Failed)
+            } else {
+                None
+            };
+            let execution_time_ms = subtasks
+                .iter()
+                .filter_map(|task| task.result.as_ref())
+                .map(|result| result.execution_time_ms)
+                .sum();
+            let count = subtasks.len();
+
+            let Some(parent) = self.tasks.iter_mut().find(|task| task.id == id) else {
+                break;
+            };
+            match status {
+                Some(status) if parent.status != status => {
+                    parent.result = match status {
+                        TaskStatus::Completed => Some(
+                            TaskResult::success(None, execution_time_ms).with_metadata("subtasks", count),
+                        ),
+                        _ => Some(TaskResult::failure(
+                            format!("A sub-task of {} failed", parent.id),
+                            execution_time_ms,
+                        )),
+                    };
+                    parent.status = status;
+                    parent.updated_at = chrono::Utc::now();
+                }
+                _ => break,
+            }
+            parent_id = parent.parent_id.clone();
+        }
+    }
+
+    /// Parents of a task that have completed, nearest first
+    pub fn completed_parents(&self, task_id: &str) -> Vec<String> {
+        self.parents_with_status(task_id, TaskStatus::Completed)
+    }
+
+    /// Parents of a task that have failed through a sub-task, nearest first
+    pub fn failed_parents(&self, task_id: &str) -> Vec<String> {
+        self.parents_with_status(task_id, TaskStatus::Failed)
+    }
+
+    /// Consecutive parents of a task, nearest first, that roll-up left in `status`
+    fn parents_with_status(&self, task_id: &str, status: TaskStatus) -> Vec<String> {
+        let mut parents = Vec::new();
+        let mut current = self.find_task(task_id).and_then(|task| task.parent_id.as_deref());
+        while let Some(parent) = current.and_then(|id| self.find_task(id)) {
+            if parent.status != status || parents.len() >= self.tasks.len() {
+                break;
+            }
+            parents.push(parent.id.clone());
+            current = parent.parent_id.as_deref();
+        }
+        parents
+    }
+
+    fn find_task(&self, task_id: &str) -> Option<&Task> {
+        self.tasks.iter().find(|task| task.id == task_id)
+    }
+
+    fn progress_below(&self, task_id: &str, remaining_depth: usize) -> TaskProgress {
+        let subtasks = self.subtasks(task_id);
+        if subtasks.is_empty() || remaining_depth == 0 {
+            return self.find_task(task_id).map(leaf_progress).unwrap_or_default();
+        }
+        let mut progress = TaskProgress::default();
+        for task in subtasks {
+            progress.add(self.progress_below(&task.id, remaining_depth - 1));
+        }
+        progress
+    }
+
+    fn push_subtree<'a>(
+        &'a self,
+        task: &'a Task,
+        depth: usize,
+        visited: &mut HashSet<&'a str>,
+        nodes: &mut Vec<TaskTreeNode<'a>>,
+    ) {
+        if !visited.insert(task.id.as_str()) {
+            return;
+        }
+        let subtasks = self.subtasks(&task.id);
+        nodes.push(TaskTreeNode {
+            task,
+            depth,
+            progress: self.task_progress(&task.id),
+            has_subtasks: !subtasks.is_empty(),
+        });
+        for subtask in subtasks {
+            self.push_subtree(subtask, depth + 1, visited, nodes);
+        }
+    }
+}
+
+/// Whether a task reads like a goal rather than a single operation
+///
+/// Tasks that ask to build or improve something and name no parameters are
+/// decomposed into a sub-plan instead of being run directly.
+pub fn is_abstract_task(task: &Task) -> bool {
+    let abstract_keywords = [
+        "refactor", "improve", "enhance", "optimize", "redesign",
+        "implement", "create", "build", "develop", "design",
+    ];
+    let description = task.description.to_lowercase();
+    abstract_keywords.iter().any(|keyword| description.contains(keyword)) && task.parameters.is_empty()
+}
+
+/// Planning prompt asking the model to split `task` into sub-tasks within `limits`
+pub fn decomposition_prompt(task: &Task, limits: &SubPlanLimits) -> String {
+    format!(
+        "Decompose this high-level task into at most {} concrete, executable subtasks. \
+        Dependencies may only refer to other subtasks in your answer.\n\n\
+        Task: {}\nTask Type: {}\nParameters: {}",
+        limits.max_fan_out,
+        task.description,
+        task_type_name(&task.task_type),
+        serde_json::to_string_pretty(&task.parameters).unwrap_or_default(),
+    )
+}
+
+fn leaf_progress(task: &Task) -> TaskProgress {
+    TaskProgress {
+        completed: usize::from(task.status == TaskStatus::Completed),
+        total: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::{PlanStatus, TaskType};
+
+    fn feature_plan() -> Plan {
+        let mut plan = Plan::new("Add a search command");
+        plan.add_task(Task::new("read", "Read the CLI module", TaskType::ReadFile).with_parameter("path", "src/cli.rs"));
+        plan.add_task(Task::new("build", "Implement the search command", TaskType::GenerateContent).with_dependency("read"));
+        plan.add_task(Task::new("test", "Run the tests", TaskType::ExecuteCommand).with_dependency("build"));
+        plan
+    }
+
+    fn build_subtasks() -> Vec<Task> {
+        vec![
+            Task::new("parse", "Parse the search arguments", TaskType::GenerateContent),
+            Task::new("write", "Write the command", TaskType::WriteFile)
+                .with_parameter("path", "src/search.rs")
+                .with_dependency("parse"),
+        ]
+    }
+
+    #[test]
+    fn test_subtasks_join_the_plan_under_their_parent() {
+        let mut plan = feature_plan();
+        let ids = plan.add_subtasks("build", build_subtasks(), &SubPlanLimits::default()).unwrap();
+        assert_eq!(ids, vec!["build.parse", "build.write"]);
+
+        let order: Vec<&str> = plan.tasks.iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(order, vec!["read", "build", "build.parse", "build.write", "test"]);
+        assert_eq!(plan.tasks[1].status, TaskStatus::InProgress);
+        assert_eq!(plan.tasks[2].dependencies, vec!["read"]);
+        assert_eq!(plan.tasks[3].dependencies, vec!["build.parse", "read"]);
+        assert_eq!(plan.task_depth("build.write"), 1);
+
+        let tree: Vec<(&str, usize, String)> = plan
+            .task_tree()
+            .iter()
+            .map(|node| (node.task.id.as_str(), node.depth, node.progress.to_string()))
+            .collect();
+        assert_eq!(
+            tree,
+            vec![
+                ("read", 0, "0/1".to_string()),
+                ("build", 0, "0/2".to_string()),
+                ("build.parse", 1, "0/1".to_string()),
+                ("build.write", 1, "0/1".to_string()),
+                ("test", 0, "0/1".to_string()),
+            ]
+        );
+        assert_eq!(plan.progress(), TaskProgress { completed: 0, total: 4 });
+    }
+
+    #[test]
+    fn test_parent_completes_with_its_subtasks() {
+        let mut plan = feature_plan();
+        plan.set_task_result("read", TaskResult::success(None, 5)).unwrap();
+        plan.add_subtasks("build", build_subtasks(), &SubPlanLimits::default()).unwrap();
+        assert!(plan.get_ready_tasks().iter().all(|task| task.id != "build"));
+
+        plan.set_task_result("build.parse", TaskResult::success(None, 10)).unwrap();
+        assert_eq!(plan.tasks[1].status, TaskStatus::InProgress);
+        assert_eq!(plan.task_progress("build"), TaskProgress { completed: 1, total: 2 });
+        assert!(plan.completed_parents("build.parse").is_empty());
+        let ready: Vec<&str> = plan.get_ready_tasks().iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(ready, vec!["build.write"]);
+
+        plan.set_task_result("build.write", TaskResult::success(None, 20)).unwrap();
+        assert_eq!(plan.tasks[1].status, TaskStatus::Completed);
+        assert_eq!(plan.tasks[1].result.as_ref().unwrap().execution_time_ms, 30);
+        assert_eq!(plan.completed_parents("build.write"), vec!["build"]);
+        let ready: Vec<&str> = plan.get_ready_tasks().iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(ready, vec!["test"]);
+
+        plan.set_task_result("test", TaskResult::success(None, 1)).unwrap();
+        assert_eq!(plan.status, PlanStatus::Completed);
+    }
+
+    #[test]
+    fn test_limits_and_failures() {
+        let mut plan = feature_plan();
+        let narrow = SubPlanLimits { max_depth: 5, max_fan_out: 1 };
+        assert!(matches!(
+            plan.add_subtasks("build", build_subtasks(), &narrow),
+            Err(KaiError::Validation { .. })
+        ));
+        assert_eq!(plan.tasks.len(), 3);
+
+        let shallow = SubPlanLimits { max_depth: 1, max_fan_out: 8 };
+        plan.add_subtasks("build", build_subtasks(), &shallow).unwrap();
+        let nested = vec![Task::new("a", "Sketch the API", TaskType::GenerateContent)];
+        assert!(plan.add_subtasks("build.parse", nested, &shallow).is_err());
+
+        // A sub-task depending on its own parent could never run
+        let looped = vec![Task::new("x", "Wait for the tests", TaskType::GenerateContent).with_dependency("test")];
+        assert!(plan.add_subtasks("test", looped, &shallow).is_err());
+
+        plan.update_task_status("build.parse", TaskStatus::Failed).unwrap();
+        assert_eq!(plan.tasks[1].status, TaskStatus::Failed);
+        assert!(plan.tasks[1].result.as_ref().unwrap().error.is_some());
+        assert_eq!(plan.failed_parents("build.parse"), vec!["build"]);
+        assert!(plan.completed_parents("build.parse").is_empty());
+    }
+}
@@ -8,11 +8,18 @@
 //! - Interruptible execution with graceful plan modification
 //! - LLM-powered task refinement and post-execution analysis
 
-use super::{Plan, PlanStatus, Task, TaskStatus, TaskType, TaskResult};
+use super::control::{merge_revision, should_decompose, PlanControl};
+use super::diff::revision_prompt;
+use super::{Plan, PlanDiff, PlanReviewPolicy, SubPlanLimits, PlanStatus, Task, TaskStatus, TaskType, TaskResult};
 use crate::{
+    config::BudgetConfig,
     context::{ContextManager, PlanContext},
-    execution::TaskExecutor,
-    llm::{LlmProvider, TaskRefinementContext, TaskExecutionResult, TaskAnalysis},
+    execution::{ensure_model_supports_plan, ExecutionMode, TaskExecutor},
+    llm::{cancellation, ContentPart, LlmProvider, TaskRefinementContext, TaskExecutionResult, TaskAnalysis, StreamOutput, StreamStage},
+    llm::content::generate_plan_with_attachments,
+    llm::streaming::utils::{analyze_task_result_observed, generate_plan_observed, refine_task_observed},
+    llm::registry::ModelRegistry,
+    llm::usage::{BudgetExceeded, UsageLedger},
     utils::errors::KaiError,
     Result,
 };
@@ -20,6 +27,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::time::Instant;
 use tokio::sync::{mpsc, RwLock, broadcast};
+use tokio_util::sync::CancellationToken;
 use std::sync::Arc;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
@@ -39,6 +47,10 @@ pub enum PlanManagerMessage {
     UserRequest(UserPrompt),
     /// Modify the current plan
     ModifyPlan(Plan),
+    /// Run the plan under review, with the user's edits
+    ApprovePlan(Plan),
+    /// Discard the plan under review
+    RejectPlan,
     /// Request current status
     GetStatus,
     /// Force task decomposition for abstract tasks
@@ -47,6 +59,17 @@ pub enum PlanManagerMessage {
     Shutdown,
 }
 
+impl PlanManagerMessage {
+    /// Whether the message ends the current plan, so its LLM requests can be aborted right away
+    fn stops_current_plan(&self) -> bool {
+        match self {
+            Self::CancelPlan | Self::Shutdown => true,
+            Self::UserRequest(prompt) => prompt.priority == PromptPriority::Emergency,
+            _ => false,
+        }
+    }
+}
+
 /// User prompt with priority and metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserPrompt {
@@ -56,6 +79,9 @@ pub struct UserPrompt {
     pub priority: PromptPriority,
     pub requires_new_plan: bool,
     pub context_hint: Option<String>,
+    /// Images and files sent to the model with the prompt
+    #[serde(default)]
+    pub attachments: Vec<ContentPart>,
 }
 
 /// Priority levels for user prompts - implements LIFO for high priority
@@ -78,6 +104,12 @@ pub struct CoordinatorStatus {
     pub execution_state: ExecutionState,
     pub current_task: Option<TaskStatusInfo>,
     pub performance_metrics: PerformanceMetrics,
+    /// Spending cap the paused plan is waiting on; resuming approves going past it
+    pub budget_alert: Option<BudgetExceeded>,
+    /// Generated plan waiting for the user to approve or reject it
+    pub pending_review: Option<Plan>,
+    /// How the plan under review changes the running plan, when it modifies one
+    pub pending_review_diff: Option<PlanDiff>,
 }
 
 /// Plan status information for UI
@@ -121,7 +153,7 @@ pub enum ExecutionState {
     ResultAnalysis,
     /// Updating contexts and plan state
     StateUpdate,
-    /// Paused by user
+    /// Paused by user, or waiting for approval to exceed a spending cap
     Paused,
     /// Cancelled
     Cancelled,
@@ -169,11 +201,19 @@ pub struct AgenticPlanningCoordinator {
     message_receiver: mpsc::UnboundedReceiver<PlanManagerMessage>,
     message_sender: mpsc::UnboundedSender<PlanManagerMessage>,
     status_broadcaster: broadcast::Sender<CoordinatorStatus>,
+    /// Text streamed from the LLM while planning, refining and analyzing
+    output_broadcaster: broadcast::Sender<StreamOutput>,
     
     /// Configuration
     current_model: String,
     config: CoordinatorConfig,
     
+    /// Model capabilities checked before planning
+    model_registry: Option<Arc<ModelRegistry>>,
+    /// Plan token, review, spending cap and decomposition state shared with the execution engine;
+    /// reviewed plans remember whether they modify the running plan
+    plan_control: PlanControl<bool>,
+    
     /// State tracking
     execution_state: Arc<RwLock<ExecutionState>>,
     start_time: Instant,
@@ -188,6 +228,8 @@ pub struct AgenticPlanningCoordinator {
 pub struct CoordinatorConfig {
     /// Maximum recursion depth for task decomposition
     pub max_decomposition_depth: usize,
+    /// Maximum number of sub-tasks a task is decomposed into
+    pub max_subtasks_per_task: usize,
     /// Maximum tasks allowed in a plan
     pub max_plan_size: usize,
     /// Timeout for individual LLM calls (ms)
@@ -200,18 +242,25 @@ pub struct CoordinatorConfig {
     pub auto_decompose_abstract_tasks: bool,
     /// Parallel task execution limit
     pub max_parallel_tasks: usize,
+    /// Spending caps checked before each task
+    pub budget: BudgetConfig,
+    /// Which generated plans wait for approval before they run
+    pub plan_review: PlanReviewPolicy,
 }
 
 impl Default for CoordinatorConfig {
     fn default() -> Self {
         Self {
-            max_decomposition_depth: 5,
+            max_decomposition_depth: SubPlanLimits::default().max_depth,
+            max_subtasks_per_task: SubPlanLimits::default().max_fan_out,
             max_plan_size: 100,
             llm_timeout_ms: 30_000,
             task_timeout_ms: 300_000,
             max_user_prompt_queue: 50,
             auto_decompose_abstract_tasks: true,
             max_parallel_tasks: 1, // Start with sequential execution
+            budget: BudgetConfig::default(),
+            plan_review: PlanReviewPolicy::default(),
         }
     }
 }
@@ -230,6 +279,7 @@ impl AgenticPlanningCoordinator {
     ) -> Self {
         let (msg_sender, msg_receiver) = mpsc::unbounded_channel();
         let (status_sender, _) = broadcast::channel(100);
+        let (output_sender, _) = broadcast::channel(1000);
         let config = config.unwrap_or_default();
         let start_time = Instant::now();
         
@@ -244,8 +294,11 @@ impl AgenticPlanningCoordinator {
             message_receiver: msg_receiver,
             message_sender: msg_sender,
             status_broadcaster: status_sender,
+            output_broadcaster: output_sender,
             current_model: model,
+            plan_control: PlanControl::new(CancellationToken::new()).with_budget(config.budget.clone()),
             config,
+            model_registry: ModelRegistry::global(),
             execution_state: Arc::new(RwLock::new(ExecutionState::Idle)),
             start_time,
             metrics: Arc::new(RwLock::new(PerformanceMetrics {
@@ -262,6 +315,31 @@ impl AgenticPlanningCoordinator {
         }
     }
 
+    /// Check spending caps against `ledger` instead of the shared usage ledger
+    pub fn with_usage_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.plan_control = self.plan_control.with_usage_ledger(ledger);
+        self
+    }
+
+    /// Check model capabilities against `registry` instead of the shared model registry
+    pub fn with_model_registry(mut self, registry: Arc<ModelRegistry>) -> Self {
+        self.model_registry = Some(registry);
+        self
+    }
+
+    /// Fail when the current model is known to lack a feature the plan needs
+    async fn ensure_model_supports_plan(&self, attachments: &[ContentPart]) -> Result<()> {
+        let mode = self.task_executor.read().await.execution_mode();
+        ensure_model_supports_plan(
+            self.model_registry.as_deref(),
+            self.llm_provider.as_ref(),
+            &self.current_model,
+            mode,
+            attachments,
+        )
+        .await
+    }
+
     /// Get a message sender for external communication
     pub fn get_message_sender(&self) -> mpsc::UnboundedSender<PlanManagerMessage> {
         self.message_sender.clone()
@@ -271,9 +349,24 @@ impl AgenticPlanningCoordinator {
     pub fn get_status_receiver(&self) -> broadcast::Receiver<CoordinatorStatus> {
         self.status_broadcaster.subscribe()
     }
+    
+    /// Get a receiver for LLM output streamed token by token
+    pub fn get_output_receiver(&self) -> broadcast::Receiver<StreamOutput> {
+        self.output_broadcaster.subscribe()
+    }
 
     /// Submit a user prompt (convenience method)
     pub async fn submit_user_prompt(&self, content: String, priority: PromptPriority) -> Result<String> {
+        self.submit_user_prompt_with_attachments(content, Vec::new(), priority).await
+    }
+
+    /// Submit a user prompt with images or files attached
+    pub async fn submit_user_prompt_with_attachments(
+        &self,
+        content: String,
+        attachments: Vec<ContentPart>,
+        priority: PromptPriority,
+    ) -> Result<String> {
         let prompt = UserPrompt {
             id: Uuid::new_v4().to_string(),
             content,
@@ -281,6 +374,7 @@ impl AgenticPlanningCoordinator {
             priority,
             requires_new_plan: true,
             context_hint: None,
+            attachments,
         };
         
         let id = prompt.id.clone();
@@ -308,6 +402,10 @@ impl AgenticPlanningCoordinator {
         self.update_execution_state(ExecutionState::Idle).await;
         self.broadcast_status().await;
 
+        // Messages are also read while a cycle runs, so the receiver is borrowed apart from `self`
+        let (_, placeholder) = mpsc::unbounded_channel();
+        let mut message_receiver = std::mem::replace(&mut self.message_receiver, placeholder);
+
         loop {
             // Check for shutdown request
             if *self.shutdown_requested.read().await {
@@ -321,7 +419,7 @@ impl AgenticPlanningCoordinator {
                 biased;
                 
                 // Handle control messages (higher priority)
-                message = self.message_receiver.recv() => {
+                message = message_receiver.recv() => {
                     if let Some(msg) = message {
                         if let Err(e) = self.handle_message(msg).await {
                             tracing::error!("Error handling message: {}", e);
@@ -333,9 +431,7 @@ impl AgenticPlanningCoordinator {
 
                 // Main agentic loop execution (lower priority)
                 _ = cycle_tick.tick() => {
-                    if let Err(e) = self.execute_agentic_cycle().await {
-                        tracing::error!("Error in agentic cycle: {}", e);
-                    }
+                    self.run_agentic_cycle(&mut message_receiver).await;
                 }
             }
 
@@ -347,12 +443,52 @@ impl AgenticPlanningCoordinator {
             tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
         }
 
+        self.message_receiver = message_receiver;
         self.update_execution_state(ExecutionState::Shutdown).await;
         self.broadcast_status().await;
         tracing::info!("Agentic Planning Coordinator stopped");
         Ok(())
     }
     
+    /// Run one agentic cycle under the current plan's cancellation token
+    ///
+    /// Messages that arrive meanwhile are handled once the cycle ends, except
+    /// that cancelling the plan aborts its outstanding LLM requests at once.
+    async fn run_agentic_cycle(&mut self, message_receiver: &mut mpsc::UnboundedReceiver<PlanManagerMessage>) {
+        let plan_control = self.plan_control.clone();
+        let token = plan_control.plan_token().await;
+        let mut deferred = Vec::new();
+
+        let result = {
+            let cycle = cancellation::with_cancellation(token, self.execute_agentic_cycle());
+            tokio::pin!(cycle);
+            loop {
+                tokio::select! {
+                    biased;
+                    result = &mut cycle => break result,
+                    Some(message) = message_receiver.recv() => {
+                        if message.stops_current_plan() {
+                            plan_control.renew_plan_token().await;
+                        }
+                        deferred.push(message);
+                    }
+                }
+            }
+        };
+
+        match result {
+            Err(e) if e.is_cancelled() => tracing::info!("Agentic cycle cancelled: {}", e),
+            Err(e) => tracing::error!("Error in agentic cycle: {}", e),
+            Ok(()) => {}
+        }
+
+        for message in deferred {
+            if let Err(e) = self.handle_message(message).await {
+                tracing::error!("Error handling message: {}", e);
+            }
+        }
+    }
+
     /// Execute the main agentic cycle - the heart of the coordinator
     async fn execute_agentic_cycle(&mut self) -> Result<()> {
         // Step 1: Check User Prompt Queue (High Priority LIFO)
@@ -360,9 +496,25 @@ impl AgenticPlanningCoordinator {
             return self.handle_user_prompt(user_prompt).await;
         }
 
+        // A paused plan keeps its queued tasks until it is resumed
+        if *self.execution_state.read().await == ExecutionState::Paused {
+            return Ok(());
+        }
+
         // Step 2: Check Main Task Queue (FIFO) 
         if let Some(task) = self.dequeue_main_task().await {
-            return self.execute_task_with_full_agentic_loop(task).await;
+            if let Some(exceeded) = self.plan_control.check_budget().await {
+                return self.hold_for_budget(task, exceeded).await;
+            }
+            let task_id = task.id.clone();
+            return match self.execute_task_with_full_agentic_loop(task).await {
+                // Cancelled tasks are recorded as such, not as failures
+                Err(e) if e.is_cancelled() => {
+                    tracing::info!("Task {} cancelled: {}", task_id, e);
+                    self.update_task_status_in_plan(&task_id, TaskStatus::Cancelled).await
+                }
+                result => result,
+            };
         }
         
         // No work available - remain idle
@@ -391,6 +543,12 @@ impl AgenticPlanningCoordinator {
             PlanManagerMessage::ModifyPlan(plan) => {
                 self.modify_plan(plan).await?;
             }
+            PlanManagerMessage::ApprovePlan(plan) => {
+                self.approve_plan(plan).await?;
+            }
+            PlanManagerMessage::RejectPlan => {
+                self.plan_control.reject_plan().await;
+            }
             PlanManagerMessage::GetStatus => {
                 self.broadcast_status().await;
             }
@@ -413,6 +571,7 @@ impl AgenticPlanningCoordinator {
         if plan.tasks.is_empty() {
             return Err(KaiError::planning("Cannot start plan with no tasks"));
         }
+        plan.ensure_valid()?;
         
         if plan.tasks.len() > self.config.max_plan_size {
             return Err(KaiError::planning(format!(
@@ -422,6 +581,12 @@ impl AgenticPlanningCoordinator {
             )));
         }
         
+        self.ensure_model_supports_plan(&[]).await?;
+        
+        self.plan_control.start_plan().await;
+        // A new plan replaces any paused one
+        self.update_execution_state(ExecutionState::Idle).await;
+        
         // Set plan status and create plan context
         plan.status = PlanStatus::Executing;
         let plan_id = plan.id.clone();
@@ -475,7 +640,12 @@ impl AgenticPlanningCoordinator {
     }
 
     /// Resume the paused plan
+    ///
+    /// Resuming a plan held on a spending cap approves exceeding that cap for
+    /// the rest of the plan.
     async fn resume_plan(&self) -> Result<()> {
+        self.plan_control.continue_past_budget().await;
+        
         let mut current_plan = self.current_plan.write().await;
         if let Some(ref mut plan) = *current_plan {
             if plan.status == PlanStatus::Paused {
@@ -489,11 +659,7 @@ impl AgenticPlanningCoordinator {
 
     /// Cancel the current plan
     async fn cancel_plan(&self) -> Result<()> {
-        let mut current_plan = self.current_plan.write().await;
-        if let Some(ref mut plan) = *current_plan {
-            plan.status = PlanStatus::Cancelled;
-            tracing::info!("Plan cancelled: {}", plan.description);
-        }
+        self.plan_control.cancel(&self.current_plan).await;
         
         // Clear task queues
         {
@@ -514,6 +680,9 @@ impl AgenticPlanningCoordinator {
     async fn handle_user_prompt(&self, prompt: UserPrompt) -> Result<()> {
         tracing::info!("Handling user prompt: {} (priority: {:?})", prompt.content, prompt.priority);
         
+        // Refuse before paying for a plan the model could not carry out
+        self.ensure_model_supports_plan(&prompt.attachments).await?;
+        
         self.update_execution_state(ExecutionState::Planning).await;
         
         // Increment interruption counter
@@ -554,7 +723,8 @@ impl AgenticPlanningCoordinator {
             task_queue.clear();
         }
         
-        // Cancel current plan
+        // Cancel current plan and abort its outstanding LLM requests
+        self.plan_control.renew_plan_token().await;
         {
             let mut current_plan = self.current_plan.write().await;
             if let Some(ref mut plan) = *current_plan {
@@ -562,9 +732,10 @@ impl AgenticPlanningCoordinator {
             }
         }
         
-        // Generate and start new plan immediately
-        let new_plan = self.generate_plan_from_prompt(&prompt).await?;
-        self.start_plan(new_plan).await?;
+        // Generate and start new plan immediately, under the new plan's token
+        let token = self.plan_control.plan_token().await;
+        let new_plan = cancellation::with_cancellation(token, self.generate_plan_from_prompt(&prompt)).await?;
+        self.start_or_review(new_plan, false).await?;
         
         Ok(())
     }
@@ -576,11 +747,20 @@ impl AgenticPlanningCoordinator {
         // Pause current plan if executing
         self.pause_plan().await?;
         
+        // Fold the request into the paused plan so its completed work is kept
+        let current_plan = self.current_plan.read().await.clone();
+        if let Some(plan) = current_plan.filter(|plan| plan.status == PlanStatus::Paused) {
+            match self.generate_modified_plan(&plan, &prompt).await {
+                Ok(modified_plan) => return self.start_or_review(modified_plan, true).await,
+                Err(e) => tracing::warn!("Failed to modify plan, creating new plan: {}", e),
+            }
+        }
+        
         // Generate plan for interrupt
         let interrupt_plan = self.generate_plan_from_prompt(&prompt).await?;
         
         // For now, replace current plan (TODO: implement plan stacking)
-        self.start_plan(interrupt_plan).await?;
+        self.start_or_review(interrupt_plan, false).await?;
         
         Ok(())
     }
@@ -595,7 +775,7 @@ impl AgenticPlanningCoordinator {
                 // Try to modify existing plan
                 match self.generate_modified_plan(&plan, &prompt).await {
                     Ok(modified_plan) => {
-                        self.modify_plan(modified_plan).await?;
+                        self.start_or_review(modified_plan, true).await?;
                         return Ok(());
                     }
                     Err(e) => {
@@ -607,11 +787,47 @@ impl AgenticPlanningCoordinator {
         
         // Fallback: create new plan
         let new_plan = self.generate_plan_from_prompt(&prompt).await?;
-        self.start_plan(new_plan).await?;
+        self.start_or_review(new_plan, false).await?;
         
         Ok(())
     }
 
+    /// Run a generated plan, or hold it for the user if the review policy asks for it
+    ///
+    /// A plan that modifies the running plan is merged into it first and always
+    /// held, so the user sees the diff before `modify_plan` applies it.
+    async fn start_or_review(&self, plan: Plan, modifies_current: bool) -> Result<()> {
+        let current = match modifies_current {
+            true => self.current_plan.read().await.clone(),
+            false => None,
+        };
+        let reviewed = self
+            .plan_control
+            .review_or_run(&self.config.plan_review, current.as_ref(), plan, modifies_current)
+            .await;
+        match reviewed {
+            Some((plan, modifies_current)) => self.run_generated_plan(plan, modifies_current).await,
+            None => {
+                self.broadcast_status().await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Run the plan under review with the user's edits, if it still validates
+    async fn approve_plan(&self, plan: Plan) -> Result<()> {
+        let (plan, modifies_current) = self.plan_control.approve_plan(plan).await?;
+        self.run_generated_plan(plan, modifies_current).await
+    }
+
+    async fn run_generated_plan(&self, plan: Plan, modifies_current: bool) -> Result<()> {
+        if modifies_current {
+            self.modify_plan(plan).await
+        } else {
+            self.start_plan(plan).await
+        }
+    }
+
     /// Execute a task using the full agentic loop from Section XV of the spec
     /// 
     /// This implements the complete cycle:
@@ -623,6 +839,7 @@ impl AgenticPlanningCoordinator {
     /// 6. Update State and Loop
     async fn execute_task_with_full_agentic_loop(&self, task: Task) -> Result<()> {
         let task_start_time = Instant::now();
+        let spend_before = self.plan_control.spend_so_far();
         
         tracing::info!("Starting agentic loop for task: {} ({})", task.description, task.id);
         
@@ -634,17 +851,27 @@ impl AgenticPlanningCoordinator {
         let refinement_context = self.assemble_task_refinement_context(&task).await?;
         
         // Check if this is an abstract task that needs decomposition
-        if self.config.auto_decompose_abstract_tasks && self.is_abstract_task(&task) {
-            return self.decompose_and_queue_subtasks(task, &refinement_context).await;
+        if self.config.auto_decompose_abstract_tasks && self.can_decompose(&task).await {
+            match self.decompose_and_queue_subtasks(task.clone(), &refinement_context).await {
+                Ok(()) => return Ok(()),
+                Err(e) if e.is_cancelled() => return Err(e),
+                Err(e) => tracing::warn!("Could not decompose task {}, executing it directly: {}", task.id, e),
+            }
         }
         
-        // STEP 3: LLM Pre-Execution Refinement
-        self.update_execution_state(ExecutionState::TaskRefinement).await;
-        let concrete_instruction = self.refine_task_for_execution(&task, &refinement_context).await?;
-        
-        // STEP 4: Execute Tool
-        self.update_execution_state(ExecutionState::TaskExecution).await;
-        let raw_execution_result = self.execute_primitive_task(&task, &concrete_instruction).await?;
+        let raw_execution_result = if self.task_executor.read().await.execution_mode() == ExecutionMode::ToolCalling {
+            // STEPS 3-4: the model picks concrete tool calls itself, so there is no separate refinement
+            self.update_execution_state(ExecutionState::TaskExecution).await;
+            self.execute_task_with_tool_calls(&task, &refinement_context).await?
+        } else {
+            // STEP 3: LLM Pre-Execution Refinement
+            self.update_execution_state(ExecutionState::TaskRefinement).await;
+            let concrete_instruction = self.refine_task_for_execution(&task, &refinement_context).await?;
+            
+            // STEP 4: Execute Tool
+            self.update_execution_state(ExecutionState::TaskExecution).await;
+            self.execute_primitive_task(&task, &concrete_instruction).await?
+        };
         
         // STEP 5: LLM Post-Execution Analysis
         self.update_execution_state(ExecutionState::ResultAnalysis).await;
@@ -692,6 +919,8 @@ impl AgenticPlanningCoordinator {
         // Queue any dependent tasks that are now ready
         self.queue_newly_ready_tasks().await?;
         
+        self.plan_control.record_task_cost(spend_before).await;
+        
         // Update metrics
         {
             let mut metrics = self.metrics.write().await;
@@ -737,11 +966,56 @@ impl AgenticPlanningCoordinator {
             execution_future
         ).await;
         
+        self.timed_execution_result(result)
+    }
+
+    /// Execute a task by letting the model drive executor primitives through tool calls
+    async fn execute_task_with_tool_calls(
+        &self,
+        task: &Task,
+        refinement_context: &TaskRefinementContext,
+    ) -> Result<TaskExecutionResult> {
+        tracing::debug!("Executing task {} through tool calls", task.id);
+        
+        let mut context_parts = vec![
+            format!("Plan: {}", refinement_context.plan_description),
+            format!("Global Context:\n{}", refinement_context.global_context),
+            format!("Plan Context:\n{}", refinement_context.plan_context),
+        ];
+        for (dep_id, output) in &refinement_context.dependency_outputs {
+            context_parts.push(format!(
+                "Dependency Output ({}): {}",
+                dep_id,
+                serde_json::to_string_pretty(output).unwrap_or_default()
+            ));
+        }
+        let context_str = context_parts.join("\n\n");
+        
+        let execution_future = async {
+            self.task_executor.write().await.execute_task_with_tools(task, &context_str).await
+        };
+        
+        let result = tokio::time::timeout(
+            std::time::Duration::from_millis(self.config.task_timeout_ms),
+            execution_future
+        ).await;
+        
+        self.timed_execution_result(result)
+    }
+
+    /// Turn a timed executor call into a result, reporting errors and timeouts as failed executions
+    ///
+    /// Cancellations are passed on as errors so the task is not recorded as failed.
+    fn timed_execution_result(
+        &self,
+        result: std::result::Result<Result<TaskExecutionResult>, tokio::time::error::Elapsed>,
+    ) -> Result<TaskExecutionResult> {
         match result {
             Ok(task_result) => {
                 // task_result is Result<TaskExecutionResult>, so we need to handle it
                 match task_result {
                     Ok(execution_result) => Ok(execution_result),
+                    Err(e) if e.is_cancelled() => Err(e),
                     Err(e) => {
                         tracing::error!("Task execution failed: {}", e);
                         Ok(TaskExecutionResult {
@@ -786,6 +1060,9 @@ impl AgenticPlanningCoordinator {
         let execution_state = self.execution_state.read().await.clone();
         let current_task = self.get_current_task_info().await;
         let metrics = self.get_current_metrics().await;
+        let budget_alert = self.plan_control.budget_hold().await;
+        let pending_review = self.plan_control.pending_review().await;
+        let pending_review_diff = self.plan_control.pending_review_diff().await;
         
         CoordinatorStatus {
             current_plan,
@@ -794,6 +1071,9 @@ impl AgenticPlanningCoordinator {
             execution_state,
             current_task,
             performance_metrics: metrics,
+            budget_alert,
+            pending_review,
+            pending_review_diff,
         }
     }
     
@@ -802,10 +1082,15 @@ impl AgenticPlanningCoordinator {
         let context_manager = self.context_manager.read().await;
         let context = context_manager.get_global_context_summary().await?;
         
-        let plan = self.llm_provider
-            .generate_plan(prompt, &context, &self.current_model)
-            .await
-            .map_err(KaiError::from)?;
+        let plan = generate_plan_observed(
+            self.llm_provider.as_ref(),
+            prompt,
+            &context,
+            &self.current_model,
+            self.output_forwarder(StreamStage::Planning),
+        )
+        .await
+        .map_err(KaiError::from)?;
             
         // Increment LLM call counter
         {
@@ -869,6 +1154,15 @@ impl AgenticPlanningCoordinator {
         queue.pop_front()
     }
     
+    /// Put a task back and pause the plan until the user approves the extra spend
+    async fn hold_for_budget(&self, task: Task, exceeded: BudgetExceeded) -> Result<()> {
+        self.main_task_queue.write().await.push_front(task);
+        self.plan_control.hold_for_budget(exceeded).await;
+        self.pause_plan().await?;
+        self.broadcast_status().await;
+        Ok(())
+    }
+    
     /// Update execution state and notify
     async fn update_execution_state(&self, new_state: ExecutionState) {
         let mut state = self.execution_state.write().await;
@@ -934,9 +1228,25 @@ impl AgenticPlanningCoordinator {
             global_context
         };
         
-        let plan = self.llm_provider
-            .generate_plan(&prompt.content, &full_context, &self.current_model)
-            .await?;
+        let plan = if prompt.attachments.is_empty() {
+            generate_plan_observed(
+                self.llm_provider.as_ref(),
+                &prompt.content,
+                &full_context,
+                &self.current_model,
+                self.output_forwarder(StreamStage::Planning),
+            )
+            .await?
+        } else {
+            generate_plan_with_attachments(
+                self.llm_provider.as_ref(),
+                &prompt.content,
+                &full_context,
+                &prompt.attachments,
+                &self.current_model,
+            )
+            .await?
+        };
             
         {
             let mut metrics = self.metrics.write().await;
@@ -951,23 +1261,16 @@ impl AgenticPlanningCoordinator {
         let context_manager = self.context_manager.read().await;
         let global_context = context_manager.get_global_context_summary().await?;
         
-        let plan_summary = format!(
-            "Current Plan: {}\nCompleted Tasks: {}\nRemaining Tasks: {}\nNew Request: {}",
-            current_plan.description,
-            current_plan.tasks.iter().filter(|t| t.status == TaskStatus::Completed).count(),
-            current_plan.tasks.iter().filter(|t| t.status != TaskStatus::Completed).count(),
-            prompt.content
-        );
-        
-        let modification_prompt = format!(
-            "Modify the existing plan to incorporate the new user request. \
-            Preserve completed work where possible.\n\n{}",
-            plan_summary
-        );
+        let modification_prompt = revision_prompt(current_plan, &prompt.content);
         
-        let modified_plan = self.llm_provider
-            .generate_plan(&modification_prompt, &global_context, &self.current_model)
-            .await?;
+        let modified_plan = generate_plan_observed(
+            self.llm_provider.as_ref(),
+            &modification_prompt,
+            &global_context,
+            &self.current_model,
+            self.output_forwarder(StreamStage::Planning),
+        )
+        .await?;
             
         {
             let mut metrics = self.metrics.write().await;
@@ -1017,9 +1320,14 @@ impl AgenticPlanningCoordinator {
     
     /// Use LLM to refine task into concrete execution instruction
     async fn refine_task_for_execution(&self, task: &Task, context: &TaskRefinementContext) -> Result<String> {
-        let instruction = self.llm_provider
-            .refine_task_for_execution(task, context, &self.current_model)
-            .await?;
+        let instruction = refine_task_observed(
+            self.llm_provider.as_ref(),
+            task,
+            context,
+            &self.current_model,
+            self.output_forwarder(StreamStage::Refinement),
+        )
+        .await?;
             
         {
             let mut metrics = self.metrics.write().await;
@@ -1031,9 +1339,15 @@ impl AgenticPlanningCoordinator {
     
     /// Use LLM to analyze task execution results
     async fn analyze_task_execution_result(&self, task: &Task, result: &TaskExecutionResult) -> Result<TaskAnalysis> {
-        let analysis = self.llm_provider
-            .analyze_task_result(task, result, &task.description, &self.current_model)
-            .await?;
+        let analysis = analyze_task_result_observed(
+            self.llm_provider.as_ref(),
+            task,
+            result,
+            &task.description,
+            &self.current_model,
+            self.output_forwarder(StreamStage::Analysis),
+        )
+        .await?;
             
         {
             let mut metrics = self.metrics.write().await;
@@ -1043,57 +1357,61 @@ impl AgenticPlanningCoordinator {
         Ok(analysis)
     }
     
-    /// Check if a task is abstract and needs decomposition
-    fn is_abstract_task(&self, task: &Task) -> bool {
-        // Simple heuristics for detecting abstract tasks
-        // TODO: Make this more sophisticated with LLM analysis
-        let abstract_keywords = [
-            "refactor", "improve", "enhance", "optimize", "redesign",
-            "implement", "create", "build", "develop", "design"
-        ];
-        
-        let description_lower = task.description.to_lowercase();
-        abstract_keywords.iter().any(|keyword| description_lower.contains(keyword))
-            && task.parameters.is_empty() // Abstract tasks usually lack specific parameters
+    /// Build a callback that broadcasts streamed LLM output for the given stage
+    fn output_forwarder(&self, stage: StreamStage) -> impl FnMut(&str) + Send + 'static {
+        let sender = self.output_broadcaster.clone();
+        move |content| {
+            // Nobody listening is fine; output is only for display
+            let _ = sender.send(StreamOutput {
+                stage,
+                content: content.to_string(),
+            });
+        }
     }
     
-    /// Decompose an abstract task into concrete subtasks
+    /// Limits on how far tasks are decomposed
+    fn sub_plan_limits(&self) -> SubPlanLimits {
+        SubPlanLimits {
+            max_depth: self.config.max_decomposition_depth,
+            max_fan_out: self.config.max_subtasks_per_task,
+        }
+    }
+    
+    /// Whether a task is abstract and its sub-tasks would stay within the depth limit
+    async fn can_decompose(&self, task: &Task) -> bool {
+        match &*self.current_plan.read().await {
+            Some(plan) => should_decompose(plan, task, &self.sub_plan_limits()),
+            None => false,
+        }
+    }
+    
+    /// Decompose an abstract task into a sub-plan of concrete tasks
+    ///
+    /// The sub-tasks are added to the current plan under the task, which then
+    /// completes through them instead of running itself.
     async fn decompose_and_queue_subtasks(&self, task: Task, context: &TaskRefinementContext) -> Result<()> {
-        tracing::info!("Decomposing abstract task: {}", task.description);
-        
-        // Use LLM to decompose the task
-        let decomposition_prompt = format!(
-            "Decompose this high-level task into concrete, executable subtasks:\n\n\
-            Task: {}\nTask Type: {:?}\nParameters: {}\n\n\
-            Context: {}\n\n\
-            Return a JSON array of subtasks with fields: id, description, task_type, parameters, dependencies",
-            task.description,
-            task.task_type,
-            serde_json::to_string_pretty(&task.parameters).unwrap_or_default(),
-            context.global_context
-        );
-        
-        let subtasks_json = self.llm_provider
-            .generate_content(&decomposition_prompt, &context.plan_context, &self.current_model, None)
+        let decomposition_context = format!("{}\n\n{}", context.global_context, context.plan_context);
+        self.plan_control
+            .decompose_task(
+                self.llm_provider.as_ref(),
+                &self.current_model,
+                &self.current_plan,
+                &task,
+                &self.sub_plan_limits(),
+                &decomposition_context,
+                self.output_forwarder(StreamStage::Planning),
+            )
             .await?;
-        
-        // Parse subtasks (simplified - in production, use structured output)
-        // TODO: Implement proper JSON parsing with error handling
-        tracing::info!("Task decomposition result: {}", subtasks_json);
-        
-        // For now, mark the original task as completed and log the decomposition
-        self.update_task_status_in_plan(&task.id, TaskStatus::Completed).await?;
+        // A decomposed task only completes through its sub-tasks
+        self.main_task_queue.write().await.retain(|queued| queued.id != task.id);
         
         {
             let mut metrics = self.metrics.write().await;
-            metrics.decompositions_performed += 1;
             metrics.llm_calls_made += 1;
+            metrics.decompositions_performed += 1;
         }
         
-        // TODO: Parse subtasks and add them to the plan and queue
-        tracing::warn!("Task decomposition parsing not fully implemented yet");
-        
-        Ok(())
+        self.queue_newly_ready_tasks().await
     }
     
     /// Update task status in the current plan
@@ -1158,25 +1476,31 @@ impl AgenticPlanningCoordinator {
     }
     
     /// Modify the current plan (preserving completed work)
+    ///
+    /// `new_plan` is merged into the current plan with `merge_plans`, so
+    /// completed tasks keep their results and the plan context keeps their
+    /// outputs.
     async fn modify_plan(&self, new_plan: Plan) -> Result<()> {
         tracing::info!("Modifying current plan: {}", new_plan.description);
         
-        // TODO: Implement intelligent plan merging that preserves completed tasks
-        // For now, just replace the plan
-        
-        let mut current_plan = self.current_plan.write().await;
-        *current_plan = Some(new_plan.clone());
-        
-        // Clear and repopulate task queue with new tasks
-        let mut task_queue = self.main_task_queue.write().await;
-        task_queue.clear();
-        
-        for task in &new_plan.tasks {
-            if task.status == TaskStatus::Pending {
-                task_queue.push_back(task.clone());
-            }
+        {
+            let mut current_plan = self.current_plan.write().await;
+            let merged = match current_plan.as_ref() {
+                Some(current) => merge_revision(current, &new_plan)?,
+                None => {
+                    new_plan.ensure_valid()?;
+                    new_plan
+                }
+            };
+            *current_plan = Some(merged);
         }
         
+        // Requeue whatever is ready in the merged plan
+        self.main_task_queue.write().await.clear();
+        self.queue_newly_ready_tasks().await?;
+        // A plan paused for an interrupt continues with the change
+        self.update_execution_state(ExecutionState::Idle).await;
+        
         Ok(())
     }
 }
@@ -1205,4 +1529,4 @@ impl PlanManager {
     pub fn get_sender(&self) -> mpsc::UnboundedSender<PlanManagerMessage> {
         self.get_message_sender()
     }
-}
\ No newline at end of file
+}
@@ -5,7 +5,18 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+pub mod control;
+pub mod diff;
+pub mod hierarchy;
 pub mod manager;
+pub mod review;
+pub mod store;
+pub mod validation;
+
+pub use diff::PlanDiff;
+pub use hierarchy::{SubPlanLimits, TaskProgress, TaskTreeNode};
+pub use review::{PlanEdit, PlanReviewPolicy};
+pub use validation::PlanDiagnostic;
 
 /// Represents a complete execution plan
 /// 
@@ -103,6 +114,9 @@ pub struct Task {
     pub parameters: HashMap<String, serde_json::Value>,
     /// List of task IDs that must complete before this task can run
     pub dependencies: Vec<String>,
+    /// Task this one was decomposed from, if it is part of a sub-plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
     /// Current status of the task
     pub status: TaskStatus,
     /// Result of task execution (if completed)
@@ -150,6 +164,8 @@ pub enum TaskStatus {
     Failed,
     /// Task was skipped
     Skipped,
+    /// Task was cancelled before it finished
+    Cancelled,
 }
 
 /// Result of task execution
@@ -189,11 +205,14 @@ impl Plan {
     }
 
     /// Get tasks that are ready to execute (all dependencies satisfied)
+    ///
+    /// Tasks with sub-tasks never run themselves; they complete through `roll_up`.
     pub fn get_ready_tasks(&self) -> Vec<&Task> {
         self.tasks
             .iter()
             .filter(|task| {
                 task.status == TaskStatus::Pending
+                    && !self.has_subtasks(&task.id)
                     && task
                         .dependencies
                         .iter()
@@ -223,7 +242,8 @@ impl Plan {
         task.updated_at = chrono::Utc::now();
         self.updated_at = chrono::Utc::now();
 
-        // Update plan status based on task statuses
+        // Parents follow their sub-tasks, then the plan follows its tasks
+        self.roll_up(task_id);
         self.update_plan_status();
 
         Ok(())
@@ -247,6 +267,7 @@ impl Plan {
         self.updated_at = chrono::Utc::now();
 
         // Update plan status
+        self.roll_up(task_id);
         self.update_plan_status();
 
         Ok(())
@@ -254,7 +275,8 @@ impl Plan {
 
     /// Update the plan status based on task statuses
     fn update_plan_status(&mut self) {
-        if self.tasks.is_empty() {
+        // A cancelled plan stays cancelled while its last tasks wind down
+        if self.tasks.is_empty() || self.status == PlanStatus::Cancelled {
             return;
         }
 
@@ -272,6 +294,8 @@ impl Plan {
     }
 
     /// Create a plan from JSON representation
+    ///
+    /// Plans written by `to_json` keep their id, status, task progress and timestamps.
     pub fn from_json(json: &serde_json::Value) -> Result<Self, KaiError> {
         let description = json["description"]
             .as_str()
@@ -289,9 +313,48 @@ impl Plan {
             plan.add_task(task);
         }
 
+        if let Some(id) = saved_field(json, "id") {
+            plan.id = id;
+        }
+        if let Some(status) = saved_field(json, "status") {
+            plan.status = status;
+        }
+        for (task, task_json) in plan.tasks.iter_mut().zip(tasks_json) {
+            if let Some(updated_at) = saved_field(task_json, "updated_at") {
+                task.updated_at = updated_at;
+            }
+        }
+        if let Some(created_at) = saved_field(json, "created_at") {
+            plan.created_at = created_at;
+        }
+        if let Some(updated_at) = saved_field(json, "updated_at") {
+            plan.updated_at = updated_at;
+        }
+
         Ok(plan)
     }
 
+    /// Check the plan for problems that would stop it from executing
+    ///
+    /// Returns an empty list for a plan that can run; see `validation` for the checks.
+    pub fn validate(&self) -> Vec<PlanDiagnostic> {
+        validation::validate_plan(self)
+    }
+
+    /// Fail with every problem `validate` finds
+    pub fn ensure_valid(&self) -> Result<(), KaiError> {
+        let diagnostics = self.validate();
+        if diagnostics.is_empty() {
+            return Ok(());
+        }
+        let problems: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+        Err(KaiError::planning(format!(
+            "Plan {} cannot be executed: {}",
+            self.id,
+            problems.join("; ")
+        )))
+    }
+
     /// Convert plan to JSON
     pub fn to_json(&self) -> Result<serde_json::Value, KaiError> {
         Ok(serde_json::to_value(self)?)
@@ -308,6 +371,7 @@ impl Task {
             task_type,
             parameters: HashMap::new(),
             dependencies: Vec::new(),
+            parent_id: None,
             status: TaskStatus::Pending,
             result: None,
             created_at: now,
@@ -382,11 +446,29 @@ impl Task {
         let mut task = Task::new(id, description, task_type);
         task.parameters = parameters;
         task.dependencies = dependencies;
+        task.parent_id = saved_field(json, "parent_id");
+
+        if let Some(status) = saved_field(json, "status") {
+            task.status = status;
+        }
+        task.result = saved_field(json, "result");
+        if let Some(created_at) = saved_field(json, "created_at") {
+            task.created_at = created_at;
+        }
+        if let Some(updated_at) = saved_field(json, "updated_at") {
+            task.updated_at = updated_at;
+        }
 
         Ok(task)
     }
 }
 
+/// Read a field written by `to_json`, ignoring it when missing or malformed
+fn saved_field<T: serde::de::DeserializeOwned>(json: &serde_json::Value, field: &str) -> Option<T> {
+    json.get(field)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+}
+
 impl TaskResult {
     /// Create a successful task result
     pub fn success(output: Option<serde_json::Value>, execution_time_ms: u64) -> Self {
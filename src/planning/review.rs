@@ -0,0 +1,187 @@
+//! Reviewing and editing a plan before it runs
+//!
+//! Depending on `PlanReviewPolicy`, a generated plan is held for the user
+//! instead of being executed straight away. The console chat and the terminal
+//! UI both change the held plan through `PlanEdit`s, and the edited plan is
+//! checked with `Plan::validate` again before it is approved.
+
+use super::{Plan, Task, TaskStatus, TaskType};
+use crate::utils::errors::KaiError;
+use serde::{Deserialize, Serialize};
+
+/// When generated plans are shown to the user for approval before they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanReviewPolicy {
+    /// Every plan waits for approval
+    Always,
+    /// Plans run as soon as they are generated
+    Never,
+    /// Only plans that write files, delete files or run commands wait for approval
+    #[default]
+    Destructive,
+}
+
+impl PlanReviewPolicy {
+    /// Whether `plan` has to be approved before it runs
+    pub fn requires_review(&self, plan: &Plan) -> bool {
+        match self {
+            PlanReviewPolicy::Always => true,
+            PlanReviewPolicy::Never => false,
+            PlanReviewPolicy::Destructive => plan
+                .tasks
+                .iter()
+                .any(|task| task.status == TaskStatus::Pending && is_destructive(&task.task_type)),
+        }
+    }
+}
+
+/// Whether a task of this type can change or remove files outside of KAI-X's control
+pub fn is_destructive(task_type: &TaskType) -> bool {
+    matches!(task_type, TaskType::WriteFile | TaskType::ExecuteCommand | TaskType::Delete)
+}
+
+/// A change the user makes to a plan under review
+#[derive(Debug, Clone)]
+pub enum PlanEdit {
+    /// Drop a task; other tasks stop depending on it
+    RemoveTask { task_id: String },
+    /// Set or replace a task parameter
+    SetParameter {
+        task_id: String,
+        name: String,
+        value: serde_json::Value,
+    },
+    /// Move a task to another position in the task list
+    MoveTask { task_id: String, position: usize },
+    /// Insert a task written by the user, at the end if no position is given
+    AddTask { task: Box<Task>, position: Option<usize> },
+}
+
+impl PlanEdit {
+    /// Apply the edit to `plan`
+    pub fn apply(self, plan: &mut Plan) -> Result<(), KaiError> {
+        match self {
+            PlanEdit::RemoveTask { task_id } => {
+                let index = task_index(plan, &task_id)?;
+                plan.tasks.remove(index);
+                for task in &mut plan.tasks {
+                    task.dependencies.retain(|dependency| *dependency != task_id);
+                }
+            }
+            PlanEdit::SetParameter { task_id, name, value } => {
+                if name.trim().is_empty() {
+                    return Err(KaiError::validation("parameter", "Parameter name must not be empty"));
+                }
+                let index = task_index(plan, &task_id)?;
+                let task = &mut plan.tasks[index];
+                task.parameters.insert(name.trim().to_string(), value);
+                task.updated_at = chrono::Utc::now();
+            }
+            PlanEdit::MoveTask { task_id, position } => {
+                let index = task_index(plan, &task_id)?;
+                let task = plan.tasks.remove(index);
+                let position = position.min(plan.tasks.len());
+                plan.tasks.insert(position, task);
+            }
+            PlanEdit::AddTask { task, position } => {
+                let position = position.unwrap_or(plan.tasks.len()).min(plan.tasks.len());
+                plan.tasks.insert(position, *task);
+            }
+        }
+        plan.updated_at = chrono::Utc::now();
+        Ok(())
+    }
+}
+
+/// An id for a task added by hand that no task in `plan` uses yet
+pub fn manual_task_id(plan: &Plan) -> String {
+    (1..)
+        .map(|n| format!("manual-{}", n))
+        .find(|id| plan.tasks.iter().all(|task| task.id != *id))
+        .unwrap_or_default()
+}
+
+/// Read a parameter value typed by the user
+///
+/// Valid JSON (numbers, booleans, arrays, quoted strings) is kept as such;
+/// anything else is taken as a plain string, so paths and commands need no quoting.
+pub fn parse_parameter_value(input: &str) -> serde_json::Value {
+    let input = input.trim();
+    serde_json::from_str(input).unwrap_or_else(|_| serde_json::Value::String(input.to_string()))
+}
+
+fn task_index(plan: &Plan, task_id: &str) -> Result<usize, KaiError> {
+    plan.tasks
+        .iter()
+        .position(|task| task.id == task_id)
+        .ok_or_else(|| KaiError::not_found(format!("task '{}'", task_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_plan() -> Plan {
+        let mut plan = Plan::new("Update the changelog");
+        plan.add_task(Task::new("read", "Read CHANGELOG.md", TaskType::ReadFile).with_parameter("path", "CHANGELOG.md"));
+        plan.add_task(
+            Task::new("write", "Add release notes", TaskType::WriteFile)
+                .with_parameter("path", "CHANGELOG.md")
+                .with_dependency("read"),
+        );
+        plan
+    }
+
+    #[test]
+    fn test_review_policy() {
+        let plan = sample_plan();
+        assert!(PlanReviewPolicy::Always.requires_review(&plan));
+        assert!(!PlanReviewPolicy::Never.requires_review(&plan));
+        assert!(PlanReviewPolicy::Destructive.requires_review(&plan));
+
+        let mut read_only = Plan::new("Look around");
+        read_only.add_task(Task::new("list", "List files", TaskType::ListFiles));
+        assert!(!PlanReviewPolicy::Destructive.requires_review(&read_only));
+        assert!(PlanReviewPolicy::Always.requires_review(&read_only));
+    }
+
+    #[test]
+    fn test_edits_keep_the_plan_valid() {
+        let mut plan = sample_plan();
+
+        let id = manual_task_id(&plan);
+        assert_eq!(id, "manual-1");
+        PlanEdit::AddTask {
+            task: Box::new(Task::new(id.clone(), "Run the tests".to_string(), TaskType::ExecuteCommand)),
+            position: Some(0),
+        }
+        .apply(&mut plan)
+        .unwrap();
+        PlanEdit::SetParameter {
+            task_id: id.clone(),
+            name: "command".to_string(),
+            value: parse_parameter_value("cargo test"),
+        }
+        .apply(&mut plan)
+        .unwrap();
+        PlanEdit::MoveTask { task_id: id.clone(), position: 10 }.apply(&mut plan).unwrap();
+        PlanEdit::RemoveTask { task_id: "read".to_string() }.apply(&mut plan).unwrap();
+
+        let ids: Vec<&str> = plan.tasks.iter().map(|task| task.id.as_str()).collect();
+        assert_eq!(ids, vec!["write", "manual-1"]);
+        assert!(plan.tasks[0].dependencies.is_empty());
+        assert_eq!(plan.tasks[1].parameters["command"], "cargo test");
+        assert!(plan.validate().is_empty());
+
+        let missing = PlanEdit::RemoveTask { task_id: "read".to_string() }.apply(&mut plan);
+        assert!(matches!(missing, Err(KaiError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_parameter_values() {
+        assert_eq!(parse_parameter_value(" src/main.rs "), serde_json::json!("src/main.rs"));
+        assert_eq!(parse_parameter_value("42"), serde_json::json!(42));
+        assert_eq!(parse_parameter_value("[\"a\", \"b\"]"), serde_json::json!(["a", "b"]));
+    }
+}
@@ -0,0 +1,243 @@
+//! Per-project store of plans and their progress
+//!
+//! Every plan the execution engine runs is written to `.kai/plans/<plan-id>.json`
+//! in the working directory after each state change: the plan with its task
+//! statuses and results (via `Plan::to_json`), and the `PlanContext` holding
+//! variables and outputs. A session that dies halfway through a plan can be
+//! picked up again with `kai resume <plan-id>`.
+
+use super::{Plan, PlanStatus, TaskStatus};
+use crate::context::PlanContext;
+use crate::utils::errors::KaiError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Directory for saved plans inside a working directory
+pub const PROJECT_PLANS_DIR: &str = ".kai/plans";
+
+/// A plan as saved on disk, with the context it was executing in
+#[derive(Debug, Clone)]
+pub struct SavedPlan {
+    pub plan: Plan,
+    /// Variables and outputs gathered so far; `None` if execution had not started
+    pub context: Option<PlanContext>,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// One line of `kai plans list`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanSummary {
+    pub id: String,
+    pub description: String,
+    pub status: PlanStatus,
+    pub completed_tasks: usize,
+    pub total_tasks: usize,
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlanFile {
+    plan: serde_json::Value,
+    #[serde(default)]
+    context: Option<PlanContext>,
+    saved_at: DateTime<Utc>,
+}
+
+/// Directory of saved plans, one JSON file per plan
+#[derive(Debug, Clone)]
+pub struct PlanStore {
+    dir: PathBuf,
+}
+
+impl PlanStore {
+    /// Open a store in the given directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store for the plans of the project in `working_dir`
+    pub fn for_project(working_dir: &Path) -> Self {
+        Self::new(working_dir.join(PROJECT_PLANS_DIR))
+    }
+
+    /// Directory holding the plans
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn plan_path(&self, plan_id: &str) -> PathBuf {
+        let name: String = plan_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Write a plan and its context, replacing the previous save
+    pub fn save(&self, plan: &Plan, context: Option<&PlanContext>) -> Result<(), KaiError> {
+        let file = PlanFile {
+            plan: plan.to_json()?,
+            context: context.cloned(),
+            saved_at: Utc::now(),
+        };
+        let content = serde_json::to_string_pretty(&file)?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| KaiError::file_system(&self.dir, e))?;
+
+        // Write then rename so a crash mid-write leaves the previous save intact
+        let path = self.plan_path(&plan.id);
+        let temp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_path);
+                KaiError::file_system(&path, e)
+            })
+    }
+
+    /// Load a plan by its id or an unambiguous prefix of it
+    pub fn load(&self, plan_id: &str) -> Result<SavedPlan, KaiError> {
+        let path = self.plan_path(plan_id);
+        if path.exists() {
+            return Self::read(&path);
+        }
+
+        let matches: Vec<PathBuf> = self
+            .plan_files()?
+            .into_iter()
+            .filter(|path| {
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| stem.starts_with(plan_id))
+            })
+            .collect();
+        match matches.as_slice() {
+            [path] => Self::read(path),
+            [] => Err(KaiError::not_found(format!("saved plan '{}'", plan_id))),
+            _ => Err(KaiError::validation(
+                "plan_id",
+                format!("'{}' matches {} saved plans; use more of the id", plan_id, matches.len()),
+            )),
+        }
+    }
+
+    /// Summaries of all saved plans, most recently saved first
+    ///
+    /// Files that cannot be read are skipped with a warning.
+    pub fn list(&self) -> Result<Vec<PlanSummary>, KaiError> {
+        let mut summaries: Vec<PlanSummary> = self
+            .plan_files()?
+            .iter()
+            .filter_map(|path| match Self::read(path) {
+                Ok(saved) => Some(PlanSummary {
+                    id: saved.plan.id.clone(),
+                    description: saved.plan.description.clone(),
+                    status: saved.plan.status.clone(),
+                    completed_tasks: saved
+                        .plan
+                        .tasks
+                        .iter()
+                        .filter(|task| task.status == TaskStatus::Completed)
+                        .count(),
+                    total_tasks: saved.plan.tasks.len(),
+                    saved_at: saved.saved_at,
+                }),
+                Err(e) => {
+                    warn!("Skipping unreadable saved plan {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.saved_at));
+        Ok(summaries)
+    }
+
+    fn plan_files(&self) -> Result<Vec<PathBuf>, KaiError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(KaiError::file_system(&self.dir, e)),
+        };
+        Ok(entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect())
+    }
+
+    fn read(path: &Path) -> Result<SavedPlan, KaiError> {
+        let content = std::fs::read_to_string(path).map_err(|e| KaiError::file_system(path, e))?;
+        let file: PlanFile = serde_json::from_str(&content)?;
+        Ok(SavedPlan {
+            plan: Plan::from_json(&file.plan)?,
+            context: file.context,
+            saved_at: file.saved_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::{Task, TaskResult, TaskType};
+
+    fn sample_plan() -> Plan {
+        let mut plan = Plan::new("Refactor the parser");
+        plan.add_task(Task::new("read", "Read parser.rs", TaskType::ReadFile).with_parameter("path", "src/parser.rs"));
+        plan.add_task(Task::new("rewrite", "Rewrite parser.rs", TaskType::WriteFile).with_dependency("read"));
+        plan
+    }
+
+    #[test]
+    fn test_saved_plan_keeps_progress_and_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PlanStore::for_project(dir.path());
+
+        let mut plan = sample_plan();
+        let result = TaskResult::success(Some(serde_json::json!("fn parse() {}")), 12);
+        plan.set_task_result("read", result.clone()).unwrap();
+        let mut context = PlanContext::new(plan.id.clone());
+        context.add_task_result("read".to_string(), "Read parser.rs".to_string(), result);
+        context.variables.insert("entry_point".to_string(), serde_json::json!("parse"));
+
+        store.save(&plan, Some(&context)).unwrap();
+        assert!(dir.path().join(PROJECT_PLANS_DIR).join(format!("{}.json", plan.id)).exists());
+
+        let saved = store.load(&plan.id).unwrap();
+        assert_eq!(saved.plan.id, plan.id);
+        assert_eq!(saved.plan.status, plan.status);
+        assert_eq!(saved.plan.tasks[0].status, TaskStatus::Completed);
+        assert_eq!(saved.plan.tasks[0].result.as_ref().unwrap().execution_time_ms, 12);
+        assert_eq!(saved.plan.tasks[1].status, TaskStatus::Pending);
+        assert_eq!(saved.plan.tasks[1].dependencies, vec!["read".to_string()]);
+
+        let context = saved.context.unwrap();
+        assert_eq!(context.variables["entry_point"], "parse");
+        assert!(context.get_task_result("read").is_some());
+    }
+
+    #[test]
+    fn test_load_by_prefix_and_list_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PlanStore::new(dir.path());
+        assert!(store.list().unwrap().is_empty());
+
+        let mut first = sample_plan();
+        first.id = "aaaa-1111".to_string();
+        let mut second = sample_plan();
+        second.id = "aaaa-2222".to_string();
+        second.set_task_result("read", TaskResult::success(None, 1)).unwrap();
+        store.save(&first, None).unwrap();
+        store.save(&second, None).unwrap();
+        std::fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+        assert_eq!(store.load("aaaa-2").unwrap().plan.id, "aaaa-2222");
+        assert!(matches!(store.load("aaaa"), Err(KaiError::Validation { .. })));
+        assert!(matches!(store.load("bbbb"), Err(KaiError::NotFound { .. })));
+
+        let summaries = store.list().unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].id, "aaaa-2222");
+        assert_eq!((summaries[0].completed_tasks, summaries[0].total_tasks), (1, 2));
+    }
+}
@@ -0,0 +1,284 @@
+//! Static checks on a plan before it runs
+//!
+//! A generated plan can be well-formed JSON and still be impossible to
+//! execute: two tasks sharing an id, a dependency on a task that does not
+//! exist, tasks waiting on each other in a cycle (which leaves
+//! `TaskQueue::pop_ready_task` with nothing to hand out), a sub-task whose
+//! parent is missing, or a file task without its `path`. `Plan::validate` finds these up front and returns one
+//! `PlanDiagnostic` per problem, so they can be shown to the user or sent back
+//! to the model for a corrected plan.
+
+use super::{Plan, TaskType};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A problem that would stop a plan from executing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlanDiagnostic {
+    /// More than one task uses the same id
+    DuplicateTaskId { task_id: String },
+    /// A task depends on an id that no task in the plan has
+    UnknownDependency { task_id: String, dependency: String },
+    /// A sub-task names a parent that no task in the plan has
+    UnknownParent { task_id: String, parent: String },
+    /// Tasks depend on each other in a loop; the first id is repeated at the end
+    DependencyCycle { cycle: Vec<String> },
+    /// A task is missing a parameter its executor needs
+    MissingParameter {
+        task_id: String,
+        task_type: TaskType,
+        parameter: String,
+    },
+}
+
+impl fmt::Display for PlanDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanDiagnostic::DuplicateTaskId { task_id } => {
+                write!(f, "task id '{}' is used by more than one task; task ids must be unique", task_id)
+            }
+            PlanDiagnostic::UnknownDependency { task_id, dependency } => write!(
+                f,
+                "task '{}' depends on '{}', which is not the id of any task in the plan",
+                task_id, dependency
+            ),
+            PlanDiagnostic::UnknownParent { task_id, parent } => write!(
+                f,
+                "task '{}' is a sub-task of '{}', which is not the id of any task in the plan",
+                task_id, parent
+            ),
+            PlanDiagnostic::DependencyCycle { cycle } => {
+                write!(f, "tasks depend on each other in a cycle: {}", cycle.join(" -> "))
+            }
+            PlanDiagnostic::MissingParameter {
+                task_id,
+                task_type,
+                parameter,
+            } => write!(
+                f,
+                "task '{}' of type {} is missing the required string parameter '{}'",
+                task_id,
+                task_type_name(task_type),
+                parameter
+            ),
+        }
+    }
+}
+
+/// Parameters a task type cannot run without
+///
+/// `execute_command` and `generate_content` fall back to the task description
+/// and `list_files` defaults to the working directory, so only the file tasks
+/// have hard requirements.
+pub fn required_parameters(task_type: &TaskType) -> &'static [&'static str] {
+    match task_type {
+        TaskType::ReadFile
+        | TaskType::WriteFile
+        | TaskType::AnalyzeCode
+        | TaskType::CreateDirectory
+        | TaskType::Delete => &["path"],
+        TaskType::ExecuteCommand | TaskType::GenerateContent | TaskType::ListFiles => &[],
+    }
+}
+
+/// Check a plan and return every problem found, in task order
+pub fn validate_plan(plan: &Plan) -> Vec<PlanDiagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for task in &plan.tasks {
+        if !seen.insert(task.id.as_str()) && reported.insert(task.id.as_str()) {
+            diagnostics.push(PlanDiagnostic::DuplicateTaskId { task_id: task.id.clone() });
+        }
+    }
+
+    for task in &plan.tasks {
+        for dependency in &task.dependencies {
+            if !seen.contains(dependency.as_str()) {
+                diagnostics.push(PlanDiagnostic::UnknownDependency {
+                    task_id: task.id.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+        }
+        if let Some(parent) = task.parent_id.as_ref().filter(|parent| !seen.contains(parent.as_str())) {
+            diagnostics.push(PlanDiagnostic::UnknownParent {
+                task_id: task.id.clone(),
+                parent: parent.clone(),
+            });
+        }
+        for parameter in required_parameters(&task.task_type) {
+            let present = task
+                .parameters
+                .get(*parameter)
+                .and_then(|value| value.as_str())
+                .is_some_and(|value| !value.trim().is_empty());
+            if !present {
+                diagnostics.push(PlanDiagnostic::MissingParameter {
+                    task_id: task.id.clone(),
+                    task_type: task.task_type.clone(),
+                    parameter: parameter.to_string(),
+                });
+            }
+        }
+    }
+
+    // Unknown dependencies are already reported; leave them out of the graph.
+    // A parent waits for its sub-tasks, so it depends on each of them.
+    let mut graph: HashMap<String, Vec<String>> = HashMap::new();
+    for task in &plan.tasks {
+        graph.entry(task.id.clone()).or_default().extend(
+            task.dependencies
+                .iter()
+                .filter(|dependency| seen.contains(dependency.as_str()))
+                .cloned(),
+        );
+        if let Some(parent) = task.parent_id.as_ref().filter(|parent| seen.contains(parent.as_str())) {
+            graph.entry(parent.clone()).or_default().push(task.id.clone());
+        }
+    }
+    if let Some(cycle) = find_dependency_cycle(&graph) {
+        diagnostics.push(PlanDiagnostic::DependencyCycle { cycle });
+    }
+
+    diagnostics
+}
+
+/// Find a cycle in a graph of task id -> ids it depends on
+///
+/// Returns the ids along the cycle with the first one repeated at the end,
+/// e.g. `["a", "b", "a"]`. Tasks are visited in sorted order so the same
+/// graph always reports the same cycle.
+pub fn find_dependency_cycle(graph: &HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        InProgress,
+        Done,
+    }
+
+    fn visit<'a>(
+        node: &'a str,
+        graph: &'a HashMap<String, Vec<String>>,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        match marks.get(node) {
+            Some(Mark::Done) => return None,
+            Some(Mark::InProgress) => {
+                let start = path.iter().position(|id| *id == node).unwrap_or(0);
+                let mut cycle: Vec<String> = path[start..].iter().map(|id| id.to_string()).collect();
+                cycle.push(node.to_string());
+                return Some(cycle);
+            }
+            None => {}
+        }
+
+        marks.insert(node, Mark::InProgress);
+        path.push(node);
+        for dependency in graph.get(node).into_iter().flatten() {
+            if let Some(cycle) = visit(dependency, graph, marks, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        marks.insert(node, Mark::Done);
+        None
+    }
+
+    let mut roots: Vec<&String> = graph.keys().collect();
+    roots.sort();
+    let mut marks = HashMap::new();
+    for root in roots {
+        if let Some(cycle) = visit(root, graph, &mut marks, &mut Vec::new()) {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+/// Name of a task type as written in plans, e.g. `write_file`
+pub fn task_type_name(task_type: &TaskType) -> String {
+    serde_json::to_value(task_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", task_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::Task;
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        edges
+            .iter()
+            .map(|(id, deps)| (id.to_string(), deps.iter().map(|d| d.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_cycle_detection_ignores_shared_dependencies() {
+        // a -> b, a -> c -> b shares b but has no cycle
+        let diamond = graph(&[("a", &["b", "c"]), ("b", &[]), ("c", &["b"])]);
+        assert_eq!(find_dependency_cycle(&diamond), None);
+
+        let looped = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"]), ("d", &["a"])]);
+        assert_eq!(
+            find_dependency_cycle(&looped),
+            Some(vec!["a".to_string(), "b".to_string(), "c".to_string(), "a".to_string()])
+        );
+        assert_eq!(
+            find_dependency_cycle(&graph(&[("x", &["x"])])),
+            Some(vec!["x".to_string(), "x".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_each_problem() {
+        let mut plan = Plan::new("Broken");
+        plan.add_task(Task::new("read", "Read", TaskType::ReadFile).with_parameter("path", "src/lib.rs"));
+        plan.add_task(Task::new("read", "Read again", TaskType::ReadFile).with_parameter("path", "src/main.rs"));
+        plan.add_task(Task::new("write", "Write", TaskType::WriteFile).with_dependency("missing"));
+        plan.add_task(Task::new("first", "First", TaskType::ExecuteCommand).with_dependency("second"));
+        plan.add_task(Task::new("second", "Second", TaskType::GenerateContent).with_dependency("first"));
+
+        let diagnostics = plan.validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                PlanDiagnostic::DuplicateTaskId { task_id: "read".to_string() },
+                PlanDiagnostic::UnknownDependency {
+                    task_id: "write".to_string(),
+                    dependency: "missing".to_string(),
+                },
+                PlanDiagnostic::MissingParameter {
+                    task_id: "write".to_string(),
+                    task_type: TaskType::WriteFile,
+                    parameter: "path".to_string(),
+                },
+                PlanDiagnostic::DependencyCycle {
+                    cycle: vec!["first".to_string(), "second".to_string(), "first".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "task 'write' of type write_file is missing the required string parameter 'path'"
+        );
+    }
+
+    #[test]
+    fn test_valid_plan_has_no_diagnostics() {
+        let mut plan = Plan::new("Fine");
+        plan.add_task(Task::new("list", "List", TaskType::ListFiles));
+        plan.add_task(
+            Task::new("read", "Read", TaskType::ReadFile)
+                .with_parameter("path", "Cargo.toml")
+                .with_dependency("list"),
+        );
+        assert!(plan.validate().is_empty());
+    }
+}
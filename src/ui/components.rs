@@ -1,17 +1,23 @@
 //! UI components for rendering different parts of the interface
 
-use crate::planning::{Plan, Task, TaskStatus};
+use crate::llm::StreamStage;
+use crate::planning::review::{manual_task_id, parse_parameter_value};
+use crate::planning::validation::task_type_name;
+use crate::planning::{Plan, PlanEdit, Task, TaskStatus, TaskType};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 
 /// Chat component for displaying conversation history
 pub struct ChatComponent {
     messages: Vec<ChatMessage>,
+    /// Stage of the streamed message currently being appended to, if any
+    streaming_stage: Option<StreamStage>,
 }
 
 #[derive(Debug, Clone)]
@@ -32,10 +38,12 @@ impl ChatComponent {
     pub fn new() -> Self {
         Self {
             messages: Vec::new(),
+            streaming_stage: None,
         }
     }
 
     pub fn add_message(&mut self, role: MessageRole, content: String) {
+        self.streaming_stage = None;
         self.messages.push(ChatMessage {
             role,
             content,
@@ -43,6 +51,17 @@ impl ChatComponent {
         });
     }
 
+    /// Append streamed LLM output, continuing the current assistant message for the same stage
+    pub fn append_stream(&mut self, stage: StreamStage, content: &str) {
+        match self.messages.last_mut() {
+            Some(last) if self.streaming_stage == Some(stage) => last.content.push_str(content),
+            _ => {
+                self.add_message(MessageRole::Assistant, content.to_string());
+                self.streaming_stage = Some(stage);
+            }
+        }
+    }
+
     pub fn render(&self, f: &mut Frame, area: Rect) {
         let items: Vec<ListItem> = self.messages
             .iter()
@@ -59,8 +78,13 @@ impl ChatComponent {
                     MessageRole::System => "System: ",
                 };
 
+                // Streamed output is multi-line; render each line separately
                 let content = format!("{}{}", role_prefix, msg.content);
-                ListItem::new(Line::from(vec![Span::styled(content, style)]))
+                let lines: Vec<Line> = content
+                    .lines()
+                    .map(|line| Line::from(vec![Span::styled(line.to_string(), style)]))
+                    .collect();
+                ListItem::new(lines)
             })
             .collect();
 
@@ -73,6 +97,7 @@ impl ChatComponent {
 
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.streaming_stage = None;
     }
 
     pub fn get_messages(&self) -> &[ChatMessage] {
@@ -89,12 +114,44 @@ impl Default for ChatComponent {
 /// Plan component for displaying current plan and task status
 pub struct PlanComponent {
     current_plan: Option<Plan>,
+    /// Plan waiting for approval, shown instead of the current plan
+    review: Option<PlanReview>,
+    /// Id of the last plan approved or rejected, so a stale status does not reopen it
+    decided_plan_id: Option<String>,
+}
+
+/// A plan under review and the edits made to it so far
+struct PlanReview {
+    plan: Plan,
+    selected: usize,
+    input: Option<ReviewInput>,
+    /// Outcome of the last key press, e.g. why the plan cannot be approved yet
+    message: Option<String>,
+}
+
+/// Text being typed while reviewing a plan
+enum ReviewInput {
+    /// `name=value` for the selected task
+    Parameter(String),
+    /// `task_type: description` of a task to add after the selected one
+    NewTask(String),
+}
+
+/// What the user decided about a plan under review
+#[derive(Debug, Clone)]
+pub enum ReviewOutcome {
+    /// Run the plan as edited
+    Approve(Plan),
+    /// Discard the plan
+    Reject,
 }
 
 impl PlanComponent {
     pub fn new() -> Self {
         Self {
             current_plan: None,
+            review: None,
+            decided_plan_id: None,
         }
     }
 
@@ -102,8 +159,113 @@ impl PlanComponent {
         self.current_plan = plan;
     }
 
+    /// Show `plan` for approval, keeping the edits if it is already under review
+    pub fn start_review(&mut self, plan: Plan) {
+        let already_shown = self.review.as_ref().is_some_and(|review| review.plan.id == plan.id);
+        if already_shown || self.decided_plan_id.as_deref() == Some(plan.id.as_str()) {
+            return;
+        }
+        self.review = Some(PlanReview {
+            plan,
+            selected: 0,
+            input: None,
+            message: None,
+        });
+    }
+
+    /// Stop showing the plan under review
+    pub fn end_review(&mut self) {
+        self.review = None;
+    }
+
+    /// Whether a plan is waiting for approval
+    pub fn is_reviewing(&self) -> bool {
+        self.review.is_some()
+    }
+
+    /// Handle a key while a plan is under review
+    ///
+    /// Up/Down select a task and Shift+Up/Down move it. `d` deletes the task,
+    /// `e` sets one of its parameters and `a` adds a manual task after it.
+    /// Enter approves the plan once it validates; `r` rejects it.
+    pub fn handle_review_key(&mut self, key: KeyEvent) -> Option<ReviewOutcome> {
+        let review = self.review.as_mut()?;
+
+        if let Some(input) = &mut review.input {
+            let text = match input {
+                ReviewInput::Parameter(text) | ReviewInput::NewTask(text) => text,
+            };
+            match key.code {
+                KeyCode::Esc => review.input = None,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Enter => {
+                    if let Some(input) = review.input.take() {
+                        review.submit_input(input);
+                    }
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        review.message = None;
+        match key.code {
+            KeyCode::Up if shift => review.move_selected(-1),
+            KeyCode::Down if shift => review.move_selected(1),
+            KeyCode::Up => review.selected = review.selected.saturating_sub(1),
+            KeyCode::Down => {
+                review.selected = (review.selected + 1).min(review.plan.tasks.len().saturating_sub(1));
+            }
+            KeyCode::Char('d') => {
+                if let Some(task_id) = review.selected_task().map(|task| task.id.clone()) {
+                    review.apply(PlanEdit::RemoveTask { task_id });
+                    review.selected = review.selected.min(review.plan.tasks.len().saturating_sub(1));
+                }
+            }
+            KeyCode::Char('e') => {
+                if let Some(task) = review.selected_task() {
+                    let mut names: Vec<&String> = task.parameters.keys().collect();
+                    names.sort();
+                    let text = names
+                        .first()
+                        .map(|name| {
+                            let value = &task.parameters[name.as_str()];
+                            let value = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+                            format!("{}={}", name, value)
+                        })
+                        .unwrap_or_default();
+                    review.input = Some(ReviewInput::Parameter(text));
+                }
+            }
+            KeyCode::Char('a') => review.input = Some(ReviewInput::NewTask(String::new())),
+            KeyCode::Char('r') => {
+                self.decided_plan_id = Some(review.plan.id.clone());
+                self.review = None;
+                return Some(ReviewOutcome::Reject);
+            }
+            KeyCode::Enter => {
+                let diagnostics = review.plan.validate();
+                if diagnostics.is_empty() {
+                    let plan = review.plan.clone();
+                    self.decided_plan_id = Some(plan.id.clone());
+                    self.review = None;
+                    return Some(ReviewOutcome::Approve(plan));
+                }
+                review.message = Some(format!("Fix the plan before approving it ({} problems)", diagnostics.len()));
+            }
+            _ => {}
+        }
+        None
+    }
+
     pub fn render(&self, f: &mut Frame, area: Rect) {
-        if let Some(plan) = &self.current_plan {
+        if let Some(review) = &self.review {
+            self.render_review(f, area, review);
+        } else if let Some(plan) = &self.current_plan {
             self.render_plan(f, area, plan);
         } else {
             self.render_no_plan(f, area);
@@ -139,14 +301,16 @@ impl PlanComponent {
         f.render_widget(plan_info, chunks[0]);
 
         // Task list
-        self.render_task_list(f, chunks[1], &plan.tasks);
+        self.render_task_list(f, chunks[1], plan);
     }
 
-    fn render_task_list(&self, f: &mut Frame, area: Rect, tasks: &[Task]) {
-        let items: Vec<ListItem> = tasks
+    fn render_task_list(&self, f: &mut Frame, area: Rect, plan: &Plan) {
+        let items: Vec<ListItem> = plan
+            .task_tree()
             .iter()
             .enumerate()
-            .map(|(i, task)| {
+            .map(|(i, node)| {
+                let task = node.task;
                 let status_symbol = match task.status {
                     TaskStatus::Pending => "⏸",
                     TaskStatus::Ready => "▶",
@@ -154,6 +318,7 @@ impl PlanComponent {
                     TaskStatus::Completed => "✅",
                     TaskStatus::Failed => "❌",
                     TaskStatus::Skipped => "⏭",
+                    TaskStatus::Cancelled => "⏹",
                 };
 
                 let style = match task.status {
@@ -163,20 +328,109 @@ impl PlanComponent {
                     TaskStatus::Completed => Style::default().fg(Color::Green),
                     TaskStatus::Failed => Style::default().fg(Color::Red),
                     TaskStatus::Skipped => Style::default().fg(Color::DarkGray),
+                    TaskStatus::Cancelled => Style::default().fg(Color::DarkGray),
                 };
 
-                let content = format!("{} {}. {}", status_symbol, i + 1, task.description);
-                ListItem::new(Line::from(vec![Span::styled(content, style)]))
+                let indent = "  ".repeat(node.depth);
+                let mut spans = vec![Span::styled(
+                    format!("{}{} {}. {}", indent, status_symbol, i + 1, task.description),
+                    style,
+                )];
+                if node.has_subtasks {
+                    spans.push(Span::styled(
+                        format!(" ({})", node.progress),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
             })
             .collect();
 
         let task_list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("Tasks"))
+            .block(Block::default().borders(Borders::ALL).title(format!("Tasks {}", plan.progress())))
             .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
         f.render_widget(task_list, area);
     }
 
+    fn render_review(&self, f: &mut Frame, area: Rect, review: &PlanReview) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3), // Plan info
+                Constraint::Min(1),    // Task list
+                Constraint::Length(8), // Selected task, problems and key help
+            ])
+            .split(area);
+
+        let plan_info = Paragraph::new(vec![Line::from(vec![
+            Span::styled("Plan: ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(&review.plan.description),
+        ])])
+        .block(Block::default().borders(Borders::ALL).title("Review Plan"))
+        .wrap(Wrap { trim: true });
+        f.render_widget(plan_info, chunks[0]);
+
+        let items: Vec<ListItem> = review
+            .plan
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, task)| {
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}. {} ", i + 1, task.description)),
+                    Span::styled(
+                        format!("[{}]", task_type_name(&task.task_type)),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]))
+            })
+            .collect();
+        let task_list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Tasks"))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol("› ");
+        let mut state = ListState::default().with_selected(Some(review.selected));
+        f.render_stateful_widget(task_list, chunks[1], &mut state);
+
+        let mut lines = Vec::new();
+        if let Some(task) = review.selected_task() {
+            let mut parameters: Vec<_> = task.parameters.iter().collect();
+            parameters.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, value) in parameters {
+                lines.push(Line::from(format!("{} = {}", name, value)));
+            }
+            if !task.dependencies.is_empty() {
+                lines.push(Line::from(Span::styled(
+                    format!("after {}", task.dependencies.join(", ")),
+                    Style::default().fg(Color::DarkGray),
+                )));
+            }
+        }
+        for diagnostic in review.plan.validate() {
+            lines.push(Line::from(Span::styled(
+                format!("⚠ {}", diagnostic),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        if let Some(message) = &review.message {
+            lines.push(Line::from(Span::styled(message.clone(), Style::default().fg(Color::Yellow))));
+        }
+        lines.push(match &review.input {
+            Some(ReviewInput::Parameter(text)) => Line::from(format!("name=value: {}_", text)),
+            Some(ReviewInput::NewTask(text)) => Line::from(format!("task_type: description: {}_", text)),
+            None => Line::from(Span::styled(
+                "Enter approve · r reject · e edit · d delete · a add · Shift+↑↓ move",
+                Style::default().fg(Color::Gray),
+            )),
+        });
+
+        let details = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Selected Task"))
+            .wrap(Wrap { trim: true });
+        f.render_widget(details, chunks[2]);
+    }
+
     fn render_no_plan(&self, f: &mut Frame, area: Rect) {
         let no_plan = Paragraph::new("No active plan")
             .block(Block::default().borders(Borders::ALL).title("Current Plan"))
@@ -198,6 +452,68 @@ impl PlanComponent {
     }
 }
 
+impl PlanReview {
+    fn selected_task(&self) -> Option<&Task> {
+        self.plan.tasks.get(self.selected)
+    }
+
+    fn apply(&mut self, edit: PlanEdit) {
+        if let Err(e) = edit.apply(&mut self.plan) {
+            self.message = Some(e.to_string());
+        }
+    }
+
+    fn move_selected(&mut self, offset: isize) {
+        let Some(task_id) = self.selected_task().map(|task| task.id.clone()) else {
+            return;
+        };
+        let last = self.plan.tasks.len().saturating_sub(1);
+        let position = self.selected.saturating_add_signed(offset).min(last);
+        self.apply(PlanEdit::MoveTask { task_id, position });
+        self.selected = position;
+    }
+
+    fn submit_input(&mut self, input: ReviewInput) {
+        match input {
+            ReviewInput::Parameter(text) => {
+                let Some(task_id) = self.selected_task().map(|task| task.id.clone()) else {
+                    return;
+                };
+                match text.split_once('=') {
+                    Some((name, value)) => self.apply(PlanEdit::SetParameter {
+                        task_id,
+                        name: name.to_string(),
+                        value: parse_parameter_value(value),
+                    }),
+                    None => self.message = Some("Write the parameter as name=value".to_string()),
+                }
+            }
+            ReviewInput::NewTask(text) => {
+                let Some((task_type, description)) = text.split_once(':') else {
+                    self.message = Some("Write the task as task_type: description".to_string());
+                    return;
+                };
+                let task_type: TaskType =
+                    match serde_json::from_value(serde_json::Value::String(task_type.trim().to_string())) {
+                        Ok(task_type) => task_type,
+                        Err(_) => {
+                            self.message = Some(format!("Unknown task type '{}'", task_type.trim()));
+                            return;
+                        }
+                    };
+
+                let mut task = Task::new(manual_task_id(&self.plan), description.trim().to_string(), task_type);
+                let position = if self.plan.tasks.is_empty() { 0 } else { self.selected + 1 };
+                if let Some(previous) = self.selected_task() {
+                    task.dependencies.push(previous.id.clone());
+                }
+                self.apply(PlanEdit::AddTask { task: Box::new(task), position: Some(position) });
+                self.selected = position.min(self.plan.tasks.len().saturating_sub(1));
+            }
+        }
+    }
+}
+
 impl Default for PlanComponent {
     fn default() -> Self {
         Self::new()
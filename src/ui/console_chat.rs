@@ -1,14 +1,23 @@
 //! Simple console-based chat interface without frames
 
+use crate::llm::content::image_attachments;
+use crate::llm::schema::TASK_TYPES;
 use crate::llm::LlmProvider;
-use crate::planning::{Plan, TaskStatus, PlanStatus};
-use crate::execution::{ExecutionEngine, PromptPriority};
+use crate::planning::review::{manual_task_id, parse_parameter_value};
+use crate::planning::validation::{required_parameters, task_type_name};
+use crate::planning::{Plan, PlanDiff, PlanEdit, Task, TaskStatus, TaskTreeNode, TaskType, PlanStatus};
+use crate::execution::{ExecutionEngine, ExecutionEvent, PromptPriority};
+use crate::ui::events::SlashCommand;
+use crate::ui::slash_commands::cache_command;
+use crate::ui::transcript::Transcript;
 use crate::Result;
 use std::io::{self, Write};
 use std::sync::Arc;
 use std::path::PathBuf;
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, RwLock};
+use tokio_util::sync::CancellationToken;
 use colored::*;
+use inquire::{Confirm, CustomType, Select, Text};
 
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
@@ -34,11 +43,57 @@ impl std::fmt::Display for MessageRole {
     }
 }
 
+/// Choices offered while reviewing a plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReviewAction {
+    Approve,
+    EditParameter,
+    RemoveTask,
+    MoveTask,
+    AddTask,
+    Reject,
+}
+
+impl ReviewAction {
+    const ALL: [ReviewAction; 6] = [
+        ReviewAction::Approve,
+        ReviewAction::EditParameter,
+        ReviewAction::RemoveTask,
+        ReviewAction::MoveTask,
+        ReviewAction::AddTask,
+        ReviewAction::Reject,
+    ];
+}
+
+impl std::fmt::Display for ReviewAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReviewAction::Approve => write!(f, "Approve and run"),
+            ReviewAction::EditParameter => write!(f, "Edit a task parameter"),
+            ReviewAction::RemoveTask => write!(f, "Delete a task"),
+            ReviewAction::MoveTask => write!(f, "Move a task"),
+            ReviewAction::AddTask => write!(f, "Add a manual task"),
+            ReviewAction::Reject => write!(f, "Reject the plan"),
+        }
+    }
+}
+
+/// What plain input in the chat is turned into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatMode {
+    /// Generate and execute a plan for each request
+    Plan,
+    /// Answer questions without creating a plan
+    Ask,
+}
+
 pub struct ConsoleChat {
     llm_provider: Arc<dyn LlmProvider>,
     execution_engine: Arc<RwLock<ExecutionEngine>>,
     working_directory: PathBuf,
-    messages: Vec<ChatMessage>,
+    transcript: Transcript,
+    mode: ChatMode,
+    shutdown: CancellationToken,
 }
 
 impl ConsoleChat {
@@ -51,9 +106,17 @@ impl ConsoleChat {
             llm_provider,
             execution_engine,
             working_directory,
-            messages: Vec::new(),
+            transcript: Transcript::new(),
+            mode: ChatMode::Plan,
+            shutdown: CancellationToken::new(),
         }
     }
+
+    /// End `run` when `shutdown` is cancelled, even while it waits for input
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
     
     pub async fn run(&mut self) -> Result<()> {
         // Simple welcome with working directory display
@@ -63,13 +126,21 @@ impl ConsoleChat {
         println!();
         
         loop {
-            // Simple prompt
-            print!("{} ", "›".bright_blue().bold());
+            // A plan held on a spending cap blocks new requests until the user decides
+            self.confirm_budget().await?;
+
+            // Simple prompt, marked while answering questions
+            match self.mode {
+                ChatMode::Plan => print!("{} ", "›".bright_blue().bold()),
+                ChatMode::Ask => print!("{} ", "?".bright_magenta().bold()),
+            }
             io::stdout().flush()?;
             
-            // Read input
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            // Read input until stdin closes or the session is shut down
+            let Some(input) = self.read_input().await? else {
+                println!();
+                break;
+            };
             let input = input.trim();
             
             if input.is_empty() { 
@@ -84,7 +155,7 @@ impl ConsoleChat {
                 }
                 "clear" => {
                     self.clear_screen();
-                    self.messages.clear();
+                    self.transcript.clear();
                     continue;
                 }
                 "help" => {
@@ -94,17 +165,52 @@ impl ConsoleChat {
                 _ => {}
             }
             
-            // Add user message
-            self.add_message(MessageRole::User, input.to_string());
+            // Slash commands may switch modes or run a single request in the other mode
+            let (mode, request) = match SlashCommand::parse(input) {
+                SlashCommand::Cache(action) => {
+                    match cache_command(&action) {
+                        Ok(message) => println!("{}", message.bright_cyan()),
+                        Err(e) => println!("{}", format!("Error: {}", e).bright_red()),
+                    }
+                    continue;
+                }
+                SlashCommand::Cancel => {
+                    self.cancel_plan().await;
+                    continue;
+                }
+                SlashCommand::Ask(question) if question.is_empty() => {
+                    self.mode = ChatMode::Ask;
+                    println!("{}", "❓ Question mode: answers without planning. Use /plan to go back.".bright_magenta());
+                    continue;
+                }
+                SlashCommand::Plan(request) if request.is_empty() => {
+                    self.mode = ChatMode::Plan;
+                    println!("{}", "🛠  Plan mode: requests are planned and executed.".bright_blue());
+                    continue;
+                }
+                SlashCommand::Ask(question) => (ChatMode::Ask, question),
+                SlashCommand::Plan(request) => (ChatMode::Plan, request),
+                _ => (self.mode, input.to_string()),
+            };
             
             // Show AI is thinking
             print!("{} ", "🤔".bright_yellow());
             io::stdout().flush()?;
             
-            // Generate and execute response
-            match self.generate_and_execute_plan(input).await {
-                Ok(plan) => {
-                    self.add_message(MessageRole::Assistant, plan.description.clone());
+            // Earlier turns go with the request so follow-ups can refer to them
+            let conversation = self.transcript.summary();
+            let reply = match mode {
+                ChatMode::Plan => self
+                    .generate_and_execute_plan(&request, conversation)
+                    .await
+                    .map(|plan| plan_summary(&plan)),
+                ChatMode::Ask => self.answer_question(&request, conversation).await,
+            };
+
+            self.add_message(MessageRole::User, request);
+            match reply {
+                Ok(reply) => {
+                    self.add_message(MessageRole::Assistant, reply);
                 }
                 Err(e) => {
                     let error_msg = format!("Error: {}", e);
@@ -118,12 +224,70 @@ impl ConsoleChat {
         
         Ok(())
     }
+
+    /// Read a line from stdin, or `None` once stdin closes or the session is shut down
+    async fn read_input(&self) -> Result<Option<String>> {
+        // A plain thread, unlike `spawn_blocking`, does not hold up runtime shutdown
+        // while it waits for a line that never comes
+        let (sender, receiver) = oneshot::channel();
+        std::thread::spawn(move || {
+            let mut input = String::new();
+            let read = io::stdin().read_line(&mut input).map(|bytes| (bytes > 0).then_some(input));
+            let _ = sender.send(read);
+        });
+        tokio::select! {
+            read = receiver => Ok(read.unwrap_or(Ok(None))?),
+            _ = self.shutdown.cancelled() => Ok(None),
+        }
+    }
     
-    async fn generate_and_execute_plan(&self, input: &str) -> Result<Plan> {
-        // Start by submitting the user prompt to the execution engine
-        let prompt_id = {
+    /// Show a resumed plan's progress until it finishes, before taking new requests
+    pub async fn follow_resumed_plan(&mut self, plan: &Plan) {
+        let completed = plan.tasks.iter().filter(|task| task.status == TaskStatus::Completed).count();
+        println!("{} {} ({} of {} tasks already completed)",
+            "▶ Resuming plan".bright_green().bold(),
+            plan.id.bright_yellow(),
+            completed,
+            plan.tasks.len()
+        );
+        self.display_plan_with_execution_status(plan).await;
+        self.add_message(MessageRole::System, format!("Resumed plan: {}", plan.description));
+        println!();
+    }
+
+    async fn answer_question(&self, question: &str, conversation: Option<String>) -> Result<String> {
+        let answer = {
+            let engine = self.execution_engine.read().await;
+            engine.answer_question(question, conversation.as_deref()).await?
+        };
+        println!();
+        println!("{}", answer.trim());
+        Ok(answer)
+    }
+
+    async fn generate_and_execute_plan(&self, input: &str, conversation: Option<String>) -> Result<Plan> {
+        // Images referenced as @path.png are sent to the model with the prompt
+        let attachments = image_attachments(input, &self.working_directory)?;
+        for (path, _) in &attachments {
+            println!("📎 Attached {}", path.display().to_string().bright_cyan());
+        }
+        let attachments = attachments.into_iter().map(|(_, part)| part).collect();
+
+        // Start by submitting the user prompt to the execution engine, subscribing
+        // first so no streamed output is missed. A request made while a plan is
+        // still running revises that plan.
+        let (prompt_id, mut events, previous_plan_id) = {
             let engine = self.execution_engine.read().await;
-            engine.submit_user_prompt(input.to_string(), PromptPriority::Normal).await
+            let events = engine.subscribe_to_events();
+            let previous_plan_id = engine.get_current_plan().await.map(|plan| plan.id);
+            let priority = match engine.has_active_plan().await {
+                true => PromptPriority::Interrupt,
+                false => PromptPriority::Normal,
+            };
+            let prompt_id = engine
+                .submit_chat_prompt(input.to_string(), attachments, conversation, priority)
+                .await;
+            (prompt_id, events, previous_plan_id)
         };
         
         println!("🔄 Plan queued with ID: {}", prompt_id);
@@ -133,20 +297,58 @@ impl ConsoleChat {
         // We'll poll for a plan to appear
         let mut attempts = 0;
         let max_attempts = 600; // 60 seconds with 100ms intervals (increased timeout for complex planning)
+        let mut streamed_output = false;
         
         while attempts < max_attempts {
-            let plan = {
+            // Show plan text token by token as the provider streams it
+            while let Ok(event) = events.try_recv() {
+                match event {
+                    ExecutionEvent::LlmOutput(output) => {
+                        print!("{}", output.content.dimmed());
+                        io::stdout().flush()?;
+                        streamed_output = true;
+                        // Output is arriving, so the model is not stuck
+                        attempts = 0;
+                    }
+                    ExecutionEvent::PlanCancelled { .. } => {
+                        if streamed_output {
+                            println!();
+                        }
+                        return Err(crate::utils::errors::KaiError::cancelled("Plan cancelled"));
+                    }
+                    ExecutionEvent::PromptRejected { prompt_id: rejected, reason } if rejected == prompt_id => {
+                        if streamed_output {
+                            println!();
+                        }
+                        return Err(crate::utils::errors::KaiError::planning(reason));
+                    }
+                    _ => {}
+                }
+            }
+
+            let (pending, plan) = {
                 let engine = self.execution_engine.read().await;
-                engine.get_current_plan().await
+                (engine.pending_review().await, engine.get_current_plan().await)
             };
+            if let Some(pending) = pending {
+                if streamed_output {
+                    println!();
+                }
+                return self.review_plan(pending).await;
+            }
+            // The plan that was running before this prompt is not the answer to it
+            let plan = plan.filter(|plan| Some(&plan.id) != previous_plan_id.as_ref());
             if let Some(plan) = plan {
+                if streamed_output {
+                    println!();
+                }
                 println!("✅ Plan generated: {}", plan.description);
                 self.display_plan_with_execution_status(&plan).await;
                 return Ok(plan);
             }
             
-            // Show progress every second
-            if attempts % 10 == 0 && attempts > 0 {
+            // Show progress every second, unless streamed output already shows it
+            if attempts % 10 == 0 && attempts > 0 && !streamed_output {
                 println!("⏳ Still waiting... ({}/{}s)", attempts / 10, max_attempts / 10);
             }
             
@@ -158,6 +360,223 @@ impl ConsoleChat {
         Err(crate::utils::errors::KaiError::execution("Timeout waiting for plan generation".to_string()))
     }
     
+    /// Stop the running plan, or the one being generated
+    async fn cancel_plan(&self) {
+        let engine = self.execution_engine.read().await;
+        if engine.has_active_plan().await {
+            engine.cancel_plan().await;
+            println!("{}", "⏹ Plan cancelled".bright_yellow());
+        } else {
+            println!("{}", "No plan is running.".dimmed());
+        }
+    }
+
+    /// Ask whether a plan paused on a spending cap should continue or be cancelled
+    ///
+    /// Continuing approves exceeding that cap for the rest of the plan.
+    async fn confirm_budget(&self) -> Result<()> {
+        let hold = {
+            let engine = self.execution_engine.read().await;
+            engine.budget_hold().await
+        };
+        let Some(exceeded) = hold else {
+            return Ok(());
+        };
+
+        println!("{} {}", "💰 Plan paused:".bright_yellow().bold(), exceeded);
+        let proceed = Confirm::new("Continue past this spending cap?")
+            .with_default(false)
+            .with_help_message("No cancels the plan")
+            .prompt_skippable()?
+            .unwrap_or(false);
+
+        let engine = self.execution_engine.read().await;
+        if proceed {
+            engine.continue_past_budget().await;
+            println!("{}", "▶ Continuing the plan".bright_green());
+        } else {
+            engine.cancel_plan().await;
+            println!("{}", "⏹ Plan cancelled".bright_yellow());
+        }
+        Ok(())
+    }
+
+    /// Let the user approve, edit or reject a plan that is waiting for review
+    ///
+    /// Edits are applied to a copy; the engine validates the result again on
+    /// approval and keeps the plan under review if it is still broken.
+    async fn review_plan(&self, mut plan: Plan) -> Result<Plan> {
+        println!("{} {}", "📝 Plan waiting for your approval:".bright_yellow().bold(), plan.id.dimmed());
+        let diff = {
+            let engine = self.execution_engine.read().await;
+            engine.pending_review_diff().await
+        };
+        if let Some(diff) = diff {
+            self.display_plan_diff(&diff);
+        }
+        loop {
+            self.display_plan_for_review(&plan);
+
+            // Escape at the top level rejects the plan
+            let action = Select::new("Review the plan:", ReviewAction::ALL.to_vec())
+                .prompt_skippable()?
+                .unwrap_or(ReviewAction::Reject);
+            let edit = match action {
+                ReviewAction::Approve => {
+                    let result = {
+                        let engine = self.execution_engine.read().await;
+                        engine.approve_plan(plan.clone()).await
+                    };
+                    match result {
+                        Ok(()) => {
+                            println!("✅ Plan approved: {}", plan.description);
+                            self.display_plan_with_execution_status(&plan).await;
+                            return Ok(plan);
+                        }
+                        Err(e) => {
+                            println!("{}", format!("Cannot run the plan yet: {}", e).bright_red());
+                            continue;
+                        }
+                    }
+                }
+                ReviewAction::Reject => {
+                    let engine = self.execution_engine.read().await;
+                    engine.reject_plan().await;
+                    println!("{}", "🗑  Plan rejected".bright_yellow());
+                    return Err(crate::utils::errors::KaiError::cancelled("Plan rejected during review"));
+                }
+                ReviewAction::EditParameter => self.prompt_parameter_edit(&plan)?,
+                ReviewAction::RemoveTask => Self::select_task(&plan, "Delete which task?")?
+                    .map(|task_id| PlanEdit::RemoveTask { task_id }),
+                ReviewAction::MoveTask => self.prompt_move(&plan)?,
+                ReviewAction::AddTask => self.prompt_manual_task(&plan)?,
+            };
+
+            if let Some(edit) = edit {
+                if let Err(e) = edit.apply(&mut plan) {
+                    println!("{}", format!("Error: {}", e).bright_red());
+                }
+            }
+        }
+    }
+
+    /// Ask which task to change; `None` if the user backs out
+    fn select_task(plan: &Plan, message: &str) -> Result<Option<String>> {
+        if plan.tasks.is_empty() {
+            println!("{}", "The plan has no tasks.".dimmed());
+            return Ok(None);
+        }
+        let labels: Vec<String> = plan
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, task)| format!("{}. {} ({})", i + 1, task.description, task.id))
+            .collect();
+        match Select::new(message, labels).raw_prompt() {
+            Ok(choice) => Ok(Some(plan.tasks[choice.index].id.clone())),
+            Err(inquire::InquireError::OperationCanceled) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn prompt_parameter_edit(&self, plan: &Plan) -> Result<Option<PlanEdit>> {
+        let Some(task_id) = Self::select_task(plan, "Edit which task?")? else {
+            return Ok(None);
+        };
+        let task = plan.tasks.iter().find(|task| task.id == task_id);
+        let mut names: Vec<String> = task.map(|task| task.parameters.keys().cloned().collect()).unwrap_or_default();
+        names.sort();
+        let help = if names.is_empty() { "no parameters yet".to_string() } else { names.join(", ") };
+        let Some(name) = Text::new("Parameter name:").with_help_message(&help).prompt_skippable()? else {
+            return Ok(None);
+        };
+        let current = task
+            .and_then(|task| task.parameters.get(name.trim()))
+            .map(|value| value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string()))
+            .unwrap_or_default();
+        let Some(value) = Text::new("Value:").with_initial_value(&current).prompt_skippable()? else {
+            return Ok(None);
+        };
+        Ok(Some(PlanEdit::SetParameter {
+            task_id,
+            name,
+            value: parse_parameter_value(&value),
+        }))
+    }
+
+    fn prompt_move(&self, plan: &Plan) -> Result<Option<PlanEdit>> {
+        let Some(task_id) = Self::select_task(plan, "Move which task?")? else {
+            return Ok(None);
+        };
+        let position = CustomType::<usize>::new("New position:")
+            .with_help_message(&format!("1 to {}", plan.tasks.len()))
+            .prompt_skippable()?;
+        Ok(position.map(|position| PlanEdit::MoveTask {
+            task_id,
+            position: position.saturating_sub(1),
+        }))
+    }
+
+    fn prompt_manual_task(&self, plan: &Plan) -> Result<Option<PlanEdit>> {
+        let Some(task_type) = Select::new("Task type:", TASK_TYPES.to_vec()).prompt_skippable()? else {
+            return Ok(None);
+        };
+        let task_type: TaskType = serde_json::from_value(serde_json::Value::String(task_type.to_string()))?;
+        let Some(description) = Text::new("Description:").prompt_skippable()? else {
+            return Ok(None);
+        };
+
+        let mut task = Task::new(manual_task_id(plan), description, task_type.clone());
+        for parameter in required_parameters(&task_type) {
+            let Some(value) = Text::new(&format!("{}:", parameter)).prompt_skippable()? else {
+                return Ok(None);
+            };
+            task.parameters.insert(parameter.to_string(), parse_parameter_value(&value));
+        }
+        // Manual tasks run after everything the model planned
+        task.dependencies = plan.tasks.last().map(|last| vec![last.id.clone()]).unwrap_or_default();
+        Ok(Some(PlanEdit::AddTask { task: Box::new(task), position: None }))
+    }
+
+    /// Changes a revision makes to the running plan, one task per line
+    fn display_plan_diff(&self, diff: &PlanDiff) {
+        println!();
+        println!("{}", "Changes to the running plan:".bright_cyan());
+        for line in diff.to_string().lines() {
+            let line = match line.chars().next() {
+                Some('+') => line.bright_green(),
+                Some('-') => line.bright_red(),
+                Some('~') => line.bright_yellow(),
+                _ => line.dimmed(),
+            };
+            println!("  {}", line);
+        }
+    }
+
+    fn display_plan_for_review(&self, plan: &Plan) {
+        println!();
+        println!("{}", plan.description.bright_cyan());
+        for (i, task) in plan.tasks.iter().enumerate() {
+            println!("  {}. {} {}",
+                (i + 1).to_string().bright_yellow(),
+                task.description,
+                format!("[{}] {}", task_type_name(&task.task_type), task.id).dimmed()
+            );
+            let mut parameters: Vec<_> = task.parameters.iter().collect();
+            parameters.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, value) in parameters {
+                println!("       {} = {}", name.bright_blue(), value);
+            }
+            if !task.dependencies.is_empty() {
+                println!("       {} {}", "after".dimmed(), task.dependencies.join(", ").dimmed());
+            }
+        }
+        for diagnostic in plan.validate() {
+            println!("  {} {}", "⚠".bright_red(), diagnostic.to_string().bright_red());
+        }
+        println!();
+    }
+
     async fn build_context(&self) -> Result<String> {
         // Simple context - you can expand this
         let working_dir = std::env::current_dir()
@@ -168,11 +587,7 @@ impl ConsoleChat {
     }
     
     fn add_message(&mut self, role: MessageRole, content: String) {
-        self.messages.push(ChatMessage {
-            role,
-            content,
-            timestamp: chrono::Utc::now(),
-        });
+        self.transcript.push(role, content);
     }
     
     async fn display_plan_with_execution_status(&self, plan: &Plan) {
@@ -186,15 +601,8 @@ impl ConsoleChat {
         let mut completed_tasks = std::collections::HashSet::new();
         
         // Initial display of tasks
-        for (i, task) in plan.tasks.iter().enumerate() {
-            let status_symbol = self.get_task_symbol(&task.status);
-            let task_color = self.get_task_color(&task.status);
-            
-            println!("{}  {}. {}", 
-                status_symbol,
-                (i + 1).to_string().bright_yellow(),
-                task.description.color(task_color)
-            );
+        for (i, node) in plan.task_tree().iter().enumerate() {
+            println!("{}", self.format_task_line(i, node));
         }
         
         // Monitor execution progress
@@ -202,15 +610,23 @@ impl ConsoleChat {
         let max_monitoring_time = 300; // 30 seconds of monitoring
         
         while monitoring_attempts < max_monitoring_time {
+            if let Err(e) = self.confirm_budget().await {
+                println!("{}", format!("Error: {}", e).bright_red());
+            }
+
             let current_plan = {
                 let engine = self.execution_engine.read().await;
                 engine.get_current_plan().await
             };
             if let Some(current_plan) = current_plan {
+                if current_plan.status == PlanStatus::Cancelled {
+                    break;
+                }
                 let mut all_completed = true;
                 let mut _has_changes = false;
                 
-                for (i, task) in current_plan.tasks.iter().enumerate() {
+                for (i, node) in current_plan.task_tree().iter().enumerate() {
+                    let task = node.task;
                     if task.status != TaskStatus::Completed && task.status != TaskStatus::Failed {
                         all_completed = false;
                     }
@@ -222,15 +638,8 @@ impl ConsoleChat {
                             completed_tasks.insert(task_key);
                             _has_changes = true;
                             
-                            let status_symbol = self.get_task_symbol(&task.status);
-                            let task_color = self.get_task_color(&task.status);
-                            
                             // Update this specific line
-                            print!("\r{} {}. {}", 
-                                status_symbol,
-                                (i + 1).to_string().bright_yellow(),
-                                task.description.color(task_color)
-                            );
+                            print!("\r{}", self.format_task_line(i, node));
                             println!(); // Move to next line
                         }
                     }
@@ -265,15 +674,8 @@ impl ConsoleChat {
         println!("{}", plan.description.bright_cyan());
         
         // Tasks
-        for (i, task) in plan.tasks.iter().enumerate() {
-            let status_symbol = self.get_task_symbol(&task.status);
-            let task_color = self.get_task_color(&task.status);
-            
-            println!("{}  {}. {}", 
-                status_symbol,
-                (i + 1).to_string().bright_yellow(),
-                task.description.color(task_color)
-            );
+        for (i, node) in plan.task_tree().iter().enumerate() {
+            println!("{}", self.format_task_line(i, node));
         }
         
         // Plan status
@@ -283,6 +685,24 @@ impl ConsoleChat {
         }
     }
     
+    /// One line of the plan tree: sub-tasks are indented below their parent,
+    /// which shows how many of its leaf tasks are done
+    fn format_task_line(&self, index: usize, node: &TaskTreeNode) -> String {
+        let task = node.task;
+        let progress = if node.has_subtasks {
+            format!(" ({})", node.progress).dimmed().to_string()
+        } else {
+            String::new()
+        };
+        format!("{}{}  {}. {}{}",
+            "   ".repeat(node.depth),
+            self.get_task_symbol(&task.status),
+            (index + 1).to_string().bright_yellow(),
+            task.description.color(self.get_task_color(&task.status)),
+            progress
+        )
+    }
+    
     fn get_task_symbol(&self, status: &TaskStatus) -> &str {
         match status {
             TaskStatus::Pending => "⏸",
@@ -291,6 +711,7 @@ impl ConsoleChat {
             TaskStatus::Completed => "✅",
             TaskStatus::Failed => "❌",
             TaskStatus::Skipped => "⏭",
+            TaskStatus::Cancelled => "⏹",
         }
     }
     
@@ -302,6 +723,7 @@ impl ConsoleChat {
             TaskStatus::Completed => colored::Color::Green,
             TaskStatus::Failed => colored::Color::Red,
             TaskStatus::Skipped => colored::Color::BrightBlack,
+            TaskStatus::Cancelled => colored::Color::BrightBlack,
         }
     }
     
@@ -331,8 +753,22 @@ impl ConsoleChat {
         println!("  {} - Exit the application", "exit/quit".bright_yellow());
         println!("  {} - Clear the chat history", "clear".bright_yellow());
         println!("  {} - Show this help message", "help".bright_yellow());
+        println!("  {} - Show or clear the LLM response cache", "/cache [clear]".bright_yellow());
+        println!("  {} - Attach an image (png, jpg, gif, webp) to your request", "@path.png".bright_yellow());
+        println!("  {} - Answer questions without creating a plan", "/ask [question]".bright_yellow());
+        println!("  {} - Plan and execute requests (the default)", "/plan [request]".bright_yellow());
+        println!("  {} - Cancel the running plan (Ctrl-C does the same)", "/cancel".bright_yellow());
         println!();
         println!("{}", "Just type your request to get started!".dimmed());
         println!();
     }
-}
\ No newline at end of file
+}
+
+/// What a plan did, as recorded in the transcript
+fn plan_summary(plan: &Plan) -> String {
+    let mut summary = format!("Planned: {}", plan.description);
+    for task in &plan.tasks {
+        summary.push_str(&format!("\n- {}", task.description));
+    }
+    summary
+}
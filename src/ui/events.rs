@@ -1,5 +1,6 @@
 //! UI event types for communication between components
 
+use crate::llm::StreamOutput;
 use crate::planning::{Plan, TaskResult};
 
 /// Main UI event types
@@ -31,6 +32,8 @@ pub enum UiEvent {
     WorkingDirectoryChanged(String),
     /// Slash command executed
     SlashCommand(SlashCommand),
+    /// Text streamed from the LLM while it is still generating
+    LlmOutput(StreamOutput),
 }
 
 /// Task completion event
@@ -71,7 +74,8 @@ pub struct StatusUpdate {
 #[derive(Debug, Clone)]
 pub enum SlashCommand {
     Model(String),
-    ListModels,
+    /// List models, listing them again from the provider when true
+    ListModels(bool),
     Provider(String),
     ResetContext,
     RefreshContext,
@@ -83,6 +87,9 @@ pub enum SlashCommand {
     Cancel,
     Pause,
     Resume,
+    Cache(String),
+    Ask(String),
+    Plan(String),
     Unknown(String),
 }
 
@@ -197,7 +204,7 @@ impl SlashCommand {
                     SlashCommand::Unknown(input.to_string())
                 }
             }
-            "list-models" => SlashCommand::ListModels,
+            "list-models" => SlashCommand::ListModels(parts.get(1) == Some(&"refresh")),
             "provider" => {
                 if parts.len() > 1 {
                     SlashCommand::Provider(parts[1].to_string())
@@ -221,6 +228,9 @@ impl SlashCommand {
             "cancel" => SlashCommand::Cancel,
             "pause" => SlashCommand::Pause,
             "resume" => SlashCommand::Resume,
+            "cache" => SlashCommand::Cache(parts[1..].join(" ")),
+            "ask" => SlashCommand::Ask(parts[1..].join(" ")),
+            "plan" => SlashCommand::Plan(parts[1..].join(" ")),
             _ => SlashCommand::Unknown(input.to_string()),
         }
     }
@@ -229,7 +239,7 @@ impl SlashCommand {
     pub fn description(&self) -> &'static str {
         match self {
             SlashCommand::Model(_) => "Set the active LLM model",
-            SlashCommand::ListModels => "List all available models",
+            SlashCommand::ListModels(_) => "List all available models",
             SlashCommand::Provider(_) => "Switch LLM provider",
             SlashCommand::ResetContext => "Reset and regenerate context",
             SlashCommand::RefreshContext => "Refresh context for modified files",
@@ -241,6 +251,9 @@ impl SlashCommand {
             SlashCommand::Cancel => "Cancel current execution",
             SlashCommand::Pause => "Pause current execution",
             SlashCommand::Resume => "Resume paused execution",
+            SlashCommand::Cache(_) => "Show or clear the LLM response cache",
+            SlashCommand::Ask(_) => "Answer questions without creating a plan",
+            SlashCommand::Plan(_) => "Turn requests into plans again",
             SlashCommand::Unknown(_) => "Unknown command",
         }
     }
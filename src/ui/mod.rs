@@ -7,13 +7,14 @@ pub mod slash_commands;
 pub mod file_browser;
 pub mod clipboard;
 pub mod console_chat;
+pub mod transcript;
 
 use crate::utils::debug::DEBUG_TRACER;
 use crate::debug_checkpoint;
 use std::collections::HashMap;
 
 pub use services::{InputBufferService, HistoryService, CompletionService, EditingMode, TextSelection};
-pub use components::{ChatComponent, PlanComponent, StatusComponent, ApplicationStatus};
+pub use components::{ChatComponent, PlanComponent, ReviewOutcome, StatusComponent, ApplicationStatus};
 pub use events::{UiEvent, KeyEvent, InputEvent, SlashCommand};
 pub use slash_commands::SlashCommandProcessor;
 pub use file_browser::{FileBrowserComponent, FileEntry};
@@ -22,11 +23,11 @@ pub use console_chat::ConsoleChat;
 
 use crate::utils::errors::KaiError;
 use crate::Result;
-use crate::llm::LlmProvider;
+use crate::llm::{LlmProvider, StreamOutput};
 use crate::config::ConfigManager;
-use crate::planning::manager::AgenticPlanningCoordinator;
+use crate::planning::manager::{AgenticPlanningCoordinator, CoordinatorStatus, PlanManagerMessage};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -73,6 +74,14 @@ pub struct UiManager {
     config_manager: Option<ConfigManager>,
     /// Planning manager for generating and executing plans
     planning_manager: Option<Arc<RwLock<AgenticPlanningCoordinator>>>,
+    /// LLM output streamed by the planning manager
+    llm_output_receiver: Option<broadcast::Receiver<StreamOutput>>,
+    /// Status updates from the planning manager
+    coordinator_status_receiver: Option<broadcast::Receiver<CoordinatorStatus>>,
+    /// Control messages to the planning manager
+    coordinator_sender: Option<mpsc::UnboundedSender<PlanManagerMessage>>,
+    /// Whether the current spending cap alert has been shown
+    budget_alert_shown: bool,
 }
 
 impl UiManager {
@@ -98,6 +107,10 @@ impl UiManager {
             llm_provider: None,
             config_manager: None,
             planning_manager: None,
+            llm_output_receiver: None,
+            coordinator_status_receiver: None,
+            coordinator_sender: None,
+            budget_alert_shown: false,
         }
     }
 
@@ -109,13 +122,23 @@ impl UiManager {
         working_directory: std::path::PathBuf,
     ) -> Self {
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        // The coordinator is not running yet, so its lock is free
+        let (llm_output_receiver, coordinator_status_receiver, coordinator_sender) = match planning_manager.try_read() {
+            Ok(coordinator) => (
+                Some(coordinator.get_output_receiver()),
+                Some(coordinator.get_status_receiver()),
+                Some(coordinator.get_message_sender()),
+            ),
+            Err(_) => (None, None, None),
+        };
 
         Self {
             input_buffer: InputBufferService::new(),
             history: HistoryService::new(),
             completion: CompletionService::new(),
             file_browser: FileBrowserComponent::new(working_directory.clone()),
-            slash_processor: SlashCommandProcessor::new(event_sender.clone()),
+            slash_processor: SlashCommandProcessor::new(event_sender.clone())
+                .with_llm_provider(llm_provider.clone(), config_manager.config().execution.mode.model_requirements()),
             chat_component: ChatComponent::new(),
             plan_component: PlanComponent::new(),
             status_component: StatusComponent::new(),
@@ -127,6 +150,10 @@ impl UiManager {
             llm_provider: Some(llm_provider),
             config_manager: Some(config_manager),
             planning_manager: Some(planning_manager),
+            llm_output_receiver,
+            coordinator_status_receiver,
+            coordinator_sender,
+            budget_alert_shown: false,
         }
     }
 
@@ -217,6 +244,38 @@ impl UiManager {
                 self.handle_ui_event(ui_event).await?;
             }
 
+            // Forward LLM output streamed by the planning manager
+            let mut streamed = Vec::new();
+            if let Some(receiver) = &mut self.llm_output_receiver {
+                loop {
+                    match receiver.try_recv() {
+                        Ok(output) => streamed.push(output),
+                        // Dropping old chunks beats stalling the UI
+                        Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                        Err(_) => break,
+                    }
+                }
+            }
+            for output in streamed {
+                self.handle_ui_event(UiEvent::LlmOutput(output)).await?;
+            }
+
+            // Ask the user before a plan continues past a spending cap
+            let mut latest_status = None;
+            if let Some(receiver) = &mut self.coordinator_status_receiver {
+                loop {
+                    match receiver.try_recv() {
+                        Ok(status) => latest_status = Some(status),
+                        Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                        Err(_) => break,
+                    }
+                }
+            }
+            if let Some(status) = latest_status {
+                self.show_budget_alert(&status);
+                self.show_plan_review(&status);
+            }
+
             // Render the UI
             terminal.draw(|f| {
                 self.render(f);
@@ -230,6 +289,14 @@ impl UiManager {
 
     /// Handle a key event
     async fn handle_key_event(&mut self, key: crossterm::event::KeyEvent) -> Result<Option<UiEvent>> {
+        // A plan under review takes all keys until it is approved or rejected
+        if self.plan_component.is_reviewing() {
+            if let Some(outcome) = self.plan_component.handle_review_key(key) {
+                self.finish_plan_review(outcome);
+            }
+            return Ok(None);
+        }
+
         // Handle file browser input first
         if self.file_browser.is_visible() {
             match key.code {
@@ -513,6 +580,18 @@ impl UiManager {
                 );
             }
             UiEvent::ExecutionStateChanged(state) => {
+                // Forward /pause, /resume and /cancel to the planning manager
+                let message = match state.as_str() {
+                    "Paused" => Some(PlanManagerMessage::PausePlan),
+                    "Executing" => Some(PlanManagerMessage::ResumePlan),
+                    "Cancelled" => Some(PlanManagerMessage::CancelPlan),
+                    _ => None,
+                };
+                if let (Some(sender), Some(message)) = (&self.coordinator_sender, message) {
+                    // A stopped coordinator has nothing to control
+                    let _ = sender.send(message);
+                }
+
                 // Update status component with new execution state
                 let mut status = ApplicationStatus::default();
                 status.execution_state = state.clone();
@@ -596,12 +675,34 @@ impl UiManager {
                     prompt.clone(),
                 );
                 
+                // Images picked from the file browser as @path.png go to the model with the prompt
+                let attachments = match crate::llm::content::image_attachments(&prompt, &self.working_directory) {
+                    Ok(attachments) => attachments,
+                    Err(e) => {
+                        self.chat_component.add_message(
+                            crate::ui::components::MessageRole::System,
+                            format!("❌ Failed to attach image: {}", e),
+                        );
+                        return Ok(());
+                    }
+                };
+                for (path, _) in &attachments {
+                    self.chat_component.add_message(
+                        crate::ui::components::MessageRole::System,
+                        format!("📎 Attached {}", path.display()),
+                    );
+                }
+                let attachments = attachments.into_iter().map(|(_, part)| part).collect();
+
                 // Submit prompt to planning manager for plan generation and execution
                 if let Some(planning_manager_arc) = &self.planning_manager {
                     use crate::planning::manager::PromptPriority;
                     
                     let planning_manager = planning_manager_arc.read().await;
-                    match planning_manager.submit_user_prompt(prompt, PromptPriority::Normal).await {
+                    match planning_manager
+                        .submit_user_prompt_with_attachments(prompt, attachments, PromptPriority::Normal)
+                        .await
+                    {
                         Ok(prompt_id) => {
                             self.chat_component.add_message(
                                 crate::ui::components::MessageRole::System,
@@ -618,6 +719,10 @@ impl UiManager {
                     }
                 }
             }
+            UiEvent::LlmOutput(output) => {
+                // Show tokens as they arrive
+                self.chat_component.append_stream(output.stage, &output.content);
+            }
             _ => {}
         }
 
@@ -625,6 +730,60 @@ impl UiManager {
     }
 
 
+    /// Show a spending cap alert once per pause
+    fn show_budget_alert(&mut self, status: &CoordinatorStatus) {
+        match &status.budget_alert {
+            Some(exceeded) if !self.budget_alert_shown => {
+                self.budget_alert_shown = true;
+                self.chat_component.add_message(
+                    crate::ui::components::MessageRole::System,
+                    format!("💸 Plan paused: {}. Type /resume to continue or /cancel to stop.", exceeded),
+                );
+            }
+            Some(_) => {}
+            None => self.budget_alert_shown = false,
+        }
+    }
+
+    /// Show a plan waiting for approval in the plan panel
+    fn show_plan_review(&mut self, status: &CoordinatorStatus) {
+        let Some(plan) = &status.pending_review else {
+            self.plan_component.end_review();
+            return;
+        };
+        if self.plan_component.is_reviewing() {
+            return;
+        }
+        self.plan_component.start_review(plan.clone());
+        if self.plan_component.is_reviewing() {
+            self.chat_component.add_message(
+                crate::ui::components::MessageRole::System,
+                match &status.pending_review_diff {
+                    Some(diff) => format!(
+                        "📝 Changes to the running plan waiting for approval: {}\n{}Review them in the plan panel.",
+                        plan.description, diff
+                    ),
+                    None => format!("📝 Plan waiting for approval: {}. Review it in the plan panel.", plan.description),
+                },
+            );
+        }
+    }
+
+    /// Send the user's decision about a reviewed plan to the planning manager
+    fn finish_plan_review(&mut self, outcome: ReviewOutcome) {
+        let (message, note) = match outcome {
+            ReviewOutcome::Approve(plan) => {
+                let note = format!("✅ Plan approved: {}", plan.description);
+                (PlanManagerMessage::ApprovePlan(plan), note)
+            }
+            ReviewOutcome::Reject => (PlanManagerMessage::RejectPlan, "🗑 Plan rejected".to_string()),
+        };
+        if let Some(sender) = &self.coordinator_sender {
+            let _ = sender.send(message);
+        }
+        self.chat_component.add_message(crate::ui::components::MessageRole::System, note);
+    }
+
     /// Render the UI
     fn render(&self, f: &mut ratatui::Frame) {
         // Main layout: split between chat/plan area and bottom panels
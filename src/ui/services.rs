@@ -755,6 +755,7 @@ impl CompletionService {
             "/cancel",
             "/pause",
             "/resume",
+            "/cache",
         ];
 
         let query_without_slash = &query[1..]; // Remove the '/' prefix
@@ -1,10 +1,14 @@
 //! Slash command processing with interactive menus
 
+use crate::llm::cache::ResponseCache;
+use crate::llm::registry::{ModelCatalog, ModelRegistry, ModelRequirements};
+use crate::llm::{LlmProvider, ModelInfo};
 use crate::ui::events::{SlashCommand, UiEvent};
 use crate::utils::errors::KaiError;
 use crate::Result;
 use inquire::{Select, Text, Confirm};
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 
 /// Slash command processor with interactive menus
@@ -13,6 +17,12 @@ pub struct SlashCommandProcessor {
     event_sender: mpsc::UnboundedSender<UiEvent>,
     /// Current working directory
     working_directory: PathBuf,
+    /// Provider whose models `/model` and `/list-models` offer
+    llm_provider: Option<Arc<dyn LlmProvider>>,
+    /// Cached model lists with capabilities
+    model_registry: Option<Arc<ModelRegistry>>,
+    /// Features plans need from the model
+    requirements: ModelRequirements,
 }
 
 impl SlashCommandProcessor {
@@ -21,9 +31,32 @@ impl SlashCommandProcessor {
         Self {
             event_sender,
             working_directory: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            llm_provider: None,
+            model_registry: ModelRegistry::global(),
+            requirements: ModelRequirements::default(),
         }
     }
 
+    /// Offer the models of `provider`, refusing those that lack `requirements`
+    pub fn with_llm_provider(mut self, provider: Arc<dyn LlmProvider>, requirements: ModelRequirements) -> Self {
+        self.llm_provider = Some(provider);
+        self.requirements = requirements;
+        self
+    }
+
+    /// Models of the active provider, from the registry unless `refresh` is set
+    async fn catalog(&self, refresh: bool) -> Result<ModelCatalog> {
+        let (Some(provider), Some(registry)) = (&self.llm_provider, &self.model_registry) else {
+            return Err(KaiError::not_found("LLM provider to list models from"));
+        };
+        let catalog = if refresh {
+            registry.refresh(provider.as_ref()).await?
+        } else {
+            registry.catalog(provider.as_ref()).await?
+        };
+        Ok(catalog)
+    }
+
     /// Process a slash command with interactive menus
     pub async fn process_command(&mut self, command: SlashCommand) -> Result<()> {
         match command {
@@ -34,8 +67,8 @@ impl SlashCommandProcessor {
                     self.set_model(model_name).await?;
                 }
             }
-            SlashCommand::ListModels => {
-                self.list_models().await?;
+            SlashCommand::ListModels(refresh) => {
+                self.list_models(refresh).await?;
             }
             SlashCommand::Provider(provider_name) => {
                 if provider_name.is_empty() {
@@ -78,6 +111,12 @@ impl SlashCommandProcessor {
             SlashCommand::Resume => {
                 self.resume_execution().await?;
             }
+            SlashCommand::Cache(action) => {
+                println!("{}", cache_command(&action)?);
+            }
+            SlashCommand::Ask(_) | SlashCommand::Plan(_) => {
+                println!("💬 /ask and /plan switch modes in console chat (kai chat)");
+            }
             SlashCommand::Unknown(cmd) => {
                 self.handle_unknown_command(cmd).await?;
             }
@@ -86,36 +125,34 @@ impl SlashCommandProcessor {
     }
 
     /// Show interactive model selector
+    ///
+    /// Only models with the features plans need are offered.
     async fn show_model_selector(&self) -> Result<()> {
-        let models = vec![
-            "anthropic/claude-3.5-sonnet",
-            "anthropic/claude-3.5-haiku", 
-            "openai/gpt-4o",
-            "openai/gpt-4o-mini",
-            "google/gemini-pro-1.5",
-            "google/gemini-flash-1.5",
-            "meta-llama/llama-3.1-405b-instruct",
-            "meta-llama/llama-3.1-70b-instruct",
-            "anthropic/claude-3-opus",
-            "anthropic/claude-3-sonnet",
-        ];
+        let catalog = self.catalog(false).await?;
+        let (models, hidden): (Vec<&ModelInfo>, Vec<&ModelInfo>) = catalog
+            .models
+            .iter()
+            .partition(|model| self.requirements.unmet_by(model).is_empty());
+        if !hidden.is_empty() {
+            println!("ℹ️  {} models are hidden because they lack features plans need", hidden.len());
+        }
+        if models.is_empty() {
+            println!("No usable models listed by {}", catalog.provider);
+            return Ok(());
+        }
+        let models: Vec<String> = models.iter().map(|model| model.id.clone()).collect();
 
-        tokio::task::spawn_blocking(move || {
-            let selection = Select::new("Select a model:", models)
+        let selection = tokio::task::spawn_blocking(move || {
+            Select::new("Select a model:", models)
                 .with_help_message("Choose the LLM model to use for code generation and analysis")
-                .prompt();
-
-            match selection {
-                Ok(model) => {
-                    println!("✓ Model set to: {}", model);
-                    // In a real implementation, this would update the configuration
-                }
-                Err(e) => {
-                    println!("Model selection cancelled: {}", e);
-                }
-            }
+                .prompt()
         }).await.map_err(|e| KaiError::ui(format!("Failed to show model selector: {}", e)))?;
 
+        match selection {
+            Ok(model) => self.set_model(model).await?,
+            Err(e) => println!("Model selection cancelled: {}", e),
+        }
+
         Ok(())
     }
 
@@ -178,11 +215,33 @@ impl SlashCommandProcessor {
     }
 
     /// Set model directly
+    ///
+    /// Models the registry knows to lack a feature plans need are refused.
     async fn set_model(&self, model_name: String) -> Result<()> {
+        if let Ok(catalog) = self.catalog(false).await {
+            match catalog.model(&model_name) {
+                Some(model) => {
+                    let unmet = self.requirements.unmet_by(model);
+                    if !unmet.is_empty() {
+                        println!("✗ {} lacks {}, which plans need", model_name, unmet.join(", "));
+                        return Ok(());
+                    }
+                }
+                None if !catalog.models.is_empty() => {
+                    println!("⚠️  {} is not in the model list of {}", model_name, catalog.provider);
+                }
+                None => {}
+            }
+        }
         println!("✓ Model set to: {}", model_name);
         
+        let provider = self
+            .llm_provider
+            .as_ref()
+            .map_or("provider", |provider| provider.provider_name())
+            .to_string();
         // Send event to update application state
-        self.event_sender.send(UiEvent::ProviderChanged("provider".to_string(), model_name))
+        self.event_sender.send(UiEvent::ProviderChanged(provider, model_name))
             .map_err(|e| KaiError::ui(format!("Failed to send provider change event: {}", e)))?;
         
         Ok(())
@@ -199,21 +258,15 @@ impl SlashCommandProcessor {
         Ok(())
     }
 
-    /// List available models
-    async fn list_models(&self) -> Result<()> {
-        println!("\n📋 Available Models:");
-        println!("┌─────────────────────────────────────┬──────────────┬─────────────┐");
-        println!("│ Model                               │ Provider     │ Context     │");
-        println!("├─────────────────────────────────────┼──────────────┼─────────────┤");
-        println!("│ anthropic/claude-3.5-sonnet        │ Anthropic    │ 200K tokens │");
-        println!("│ anthropic/claude-3.5-haiku         │ Anthropic    │ 200K tokens │");
-        println!("│ openai/gpt-4o                      │ OpenAI       │ 128K tokens │");
-        println!("│ openai/gpt-4o-mini                 │ OpenAI       │ 128K tokens │");
-        println!("│ google/gemini-pro-1.5              │ Google       │ 2M tokens   │");
-        println!("│ google/gemini-flash-1.5            │ Google       │ 1M tokens   │");
-        println!("│ meta-llama/llama-3.1-405b-instruct │ Meta         │ 32K tokens  │");
-        println!("│ meta-llama/llama-3.1-70b-instruct  │ Meta         │ 32K tokens  │");
-        println!("└─────────────────────────────────────┴──────────────┴─────────────┘");
+    /// List the models of the active provider, listing them again if `refresh` is set
+    async fn list_models(&self, refresh: bool) -> Result<()> {
+        let catalog = self.catalog(refresh).await?;
+        println!(
+            "\n📋 Models from {} (listed {} minutes ago):",
+            catalog.provider,
+            catalog.age().as_secs() / 60
+        );
+        println!("{}", format_model_table(&catalog.models, &self.requirements));
         
         Ok(())
     }
@@ -260,7 +313,7 @@ impl SlashCommandProcessor {
         println!();
         println!("📋 Model & Provider Management:");
         println!("  /model [name]     - Set or select LLM model");
-        println!("  /list-models      - Show all available models");
+        println!("  /list-models      - Show all available models (add 'refresh' to re-list)");
         println!("  /provider [name]  - Set or select LLM provider");
         println!();
         println!("🗂️  Context Management:");
@@ -273,6 +326,7 @@ impl SlashCommandProcessor {
         println!("  /pause           - Pause current plan execution");
         println!("  /resume          - Resume paused execution");
         println!("  /status          - Show application status");
+        println!("  /cache [clear]   - Show or clear the LLM response cache");
         println!();
         println!("💬 Interface Commands:");
         println!("  /history         - Show command history");
@@ -393,7 +447,7 @@ impl SlashCommandProcessor {
         let all_commands = vec![
             "/model", "/list-models", "/provider", "/reset-context", 
             "/refresh-context", "/help", "/workdir", "/history", 
-            "/clear", "/status", "/cancel", "/pause", "/resume"
+            "/clear", "/status", "/cancel", "/pause", "/resume", "/cache"
         ];
         
         let suggestions: Vec<&str> = all_commands
@@ -424,6 +478,83 @@ impl SlashCommandProcessor {
     }
 }
 
+/// Run a `/cache` action against the on-disk LLM response cache
+///
+/// With no action the cache size is reported; `clear` deletes every entry.
+pub fn cache_command(action: &str) -> Result<String> {
+    let dir = ResponseCache::default_dir()
+        .ok_or_else(|| KaiError::not_found("data directory for the response cache"))?;
+    let cache = ResponseCache::new(dir);
+
+    match action.trim() {
+        "" => {
+            let stats = cache.stats();
+            Ok(format!(
+                "💾 Response cache: {} entries, {:.1} MB in {}",
+                stats.entries,
+                stats.bytes as f64 / (1024.0 * 1024.0),
+                cache.dir().display()
+            ))
+        }
+        "clear" => {
+            let removed = cache.clear()?;
+            Ok(format!("🧹 Cleared {} cached responses", removed))
+        }
+        other => Err(KaiError::validation(
+            "cache",
+            format!("Unknown cache action '{}'. Use /cache or /cache clear", other),
+        )),
+    }
+}
+
+/// Table of models with their context window, capabilities and pricing
+///
+/// Capabilities the provider did not report are shown as `?`. Models that
+/// lack a feature in `requirements` are marked with what is missing.
+pub fn format_model_table(models: &[ModelInfo], requirements: &ModelRequirements) -> String {
+    if models.is_empty() {
+        return "   No models listed".to_string();
+    }
+
+    let flag = |supported: Option<bool>| match supported {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "?",
+    };
+    let price = |cost: Option<f64>| cost.map_or("?".to_string(), |cost| format!("${}", cost));
+    let width = models
+        .iter()
+        .map(|model| model.id.chars().count())
+        .max()
+        .unwrap_or_default()
+        .clamp(5, 48);
+
+    let mut lines = vec![format!(
+        "   {:<width$}  {:>9}  {:<5}  {:<6}  {:<4}  Price per 1M (in / out)",
+        "Model", "Context", "Tools", "Vision", "JSON"
+    )];
+    for model in models {
+        let capabilities = &model.capabilities;
+        let mut line = format!(
+            "   {:<width$}  {:>9}  {:<5}  {:<6}  {:<4}  {}",
+            model.id,
+            model.context_length.map_or("?".to_string(), |tokens| tokens.to_string()),
+            flag(capabilities.tools),
+            flag(capabilities.vision),
+            flag(capabilities.json_mode),
+            model.pricing.as_ref().map_or("-".to_string(), |pricing| {
+                format!("{} / {}", price(pricing.prompt), price(pricing.completion))
+            }),
+        );
+        let unmet = requirements.unmet_by(model);
+        if !unmet.is_empty() {
+            line.push_str(&format!("  ✗ lacks {}", unmet.join(", ")));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
 /// Simple Levenshtein distance calculation for command suggestions
 fn levenshtein_distance(s1: &str, s2: &str) -> usize {
     let len1 = s1.len();
@@ -0,0 +1,128 @@
+//! Chat transcript kept across turns and summarised for the model
+
+use super::console_chat::{ChatMessage, MessageRole};
+
+/// Messages at the end of the transcript that are sent nearly in full
+pub const RECENT_MESSAGES: usize = 6;
+
+/// Longest recent message that is sent in full
+const MAX_RECENT_CHARS: usize = 1500;
+
+/// Length that older messages are cut to
+const MAX_EARLIER_CHARS: usize = 160;
+
+/// Older messages beyond this count are dropped from the summary
+const MAX_EARLIER_MESSAGES: usize = 20;
+
+/// Conversation history of a chat session
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    messages: Vec<ChatMessage>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a message
+    pub fn push<S: Into<String>>(&mut self, role: MessageRole, content: S) {
+        self.messages.push(ChatMessage {
+            role,
+            content: content.into(),
+            timestamp: chrono::Utc::now(),
+        });
+    }
+
+    /// Forget all messages
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    /// All recorded messages, oldest first
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Summary of the conversation to send with the next request
+    ///
+    /// System messages are local notes and are left out. The most recent
+    /// messages are kept nearly verbatim, while older ones are cut to their
+    /// first line, so the summary stays small however long the session runs.
+    /// Returns `None` until the user and assistant have exchanged a message.
+    pub fn summary(&self) -> Option<String> {
+        let turns: Vec<&ChatMessage> = self
+            .messages
+            .iter()
+            .filter(|m| !matches!(m.role, MessageRole::System))
+            .collect();
+        if turns.is_empty() {
+            return None;
+        }
+
+        let recent_start = turns.len().saturating_sub(RECENT_MESSAGES);
+        let earlier_start = recent_start.saturating_sub(MAX_EARLIER_MESSAGES);
+
+        let mut lines = Vec::new();
+        if earlier_start > 0 {
+            lines.push(format!("({} earlier messages omitted)", earlier_start));
+        }
+        for message in &turns[earlier_start..recent_start] {
+            let first_line = message.content.lines().next().unwrap_or_default();
+            lines.push(format!("{}: {}", message.role, truncate(first_line, MAX_EARLIER_CHARS)));
+        }
+        for message in &turns[recent_start..] {
+            lines.push(format!("{}: {}", message.role, truncate(message.content.trim(), MAX_RECENT_CHARS)));
+        }
+
+        Some(lines.join("\n"))
+    }
+}
+
+/// Cut text to at most `max_chars` characters, marking the cut
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_skips_system_messages() {
+        let mut transcript = Transcript::new();
+        assert_eq!(transcript.summary(), None);
+
+        transcript.push(MessageRole::System, "Error: timeout");
+        assert_eq!(transcript.summary(), None);
+
+        transcript.push(MessageRole::User, "add logging to src/api");
+        transcript.push(MessageRole::Assistant, "Planned: Add logging\n- Update src/api/mod.rs");
+        assert_eq!(
+            transcript.summary().unwrap(),
+            "You: add logging to src/api\nKAI-X: Planned: Add logging\n- Update src/api/mod.rs"
+        );
+    }
+
+    #[test]
+    fn test_summary_shortens_older_messages() {
+        let mut transcript = Transcript::new();
+        let total = RECENT_MESSAGES + MAX_EARLIER_MESSAGES + 2;
+        for i in 0..total {
+            transcript.push(MessageRole::User, format!("request {}\n{}", i, "detail ".repeat(40)));
+        }
+
+        let summary = transcript.summary().unwrap();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], "(2 earlier messages omitted)");
+        assert_eq!(lines[1], "You: request 2");
+        assert!(!summary.contains("You: request 1\n"));
+
+        // Recent messages keep their detail
+        assert!(summary.ends_with(&format!("You: request {}\n{}", total - 1, "detail ".repeat(40).trim())));
+        assert_eq!(truncate("héllo", 2), "hé…");
+    }
+}
@@ -1,6 +1,6 @@
 //! Configuration access patterns and utilities
 
-use crate::config::{Config, ProviderConfig};
+use crate::config::Config;
 use std::collections::HashMap;
 
 /// Trait for unified configuration access
@@ -19,7 +19,7 @@ pub trait ConfigAccess {
     
     /// Get base URL for provider
     fn get_base_url(&self, provider_name: &str) -> Option<String> {
-        ProviderConfig::get_base_url_for_provider(provider_name)
+        self.config().get_base_url_for_provider(provider_name)
     }
     
     /// Get provider settings for LLM factory
@@ -36,19 +36,15 @@ pub trait ConfigAccess {
         
         // Add custom settings from provider config
         if let Some(provider_config) = self.config().providers.get(provider_name) {
-            for (key, value) in &provider_config.settings {
-                if let Some(string_value) = value.as_str() {
-                    settings.insert(key.clone(), string_value.to_string());
-                }
-            }
+            settings.extend(provider_config.string_settings());
         }
         
         settings
     }
     
-    /// Check if provider is ready (has API key)
+    /// Check if provider is ready (has API key, or needs none)
     fn is_provider_ready(&self, provider_name: &str) -> bool {
-        self.get_api_key(provider_name).is_some()
+        super::ApiKeyResolver::is_ready(provider_name, self.get_api_key(provider_name).as_deref())
     }
     
     /// Get active provider settings
@@ -94,9 +90,9 @@ impl ApiKeyResolver {
         super::ApiKeyResolver::env_key_name(provider_name)
     }
     
-    /// Check if API key is available
+    /// Check if API key is available, or the provider does not need one
     pub fn is_available(provider_name: &str, config_api_key: Option<&str>) -> bool {
-        Self::resolve(provider_name, config_api_key).is_some()
+        super::ApiKeyResolver::is_ready(provider_name, config_api_key)
     }
     
     /// Get status of API key sources
@@ -108,6 +104,9 @@ impl ApiKeyResolver {
         match (has_env_key, has_config_key) {
             (true, _) => ApiKeyStatus::Environment(env_var_name),
             (false, true) => ApiKeyStatus::Config,
+            (false, false) if !super::ApiKeyResolver::requires_api_key(provider_name) => {
+                ApiKeyStatus::NotRequired
+            }
             (false, false) => ApiKeyStatus::Missing(env_var_name),
         }
     }
@@ -122,6 +121,8 @@ pub enum ApiKeyStatus {
     Config,
     /// Key missing, shows expected env var name
     Missing(String),
+    /// Provider works without an API key
+    NotRequired,
 }
 
 impl ApiKeyStatus {
@@ -136,6 +137,7 @@ impl ApiKeyStatus {
             ApiKeyStatus::Environment(_) => "🌍 Environment",
             ApiKeyStatus::Config => "📁 Config",
             ApiKeyStatus::Missing(_) => "❌ Missing",
+            ApiKeyStatus::NotRequired => "🔓 Not required",
         }
     }
 }
@@ -171,10 +173,13 @@ mod tests {
             active_model: "test-model".to_string(),
             working_directory: None,
             providers,
+            fallback_chain: Vec::new(),
             ui: Default::default(),
             context: Default::default(),
             execution: Default::default(),
             logging: Default::default(),
+            cache: Default::default(),
+            budget: Default::default(),
         };
 
         let accessor = TestConfigAccess { config };
@@ -212,4 +217,34 @@ mod tests {
         
         std::env::remove_var("TEST_API_KEY");
     }
+
+    #[test]
+    fn test_key_less_provider_status() {
+        std::env::remove_var("OLLAMA_API_KEY");
+
+        let status = ApiKeyResolver::get_key_status("ollama", None);
+        assert_eq!(status, ApiKeyStatus::NotRequired);
+        assert!(status.is_available());
+        assert!(ApiKeyResolver::is_available("ollama", None));
+
+        let config = Config {
+            active_provider: "ollama".to_string(),
+            active_model: "llama3.2".to_string(),
+            working_directory: None,
+            providers: HashMap::from([(
+                "ollama".to_string(),
+                ProviderConfig::new_for_provider("ollama"),
+            )]),
+            fallback_chain: Vec::new(),
+            ui: Default::default(),
+            context: Default::default(),
+            execution: Default::default(),
+            logging: Default::default(),
+            cache: Default::default(),
+            budget: Default::default(),
+        };
+        let accessor = TestConfigAccess { config };
+        assert!(accessor.is_provider_ready("ollama"));
+        assert!(accessor.validate_active_provider().is_ok());
+    }
 }
\ No newline at end of file
@@ -20,13 +20,13 @@ pub trait ConfigAccessPattern {
     /// Get API key for a provider (environment variable takes precedence)
     fn get_provider_api_key(&self, provider_name: &str) -> Option<String>;
     
-    /// Get base URL for a provider (hardcoded based on provider type)
+    /// Get base URL for a provider (configured value, then hardcoded default)
     fn get_provider_base_url(&self, provider_name: &str) -> Option<String>;
     
     /// Get provider settings as HashMap for LLM factory
     fn get_provider_settings(&self, provider_name: &str) -> HashMap<String, String>;
     
-    /// Check if provider is configured and has API key
+    /// Check if provider is configured and has an API key (or needs none)
     fn is_provider_ready(&self, provider_name: &str) -> bool;
 }
 
@@ -37,7 +37,7 @@ impl ConfigAccessPattern for Config {
     }
     
     fn get_provider_base_url(&self, provider_name: &str) -> Option<String> {
-        ProviderConfig::get_base_url_for_provider(provider_name)
+        self.get_base_url_for_provider(provider_name)
     }
     
     fn get_provider_settings(&self, provider_name: &str) -> HashMap<String, String> {
@@ -55,18 +55,14 @@ impl ConfigAccessPattern for Config {
         
         // Add provider-specific settings from config
         if let Some(provider_config) = self.providers.get(provider_name) {
-            for (key, value) in &provider_config.settings {
-                if let Some(string_value) = value.as_str() {
-                    settings.insert(key.clone(), string_value.to_string());
-                }
-            }
+            settings.extend(provider_config.string_settings());
         }
         
         settings
     }
     
     fn is_provider_ready(&self, provider_name: &str) -> bool {
-        self.get_provider_api_key(provider_name).is_some()
+        ApiKeyResolver::is_ready(provider_name, self.get_provider_api_key(provider_name).as_deref())
     }
 }
 
@@ -142,6 +138,18 @@ impl ApiKeyResolver {
     pub fn has_api_key(provider_name: &str, config_api_key: Option<&str>) -> bool {
         Self::resolve_api_key(provider_name, config_api_key).is_some()
     }
+    
+    /// Check if provider needs an API key at all (unknown providers are assumed to)
+    pub fn requires_api_key(provider_name: &str) -> bool {
+        provider::ProviderMetadata::for_provider(provider_name)
+            .map(|metadata| metadata.requires_api_key)
+            .unwrap_or(true)
+    }
+    
+    /// Check if provider can be used: key-less providers are always ready
+    pub fn is_ready(provider_name: &str, config_api_key: Option<&str>) -> bool {
+        !Self::requires_api_key(provider_name) || Self::has_api_key(provider_name, config_api_key)
+    }
 }
 
 #[cfg(test)]
@@ -179,6 +187,18 @@ mod tests {
         assert_eq!(result, None);
     }
     
+    #[test]
+    fn test_key_less_providers_are_ready() {
+        std::env::remove_var("OLLAMA_API_KEY");
+        std::env::remove_var("TEST_PROVIDER_API_KEY");
+        
+        assert!(!ApiKeyResolver::requires_api_key("ollama"));
+        assert!(ApiKeyResolver::is_ready("ollama", None));
+        assert!(ApiKeyResolver::requires_api_key("test_provider"));
+        assert!(!ApiKeyResolver::is_ready("test_provider", None));
+        assert!(ApiKeyResolver::is_ready("test_provider", Some("config-key")));
+    }
+    
     #[test]
     fn test_env_key_name_generation() {
         assert_eq!(ApiKeyResolver::env_key_name("openrouter"), "OPENROUTER_API_KEY");
@@ -74,18 +74,17 @@ impl ProviderUtils {
             settings.insert("api_key".to_string(), api_key);
         }
         
-        // Add base URL
-        if let Some(base_url) = ProviderConfig::get_base_url_for_provider(provider_name) {
+        // Add base URL, preferring the configured one
+        if let Some(base_url) = provider_config
+            .and_then(|p| p.base_url.clone())
+            .or_else(|| ProviderConfig::get_base_url_for_provider(provider_name))
+        {
             settings.insert("base_url".to_string(), base_url);
         }
         
         // Add custom settings from provider config
         if let Some(config) = provider_config {
-            for (key, value) in &config.settings {
-                if let Some(string_value) = value.as_str() {
-                    settings.insert(key.clone(), string_value.to_string());
-                }
-            }
+            settings.extend(config.string_settings());
         }
         
         settings
@@ -107,6 +106,23 @@ impl ProviderUtils {
                     ));
                 }
             }
+            "openai_compatible" | "ollama" => {
+                // Self-hosted endpoints often run without authentication
+                if !settings.contains_key("base_url") {
+                    return Err(crate::utils::errors::KaiError::validation(
+                        "provider.base_url",
+                        format!("Base URL required for {}. Configure base_url in the provider settings.", provider_name)
+                    ));
+                }
+            }
+            "record" | "replay" => {
+                if !settings.contains_key("cassette") {
+                    return Err(crate::utils::errors::KaiError::validation(
+                        "provider.cassette",
+                        format!("Cassette path required for {}. Configure cassette in the provider settings.", provider_name)
+                    ));
+                }
+            }
             _ => {
                 // For unknown providers, just require API key
                 if !settings.contains_key("api_key") {
@@ -133,6 +149,7 @@ impl ProviderUtils {
             "gemini" => Some("gemini-pro".to_string()),
             "openai" => Some("gpt-3.5-turbo".to_string()),
             "anthropic" => Some("claude-3-haiku-20240307".to_string()),
+            "ollama" => Some("llama3.2".to_string()),
             _ => None,
         }
     }
@@ -141,7 +158,8 @@ impl ProviderUtils {
     pub fn is_known_provider(provider_name: &str) -> bool {
         matches!(
             provider_name.to_lowercase().as_str(),
-            "openrouter" | "gemini" | "openai" | "anthropic"
+            "openrouter" | "gemini" | "openai" | "anthropic" | "openai_compatible" | "ollama"
+                | "record" | "replay"
         )
     }
 }
@@ -203,13 +221,53 @@ impl ProviderMetadata {
                 supports_tools: true,
                 requires_api_key: true,
             }),
+            "openai_compatible" => Some(Self {
+                name: "openai_compatible".to_string(),
+                display_name: "OpenAI-compatible".to_string(),
+                description: "Any /v1/chat/completions endpoint (vLLM, llama.cpp server, ...)".to_string(),
+                base_url: Some("http://localhost:8000/v1".to_string()),
+                default_model: None,
+                supports_streaming: false,
+                supports_tools: true,
+                requires_api_key: false,
+            }),
+            "ollama" => Some(Self {
+                name: "ollama".to_string(),
+                display_name: "Ollama".to_string(),
+                description: "Locally pulled models served by an Ollama daemon".to_string(),
+                base_url: Some("http://localhost:11434".to_string()),
+                default_model: Some("llama3.2".to_string()),
+                supports_streaming: false,
+                supports_tools: true,
+                requires_api_key: false,
+            }),
+            "record" => Some(Self {
+                name: "record".to_string(),
+                display_name: "Record".to_string(),
+                description: "Wraps another provider and records its responses to a cassette".to_string(),
+                base_url: None,
+                default_model: None,
+                supports_streaming: false,
+                supports_tools: true,
+                requires_api_key: false,
+            }),
+            "replay" => Some(Self {
+                name: "replay".to_string(),
+                display_name: "Replay".to_string(),
+                description: "Serves recorded responses from a cassette for deterministic tests".to_string(),
+                base_url: None,
+                default_model: None,
+                supports_streaming: false,
+                supports_tools: true,
+                requires_api_key: false,
+            }),
             _ => None,
         }
     }
     
-    /// Get all known providers
+    /// Get all known model backends (the record/replay test harness is not listed)
     pub fn all_providers() -> Vec<Self> {
-        vec!["openrouter", "gemini", "openai", "anthropic"]
+        vec!["openrouter", "gemini", "openai", "anthropic", "openai_compatible", "ollama"]
             .into_iter()
             .filter_map(Self::for_provider)
             .collect()
@@ -279,7 +337,7 @@ mod tests {
         assert!(metadata.requires_api_key);
 
         let all_providers = ProviderMetadata::all_providers();
-        assert_eq!(all_providers.len(), 4);
+        assert_eq!(all_providers.len(), 6);
     }
 
     #[test]
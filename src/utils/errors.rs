@@ -358,6 +358,14 @@ impl KaiError {
         }
     }
 
+    /// Check if this error comes from a cancelled plan, task or LLM request
+    pub fn is_cancelled(&self) -> bool {
+        matches!(
+            self,
+            Self::Cancelled { .. } | Self::Llm(crate::llm::LlmError::Cancelled)
+        )
+    }
+
     /// Check if this error is recoverable
     pub fn is_recoverable(&self) -> bool {
         match self {
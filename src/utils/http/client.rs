@@ -2,8 +2,9 @@
 
 use crate::llm::LlmError;
 use reqwest::Client;
+use std::collections::HashMap;
 use std::time::Duration;
-use super::{DEFAULT_TIMEOUT, DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_DELAY};
+use super::{parse_setting, DEFAULT_TIMEOUT, DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_DELAY};
 
 /// Configuration for HTTP client
 #[derive(Debug, Clone)]
@@ -13,6 +14,7 @@ pub struct HttpClientConfig {
     pub retry_delay: Duration,
     pub user_agent: Option<String>,
     pub max_redirects: Option<usize>,
+    pub retry_rate_limits: bool,
 }
 
 impl Default for HttpClientConfig {
@@ -23,6 +25,38 @@ impl Default for HttpClientConfig {
             retry_delay: DEFAULT_RETRY_DELAY,
             user_agent: Some("KAI-X/1.0".to_string()),
             max_redirects: Some(10),
+            retry_rate_limits: true,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// Apply `timeout_secs`, `retry_attempts`, `retry_delay_ms` and
+    /// `retry_rate_limits` from provider settings
+    pub fn with_settings(mut self, settings: &HashMap<String, String>) -> Result<Self, LlmError> {
+        if let Some(timeout) = parse_setting::<u64>(settings, "timeout_secs")? {
+            self.timeout = Duration::from_secs(timeout);
+        }
+        if let Some(attempts) = parse_setting::<usize>(settings, "retry_attempts")? {
+            self.retry_attempts = attempts;
+        }
+        if let Some(delay) = parse_setting::<u64>(settings, "retry_delay_ms")? {
+            self.retry_delay = Duration::from_millis(delay);
+        }
+        if let Some(retry_rate_limits) = parse_setting::<bool>(settings, "retry_rate_limits")? {
+            self.retry_rate_limits = retry_rate_limits;
+        }
+        Ok(self)
+    }
+
+    /// Get retry configuration
+    pub fn retry_config(&self) -> super::retry::RetryConfig {
+        super::retry::RetryConfig {
+            max_attempts: self.retry_attempts,
+            base_delay: self.retry_delay,
+            max_delay: self.retry_delay * 10, // Cap max delay at 10x base
+            exponential_backoff: true,
+            retry_rate_limits: self.retry_rate_limits,
         }
     }
 }
@@ -118,11 +152,6 @@ impl HttpClient {
 
     /// Get retry configuration
     pub fn retry_config(&self) -> super::retry::RetryConfig {
-        super::retry::RetryConfig {
-            max_attempts: self.config.retry_attempts,
-            base_delay: self.config.retry_delay,
-            max_delay: self.config.retry_delay * 10, // Cap max delay at 10x base
-            exponential_backoff: true,
-        }
+        self.config.retry_config()
     }
 }
\ No newline at end of file
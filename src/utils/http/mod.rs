@@ -5,6 +5,7 @@
 
 use crate::llm::LlmError;
 use reqwest::Client;
+use std::collections::HashMap;
 use std::time::Duration;
 
 pub mod client;
@@ -12,7 +13,7 @@ pub mod retry;
 pub mod headers;
 
 pub use client::{HttpClient, HttpClientConfig, HttpClientBuilder};
-pub use retry::{RetryConfig, RetryableOperation, execute_with_retry};
+pub use retry::{RetryConfig, RetryableOperation, execute_with_retry, is_retryable_error};
 pub use headers::{HeaderBuilder, CommonHeaders};
 
 /// Default timeout for HTTP requests (2 minutes)
@@ -59,6 +60,10 @@ pub fn create_client(config: &SharedHttpConfig) -> Result<Client, LlmError> {
 
 /// Parse standard HTTP error responses
 pub fn parse_http_error(status: u16, body: &str, model_name: Option<&str>) -> LlmError {
+    if let Some(error) = context_length_error(status, body) {
+        return error;
+    }
+
     match status {
         429 => {
             // Try to extract retry-after from the response
@@ -87,8 +92,86 @@ pub fn parse_http_error(status: u16, body: &str, model_name: Option<&str>) -> Ll
     }
 }
 
+/// Recognise an error for a request that does not fit the model's context window
+///
+/// Providers report this as a 400 or 413 with their own wording, so the body
+/// is matched against the phrases OpenAI, Anthropic, Gemini, OpenRouter and
+/// llama.cpp-style servers use. The window size is extracted when the
+/// message states it.
+pub fn context_length_error(status: u16, body: &str) -> Option<LlmError> {
+    const PHRASES: [&str; 8] = [
+        "context_length_exceeded",
+        "maximum context length",
+        "context length",
+        "context window",
+        "prompt is too long",
+        "maximum number of tokens",
+        "too many tokens",
+        "exceeds the available context",
+    ];
+
+    if !matches!(status, 400 | 413) {
+        return None;
+    }
+    let lower = body.to_lowercase();
+    if !PHRASES.iter().any(|phrase| lower.contains(phrase)) {
+        return None;
+    }
+
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| {
+            json.pointer("/error/message")
+                .or_else(|| json.get("error"))
+                .or_else(|| json.get("message"))
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+        })
+        .unwrap_or_else(|| body.to_string());
+
+    Some(LlmError::ContextLengthExceeded {
+        limit: extract_context_limit(&message.to_lowercase()),
+        message,
+    })
+}
+
+/// Context window size stated in an overflow message, e.g. "maximum context length is 8192 tokens"
+fn extract_context_limit(message: &str) -> Option<u32> {
+    lazy_static::lazy_static! {
+        static ref LIMIT_PATTERNS: Vec<regex::Regex> = [
+            r"maximum context length is (\d+)",
+            r"tokens? > (\d+) maximum",
+            r"maximum number of tokens allowed \((\d+)\)",
+            r"context (?:length|window) (?:of|is) (?:only )?(\d+)",
+        ]
+        .iter()
+        .filter_map(|pattern| regex::Regex::new(pattern).ok())
+        .collect();
+    }
+
+    LIMIT_PATTERNS
+        .iter()
+        .find_map(|pattern| pattern.captures(message))
+        .and_then(|captures| captures[1].parse().ok())
+}
+
+/// Parse an optional setting from provider factory settings
+pub fn parse_setting<T: std::str::FromStr>(
+    settings: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>, LlmError> {
+    settings
+        .get(key)
+        .map(|value| {
+            value.trim().parse::<T>().map_err(|_| LlmError::Unknown {
+                message: format!("Invalid value for setting '{}': {}", key, value),
+            })
+        })
+        .transpose()
+}
+
 /// Extract retry-after value from error response
-fn extract_retry_after(body: &str) -> Option<u64> {
+pub fn extract_retry_after(body: &str) -> Option<u64> {
     // Try to parse retry_after from JSON response
     if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
         json.get("retry_after")
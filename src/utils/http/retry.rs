@@ -1,6 +1,6 @@
 //! Retry logic for HTTP operations
 
-use crate::llm::LlmError;
+use crate::llm::{cancellation, LlmError};
 use std::time::Duration;
 
 /// Configuration for retry behavior
@@ -10,6 +10,9 @@ pub struct RetryConfig {
     pub base_delay: Duration,
     pub max_delay: Duration,
     pub exponential_backoff: bool,
+    /// Whether `RateLimit` errors are retried here; disabled when a
+    /// `RateLimitedProvider` above the client owns the 429 policy
+    pub retry_rate_limits: bool,
 }
 
 impl Default for RetryConfig {
@@ -19,6 +22,7 @@ impl Default for RetryConfig {
             base_delay: Duration::from_millis(1000),
             max_delay: Duration::from_secs(240),
             exponential_backoff: true,
+            retry_rate_limits: true,
         }
     }
 }
@@ -42,6 +46,9 @@ pub trait RetryableOperation<T> {
 }
 
 /// Execute an operation with retry logic
+///
+/// Attempts and the waits between them are aborted with `LlmError::Cancelled`
+/// once the surrounding `cancellation::with_cancellation` token is cancelled.
 pub async fn execute_with_retry<F, Fut, T>(
     operation: F,
     config: &RetryConfig,
@@ -53,16 +60,17 @@ where
     let mut last_error = None;
     
     for attempt in 0..=config.max_attempts {
-        match operation().await {
+        match cancellation::cancellable(operation()).await {
             Ok(result) => return Ok(result),
+            Err(LlmError::Cancelled) => return Err(LlmError::Cancelled),
             Err(e) => {
                 last_error = Some(e);
                 
                 if attempt < config.max_attempts {
                     if let Some(ref error) = last_error {
-                        if is_retryable_error(error) {
+                        if should_retry(error, config) {
                             let delay = calculate_delay(error, attempt, config);
-                            tokio::time::sleep(delay).await;
+                            cancellation::sleep(delay).await?;
                         } else {
                             // Non-retryable error, break immediately
                             break;
@@ -78,8 +86,8 @@ where
     }))
 }
 
-/// Check if an error is retryable
-fn is_retryable_error(error: &LlmError) -> bool {
+/// Check if an error is transient (rate limits, network failures, 5xx responses)
+pub fn is_retryable_error(error: &LlmError) -> bool {
     match error {
         LlmError::RateLimit { .. } => true,
         LlmError::Network(_) => true,
@@ -88,13 +96,21 @@ fn is_retryable_error(error: &LlmError) -> bool {
     }
 }
 
+/// Check if an error should be retried under the given config
+fn should_retry(error: &LlmError, config: &RetryConfig) -> bool {
+    match error {
+        LlmError::RateLimit { .. } => config.retry_rate_limits,
+        _ => is_retryable_error(error),
+    }
+}
+
 /// Calculate delay for retry attempt
 fn calculate_delay(error: &LlmError, attempt: usize, config: &RetryConfig) -> Duration {
     match error {
         LlmError::RateLimit { retry_after } => {
-            // Use retry_after if provided, otherwise use exponential backoff
+            // Use retry_after if provided (capped at max_delay), otherwise use exponential backoff
             if let Some(retry_after) = retry_after {
-                Duration::from_secs(*retry_after)
+                std::cmp::min(Duration::from_secs(*retry_after), config.max_delay)
             } else {
                 calculate_exponential_delay(attempt, config)
             }
@@ -172,6 +188,7 @@ mod tests {
             base_delay: Duration::from_millis(1),
             max_delay: Duration::from_millis(240),
             exponential_backoff: false,
+            retry_rate_limits: true,
         };
         
         let result = execute_with_retry(operation, &config).await;
@@ -201,6 +218,7 @@ mod tests {
             base_delay: Duration::from_millis(1),
             max_delay: Duration::from_millis(240),
             exponential_backoff: false,
+            retry_rate_limits: true,
         };
         
         let result: Result<String, LlmError> = execute_with_retry(operation, &config).await;
@@ -228,10 +246,67 @@ mod tests {
             base_delay: Duration::from_millis(1),
             max_delay: Duration::from_millis(240),
             exponential_backoff: false,
+            retry_rate_limits: true,
         };
         
         let result: Result<String, LlmError> = execute_with_retry(operation, &config).await;
         assert!(result.is_err());
         assert_eq!(attempt_count.load(Ordering::SeqCst), 1); // Should stop after first attempt
     }
+
+    #[tokio::test]
+    async fn test_cancellation_interrupts_backoff() {
+        let attempt_count = Arc::new(AtomicUsize::new(0));
+        let attempt_count_clone = attempt_count.clone();
+        let token = tokio_util::sync::CancellationToken::new();
+        let trigger = token.clone();
+        
+        let operation = move || {
+            let count = attempt_count_clone.clone();
+            let trigger = trigger.clone();
+            async move {
+                count.fetch_add(1, Ordering::SeqCst);
+                trigger.cancel();
+                Err::<String, _>(LlmError::RateLimit { retry_after: Some(3600) })
+            }
+        };
+        
+        let config = RetryConfig::default();
+        let result = cancellation::with_cancellation(token, execute_with_retry(operation, &config)).await;
+        assert!(matches!(result, Err(LlmError::Cancelled)));
+        assert_eq!(attempt_count.load(Ordering::SeqCst), 1); // The hour-long wait is skipped
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_are_left_to_the_caller_when_disabled() {
+        let attempt_count = Arc::new(AtomicUsize::new(0));
+        let attempt_count_clone = attempt_count.clone();
+        
+        let operation = move || {
+            let count = attempt_count_clone.clone();
+            async move {
+                count.fetch_add(1, Ordering::SeqCst);
+                Err::<String, _>(LlmError::RateLimit { retry_after: Some(1) })
+            }
+        };
+        
+        let config = RetryConfig {
+            retry_rate_limits: false,
+            ..RetryConfig::default()
+        };
+        
+        let result = execute_with_retry(operation, &config).await;
+        assert!(matches!(result, Err(LlmError::RateLimit { .. })));
+        assert_eq!(attempt_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_after_is_capped_at_max_delay() {
+        let config = RetryConfig {
+            max_delay: Duration::from_secs(5),
+            ..RetryConfig::default()
+        };
+        let error = LlmError::RateLimit { retry_after: Some(3600) };
+        assert_eq!(calculate_delay(&error, 0, &config), Duration::from_secs(5));
+    }
 }
\ No newline at end of file
@@ -128,6 +128,7 @@ impl MessageBuilder {
             content: content.into(),
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         });
         self
     }
@@ -139,6 +140,7 @@ impl MessageBuilder {
             content: content.into(),
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         });
         self
     }
@@ -150,6 +152,7 @@ impl MessageBuilder {
             content: content.into(),
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         });
         self
     }
@@ -161,12 +164,14 @@ impl MessageBuilder {
             content: system,
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         });
         self.messages.push(Message {
             role: MessageRole::User,
             content: user,
             tool_calls: None,
             tool_call_id: None,
+            parts: Vec::new(),
         });
         self
     }
@@ -314,6 +319,25 @@ impl ExecutionAnalysisMessageBuilder {
     }
 }
 
+pub struct ConversationMessageBuilder;
+
+impl ConversationMessageBuilder {
+    pub fn create(
+        user_message: &str,
+        conversation_history: &str,
+        project_context: &str,
+        working_directory: &std::path::Path,
+    ) -> Result<Vec<Message>, LlmError> {
+        LlmMessageBuilder::with_base_context()
+            .var("user_message", user_message)
+            .var("conversation_history", conversation_history)
+            .var("project_context", project_context)
+            .var("working_directory", working_directory.display().to_string())
+            .var("recent_changes", "") // Plans run this session are part of the conversation history
+            .fill_and_create_messages(&crate::llm::prompts::PromptTemplates::conversation())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,45 +81,7 @@ impl TemplateHandler for StandardTemplateHandler {
                 message: format!("Failed to parse task analysis JSON: {}. Content: {}", e, content),
             })?;
 
-        // Extract fields with robust defaults
-        let success = analysis_json["success"].as_bool().unwrap_or(false);
-        let summary = analysis_json["summary"].as_str().unwrap_or("Analysis unavailable").to_string();
-        let details = analysis_json["details"].as_str().unwrap_or("").to_string();
-        let extracted_data = analysis_json.get("extracted_data").cloned();
-        let next_steps = analysis_json["next_steps"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                .collect::<Vec<String>>());
-        let context_updates = analysis_json["context_updates"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect());
-        let modified_files = analysis_json["modified_files"].as_array()
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str().map(|s| std::path::PathBuf::from(s)))
-                .collect::<Vec<std::path::PathBuf>>());
-        let error = if !success {
-            Some(analysis_json["error"].as_str().unwrap_or("Task failed").to_string())
-        } else {
-            None
-        };
-        let metadata = analysis_json["metadata"].as_object()
-            .map(|obj| obj.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect())
-            .unwrap_or_default();
-
-        Ok(TaskAnalysis {
-            success,
-            summary,
-            details,
-            extracted_data,
-            next_steps,
-            context_updates,
-            modified_files,
-            error,
-            metadata,
-        })
+        Ok(TaskAnalysis::from_json(&analysis_json))
     }
 }
 
@@ -109,12 +109,14 @@ impl LlmTemplateHandler for StandardLlmTemplateHandler {
                 content: system_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
             Message {
                 role: MessageRole::User,
                 content: user_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ])
     }
@@ -149,12 +151,14 @@ impl LlmTemplateHandler for StandardLlmTemplateHandler {
                 content: system_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
             Message {
                 role: MessageRole::User,
                 content: user_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ])
     }
@@ -194,12 +198,14 @@ impl LlmTemplateHandler for StandardLlmTemplateHandler {
                 content: system_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
             Message {
                 role: MessageRole::User,
                 content: user_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ])
     }
@@ -219,6 +225,7 @@ impl LlmTemplateHandler for StandardLlmTemplateHandler {
                 content,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ])
     }
@@ -260,12 +267,14 @@ impl TemplateUtils {
                 content: system_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
             Message {
                 role: MessageRole::User,
                 content: user_message,
                 tool_calls: None,
                 tool_call_id: None,
+                parts: Vec::new(),
             },
         ]
     }
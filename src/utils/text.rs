@@ -1,5 +1,6 @@
 //! Text processing utilities
 
+use crate::llm::tokenizer::TokenizerRegistry;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 
 /// Text processing utilities
@@ -61,9 +62,15 @@ pub fn truncate(text: &str, max_length: usize) -> String {
     }
 }
 
-/// Split text into chunks of maximum size, preserving word boundaries when possible
-pub fn chunk_text(text: &str, max_chunk_size: usize) -> Vec<String> {
-    if text.len() <= max_chunk_size {
+/// Split text into chunks of at most `max_tokens` tokens for `model`, preserving word boundaries when possible
+///
+/// Tokens are counted by the model's tokenizer from `TokenizerRegistry`. Words
+/// are measured with their leading space, which is how BPE tokenizers attach
+/// whitespace, so the counts of consecutive words add up.
+pub fn chunk_text(text: &str, max_tokens: usize, model: &str) -> Vec<String> {
+    let tokenizer = TokenizerRegistry::global().for_model(model);
+    let measure = |piece: &str| tokenizer.count_tokens(piece);
+    if measure(text) <= max_tokens {
         return vec![text.to_string()];
     }
 
@@ -72,21 +79,24 @@ pub fn chunk_text(text: &str, max_chunk_size: usize) -> Vec<String> {
     let mut current_size = 0;
 
     for word in text.split_whitespace() {
-        let word_len = word.len() + if current_chunk.is_empty() { 0 } else { 1 }; // +1 for space
+        let word_size = if current_chunk.is_empty() {
+            measure(word)
+        } else {
+            measure(&format!(" {}", word))
+        };
 
-        if current_size + word_len > max_chunk_size && !current_chunk.is_empty() {
+        if current_size + word_size > max_tokens && !current_chunk.is_empty() {
             // Start new chunk
             chunks.push(current_chunk.trim().to_string());
             current_chunk = word.to_string();
-            current_size = word.len();
+            current_size = measure(word);
         } else {
             // Add to current chunk
             if !current_chunk.is_empty() {
                 current_chunk.push(' ');
-                current_size += 1;
             }
             current_chunk.push_str(word);
-            current_size += word.len();
+            current_size += word_size;
         }
     }
 
@@ -140,10 +150,12 @@ mod tests {
 
     #[test]
     fn test_chunk_text() {
-        let text = "hello world this is a test";
-        let chunks = chunk_text(text, 10);
-        assert!(chunks.iter().all(|chunk| chunk.len() <= 10));
-        assert!(!chunks.is_empty());
+        let tokenizer = TokenizerRegistry::global().for_model("gpt-4");
+        let text = "alpha beta gamma delta epsilon zeta eta theta iota kappa";
+        let chunks = chunk_text(text, 8, "gpt-4");
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| tokenizer.count_tokens(chunk) <= 8));
+        assert_eq!(chunks.join(" "), text);
     }
 
     #[test]